@@ -0,0 +1,92 @@
+//! MQTT v5 enhanced authentication.
+//!
+//! Enhanced authentication is a challenge/response exchange carried in the
+//! Authentication Method and Authentication Data properties of CONNECT,
+//! CONNACK and AUTH packets. Each supported method implements
+//! [`Authenticator`], which turns the server challenges into client
+//! responses without knowing anything about the transport. The exchange is
+//! carried by [`AuthRelay`].
+
+use std::fmt::Display;
+
+mod relay;
+mod scram;
+
+pub use relay::{AUTH_EVENT_TYPE, AuthEvent, AuthRelay};
+pub use scram::ScramSha256;
+
+/// Result of handing a server challenge to an [`Authenticator`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuthStep {
+    /// Send the response data back to the server in an AUTH packet.
+    Continue(Vec<u8>),
+    /// The exchange completed and the server was verified.
+    Complete,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum AuthError {
+    /// The server data could not be parsed.
+    Malformed(String),
+    /// The server rejected the exchange or failed verification.
+    Rejected(String),
+    /// A challenge arrived after the exchange had completed.
+    UnexpectedChallenge,
+}
+
+impl Display for AuthError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AuthError::Malformed(msg) => write!(f, "malformed authentication data: {msg}"),
+            AuthError::Rejected(msg) => write!(f, "authentication rejected: {msg}"),
+            AuthError::UnexpectedChallenge => write!(f, "unexpected authentication challenge"),
+        }
+    }
+}
+
+impl std::error::Error for AuthError {}
+
+/// Pluggable challenge/response handler for one authentication method.
+pub trait Authenticator: Send {
+    /// Authentication Method property value, e.g. "SCRAM-SHA-256".
+    fn method(&self) -> &str;
+
+    /// Authentication Data to place in the CONNECT packet, if any.
+    fn initial_data(&mut self) -> Option<Vec<u8>>;
+
+    /// Handle Authentication Data received from the server in an AUTH or
+    /// CONNACK packet.
+    fn challenge(&mut self, data: &[u8]) -> Result<AuthStep, AuthError>;
+}
+
+/// Authentication methods selectable from the credentials tab.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AuthMethod {
+    /// Plain username and password in the CONNECT packet.
+    #[default]
+    Basic,
+    ScramSha256,
+}
+
+impl AuthMethod {
+    pub const ALL: [AuthMethod; 2] = [AuthMethod::Basic, AuthMethod::ScramSha256];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            AuthMethod::Basic => "Username/Password",
+            AuthMethod::ScramSha256 => scram::METHOD,
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|m| m.name() == name)
+    }
+
+    /// Create the authenticator for an enhanced authentication method.
+    pub fn authenticator(&self, username: &str, password: &str) -> Option<Box<dyn Authenticator>> {
+        match self {
+            AuthMethod::Basic => None,
+            AuthMethod::ScramSha256 => Some(Box::new(ScramSha256::new(username, password))),
+        }
+    }
+}
@@ -0,0 +1,538 @@
+//! Local relay that carries the enhanced authentication exchange.
+//!
+//! vaux-mqtt has no AUTH packet, so a client using an enhanced method
+//! connects to this relay instead of the broker. The relay adds the
+//! Authentication Method and Data to the client's CONNECT, answers the
+//! broker's AUTH challenges with the [`Authenticator`] and verifies the
+//! CONNACK. The authentication properties are taken out of the CONNACK before
+//! it reaches the client, after which bytes are forwarded unchanged. Each
+//! relay carries a single connection. Re-authentication is not supported.
+
+use std::{net::SocketAddr, time::Duration};
+
+use bytes::{BufMut, Bytes, BytesMut};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{TcpListener, TcpStream},
+};
+
+use crate::codec::{self, Frame, Reader};
+use crate::properties::put_variable_byte_int;
+use crate::reason;
+
+use super::{AuthStep, Authenticator};
+
+/// Packet type shown in the message log for the exchange.
+pub const AUTH_EVENT_TYPE: &str = "Auth";
+
+const AUTHENTICATION_METHOD: u8 = 0x15;
+const AUTHENTICATION_DATA: u8 = 0x16;
+const CONTINUE_AUTHENTICATION: u8 = 0x18;
+/// How long the relay waits for the client to connect and for each packet
+/// of the exchange.
+const RELAY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// A step of the exchange, for the message log.
+#[derive(Debug, Clone, PartialEq)]
+pub enum AuthEvent {
+    /// CONNECT or AUTH sent to the broker with the reason code and data.
+    Sent {
+        packet: &'static str,
+        reason: Option<u8>,
+        data: Option<Vec<u8>>,
+    },
+    /// AUTH or CONNACK from the broker with the reason code and data.
+    Received {
+        packet: &'static str,
+        reason: u8,
+        data: Option<Vec<u8>>,
+    },
+    /// The broker accepted the client and proved itself, with the method.
+    Verified(String),
+    Failed(String),
+}
+
+impl AuthEvent {
+    pub fn text(&self) -> String {
+        let exchange = |arrow: &str, packet: &str, reason: Option<u8>, data: &Option<Vec<u8>>| {
+            let mut text = format!("{arrow} {packet}");
+            if let Some(code) = reason {
+                text.push_str(&format!(" 0x{code:02X} {}", reason::name(code)));
+            }
+            if let Some(data) = data {
+                text.push_str(&format!(": {}", String::from_utf8_lossy(data)));
+            }
+            text
+        };
+        match self {
+            AuthEvent::Sent {
+                packet,
+                reason,
+                data,
+            } => exchange("→", packet, *reason, data),
+            AuthEvent::Received {
+                packet,
+                reason,
+                data,
+            } => exchange("←", packet, Some(*reason), data),
+            AuthEvent::Verified(method) => format!("{method} complete, broker verified"),
+            AuthEvent::Failed(e) => format!("Authentication failed: {e}"),
+        }
+    }
+}
+
+/// Relay for one connection, listening on a free local port until it runs.
+pub struct AuthRelay {
+    listener: std::net::TcpListener,
+    upstream: String,
+    authenticator: Box<dyn Authenticator>,
+}
+
+impl std::fmt::Debug for AuthRelay {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AuthRelay")
+            .field("address", &self.listener.local_addr().ok())
+            .field("upstream", &self.upstream)
+            .field("method", &self.authenticator.method())
+            .finish()
+    }
+}
+
+impl AuthRelay {
+    pub fn bind(upstream: String, authenticator: Box<dyn Authenticator>) -> Result<Self, String> {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.set_nonblocking(true).map(|_| listener))
+            .map_err(|e| format!("Failed to start the authentication relay: {e}"))?;
+        Ok(Self {
+            listener,
+            upstream,
+            authenticator,
+        })
+    }
+
+    /// Address the client connects to instead of the broker.
+    pub fn address(&self) -> SocketAddr {
+        self.listener
+            .local_addr()
+            .expect("bound listener has an address")
+    }
+
+    /// Carry one connection, reporting the exchange to `on_event`.
+    pub async fn run(self, mut on_event: Box<dyn FnMut(AuthEvent) + Send>) {
+        if let Err(e) = self.serve(&mut *on_event).await {
+            on_event(AuthEvent::Failed(e));
+        }
+    }
+
+    async fn serve(mut self, on_event: &mut (dyn FnMut(AuthEvent) + Send)) -> Result<(), String> {
+        let listener = TcpListener::from_std(self.listener).map_err(|e| e.to_string())?;
+        let (mut client, _) = tokio::time::timeout(RELAY_TIMEOUT, listener.accept())
+            .await
+            .map_err(|_| "the client did not connect to the relay".to_string())?
+            .map_err(|e| format!("relay accept failed: {e}"))?;
+        drop(listener);
+        let mut broker = tokio::time::timeout(RELAY_TIMEOUT, TcpStream::connect(&self.upstream))
+            .await
+            .map_err(|_| format!("no answer from {}", self.upstream))?
+            .map_err(|e| format!("failed to connect to {}: {e}", self.upstream))?;
+        let mut from_client = BytesMut::new();
+        let mut from_broker = BytesMut::new();
+
+        let connect = read_frame(&mut client, &mut from_client).await?;
+        if connect.kind != codec::CONNECT {
+            return Err(format!(
+                "the client sent {} before CONNECT",
+                codec::name(connect.kind)
+            ));
+        }
+        let method = self.authenticator.method().to_string();
+        let data = self.authenticator.initial_data();
+        let connect = with_authentication(connect.body, &method, data.as_deref())?;
+        write(&mut broker, &connect).await?;
+        on_event(AuthEvent::Sent {
+            packet: "CONNECT",
+            reason: None,
+            data,
+        });
+
+        loop {
+            let frame = read_frame(&mut broker, &mut from_broker).await?;
+            match frame.kind {
+                codec::AUTH => {
+                    let (reason, data) = auth_data(frame.body)?;
+                    on_event(AuthEvent::Received {
+                        packet: "AUTH",
+                        reason,
+                        data: data.clone(),
+                    });
+                    if reason != CONTINUE_AUTHENTICATION {
+                        return Err(format!("AUTH with reason 0x{reason:02X} during CONNECT"));
+                    }
+                    let AuthStep::Continue(response) = self
+                        .authenticator
+                        .challenge(&data.unwrap_or_default())
+                        .map_err(|e| e.to_string())?
+                    else {
+                        return Err("the broker continued a completed exchange".to_string());
+                    };
+                    let mut properties = BytesMut::new();
+                    put_authentication(&mut properties, &method, Some(&response));
+                    write(
+                        &mut broker,
+                        &codec::auth(CONTINUE_AUTHENTICATION, &properties),
+                    )
+                    .await?;
+                    on_event(AuthEvent::Sent {
+                        packet: "AUTH",
+                        reason: Some(CONTINUE_AUTHENTICATION),
+                        data: Some(response),
+                    });
+                }
+                codec::CONNACK => {
+                    let (connack, reason, data) = without_authentication(frame.body)?;
+                    on_event(AuthEvent::Received {
+                        packet: "CONNACK",
+                        reason,
+                        data: data.clone(),
+                    });
+                    if !reason::is_failure(reason) {
+                        // the final server data proves the broker knows the
+                        // credentials too
+                        let data = data.ok_or_else(|| {
+                            "CONNACK without Authentication Data, the broker is not verified"
+                                .to_string()
+                        })?;
+                        match self.authenticator.challenge(&data) {
+                            Ok(AuthStep::Complete) => on_event(AuthEvent::Verified(method.clone())),
+                            Ok(AuthStep::Continue(_)) => {
+                                return Err(
+                                    "the broker accepted an unfinished exchange".to_string()
+                                );
+                            }
+                            Err(e) => return Err(e.to_string()),
+                        }
+                    }
+                    // a refusal is passed on for the client to report
+                    write(&mut client, &connack).await?;
+                    break;
+                }
+                kind => {
+                    return Err(format!(
+                        "{} from the broker during CONNECT",
+                        codec::name(kind)
+                    ));
+                }
+            }
+        }
+
+        // anything read past the exchange goes on first
+        write(&mut broker, &from_client).await?;
+        write(&mut client, &from_broker).await?;
+        let _ = tokio::io::copy_bidirectional(&mut client, &mut broker).await;
+        Ok(())
+    }
+}
+
+async fn read_frame(stream: &mut TcpStream, buffer: &mut BytesMut) -> Result<Frame, String> {
+    loop {
+        if let Some(frame) = codec::next_frame(buffer)? {
+            return Ok(frame);
+        }
+        match tokio::time::timeout(RELAY_TIMEOUT, stream.read_buf(buffer)).await {
+            Ok(Ok(0)) => return Err("connection closed during the exchange".to_string()),
+            Ok(Ok(_)) => {}
+            Ok(Err(e)) => return Err(e.to_string()),
+            Err(_) => {
+                return Err(format!(
+                    "no packet within {}s during the exchange",
+                    RELAY_TIMEOUT.as_secs()
+                ));
+            }
+        }
+    }
+}
+
+async fn write(stream: &mut TcpStream, bytes: &[u8]) -> Result<(), String> {
+    stream.write_all(bytes).await.map_err(|e| e.to_string())
+}
+
+fn put_authentication(properties: &mut BytesMut, method: &str, data: Option<&[u8]>) {
+    properties.put_u8(AUTHENTICATION_METHOD);
+    codec::put_string(properties, method);
+    if let Some(data) = data {
+        properties.put_u8(AUTHENTICATION_DATA);
+        properties.put_u16(data.len() as u16);
+        properties.extend_from_slice(data);
+    }
+}
+
+/// Authentication Data of a property block, without its length.
+fn authentication_data(properties: &[u8]) -> Result<Option<Vec<u8>>, String> {
+    Ok(codec::split_properties(properties)?
+        .into_iter()
+        .find(|(id, _)| *id == AUTHENTICATION_DATA)
+        .map(|(_, value)| value[2..].to_vec()))
+}
+
+/// The client's CONNECT body as a CONNECT with the method and data added.
+fn with_authentication(body: Bytes, method: &str, data: Option<&[u8]>) -> Result<Bytes, String> {
+    let mut reader = Reader::new(body);
+    let mut connect = BytesMut::new();
+    codec::put_string(&mut connect, &reader.string()?);
+    // protocol version, connect flags and keep alive
+    connect.put_u8(reader.u8()?);
+    connect.put_u8(reader.u8()?);
+    connect.put_u16(reader.u16()?);
+    let mut properties = BytesMut::from(
+        &codec::without(
+            &reader.properties()?,
+            &[AUTHENTICATION_METHOD, AUTHENTICATION_DATA],
+        )?[..],
+    );
+    put_authentication(&mut properties, method, data);
+    put_variable_byte_int(&mut connect, properties.len() as u32);
+    connect.extend_from_slice(&properties);
+    connect.extend_from_slice(&reader.rest());
+    Ok(codec::frame(codec::CONNECT << 4, &connect))
+}
+
+/// Reason code and Authentication Data of an AUTH body.
+fn auth_data(body: Bytes) -> Result<(u8, Option<Vec<u8>>), String> {
+    let mut reader = Reader::new(body);
+    // no body at all means Success without properties
+    if reader.is_empty() {
+        return Ok((0x00, None));
+    }
+    let reason = reader.u8()?;
+    if reader.is_empty() {
+        return Ok((reason, None));
+    }
+    Ok((reason, authentication_data(&reader.properties()?)?))
+}
+
+/// The broker's CONNACK without the authentication properties, with its
+/// reason code and Authentication Data.
+fn without_authentication(body: Bytes) -> Result<(Bytes, u8, Option<Vec<u8>>), String> {
+    let mut reader = Reader::new(body);
+    let flags = reader.u8()?;
+    let reason = reader.u8()?;
+    let properties = if reader.is_empty() {
+        Bytes::new()
+    } else {
+        reader.properties()?
+    };
+    let data = authentication_data(&properties)?;
+    let properties = codec::without(&properties, &[AUTHENTICATION_METHOD, AUTHENTICATION_DATA])?;
+    let mut connack = BytesMut::with_capacity(properties.len() + 6);
+    connack.put_u8(flags);
+    connack.put_u8(reason);
+    put_variable_byte_int(&mut connack, properties.len() as u32);
+    connack.extend_from_slice(&properties);
+    Ok((codec::frame(codec::CONNACK << 4, &connack), reason, data))
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{Arc, Mutex};
+
+    use super::*;
+    use crate::auth::ScramSha256;
+    use crate::raw;
+    use crate::tests::run_local;
+
+    // the example exchange of RFC 7677 section 3
+    const CLIENT_FIRST: &str = "n,,n=user,r=rOprNGfwEbeRWgbNEkqO";
+    const SERVER_FIRST: &str =
+        "r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096";
+    const CLIENT_FINAL: &str = "c=biws,r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,\
+        p=dHzbZapWIk4jUhN+Ute9ytag9zjfMHgsqmmiz7AndVQ=";
+    const SERVER_FINAL: &str = "v=6rriTRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4=";
+
+    fn frame(bytes: &[u8]) -> Frame {
+        codec::next_frame(&mut BytesMut::from(bytes))
+            .unwrap()
+            .expect("complete frame")
+    }
+
+    /// Properties of a CONNECT body and what follows them.
+    fn connect_properties(body: Bytes) -> (Vec<(u8, Vec<u8>)>, Bytes) {
+        let mut reader = Reader::new(body);
+        reader.string().unwrap();
+        reader.u8().unwrap();
+        reader.u8().unwrap();
+        reader.u16().unwrap();
+        let properties = reader.properties().unwrap();
+        let properties = codec::split_properties(&properties)
+            .unwrap()
+            .into_iter()
+            .map(|(id, value)| (id, value.to_vec()))
+            .collect();
+        (properties, reader.rest())
+    }
+
+    fn authentication(method: &str, data: &str) -> BytesMut {
+        let mut properties = BytesMut::new();
+        put_authentication(&mut properties, method, Some(data.as_bytes()));
+        properties
+    }
+
+    #[test]
+    fn connect_gets_method_and_data() {
+        let original = frame(&raw::connect_packet("relayed", 30, None));
+        let (_, payload) = connect_properties(original.body.clone());
+        let connect =
+            frame(&with_authentication(original.body, "SCRAM-SHA-256", Some(b"n,,")).unwrap());
+        assert_eq!(connect.kind, codec::CONNECT);
+        let (properties, rest) = connect_properties(connect.body);
+        assert_eq!(
+            properties,
+            vec![
+                (AUTHENTICATION_METHOD, b"\x00\x0dSCRAM-SHA-256".to_vec()),
+                (AUTHENTICATION_DATA, b"\x00\x03n,,".to_vec()),
+            ]
+        );
+        assert_eq!(rest, payload);
+    }
+
+    #[test]
+    fn connack_loses_authentication_properties() {
+        let mut properties = authentication("SCRAM-SHA-256", SERVER_FINAL);
+        properties.put_u8(0x12);
+        codec::put_string(&mut properties, "assigned");
+        let mut body = BytesMut::from(&[0x00, 0x00][..]);
+        put_variable_byte_int(&mut body, properties.len() as u32);
+        body.extend_from_slice(&properties);
+
+        let (connack, reason, data) = without_authentication(body.freeze()).unwrap();
+        assert_eq!(reason, 0x00);
+        assert_eq!(data, Some(SERVER_FINAL.as_bytes().to_vec()));
+        assert_eq!(
+            connack.to_vec(),
+            b"\x20\x0e\x00\x00\x0b\x12\x00\x08assigned".to_vec()
+        );
+    }
+
+    #[test]
+    fn empty_auth_is_success() {
+        assert_eq!(auth_data(Bytes::new()), Ok((0x00, None)));
+    }
+
+    /// Broker side of the RFC 7677 exchange, answering the final client
+    /// message with `server_final`, then echoing one packet.
+    async fn scram_broker(listener: TcpListener, server_final: &'static str) {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut buffer = BytesMut::new();
+        let connect = read_frame(&mut stream, &mut buffer).await.unwrap();
+        let (properties, _) = connect_properties(connect.body);
+        assert!(properties.contains(&(
+            AUTHENTICATION_DATA,
+            [&[0, CLIENT_FIRST.len() as u8][..], CLIENT_FIRST.as_bytes()].concat()
+        )));
+        let challenge = authentication("SCRAM-SHA-256", SERVER_FIRST);
+        write(
+            &mut stream,
+            &codec::auth(CONTINUE_AUTHENTICATION, &challenge),
+        )
+        .await
+        .unwrap();
+        let response = read_frame(&mut stream, &mut buffer).await.unwrap();
+        assert_eq!(response.kind, codec::AUTH);
+        assert_eq!(
+            auth_data(response.body),
+            Ok((
+                CONTINUE_AUTHENTICATION,
+                Some(CLIENT_FINAL.as_bytes().to_vec())
+            ))
+        );
+        let properties = authentication("SCRAM-SHA-256", server_final);
+        let mut body = BytesMut::from(&[0x00, 0x00][..]);
+        put_variable_byte_int(&mut body, properties.len() as u32);
+        body.extend_from_slice(&properties);
+        write(&mut stream, &codec::frame(codec::CONNACK << 4, &body))
+            .await
+            .unwrap();
+        // the relay hangs up instead when it cannot verify the broker
+        if let Ok(ping) = read_frame(&mut stream, &mut buffer).await {
+            assert_eq!(ping.kind, codec::PINGREQ);
+            write(&mut stream, &[codec::PINGRESP << 4, 0])
+                .await
+                .unwrap();
+        }
+    }
+
+    /// Connect through a relay to a broker answering with `server_final`,
+    /// returning what the client received and the reported events.
+    async fn relay_exchange(server_final: &'static str) -> (Vec<u8>, Vec<AuthEvent>) {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let upstream = listener.local_addr().unwrap().to_string();
+        let broker = tokio::spawn(scram_broker(listener, server_final));
+        let scram = ScramSha256::with_nonce("user", "pencil", "rOprNGfwEbeRWgbNEkqO");
+        let relay = AuthRelay::bind(upstream, Box::new(scram)).unwrap();
+        let address = relay.address();
+        let events = Arc::new(Mutex::new(Vec::new()));
+        let reported = Arc::clone(&events);
+        let relay =
+            tokio::spawn(relay.run(Box::new(move |event| reported.lock().unwrap().push(event))));
+
+        let mut client = TcpStream::connect(address).await.unwrap();
+        write(&mut client, &raw::connect_packet("relayed", 30, None))
+            .await
+            .unwrap();
+        let mut received = Vec::new();
+        let mut buffer = BytesMut::new();
+        if let Ok(connack) = read_frame(&mut client, &mut buffer).await {
+            received.extend_from_slice(&[connack.kind << 4, connack.body.len() as u8]);
+            received.extend_from_slice(&connack.body);
+            write(&mut client, &[codec::PINGREQ << 4, 0]).await.unwrap();
+            let pingresp = read_frame(&mut client, &mut buffer).await.unwrap();
+            assert_eq!(pingresp.kind, codec::PINGRESP);
+            drop(client);
+        }
+        broker.await.unwrap();
+        relay.await.unwrap();
+        let events = events.lock().unwrap().clone();
+        (received, events)
+    }
+
+    #[test]
+    fn relay_carries_scram_exchange() {
+        let (connack, events) = run_local(relay_exchange(SERVER_FINAL));
+        assert_eq!(connack, vec![0x20, 0x03, 0x00, 0x00, 0x00]);
+        assert_eq!(
+            events,
+            vec![
+                AuthEvent::Sent {
+                    packet: "CONNECT",
+                    reason: None,
+                    data: Some(CLIENT_FIRST.as_bytes().to_vec()),
+                },
+                AuthEvent::Received {
+                    packet: "AUTH",
+                    reason: CONTINUE_AUTHENTICATION,
+                    data: Some(SERVER_FIRST.as_bytes().to_vec()),
+                },
+                AuthEvent::Sent {
+                    packet: "AUTH",
+                    reason: Some(CONTINUE_AUTHENTICATION),
+                    data: Some(CLIENT_FINAL.as_bytes().to_vec()),
+                },
+                AuthEvent::Received {
+                    packet: "CONNACK",
+                    reason: 0x00,
+                    data: Some(SERVER_FINAL.as_bytes().to_vec()),
+                },
+                AuthEvent::Verified("SCRAM-SHA-256".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn unverified_broker_is_not_connected() {
+        // a server signature that does not verify
+        let (connack, events) = run_local(relay_exchange(
+            "v=AAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAAA=",
+        ));
+        assert!(connack.is_empty());
+        assert!(matches!(events.last(), Some(AuthEvent::Failed(_))));
+    }
+}
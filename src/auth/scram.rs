@@ -0,0 +1,251 @@
+//! SCRAM-SHA-256 client (RFC 5802, RFC 7677) without channel binding.
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+
+use super::{AuthError, AuthStep, Authenticator};
+
+pub(super) const METHOD: &str = "SCRAM-SHA-256";
+/// GS2 header for a client that does not support channel binding.
+const GS2_HEADER: &str = "n,,";
+/// Lower bound recommended by RFC 7677 for the iteration count.
+const MIN_ITERATIONS: u32 = 4096;
+
+type HmacSha256 = Hmac<Sha256>;
+
+enum State {
+    Initial,
+    ClientFirstSent,
+    ClientFinalSent { server_signature: Vec<u8> },
+    Complete,
+}
+
+pub struct ScramSha256 {
+    username: String,
+    password: String,
+    nonce: String,
+    state: State,
+}
+
+impl ScramSha256 {
+    pub fn new(username: &str, password: &str) -> Self {
+        let nonce = STANDARD.encode(uuid::Uuid::new_v4().as_bytes());
+        Self::with_nonce(username, password, &nonce)
+    }
+
+    pub fn with_nonce(username: &str, password: &str, nonce: &str) -> Self {
+        Self {
+            username: username.to_string(),
+            password: password.to_string(),
+            nonce: nonce.to_string(),
+            state: State::Initial,
+        }
+    }
+
+    fn client_first_bare(&self) -> String {
+        // '=' and ',' must be escaped in the SASL user name
+        let username = self.username.replace('=', "=3D").replace(',', "=2C");
+        format!("n={username},r={}", self.nonce)
+    }
+
+    fn client_final(&mut self, server_first: &str) -> Result<AuthStep, AuthError> {
+        let mut nonce = None;
+        let mut salt = None;
+        let mut iterations = None;
+        for attr in server_first.split(',') {
+            match attr.split_once('=') {
+                Some(("r", v)) => nonce = Some(v),
+                Some(("s", v)) => {
+                    salt = Some(
+                        STANDARD
+                            .decode(v)
+                            .map_err(|e| AuthError::Malformed(format!("salt: {e}")))?,
+                    )
+                }
+                Some(("i", v)) => {
+                    iterations = Some(
+                        v.parse::<u32>()
+                            .map_err(|e| AuthError::Malformed(format!("iteration count: {e}")))?,
+                    )
+                }
+                Some(("e", v)) => return Err(AuthError::Rejected(v.to_string())),
+                _ => {}
+            }
+        }
+        let nonce = nonce.ok_or_else(|| AuthError::Malformed("missing nonce".to_string()))?;
+        let salt = salt.ok_or_else(|| AuthError::Malformed("missing salt".to_string()))?;
+        let iterations = iterations
+            .ok_or_else(|| AuthError::Malformed("missing iteration count".to_string()))?;
+        if !nonce.starts_with(&self.nonce) {
+            return Err(AuthError::Rejected(
+                "server nonce does not extend client nonce".to_string(),
+            ));
+        }
+        if iterations < MIN_ITERATIONS {
+            return Err(AuthError::Rejected(format!(
+                "iteration count {iterations} below {MIN_ITERATIONS}"
+            )));
+        }
+
+        let salted_password = hi(self.password.as_bytes(), &salt, iterations);
+        let client_key = hmac(&salted_password, b"Client Key");
+        let stored_key = Sha256::digest(&client_key);
+        let final_without_proof = format!("c={},r={nonce}", STANDARD.encode(GS2_HEADER));
+        let auth_message = format!(
+            "{},{server_first},{final_without_proof}",
+            self.client_first_bare()
+        );
+        let client_signature = hmac(&stored_key, auth_message.as_bytes());
+        let proof: Vec<u8> = client_key
+            .iter()
+            .zip(client_signature.iter())
+            .map(|(k, s)| k ^ s)
+            .collect();
+        let server_key = hmac(&salted_password, b"Server Key");
+        self.state = State::ClientFinalSent {
+            server_signature: hmac(&server_key, auth_message.as_bytes()),
+        };
+        Ok(AuthStep::Continue(
+            format!("{final_without_proof},p={}", STANDARD.encode(proof)).into_bytes(),
+        ))
+    }
+}
+
+impl Authenticator for ScramSha256 {
+    fn method(&self) -> &str {
+        METHOD
+    }
+
+    fn initial_data(&mut self) -> Option<Vec<u8>> {
+        self.state = State::ClientFirstSent;
+        Some(format!("{GS2_HEADER}{}", self.client_first_bare()).into_bytes())
+    }
+
+    fn challenge(&mut self, data: &[u8]) -> Result<AuthStep, AuthError> {
+        let message = std::str::from_utf8(data)
+            .map_err(|e| AuthError::Malformed(format!("not UTF-8: {e}")))?;
+        match &self.state {
+            State::Initial | State::Complete => Err(AuthError::UnexpectedChallenge),
+            State::ClientFirstSent => self.client_final(message),
+            State::ClientFinalSent { server_signature } => {
+                if let Some(error) = message.strip_prefix("e=") {
+                    return Err(AuthError::Rejected(error.to_string()));
+                }
+                let verifier = message
+                    .strip_prefix("v=")
+                    .ok_or_else(|| AuthError::Malformed("missing server signature".to_string()))?;
+                let verifier = STANDARD
+                    .decode(verifier)
+                    .map_err(|e| AuthError::Malformed(format!("server signature: {e}")))?;
+                if &verifier != server_signature {
+                    return Err(AuthError::Rejected("server signature mismatch".to_string()));
+                }
+                self.state = State::Complete;
+                Ok(AuthStep::Complete)
+            }
+        }
+    }
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = HmacSha256::new_from_slice(key).expect("HMAC accepts keys of any length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// The SCRAM `Hi` function, PBKDF2 with HMAC-SHA-256 and a single block.
+fn hi(password: &[u8], salt: &[u8], iterations: u32) -> Vec<u8> {
+    let mut block = salt.to_vec();
+    block.extend_from_slice(&1u32.to_be_bytes());
+    let mut u = hmac(password, &block);
+    let mut result = u.clone();
+    for _ in 1..iterations {
+        u = hmac(password, &u);
+        result.iter_mut().zip(u.iter()).for_each(|(r, b)| *r ^= b);
+    }
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // the example exchange of RFC 7677 section 3
+    const CLIENT_NONCE: &str = "rOprNGfwEbeRWgbNEkqO";
+    const SERVER_FIRST: &str =
+        "r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096";
+    const CLIENT_FINAL: &str = "c=biws,r=rOprNGfwEbeRWgbNEkqO%hvYDpWUa2RaTCAfuxFIlj)hNlF$k0,\
+        p=dHzbZapWIk4jUhN+Ute9ytag9zjfMHgsqmmiz7AndVQ=";
+    const SERVER_FINAL: &str = "v=6rriTRBi23WpRR/wtup+mMhUZUn/dB5nLTJRsjl95G4=";
+
+    fn after_server_first() -> ScramSha256 {
+        let mut scram = ScramSha256::with_nonce("user", "pencil", CLIENT_NONCE);
+        scram.initial_data();
+        assert_eq!(
+            scram.challenge(SERVER_FIRST.as_bytes()),
+            Ok(AuthStep::Continue(CLIENT_FINAL.as_bytes().to_vec()))
+        );
+        scram
+    }
+
+    #[test]
+    fn rfc_7677_exchange() {
+        let mut scram = ScramSha256::with_nonce("user", "pencil", CLIENT_NONCE);
+        assert_eq!(
+            scram.initial_data(),
+            Some(b"n,,n=user,r=rOprNGfwEbeRWgbNEkqO".to_vec())
+        );
+        assert_eq!(
+            scram.challenge(SERVER_FIRST.as_bytes()),
+            Ok(AuthStep::Continue(CLIENT_FINAL.as_bytes().to_vec()))
+        );
+        assert_eq!(
+            scram.challenge(SERVER_FINAL.as_bytes()),
+            Ok(AuthStep::Complete)
+        );
+        assert_eq!(
+            scram.challenge(SERVER_FINAL.as_bytes()),
+            Err(AuthError::UnexpectedChallenge)
+        );
+    }
+
+    #[test]
+    fn wrong_server_signature_is_rejected() {
+        let mut scram = after_server_first();
+        let forged = format!("v={}", STANDARD.encode([0u8; 32]));
+        assert!(matches!(
+            scram.challenge(forged.as_bytes()),
+            Err(AuthError::Rejected(_))
+        ));
+    }
+
+    #[test]
+    fn server_error_is_rejected() {
+        let mut scram = after_server_first();
+        assert_eq!(
+            scram.challenge(b"e=invalid-proof"),
+            Err(AuthError::Rejected("invalid-proof".to_string()))
+        );
+    }
+
+    #[test]
+    fn server_nonce_must_extend_client_nonce() {
+        let mut scram = ScramSha256::with_nonce("user", "pencil", CLIENT_NONCE);
+        scram.initial_data();
+        let server_first = "r=someone-else,s=W22ZaJ0SNY7soEsUEjb6gQ==,i=4096";
+        assert!(matches!(
+            scram.challenge(server_first.as_bytes()),
+            Err(AuthError::Rejected(_))
+        ));
+    }
+
+    #[test]
+    fn user_name_is_escaped() {
+        let mut scram = ScramSha256::with_nonce("a=b,c", "pencil", CLIENT_NONCE);
+        assert_eq!(
+            scram.initial_data(),
+            Some(b"n,,n=a=3Db=2Cc,r=rOprNGfwEbeRWgbNEkqO".to_vec())
+        );
+    }
+}
@@ -0,0 +1,826 @@
+//! Minimal MQTT v5 broker for testing without an installed broker.
+//!
+//! It accepts v5 CONNECT and routes PUBLISH to matching subscriptions at QoS
+//! 0, 1 and 2. It keeps retained messages, sessions for resuming, wills and
+//! shared subscriptions. Faults can be injected while it runs: delayed
+//! acknowledgements, dropped packets and forced disconnects. Everything is
+//! kept in memory and is gone when it stops.
+
+use std::{
+    collections::{BTreeMap, HashMap, HashSet},
+    net::SocketAddr,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use bytes::{Bytes, BytesMut};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::tcp::OwnedReadHalf;
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::codec::{self, Frame, Message};
+
+pub const DEFAULT_PORT: u16 = 1883;
+/// How long a new connection has to send CONNECT.
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+const SUCCESS: u8 = 0x00;
+const DISCONNECT_WITH_WILL: u8 = 0x04;
+const NO_SUBSCRIPTION_EXISTED: u8 = 0x11;
+const MALFORMED_PACKET: u8 = 0x81;
+const PROTOCOL_ERROR: u8 = 0x82;
+const KEEP_ALIVE_TIMEOUT: u8 = 0x8D;
+const SESSION_TAKEN_OVER: u8 = 0x8E;
+const TOPIC_FILTER_INVALID: u8 = 0x8F;
+const TOPIC_NAME_INVALID: u8 = 0x90;
+const PACKET_IDENTIFIER_NOT_FOUND: u8 = 0x92;
+const ADMINISTRATIVE_ACTION: u8 = 0x98;
+
+/// Faults injected into every connection, changed while the broker runs.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Faults {
+    /// Wait before each acknowledgement. The connection's later packets
+    /// wait too.
+    pub ack_delay: Duration,
+    /// Ignore every Nth packet received from clients, 0 for none.
+    pub drop_every: u32,
+    /// Disconnect each client this long after it connects.
+    pub disconnect_after: Option<Duration>,
+}
+
+enum Outgoing {
+    Frame(Bytes),
+    /// Send DISCONNECT with the reason code, then close.
+    Disconnect(u8),
+    /// Close without DISCONNECT.
+    Close,
+}
+
+struct Subscription {
+    filter: String,
+    /// Share name of a `$share/<group>/<filter>` subscription.
+    group: Option<String>,
+    qos: u8,
+    no_local: bool,
+    retain_as_published: bool,
+}
+
+/// A message sent at QoS 1 or 2 and not yet acknowledged.
+struct Inflight {
+    message: Message,
+    qos: u8,
+    /// PUBREC received, waiting for PUBCOMP.
+    released: bool,
+    /// Group and filter of the shared subscription it was sent for.
+    share: Option<(String, String)>,
+}
+
+/// State kept between the connections of a client ID.
+#[derive(Default)]
+struct Session {
+    subscriptions: Vec<Subscription>,
+    inflight: BTreeMap<u16, Inflight>,
+    /// QoS 2 packet IDs received and not yet released by the client.
+    incoming: HashSet<u16>,
+    next_packet_id: u16,
+    expiry: u32,
+    /// Connection number and writer of the connected client.
+    connection: Option<(u64, UnboundedSender<Outgoing>)>,
+}
+
+impl Session {
+    fn send(&self, frame: Bytes) {
+        if let Some((_, tx)) = &self.connection {
+            let _ = tx.send(Outgoing::Frame(frame));
+        }
+    }
+
+    fn packet_id(&mut self) -> u16 {
+        loop {
+            self.next_packet_id = self.next_packet_id.wrapping_add(1).max(1);
+            if !self.inflight.contains_key(&self.next_packet_id) {
+                return self.next_packet_id;
+            }
+        }
+    }
+
+    /// Send a message at `qos`. QoS 1 and 2 messages for an offline session
+    /// are kept until it resumes, QoS 0 messages are dropped.
+    fn deliver(
+        &mut self,
+        message: &Message,
+        qos: u8,
+        retain: bool,
+        share: Option<(String, String)>,
+    ) {
+        if qos == 0 {
+            self.send(codec::publish_frame(message, 0, 0, retain, false));
+            return;
+        }
+        let packet_id = self.packet_id();
+        self.send(codec::publish_frame(message, qos, packet_id, retain, false));
+        let message = Message {
+            retain,
+            ..message.clone()
+        };
+        self.inflight.insert(
+            packet_id,
+            Inflight {
+                message,
+                qos,
+                released: false,
+                share,
+            },
+        );
+    }
+
+    /// Resend what the previous connection left unacknowledged.
+    fn resend(&self) {
+        for (packet_id, inflight) in &self.inflight {
+            self.send(if inflight.released {
+                codec::ack(codec::PUBREL, *packet_id, SUCCESS)
+            } else {
+                let message = &inflight.message;
+                codec::publish_frame(message, inflight.qos, *packet_id, message.retain, true)
+            });
+        }
+    }
+}
+
+#[derive(Default)]
+struct State {
+    sessions: HashMap<String, Session>,
+    retained: BTreeMap<String, Message>,
+    /// Next member to deliver to, by shared subscription group and filter.
+    next_member: HashMap<(String, String), usize>,
+    connections: u64,
+    /// Packets received from clients, counted for `Faults::drop_every`.
+    received: u32,
+}
+
+impl State {
+    /// Send a message to one connected member of a shared subscription,
+    /// other than `except`.
+    fn deliver_shared(&mut self, share: (String, String), message: &Message, except: Option<&str>) {
+        let mut members: Vec<(&String, u8)> = self
+            .sessions
+            .iter()
+            .filter(|(client_id, session)| {
+                session.connection.is_some() && except != Some(client_id.as_str())
+            })
+            .filter_map(|(client_id, session)| {
+                session
+                    .subscriptions
+                    .iter()
+                    .find(|s| s.group.as_ref() == Some(&share.0) && s.filter == share.1)
+                    .map(|s| (client_id, s.qos))
+            })
+            .collect();
+        if members.is_empty() {
+            return;
+        }
+        members.sort();
+        let next = self.next_member.entry(share.clone()).or_default();
+        let (client_id, qos) = members[*next % members.len()];
+        *next = next.wrapping_add(1);
+        let client_id = client_id.clone();
+        if let Some(session) = self.sessions.get_mut(&client_id) {
+            session.deliver(message, qos.min(message.qos), false, Some(share));
+        }
+    }
+}
+
+pub struct Broker {
+    state: Mutex<State>,
+    faults: Mutex<Faults>,
+}
+
+impl Broker {
+    pub fn new(faults: Faults) -> Arc<Self> {
+        Arc::new(Self {
+            state: Mutex::new(State::default()),
+            faults: Mutex::new(faults),
+        })
+    }
+
+    pub fn set_faults(&self, faults: Faults) {
+        *self.faults.lock().unwrap() = faults;
+    }
+
+    fn faults(&self) -> Faults {
+        self.faults.lock().unwrap().clone()
+    }
+
+    /// Connected clients.
+    pub fn clients(&self) -> usize {
+        let state = self.state.lock().unwrap();
+        state
+            .sessions
+            .values()
+            .filter(|session| session.connection.is_some())
+            .count()
+    }
+
+    /// Send every connected client DISCONNECT with Administrative action.
+    pub fn disconnect_all(&self) {
+        let state = self.state.lock().unwrap();
+        for (_, tx) in state
+            .sessions
+            .values()
+            .filter_map(|s| s.connection.as_ref())
+        {
+            let _ = tx.send(Outgoing::Disconnect(ADMINISTRATIVE_ACTION));
+        }
+    }
+
+    /// Whether to ignore the next packet received from a client.
+    fn drop_packet(&self) -> bool {
+        let drop_every = self.faults().drop_every;
+        let mut state = self.state.lock().unwrap();
+        state.received = state.received.wrapping_add(1);
+        drop_every > 0 && state.received.is_multiple_of(drop_every)
+    }
+
+    async fn acknowledge(&self, tx: &UnboundedSender<Outgoing>, frame: Bytes) {
+        let delay = self.faults().ack_delay;
+        if !delay.is_zero() {
+            tokio::time::sleep(delay).await;
+        }
+        let _ = tx.send(Outgoing::Frame(frame));
+    }
+
+    fn route(&self, message: &Message, from: &str) {
+        let mut state = self.state.lock().unwrap();
+        if message.retain {
+            if message.payload.is_empty() {
+                state.retained.remove(&message.topic);
+            } else {
+                state
+                    .retained
+                    .insert(message.topic.clone(), message.clone());
+            }
+        }
+        let mut shares = HashSet::new();
+        for (client_id, session) in state.sessions.iter_mut() {
+            // overlapping subscriptions get one copy at the highest QoS
+            let mut best: Option<(u8, bool)> = None;
+            for subscription in &session.subscriptions {
+                if !topic_matches(&subscription.filter, &message.topic) {
+                    continue;
+                }
+                match &subscription.group {
+                    Some(group) => {
+                        shares.insert((group.clone(), subscription.filter.clone()));
+                    }
+                    None if subscription.no_local && client_id == from => {}
+                    None => {
+                        let retain = subscription.retain_as_published && message.retain;
+                        best = Some(best.map_or((subscription.qos, retain), |(qos, r)| {
+                            (qos.max(subscription.qos), r || retain)
+                        }));
+                    }
+                }
+            }
+            if let Some((qos, retain)) = best {
+                session.deliver(message, qos.min(message.qos), retain, None);
+            }
+        }
+        for share in shares {
+            state.deliver_shared(share, message, None);
+        }
+    }
+
+    /// Register a connection for `client_id`, returning its number and
+    /// whether an earlier session was resumed. CONNACK and any messages left
+    /// from the earlier session are queued on `tx`.
+    fn attach(
+        &self,
+        client_id: &str,
+        connect: &codec::Connect,
+        assigned: bool,
+        tx: &UnboundedSender<Outgoing>,
+    ) -> u64 {
+        let mut state = self.state.lock().unwrap();
+        state.connections += 1;
+        let number = state.connections;
+        if let Some((_, previous)) = state
+            .sessions
+            .get_mut(client_id)
+            .and_then(|session| session.connection.take())
+        {
+            let _ = previous.send(Outgoing::Disconnect(SESSION_TAKEN_OVER));
+        }
+        if connect.clean_start {
+            state.sessions.remove(client_id);
+        }
+        let present = state.sessions.contains_key(client_id);
+        let session = state.sessions.entry(client_id.to_string()).or_default();
+        session.expiry = connect.session_expiry;
+        session.connection = Some((number, tx.clone()));
+        session.send(codec::connack(
+            present,
+            SUCCESS,
+            assigned.then_some(client_id),
+        ));
+        session.resend();
+        eprintln!(
+            "Broker: {client_id} connected{}",
+            if present { ", session resumed" } else { "" }
+        );
+        number
+    }
+
+    /// Forget connection `number` of `client_id`. Unacknowledged shared
+    /// subscription messages go to another member of the group.
+    fn detach(&self, client_id: &str, number: u64, will: Option<Message>) {
+        {
+            let mut state = self.state.lock().unwrap();
+            let Some(session) = state.sessions.get_mut(client_id) else {
+                return;
+            };
+            if session
+                .connection
+                .as_ref()
+                .is_none_or(|(n, _)| *n != number)
+            {
+                // taken over by a newer connection
+                return;
+            }
+            session.connection = None;
+            let shared_ids: Vec<u16> = session
+                .inflight
+                .iter()
+                .filter(|(_, inflight)| inflight.share.is_some() && !inflight.released)
+                .map(|(packet_id, _)| *packet_id)
+                .collect();
+            let shared: Vec<(Message, (String, String))> = shared_ids
+                .iter()
+                .filter_map(|packet_id| session.inflight.remove(packet_id))
+                .filter_map(|inflight| inflight.share.map(|share| (inflight.message, share)))
+                .collect();
+            if session.expiry == 0 {
+                state.sessions.remove(client_id);
+            }
+            for (message, share) in shared {
+                state.deliver_shared(share, &message, Some(client_id));
+            }
+            eprintln!("Broker: {client_id} disconnected");
+        }
+        if let Some(will) = will {
+            eprintln!("Broker: publishing the will of {client_id}");
+            self.route(&will, client_id);
+        }
+    }
+
+    /// Handle one packet from a connected client. Returns the DISCONNECT
+    /// reason code when the client disconnected, or the reason code to
+    /// disconnect it with on a protocol violation.
+    async fn handle(
+        &self,
+        client_id: &str,
+        frame: Frame,
+        tx: &UnboundedSender<Outgoing>,
+    ) -> Result<Option<u8>, u8> {
+        let malformed = |e: String| {
+            eprintln!(
+                "Broker: malformed {} from {client_id}: {e}",
+                codec::name(frame.kind)
+            );
+            MALFORMED_PACKET
+        };
+        match frame.kind {
+            codec::PUBLISH => {
+                let (message, packet_id) =
+                    codec::publish(frame.flags, frame.body.clone()).map_err(malformed)?;
+                if message.topic.is_empty() || message.topic.contains(['+', '#']) {
+                    return Err(TOPIC_NAME_INVALID);
+                }
+                match message.qos {
+                    0 => self.route(&message, client_id),
+                    1 => {
+                        self.route(&message, client_id);
+                        self.acknowledge(tx, codec::ack(codec::PUBACK, packet_id, SUCCESS))
+                            .await;
+                    }
+                    _ => {
+                        let first = self
+                            .state
+                            .lock()
+                            .unwrap()
+                            .sessions
+                            .get_mut(client_id)
+                            .is_some_and(|session| session.incoming.insert(packet_id));
+                        // a resent QoS 2 message is only routed once
+                        if first {
+                            self.route(&message, client_id);
+                        }
+                        self.acknowledge(tx, codec::ack(codec::PUBREC, packet_id, SUCCESS))
+                            .await;
+                    }
+                }
+            }
+            codec::PUBACK | codec::PUBREC | codec::PUBCOMP => {
+                let packet_id = codec::packet_id(frame.body.clone()).map_err(malformed)?;
+                let found = {
+                    let mut state = self.state.lock().unwrap();
+                    let session = state.sessions.get_mut(client_id);
+                    match (frame.kind, session) {
+                        (codec::PUBREC, Some(session)) => {
+                            match session.inflight.get_mut(&packet_id) {
+                                Some(inflight) => {
+                                    inflight.released = true;
+                                    true
+                                }
+                                None => false,
+                            }
+                        }
+                        (_, Some(session)) => session.inflight.remove(&packet_id).is_some(),
+                        (_, None) => false,
+                    }
+                };
+                if frame.kind == codec::PUBREC {
+                    let reason = if found {
+                        SUCCESS
+                    } else {
+                        PACKET_IDENTIFIER_NOT_FOUND
+                    };
+                    self.acknowledge(tx, codec::ack(codec::PUBREL, packet_id, reason))
+                        .await;
+                }
+            }
+            codec::PUBREL => {
+                if frame.flags != 0x02 {
+                    return Err(MALFORMED_PACKET);
+                }
+                let packet_id = codec::packet_id(frame.body.clone()).map_err(malformed)?;
+                let found = self
+                    .state
+                    .lock()
+                    .unwrap()
+                    .sessions
+                    .get_mut(client_id)
+                    .is_some_and(|session| session.incoming.remove(&packet_id));
+                let reason = if found {
+                    SUCCESS
+                } else {
+                    PACKET_IDENTIFIER_NOT_FOUND
+                };
+                self.acknowledge(tx, codec::ack(codec::PUBCOMP, packet_id, reason))
+                    .await;
+            }
+            codec::SUBSCRIBE => {
+                if frame.flags != 0x02 {
+                    return Err(MALFORMED_PACKET);
+                }
+                let (packet_id, filters) =
+                    codec::subscribe(frame.body.clone()).map_err(malformed)?;
+                let mut codes = Vec::with_capacity(filters.len());
+                // retained messages to send once SUBACK is on its way
+                let mut retained = Vec::new();
+                {
+                    let mut state = self.state.lock().unwrap();
+                    let state = &mut *state;
+                    let Some(session) = state.sessions.get_mut(client_id) else {
+                        return Err(PROTOCOL_ERROR);
+                    };
+                    for (filter, options) in filters {
+                        let qos = options & 0x03;
+                        if qos == 3 {
+                            return Err(MALFORMED_PACKET);
+                        }
+                        let Some((group, filter)) = parse_filter(&filter) else {
+                            codes.push(TOPIC_FILTER_INVALID);
+                            continue;
+                        };
+                        let existed = session
+                            .subscriptions
+                            .iter()
+                            .position(|s| s.filter == filter && s.group == group)
+                            .map(|position| session.subscriptions.remove(position))
+                            .is_some();
+                        let retain_handling = (options >> 4) & 0x03;
+                        if group.is_none()
+                            && (retain_handling == 0 || (retain_handling == 1 && !existed))
+                        {
+                            retained.extend(
+                                state
+                                    .retained
+                                    .values()
+                                    .filter(|m| topic_matches(&filter, &m.topic))
+                                    .map(|m| (m.clone(), qos.min(m.qos))),
+                            );
+                        }
+                        session.subscriptions.push(Subscription {
+                            filter,
+                            group,
+                            qos,
+                            no_local: options & 0x04 != 0,
+                            retain_as_published: options & 0x08 != 0,
+                        });
+                        codes.push(qos);
+                    }
+                }
+                self.acknowledge(tx, codec::suback(codec::SUBACK, packet_id, &codes))
+                    .await;
+                let mut state = self.state.lock().unwrap();
+                if let Some(session) = state.sessions.get_mut(client_id) {
+                    for (message, qos) in retained {
+                        session.deliver(&message, qos, true, None);
+                    }
+                }
+            }
+            codec::UNSUBSCRIBE => {
+                if frame.flags != 0x02 {
+                    return Err(MALFORMED_PACKET);
+                }
+                let (packet_id, filters) =
+                    codec::unsubscribe(frame.body.clone()).map_err(malformed)?;
+                let codes: Vec<u8> = {
+                    let mut state = self.state.lock().unwrap();
+                    let Some(session) = state.sessions.get_mut(client_id) else {
+                        return Err(PROTOCOL_ERROR);
+                    };
+                    filters
+                        .iter()
+                        .map(|filter| {
+                            let (group, filter) = parse_filter(filter).unwrap_or_default();
+                            let before = session.subscriptions.len();
+                            session
+                                .subscriptions
+                                .retain(|s| s.filter != filter || s.group != group);
+                            if session.subscriptions.len() < before {
+                                SUCCESS
+                            } else {
+                                NO_SUBSCRIPTION_EXISTED
+                            }
+                        })
+                        .collect()
+                };
+                self.acknowledge(tx, codec::suback(codec::UNSUBACK, packet_id, &codes))
+                    .await;
+            }
+            codec::PINGREQ => self.acknowledge(tx, codec::pingresp()).await,
+            codec::DISCONNECT => return Ok(Some(codec::disconnect_reason(frame.body.clone()))),
+            kind => {
+                eprintln!("Broker: unexpected {} from {client_id}", codec::name(kind));
+                return Err(PROTOCOL_ERROR);
+            }
+        }
+        Ok(None)
+    }
+}
+
+/// Whether `topic` matches `filter`. Wildcards at the first level do not
+/// match topics starting with `$`.
+pub fn topic_matches(filter: &str, topic: &str) -> bool {
+    if topic.starts_with('$') && filter.starts_with(['+', '#']) {
+        return false;
+    }
+    let mut filter_levels = filter.split('/');
+    let mut topic_levels = topic.split('/');
+    loop {
+        match (filter_levels.next(), topic_levels.next()) {
+            (Some("#"), _) => return true,
+            (Some("+"), Some(_)) => {}
+            (Some(f), Some(t)) if f == t => {}
+            (None, None) => return true,
+            _ => return false,
+        }
+    }
+}
+
+/// Split `$share/<group>/<filter>` into its group and filter, `None` when
+/// the filter is invalid.
+fn parse_filter(filter: &str) -> Option<(Option<String>, String)> {
+    let (group, filter) = match filter.strip_prefix("$share/") {
+        Some(shared) => {
+            let (group, filter) = shared.split_once('/')?;
+            if group.is_empty() || group.contains(['+', '#']) {
+                return None;
+            }
+            (Some(group.to_string()), filter)
+        }
+        None => (None, filter),
+    };
+    let levels: Vec<&str> = filter.split('/').collect();
+    let valid = !filter.is_empty()
+        && levels.iter().enumerate().all(|(i, level)| {
+            (!level.contains('#') || (*level == "#" && i == levels.len() - 1))
+                && (!level.contains('+') || *level == "+")
+        });
+    valid.then(|| (group, filter.to_string()))
+}
+
+async fn read_frame(
+    reader: &mut OwnedReadHalf,
+    buf: &mut BytesMut,
+) -> Result<Option<Frame>, String> {
+    loop {
+        if let Some(frame) = codec::next_frame(buf)? {
+            return Ok(Some(frame));
+        }
+        match reader.read_buf(buf).await {
+            Ok(0) => return Ok(None),
+            Ok(_) => {}
+            Err(e) => return Err(e.to_string()),
+        }
+    }
+}
+
+async fn handle_connection(broker: Arc<Broker>, stream: TcpStream, peer: SocketAddr) {
+    let (mut reader, mut writer) = stream.into_split();
+    let mut buf = BytesMut::with_capacity(4096);
+    let frame = match tokio::time::timeout(CONNECT_TIMEOUT, read_frame(&mut reader, &mut buf)).await
+    {
+        Ok(Ok(Some(frame))) if frame.kind == codec::CONNECT => frame,
+        Ok(Ok(Some(frame))) => {
+            eprintln!(
+                "Broker: {peer} sent {} before CONNECT",
+                codec::name(frame.kind)
+            );
+            return;
+        }
+        Ok(Err(e)) => {
+            eprintln!("Broker: {peer}: {e}");
+            return;
+        }
+        Ok(Ok(None)) | Err(_) => return,
+    };
+    let connect = match codec::connect(frame.body) {
+        Ok(connect) => connect,
+        Err(reason) => {
+            eprintln!("Broker: refused CONNECT from {peer} with 0x{reason:02X}");
+            let _ = writer.write_all(&codec::connack(false, reason, None)).await;
+            return;
+        }
+    };
+
+    let (tx, mut rx) = tokio::sync::mpsc::unbounded_channel();
+    let mut writer_task = tokio::spawn(async move {
+        while let Some(outgoing) = rx.recv().await {
+            let written = match outgoing {
+                Outgoing::Frame(frame) => writer.write_all(&frame).await,
+                Outgoing::Disconnect(reason) => {
+                    let _ = writer.write_all(&codec::disconnect(reason)).await;
+                    break;
+                }
+                Outgoing::Close => break,
+            };
+            if written.is_err() {
+                break;
+            }
+        }
+        let _ = writer.shutdown().await;
+    });
+
+    let faults = broker.faults();
+    if !faults.ack_delay.is_zero() {
+        tokio::time::sleep(faults.ack_delay).await;
+    }
+    let assigned = connect.client_id.is_empty();
+    let client_id = if assigned {
+        format!("vaux-broker-{}", peer.port())
+    } else {
+        connect.client_id.clone()
+    };
+    let number = broker.attach(&client_id, &connect, assigned, &tx);
+    if let Some(after) = faults.disconnect_after {
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            tokio::time::sleep(after).await;
+            let _ = tx.send(Outgoing::Disconnect(ADMINISTRATIVE_ACTION));
+        });
+    }
+
+    // one and a half keep alive periods without a packet ends the connection
+    let keep_alive = if connect.keep_alive > 0 {
+        Duration::from_millis(connect.keep_alive as u64 * 1500)
+    } else {
+        Duration::MAX
+    };
+    let mut will = connect.will;
+    loop {
+        let frame = tokio::select! {
+            frame = tokio::time::timeout(keep_alive, read_frame(&mut reader, &mut buf)) => frame,
+            // closed by a fault, a takeover or a write error
+            _ = &mut writer_task => break,
+        };
+        let frame = match frame {
+            Ok(Ok(Some(frame))) => frame,
+            Ok(Ok(None)) => break,
+            Ok(Err(e)) => {
+                eprintln!("Broker: {client_id}: {e}");
+                let _ = tx.send(Outgoing::Disconnect(MALFORMED_PACKET));
+                break;
+            }
+            Err(_) => {
+                eprintln!("Broker: keep alive timeout for {client_id}");
+                let _ = tx.send(Outgoing::Disconnect(KEEP_ALIVE_TIMEOUT));
+                break;
+            }
+        };
+        if broker.drop_packet() {
+            eprintln!(
+                "Broker: dropped {} from {client_id}",
+                codec::name(frame.kind)
+            );
+            continue;
+        }
+        match broker.handle(&client_id, frame, &tx).await {
+            Ok(None) => {}
+            Ok(Some(reason)) => {
+                if reason != DISCONNECT_WITH_WILL {
+                    will = None;
+                }
+                break;
+            }
+            Err(reason) => {
+                let _ = tx.send(Outgoing::Disconnect(reason));
+                break;
+            }
+        }
+    }
+    let _ = tx.send(Outgoing::Close);
+    broker.detach(&client_id, number, will);
+}
+
+/// Accept connections until the listener fails.
+pub async fn serve(listener: TcpListener, broker: Arc<Broker>) {
+    loop {
+        match listener.accept().await {
+            Ok((stream, peer)) => {
+                tokio::spawn(handle_connection(broker.clone(), stream, peer));
+            }
+            Err(e) => {
+                eprintln!("Broker stopped accepting connections: {e}");
+                return;
+            }
+        }
+    }
+}
+
+/// A broker listening on localhost on its own thread, stopped when dropped.
+pub struct BrokerHandle {
+    broker: Arc<Broker>,
+    address: SocketAddr,
+    shutdown: Option<tokio::sync::oneshot::Sender<()>>,
+}
+
+impl BrokerHandle {
+    /// Listen on `port`, 0 for any free port.
+    pub fn start(port: u16, faults: Faults) -> Result<Self, String> {
+        let listener = std::net::TcpListener::bind(("127.0.0.1", port))
+            .map_err(|e| format!("Failed to listen on port {port}: {e}"))?;
+        listener
+            .set_nonblocking(true)
+            .map_err(|e| format!("Failed to listen on port {port}: {e}"))?;
+        let address = listener
+            .local_addr()
+            .map_err(|e| format!("Failed to listen on port {port}: {e}"))?;
+        let broker = Broker::new(faults);
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel();
+        let serving = broker.clone();
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|e| format!("Failed to start the broker runtime: {e}"))?;
+        std::thread::spawn(move || {
+            rt.block_on(async move {
+                match TcpListener::from_std(listener) {
+                    Ok(listener) => {
+                        eprintln!("Broker listening on {address}");
+                        tokio::select! {
+                            _ = serve(listener, serving) => {}
+                            _ = shutdown_rx => {}
+                        }
+                    }
+                    Err(e) => eprintln!("Broker failed to start: {e}"),
+                }
+            });
+            // dropping the runtime closes every client connection
+            eprintln!("Broker on {address} stopped");
+        });
+        Ok(Self {
+            broker,
+            address,
+            shutdown: Some(shutdown_tx),
+        })
+    }
+
+    pub fn broker(&self) -> &Arc<Broker> {
+        &self.broker
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+}
+
+impl Drop for BrokerHandle {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+    }
+}
@@ -0,0 +1,356 @@
+//! Headless mode: connect with a saved profile, subscribe, publish and print
+//! the packet stream without starting GTK.
+
+use std::{
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use vaux_mqtt::QoSLevel;
+
+use crate::client::{self, ClientSetting, Command, DisconnectOptions, Event};
+use crate::export::jsonl::Record;
+use crate::model::packet::Exchange;
+use crate::profile::Profile;
+use crate::reason;
+use crate::scenario::Scenario;
+use crate::scenario::runner::{self, StepResult};
+
+pub const USAGE: &str = "\
+Usage: vaux-gtk --headless --profile <file> [options]
+       vaux-gtk --headless --scenario <file> [--profile <file>] [--json]
+
+Options:
+  --profile <file>            profile saved from the Settings tab
+  --scenario <file>           run a TOML test scenario and report each step
+  --password <password>       password, overrides the profile
+  --qos <0|1|2>               QoS for the following subscribes and publishes
+  --subscribe <filter>        subscribe to a topic filter, repeatable
+  --publish <topic> <payload> publish a text payload, repeatable
+  --count <n>                 exit after receiving n PUBLISH packets
+  --wait <seconds>            exit after this long, default 2 without subscriptions
+  --json                      print JSON Lines instead of text";
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+enum Step {
+    Subscribe(QoSLevel, String),
+    Publish(QoSLevel, String, String),
+}
+
+struct Options {
+    profile: Option<PathBuf>,
+    scenario: Option<PathBuf>,
+    password: Option<String>,
+    steps: Vec<Step>,
+    count: Option<usize>,
+    wait: Option<Duration>,
+    json: bool,
+}
+
+fn parse(args: &[String]) -> Result<Options, String> {
+    let mut profile = None;
+    let mut scenario = None;
+    let mut password = None;
+    let mut qos = QoSLevel::AtMostOnce;
+    let mut steps = Vec::new();
+    let mut count = None;
+    let mut wait = None;
+    let mut json = false;
+    let mut args = args.iter();
+    let value = |args: &mut std::slice::Iter<String>, flag: &str| {
+        args.next()
+            .cloned()
+            .ok_or_else(|| format!("{flag} needs a value"))
+    };
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--profile" => profile = Some(PathBuf::from(value(&mut args, arg)?)),
+            "--scenario" => scenario = Some(PathBuf::from(value(&mut args, arg)?)),
+            "--password" => password = Some(value(&mut args, arg)?),
+            "--qos" => {
+                let level = value(&mut args, arg)?;
+                qos = level
+                    .parse::<u8>()
+                    .ok()
+                    .and_then(|level| QoSLevel::try_from(level).ok())
+                    .ok_or_else(|| format!("invalid QoS '{level}'"))?;
+            }
+            "--subscribe" => steps.push(Step::Subscribe(qos.clone(), value(&mut args, arg)?)),
+            "--publish" => {
+                let topic = value(&mut args, arg)?;
+                let payload = value(&mut args, arg)?;
+                steps.push(Step::Publish(qos.clone(), topic, payload));
+            }
+            "--count" => {
+                let n = value(&mut args, arg)?;
+                count = Some(n.parse().map_err(|_| format!("invalid count '{n}'"))?);
+            }
+            "--wait" => {
+                let seconds = value(&mut args, arg)?;
+                // negative, NaN and infinite waits have no Duration
+                wait = Some(
+                    seconds
+                        .parse()
+                        .ok()
+                        .and_then(|s| Duration::try_from_secs_f64(s).ok())
+                        .ok_or_else(|| format!("invalid wait '{seconds}'"))?,
+                );
+            }
+            "--json" => json = true,
+            "--help" | "-h" => return Err(USAGE.to_string()),
+            other => return Err(format!("unknown argument '{other}'\n\n{USAGE}")),
+        }
+    }
+    if profile.is_none() && scenario.is_none() {
+        return Err(format!("--profile is required\n\n{USAGE}"));
+    }
+    Ok(Options {
+        profile,
+        scenario,
+        password,
+        steps,
+        count,
+        wait,
+        json,
+    })
+}
+
+/// Print one packet, as text or as the JSON Lines export record.
+pub fn print_packet(exchange: &Exchange, packet: &vaux_mqtt::Packet, json: bool) {
+    let record = Record::from_packet(exchange, packet, chrono::Local::now().to_rfc3339());
+    if json {
+        match serde_json::to_string(&record) {
+            Ok(line) => println!("{line}"),
+            Err(e) => eprintln!("Failed to serialize packet: {e}"),
+        }
+        return;
+    }
+    let arrow = if let Exchange::Receive = exchange {
+        "<-"
+    } else {
+        "->"
+    };
+    let mut line = format!("{} {arrow} {}", record.timestamp, record.packet_type);
+    if record.packet_id != 0 {
+        line.push_str(&format!(" #{}", record.packet_id));
+    }
+    if !record.topic.is_empty() {
+        line.push_str(&format!(" {}", record.topic));
+    }
+    if let vaux_mqtt::Packet::Publish(publish) = packet {
+        if let Some(payload) = &publish.payload {
+            line.push_str(&format!(" {}", String::from_utf8_lossy(payload)));
+        }
+    } else if !record.detail.is_empty() {
+        line.push_str(&format!(" {}", record.detail));
+    }
+    println!("{line}");
+}
+
+fn load_setting(profile: &Path, password: &Option<String>) -> Result<ClientSetting, String> {
+    let setting = Profile::load(profile)?.to_setting()?;
+    if let Some(password) = password {
+        setting.password.replace(password.clone());
+    }
+    Ok(setting)
+}
+
+/// Run the headless client with the arguments after `--headless`.
+pub fn run(args: &[String]) -> Result<(), String> {
+    let options = parse(args)?;
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .map_err(|e| e.to_string())?;
+    match &options.scenario {
+        Some(path) => rt.block_on(run_scenario(path, &options)),
+        None => rt.block_on(run_commands(&options)),
+    }
+}
+
+async fn run_scenario(path: &Path, options: &Options) -> Result<(), String> {
+    let scenario = Scenario::load(path)?;
+    let profile = options
+        .profile
+        .as_ref()
+        .or(scenario.profile.as_ref())
+        .ok_or_else(|| format!("{} has no profile, pass --profile", path.display()))?;
+    let setting = load_setting(profile, &options.password)?;
+
+    let json = options.json;
+    let mut on_result = |result: &StepResult| {
+        if json {
+            // step results share the stream with the packet records
+            let line = serde_json::json!({
+                "index": result.index,
+                "description": result.description,
+                "passed": result.passed,
+                "detail": result.detail,
+                "elapsed_ms": result.elapsed.as_millis() as u64,
+            });
+            println!("{line}");
+            return;
+        }
+        println!(
+            "{} {}: {} ({} ms) {}",
+            if result.passed { "PASS" } else { "FAIL" },
+            result.index,
+            result.description,
+            result.elapsed.as_millis(),
+            result.detail
+        );
+    };
+    let mut on_packet = |exchange: &Exchange, packet: &vaux_mqtt::Packet| {
+        if json {
+            print_packet(exchange, packet, true);
+        }
+    };
+    let results = runner::execute(&scenario, &setting, &mut on_result, &mut on_packet).await;
+    let passed = results.iter().filter(|r| r.passed).count();
+    let summary = format!(
+        "{}: {passed} of {} steps passed",
+        scenario.name,
+        scenario.steps.len()
+    );
+    if passed == scenario.steps.len() {
+        eprintln!("{summary}");
+        Ok(())
+    } else {
+        Err(summary)
+    }
+}
+
+async fn run_commands(options: &Options) -> Result<(), String> {
+    let profile = options.profile.as_ref().expect("checked by parse");
+    let start = load_setting(profile, &options.password)?.start_client()?;
+
+    let (cmd_tx, cmd_rx) = tokio::sync::mpsc::channel(32);
+    let (packet_tx, mut packet_rx) = tokio::sync::mpsc::channel(32);
+    let (event_tx, mut event_rx) = tokio::sync::mpsc::channel::<Event>(8);
+
+    let driver = async move {
+        let send = |command: Command| {
+            let cmd_tx = cmd_tx.clone();
+            async move { cmd_tx.send(command.into()).await.map_err(|e| e.to_string()) }
+        };
+        let result = match send(start).await {
+            Ok(()) => {
+                // wait for the CONNACK before sending anything else
+                let connack = tokio::time::timeout(CONNECT_TIMEOUT, async {
+                    while let Some((exchange, packet)) = packet_rx.recv().await {
+                        print_packet(&exchange, &packet, options.json);
+                        if let vaux_mqtt::Packet::ConnAck(ack) = packet {
+                            return Some(ack);
+                        }
+                    }
+                    None
+                })
+                .await;
+                match connack {
+                    Ok(Some(ack)) if reason::code(&ack.reason) < 0x80 => {
+                        run_steps(options, &send, &mut packet_rx, &mut event_rx).await
+                    }
+                    Ok(Some(ack)) => {
+                        let code = reason::code(&ack.reason);
+                        Err(format!(
+                            "connection refused: 0x{code:02X} {}",
+                            reason::name(code)
+                        ))
+                    }
+                    Ok(None) => Err("client runner stopped before CONNACK".to_string()),
+                    Err(_) => Err(format!("no CONNACK within {}s", CONNECT_TIMEOUT.as_secs())),
+                }
+            }
+            Err(e) => Err(e),
+        };
+
+        let _ = send(Command::Disconnect(DisconnectOptions::default())).await;
+        let _ = send(Command::StopRunner).await;
+        // keep receiving until the runner exits so its last sends succeed
+        while let Some((exchange, packet)) = packet_rx.recv().await {
+            print_packet(&exchange, &packet, options.json);
+        }
+        result
+    };
+
+    let (_, result) = tokio::join!(client::run(packet_tx, event_tx, cmd_rx), driver);
+    result
+}
+
+async fn run_steps<F, Fut>(
+    options: &Options,
+    send: &F,
+    packet_rx: &mut tokio::sync::mpsc::Receiver<(Exchange, vaux_mqtt::Packet)>,
+    event_rx: &mut tokio::sync::mpsc::Receiver<Event>,
+) -> Result<(), String>
+where
+    F: Fn(Command) -> Fut,
+    Fut: std::future::Future<Output = Result<(), String>>,
+{
+    let mut packet_id: u16 = 0;
+    let mut subscribed = false;
+    for step in &options.steps {
+        packet_id = packet_id.wrapping_add(1).max(1);
+        match step {
+            Step::Subscribe(qos, filter) => {
+                subscribed = true;
+                send(Command::Subscribe(packet_id, qos.clone(), filter.clone())).await?;
+            }
+            Step::Publish(qos, topic, payload) => {
+                let publish = vaux_mqtt::publish::Publish::new_with_message(
+                    packet_id,
+                    topic,
+                    qos.clone(),
+                    payload.as_str(),
+                )
+                .map_err(|e| format!("invalid publish to '{topic}': {e}"))?;
+                send(Command::Publish(publish)).await?;
+            }
+        }
+    }
+
+    let wait = match (options.wait, options.count, subscribed) {
+        (Some(wait), _, _) => Some(wait),
+        // with a count, or subscriptions and no limit, run until stopped
+        (None, Some(_), _) | (None, None, true) => None,
+        (None, None, false) => Some(Duration::from_secs(2)),
+    };
+    let deadline = wait.map(|wait| tokio::time::Instant::now() + wait);
+    let mut received = 0;
+    loop {
+        let sleep = async {
+            match deadline {
+                Some(deadline) => tokio::time::sleep_until(deadline).await,
+                None => std::future::pending().await,
+            }
+        };
+        tokio::select! {
+            packet = packet_rx.recv() => {
+                let Some((exchange, packet)) = packet else {
+                    return Err("client runner stopped".to_string());
+                };
+                print_packet(&exchange, &packet, options.json);
+                match (&exchange, &packet) {
+                    (Exchange::Receive, vaux_mqtt::Packet::Publish(_)) => received += 1,
+                    (Exchange::Receive, vaux_mqtt::Packet::Disconnect(disconnect)) => {
+                        let code = reason::code(&disconnect.reason);
+                        return Err(format!("disconnected by server: 0x{code:02X} {}", reason::disconnect_name(code)));
+                    }
+                    _ => {}
+                }
+                if options.count.is_some_and(|count| received >= count) {
+                    return Ok(());
+                }
+            }
+            // the authentication exchange goes to stderr, session snapshots
+            // are only of interest to the GUI
+            Some(event) = event_rx.recv() => {
+                if let Event::Auth(event) = event {
+                    eprintln!("{}", event.text());
+                }
+            }
+            _ = sleep => return Ok(()),
+        }
+    }
+}
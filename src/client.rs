@@ -1,12 +1,29 @@
-use std::{cell::RefCell, rc::Rc, time::Duration};
+use std::{cell::RefCell, collections::HashMap, path::PathBuf, rc::Rc, time::Duration};
 use tokio::{select, task::JoinHandle};
 use vaux_client::{ClientBuilder, MqttConnection, client::ClientError, session::SessionState};
-use vaux_mqtt::{QoSLevel, publish::Publish, unsubscribe};
+use bytes::BytesMut;
+use vaux_mqtt::{
+    Disconnect, QoSLevel, UserPropertyMap, WillMessage, publish::Publish, unsubscribe,
+};
 
+use crate::auth::{AuthEvent, AuthMethod, AuthRelay};
+use crate::codec;
 use crate::model::packet::Exchange;
+use crate::payload::PayloadEncoding;
+use crate::properties::put_variable_byte_int;
+use crate::reason;
+use crate::recording::Recorder;
+use crate::script::{ScriptActions, ScriptHost};
+use crate::session::{SessionSnapshot, SessionTracker};
+use crate::wire::{self, AckReasons};
 
 pub const DEFAULT_WILL_DELAY_SECONDS: u32 = 60; // 1 minute
 pub const DEFAULT_WILL_EXPIRY_SECONDS: u32 = 300; // 5 minutes
+pub const DEFAULT_KEEP_ALIVE_SECONDS: u16 = 60;
+/// Script publishes use packet IDs from here up, away from the IDs typed in the UI.
+const SCRIPT_PACKET_ID_START: u16 = 60000;
+/// How long the session task has to end once DISCONNECT is sent.
+const DISCONNECT_TIMEOUT: Duration = Duration::from_secs(5);
 
 #[derive(Debug, Clone)]
 pub struct ClientSetting {
@@ -17,7 +34,14 @@ pub struct ClientSetting {
     pub client_cert: Rc<RefCell<String>>,
     pub host: Rc<RefCell<String>>,
     pub port: Rc<RefCell<u16>>,
+    /// Local fault-injection proxy port to connect through instead of host
+    /// and port, while the proxy runs.
+    pub proxy_port: Rc<RefCell<Option<u16>>>,
     pub session_expiry: Rc<RefCell<u32>>,
+    pub keep_alive: Rc<RefCell<u16>>,
+    /// Set while pings are suppressed. vaux pings on its own whenever keep
+    /// alive is set, so the next connect asks for keep alive 0.
+    pub pings_suppressed: Rc<RefCell<bool>>,
     pub auto_ack: Rc<RefCell<bool>>,
     pub auto_packet_id: Rc<RefCell<bool>>,
     pub with_ping_resp: Rc<RefCell<bool>>,
@@ -25,14 +49,22 @@ pub struct ClientSetting {
     pub with_credentials: Rc<RefCell<bool>>,
     pub username: Rc<RefCell<String>>,
     pub password: Rc<RefCell<String>>,
+    pub auth_method: Rc<RefCell<AuthMethod>>,
 
     pub with_will: Rc<RefCell<bool>>,
     pub will_topic: Rc<RefCell<String>>,
     pub will_payload: Rc<RefCell<String>>,
+    pub will_payload_encoding: Rc<RefCell<PayloadEncoding>>,
     pub will_qos: Rc<RefCell<vaux_mqtt::QoSLevel>>,
     pub will_retain: Rc<RefCell<bool>>,
     pub will_delay: Rc<RefCell<u32>>,
     pub will_expiry: Rc<RefCell<u32>>,
+    pub will_payload_utf8: Rc<RefCell<bool>>,
+    pub will_content_type: Rc<RefCell<String>>,
+    pub will_response_topic: Rc<RefCell<String>>,
+    /// Correlation data as hex.
+    pub will_correlation_data: Rc<RefCell<String>>,
+    pub will_user_properties: Rc<RefCell<Vec<(String, String)>>>,
 }
 
 impl ClientSetting {
@@ -47,7 +79,10 @@ impl ClientSetting {
 
             host: Rc::new(RefCell::new("localhost".to_string())),
             port: Rc::new(RefCell::new(1883)),
+            proxy_port: Rc::new(RefCell::new(None)),
             session_expiry: Rc::new(RefCell::new(DEFAULT_WILL_EXPIRY_SECONDS)),
+            keep_alive: Rc::new(RefCell::new(DEFAULT_KEEP_ALIVE_SECONDS)),
+            pings_suppressed: Rc::new(RefCell::new(false)),
             auto_ack: Rc::new(RefCell::new(true)),
             auto_packet_id: Rc::new(RefCell::new(true)),
             with_ping_resp: Rc::new(RefCell::new(true)),
@@ -55,33 +90,550 @@ impl ClientSetting {
             with_credentials: Rc::new(RefCell::new(false)),
             username: Rc::new(RefCell::new(String::new())),
             password: Rc::new(RefCell::new(String::new())),
+            auth_method: Rc::new(RefCell::new(AuthMethod::default())),
 
             with_will: Rc::new(RefCell::new(false)),
             will_topic: Rc::new(RefCell::new(String::new())),
             will_payload: Rc::new(RefCell::new(String::new())),
+            will_payload_encoding: Rc::new(RefCell::new(PayloadEncoding::default())),
             will_qos: Rc::new(RefCell::new(vaux_mqtt::QoSLevel::AtMostOnce)), // Default Qo
             will_retain: Rc::new(RefCell::new(false)),
             will_delay: Rc::new(RefCell::new(DEFAULT_WILL_DELAY_SECONDS)), // Default delay
             will_expiry: Rc::new(RefCell::new(DEFAULT_WILL_EXPIRY_SECONDS)),
+            will_payload_utf8: Rc::new(RefCell::new(false)),
+            will_content_type: Rc::new(RefCell::new(String::new())),
+            will_response_topic: Rc::new(RefCell::new(String::new())),
+            will_correlation_data: Rc::new(RefCell::new(String::new())),
+            will_user_properties: Rc::new(RefCell::new(Vec::new())),
         }
     }
+
+    /// Broker connection from the host, TLS and credential settings, through
+    /// the proxy when one is running. Enhanced authentication connects to a
+    /// new relay instead, which carries the exchange with the broker.
+    fn connection(&self) -> Result<(MqttConnection, Option<AuthRelay>), String> {
+        let with_tls = *self.with_tls.borrow();
+        let (host, port) = match *self.proxy_port.borrow() {
+            // the broker certificate is for its own host name, not the proxy's address
+            Some(_) if with_tls => {
+                return Err("The proxy forwards plain TCP only, turn off TLS".to_string());
+            }
+            Some(port) => ("127.0.0.1".to_string(), port),
+            None => (self.host.borrow().to_string(), *self.port.borrow()),
+        };
+        let with_credentials = *self.with_credentials.borrow();
+        let username = self.username.borrow();
+        let password = self.password.borrow();
+        let authenticator = with_credentials
+            .then(|| {
+                self.auth_method
+                    .borrow()
+                    .authenticator(username.as_str(), password.as_str())
+            })
+            .flatten();
+        let relay = match authenticator {
+            // the relay has to read the CONNECT, so it cannot be inside TLS
+            Some(_) if with_tls => {
+                return Err(
+                    "Enhanced authentication is carried over plain TCP only, turn off TLS"
+                        .to_string(),
+                );
+            }
+            Some(authenticator) => Some(AuthRelay::bind(format!("{host}:{port}"), authenticator)?),
+            None => None,
+        };
+        let mut connection = match &relay {
+            Some(relay) => MqttConnection::new()
+                .with_host("127.0.0.1")
+                .with_port(relay.address().port()),
+            None => MqttConnection::new().with_host(host.as_str()).with_port(port),
+        };
+        if with_tls {
+            connection = connection.with_tls();
+        }
+        // enhanced methods carry the user name in their authentication data
+        if with_credentials && relay.is_none() {
+            connection = connection.with_credentials(username.as_str(), password.as_str());
+        }
+        Ok((connection, relay))
+    }
+
+    /// Command to connect a new session with these settings.
+    pub fn start_client(&self) -> Result<Command, String> {
+        let will_message = self
+            .will_message()
+            .map_err(|e| format!("Invalid will message: {e}"))?;
+        let (connection, relay) = self.connection()?;
+        let mut builder = ClientBuilder::new(connection)
+            .with_client_id(self.client_id.borrow().as_str())
+            .with_session_expiry(Duration::from_secs(*self.session_expiry.borrow() as u64))
+            .with_keep_alive(self.requested_keep_alive())
+            .with_auto_ack(*self.auto_ack.borrow())
+            .with_auto_packet_id(*self.auto_packet_id.borrow())
+            .with_pingresp(*self.with_ping_resp.borrow());
+        if let Some(will_message) = will_message {
+            builder = builder.with_will_message(will_message);
+        }
+        let session =
+            SessionSnapshot::new(self.client_id.borrow().as_str(), *self.session_expiry.borrow());
+        Ok(Command::StartClient(builder, relay, session))
+    }
+
+    /// Command to resume the runner's session state over a connection with
+    /// these settings.
+    pub fn resume_session(&self) -> Result<Command, String> {
+        let (connection, relay) = self.connection()?;
+        Ok(Command::ResumeSession(connection, relay, self.requested_keep_alive()))
+    }
+
+    /// Keep alive to ask for in CONNECT, 0 while pings are suppressed.
+    pub fn requested_keep_alive(&self) -> Duration {
+        if *self.pings_suppressed.borrow() {
+            Duration::ZERO
+        } else {
+            Duration::from_secs(*self.keep_alive.borrow() as u64)
+        }
+    }
+
+    /// Will message built from the will settings, `None` when disabled.
+    pub fn will_message(&self) -> Result<Option<WillMessage>, String> {
+        if !*self.with_will.borrow() {
+            return Ok(None);
+        }
+        let payload = self
+            .will_payload_encoding
+            .borrow()
+            .decode(self.will_payload.borrow().as_str())?;
+        let mut will_message = WillMessage::new(
+            self.will_topic.borrow().to_string(),
+            &payload,
+            self.will_qos.borrow().clone(),
+            *self.will_retain.borrow(),
+        )
+        .with_delay(*self.will_delay.borrow())
+        .with_message_expiry(*self.will_expiry.borrow());
+        will_message.payload_utf8 = *self.will_payload_utf8.borrow();
+        will_message.content_type = non_empty(&self.will_content_type.borrow());
+        will_message.response_topic = non_empty(&self.will_response_topic.borrow());
+        will_message.correlation_data = self.will_correlation_bytes()?;
+        will_message.user_props = user_property_map(&self.will_user_properties.borrow());
+        Ok(Some(will_message))
+    }
+
+    fn will_correlation_bytes(&self) -> Result<Option<Vec<u8>>, String> {
+        let correlation_data = self.will_correlation_data.borrow();
+        if correlation_data.trim().is_empty() {
+            return Ok(None);
+        }
+        crate::payload::decode_hex(&correlation_data)
+            .map(Some)
+            .map_err(|e| format!("correlation data: {e}"))
+    }
+
+    /// Will properties block as vaux encodes it in the CONNECT payload,
+    /// `None` when the will is disabled.
+    pub fn will_properties(&self) -> Result<Option<BytesMut>, String> {
+        let Some(will_message) = self.will_message()? else {
+            return Ok(None);
+        };
+        let mut connect = vaux_mqtt::Connect::default();
+        connect.will_message = Some(will_message);
+        let raw = wire::encode(&vaux_mqtt::Packet::Connect(connect))?;
+        let Some(will_properties) = codec::will_properties(&raw)? else {
+            return Err("the encoded CONNECT has no will".to_string());
+        };
+        let mut block = BytesMut::with_capacity(will_properties.len() + 4);
+        put_variable_byte_int(&mut block, will_properties.len() as u32);
+        block.extend_from_slice(&will_properties);
+        Ok(Some(block))
+    }
+}
+
+fn non_empty(value: &str) -> Option<String> {
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+/// Options for a client initiated disconnect.
+#[derive(Debug, Clone, Default)]
+pub struct DisconnectOptions {
+    pub reason_code: u8,
+    /// Overrides the session expiry interval sent in CONNECT.
+    pub session_expiry: Option<u32>,
+    pub reason_string: Option<String>,
+    pub user_properties: Vec<(String, String)>,
+    /// Close the socket without sending DISCONNECT, as if the network dropped.
+    pub abrupt: bool,
+}
+
+impl DisconnectOptions {
+    fn to_packet(&self) -> Result<Disconnect, String> {
+        let reason = reason::from_code(self.reason_code).ok_or_else(|| {
+            format!("0x{:02X} is not a DISCONNECT reason code", self.reason_code)
+        })?;
+        let mut disconnect = Disconnect::new(reason);
+        disconnect.session_expiry = self.session_expiry;
+        disconnect.reason_desc = self.reason_string.clone();
+        disconnect.user_props = user_property_map(&self.user_properties);
+        Ok(disconnect)
+    }
+}
+
+pub(crate) fn user_property_map(properties: &[(String, String)]) -> Option<UserPropertyMap> {
+    if properties.is_empty() {
+        return None;
+    }
+    let mut map = UserPropertyMap::default();
+    for (key, value) in properties {
+        map.add_property(key, value);
+    }
+    Some(map)
+}
+
+/// When the runner sends PINGREQ on its own.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PingSchedule {
+    /// Only when the Ping button is pressed.
+    Manual,
+    Every(Duration),
+    /// Never, including the Ping button, to let the broker enforce keep alive.
+    Suppressed,
 }
 
 pub enum Command {
-    StartClient(ClientBuilder),
-    ResumeSession(MqttConnection),
+    /// Connect a new session, through the relay when it carries enhanced
+    /// authentication. The snapshot is the empty session the runner follows
+    /// in case the session task cannot return it.
+    StartClient(ClientBuilder, Option<AuthRelay>, SessionSnapshot),
+    /// Resume the runner's session, asking for this keep alive.
+    ResumeSession(MqttConnection, Option<AuthRelay>, Duration),
     Ping,
     Publish(Publish),                 // publish packet
     Subscribe(u16, QoSLevel, String), // topic
     Unsubscribe(u16, String),         // topic
+    /// Subscribe at QoS 0 with Retain Handling 0, so the broker sends the
+    /// retained message of every matching topic.
+    BrowseRetained(u16, String),
     StopClient,
-    #[allow(dead_code)]
+    Disconnect(DisconnectOptions),
+    SaveSession(PathBuf),
+    LoadSession(PathBuf),
+    StartRecording(PathBuf),
+    StopRecording,
+    SetPingSchedule(PingSchedule),
+    /// Rhai source for the packet hooks, replacing any loaded script.
+    LoadScript(String),
+    UnloadScript,
     StopRunner,
 }
 
+/// How the runner finished a command.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Reply {
+    /// Carried out, or sent when the broker does not answer it.
+    Done,
+    /// The broker's acknowledgement, with one reason code per topic filter
+    /// for SUBACK and UNSUBACK.
+    Acknowledged(AckReasons),
+}
+
+pub type CommandResult = Result<Reply, String>;
+
+/// A command for the runner and, when the sender wants it, where to send the
+/// result. Subscribe, Unsubscribe and QoS 1 or 2 Publish are answered once
+/// the broker acknowledges them.
+pub struct Request {
+    pub command: Command,
+    pub reply: Option<tokio::sync::oneshot::Sender<CommandResult>>,
+}
+
+impl From<Command> for Request {
+    fn from(command: Command) -> Self {
+        Self {
+            command,
+            reply: None,
+        }
+    }
+}
+
+/// Broker acknowledgement a command reply is waiting for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum AckKind {
+    Publish,
+    Subscribe,
+    Unsubscribe,
+}
+
+/// The command reply a received packet answers, with its reason codes.
+fn acknowledgement(packet: &vaux_mqtt::Packet) -> Option<((AckKind, u16), AckReasons)> {
+    let publish_ack = |code| AckReasons {
+        codes: vec![code],
+        ..Default::default()
+    };
+    match packet {
+        vaux_mqtt::Packet::PubAck(ack) => Some((
+            (AckKind::Publish, ack.packet_id),
+            publish_ack(reason::code(&ack.reason)),
+        )),
+        // the broker's verdict on a QoS 2 publish comes with PUBREC
+        vaux_mqtt::Packet::PubRec(rec) => Some((
+            (AckKind::Publish, rec.packet_id),
+            publish_ack(reason::code(&rec.reason)),
+        )),
+        vaux_mqtt::Packet::SubAck(ack) => Some((
+            (AckKind::Subscribe, ack.packet_id()),
+            wire::ack_reasons(packet)?,
+        )),
+        vaux_mqtt::Packet::UnsubAck(ack) => Some((
+            (AckKind::Unsubscribe, ack.packet_id),
+            wire::ack_reasons(packet)?,
+        )),
+        _ => None,
+    }
+}
+
+/// Log a command failure and return it for the command's reply.
+fn failed(message: String) -> CommandResult {
+    eprintln!("{message}");
+    Err(message)
+}
+
+/// Fail every reply still waiting for the broker, once the connection is gone.
+fn abandon_replies(
+    awaiting: &mut HashMap<(AckKind, u16), tokio::sync::oneshot::Sender<CommandResult>>,
+) {
+    for (_, reply) in awaiting.drain() {
+        let _ = reply.send(Err(
+            "connection closed before the broker answered".to_string()
+        ));
+    }
+}
+
+/// Runner state changes reported to the UI alongside the packet stream.
+pub enum Event {
+    /// Session state available to resume, `None` once consumed or discarded.
+    Session(Option<SessionSnapshot>),
+    /// Recording file in use, `None` once stopped or after a write error.
+    Recording(Option<PathBuf>),
+    Script(ScriptEvent),
+    /// A step of the enhanced authentication exchange.
+    Auth(AuthEvent),
+    /// Script output for the packet with this sequence number, counting every
+    /// packet sent on the packet channel from 1.
+    Annotate {
+        sequence: u64,
+        tags: Vec<String>,
+        display: Option<String>,
+    },
+}
+
+pub enum ScriptEvent {
+    Loaded,
+    Unloaded,
+    Error(String),
+    Alert(String),
+}
+
+/// Stop the client and wait for its session task, returning the session state
+/// to use when resuming.
+async fn stop_client(
+    mut c: vaux_client::MqttClient,
+    handle: Option<JoinHandle<Result<SessionState, ClientError>>>,
+) -> Option<SessionState> {
+    if let Err(e) = c.stop().await {
+        eprintln!("Failed to stop MQTT Client: {e}");
+    } else {
+        eprintln!("MQTT Client stopped successfully");
+    }
+    join_session(handle).await
+}
+
+/// Wait for the session task to end and return its session state. A task
+/// still running after `DISCONNECT_TIMEOUT` is aborted.
+async fn join_session(
+    handle: Option<JoinHandle<Result<SessionState, ClientError>>>,
+) -> Option<SessionState> {
+    let mut h = handle?;
+    match tokio::time::timeout(DISCONNECT_TIMEOUT, &mut h).await {
+        Ok(Ok(Ok(s))) => {
+            eprintln!("Client session ended successfully");
+            Some(s)
+        }
+        Ok(Ok(Err(e))) => {
+            eprintln!("Error in client session: {e}");
+            None
+        }
+        Ok(Err(e)) => {
+            eprintln!("Error while ending client session: {e}");
+            None
+        }
+        Err(_) => {
+            eprintln!("Client session did not end, aborting it");
+            h.abort();
+            None
+        }
+    }
+}
+
+/// Session to resume once the client has stopped: the state its session task
+/// returned, else the one the runner followed.
+fn resumable(
+    state: Option<SessionState>,
+    tracker: Option<SessionTracker>,
+) -> Option<(SessionSnapshot, SessionState)> {
+    if let Some(state) = state {
+        match SessionSnapshot::from_state(&state) {
+            Ok(snapshot) => return Some((snapshot, state)),
+            Err(e) => eprintln!("Failed to read the session state: {e}"),
+        }
+    }
+    match tracker.map(SessionTracker::finish) {
+        Some(Ok(resumable)) => Some(resumable),
+        Some(Err(e)) => {
+            eprintln!("Failed to keep the session: {e}");
+            None
+        }
+        None => None,
+    }
+}
+
+/// Run the relay for the connection about to be made, reporting the
+/// exchange as events.
+fn start_relay(relay: Option<AuthRelay>, event_tx: &tokio::sync::mpsc::Sender<Event>) {
+    if let Some(relay) = relay {
+        let event_tx = event_tx.clone();
+        tokio::spawn(relay.run(Box::new(move |event| {
+            let _ = event_tx.try_send(Event::Auth(event));
+        })));
+    }
+}
+
+/// Packet stream to the UI. Packets are recorded when a recording is active
+/// and numbered in the order sent, so events can refer to them.
+struct PacketSink {
+    mqtt_tx: tokio::sync::mpsc::Sender<(Exchange, vaux_mqtt::Packet)>,
+    event_tx: tokio::sync::mpsc::Sender<Event>,
+    recorder: Option<Recorder>,
+    /// Follows the session of the running client.
+    tracker: Option<SessionTracker>,
+    sequence: u64,
+}
+
+impl PacketSink {
+    /// Pass the packet on, returning its sequence number. A failed recording
+    /// write ends the recording rather than the runner.
+    async fn forward(
+        &mut self,
+        exchange: Exchange,
+        packet: vaux_mqtt::Packet,
+    ) -> Result<u64, tokio::sync::mpsc::error::SendError<(Exchange, vaux_mqtt::Packet)>> {
+        if let Some(Err(e)) = self.recorder.as_mut().map(|r| r.record(&exchange, &packet)) {
+            eprintln!("Recording stopped, failed to write packet: {e}");
+            self.recorder.take();
+            let _ = self.event_tx.send(Event::Recording(None)).await;
+        }
+        if let Some(tracker) = self.tracker.as_mut() {
+            tracker.exchanged(&exchange, &packet);
+        }
+        self.mqtt_tx.send((exchange, packet)).await?;
+        self.sequence += 1;
+        Ok(self.sequence)
+    }
+}
+
+async fn send_ping(c: &mut vaux_client::MqttClient, sink: &mut PacketSink) {
+    if let Err(e) = c.ping().await {
+        eprintln!("Failed to send ping: {e}");
+    } else {
+        sink.forward(
+            Exchange::Send,
+            vaux_mqtt::Packet::PingRequest(vaux_mqtt::PingReq::default()),
+        )
+        .await
+        .expect("Failed to send ping packet");
+    }
+}
+
+/// Send what a script hook asked for after handling packet `sequence`.
+async fn apply_script_actions(
+    actions: ScriptActions,
+    sequence: u64,
+    client: Option<&mut vaux_client::MqttClient>,
+    sink: &mut PacketSink,
+    next_packet_id: &mut u16,
+) {
+    if !actions.tags.is_empty() || actions.display.is_some() {
+        let _ = sink
+            .event_tx
+            .send(Event::Annotate {
+                sequence,
+                tags: actions.tags,
+                display: actions.display,
+            })
+            .await;
+    }
+    for alert in actions.alerts {
+        eprintln!("Script alert: {alert}");
+        let _ = sink
+            .event_tx
+            .send(Event::Script(ScriptEvent::Alert(alert)))
+            .await;
+    }
+    if actions.publish.is_empty() {
+        return;
+    }
+    let Some(c) = client else {
+        eprintln!("Client not initialized, cannot publish script replies");
+        return;
+    };
+    for (topic, payload, qos) in actions.publish {
+        let qos = QoSLevel::try_from(qos).unwrap_or(QoSLevel::AtMostOnce);
+        let packet_id = if matches!(qos, QoSLevel::AtMostOnce) {
+            0
+        } else {
+            match script_packet_id(next_packet_id, sink.tracker.as_ref()) {
+                Some(packet_id) => packet_id,
+                None => {
+                    eprintln!("No free packet ID for the script publish to '{topic}'");
+                    continue;
+                }
+            }
+        };
+        let packet = match Publish::new_with_message(packet_id, &topic, qos, payload.as_str()) {
+            Ok(publish) => vaux_mqtt::Packet::Publish(publish),
+            Err(e) => {
+                eprintln!("Invalid script publish to '{topic}': {e}");
+                continue;
+            }
+        };
+        match c.packet_producer().send(packet.clone()).await {
+            Ok(_) => {
+                if let Err(e) = sink.forward(Exchange::Send, packet).await {
+                    eprintln!("Failed to report script publish: {e}");
+                    return;
+                }
+            }
+            Err(e) => eprintln!("Failed to send script publish: {e}"),
+        }
+    }
+}
+
+/// Take the next script packet ID, skipping those the session still has in
+/// flight. `None` when every ID in the script range is in use.
+fn script_packet_id(next: &mut u16, tracker: Option<&SessionTracker>) -> Option<u16> {
+    for _ in SCRIPT_PACKET_ID_START..=u16::MAX {
+        let packet_id = *next;
+        *next = packet_id.checked_add(1).unwrap_or(SCRIPT_PACKET_ID_START);
+        if !tracker.is_some_and(|t| t.in_flight(packet_id)) {
+            return Some(packet_id);
+        }
+    }
+    None
+}
+
 pub async fn run(
     mqtt_tx: tokio::sync::mpsc::Sender<(Exchange, vaux_mqtt::Packet)>,
-    mut cmd_channel: tokio::sync::mpsc::Receiver<Command>,
+    event_tx: tokio::sync::mpsc::Sender<Event>,
+    mut cmd_channel: tokio::sync::mpsc::Receiver<Request>,
 ) {
     let mut running = true;
     let mut client: Option<vaux_client::MqttClient> = None; // Placeholder for the client instance
@@ -90,51 +642,133 @@ pub async fn run(
 
     let mut session: Option<vaux_client::session::SessionState> = None;
     let mut handle: Option<JoinHandle<Result<SessionState, ClientError>>> = None;
+    let mut sink = PacketSink {
+        mqtt_tx,
+        event_tx: event_tx.clone(),
+        recorder: None,
+        tracker: None,
+        sequence: 0,
+    };
+    let mut script: Option<ScriptHost> = None;
+    let mut script_packet_id = SCRIPT_PACKET_ID_START;
+    let mut ping_schedule = PingSchedule::Manual;
+    let mut ping_interval =
+        tokio::time::interval(Duration::from_secs(DEFAULT_KEEP_ALIVE_SECONDS as u64));
+    let mut awaiting: HashMap<(AckKind, u16), tokio::sync::oneshot::Sender<CommandResult>> =
+        HashMap::new();
 
     while running {
         select! {
-            packet = packet_consumer.recv() => {
-                if let Some(p) = packet {
-                        // Process the received packet
-                        match &p {
-                            vaux_mqtt::Packet::UnsubAck(ack) => {
-                                println!("UnsubAck received for packet ID: {}", ack.packet_id);
-                                for (i, code) in ack.reason_code.iter().enumerate() {
-                                    println!("  Reason code {}: {:?}", i + 1, code);
-                                }
-                            }
-                            _ => {}
+            _ = ping_interval.tick(), if matches!(ping_schedule, PingSchedule::Every(_)) && client.is_some() => {
+                if let Some(ref mut c) = client {
+                    send_ping(c, &mut sink).await;
+                }
+            }
+            packet = packet_consumer.recv(), if client.is_some() => {
+                let Some(p) = packet else {
+                    // the session task ended on its own, the broker closed the connection
+                    eprintln!("MQTT Client connection closed");
+                    let (_dummy_tx, dummy_rx) = tokio::sync::mpsc::channel(1);
+                    packet_consumer = dummy_rx;
+                    if let Some(c) = client.take() {
+                        let state = join_session(handle.take()).await;
+                        drop(c);
+                        if let Some((snapshot, s)) = resumable(state, sink.tracker.take()) {
+                            let _ = event_tx.send(Event::Session(Some(snapshot))).await;
+                            session = Some(s);
                         }
-                        mqtt_tx.send((Exchange::Receive, p)).await.expect("Failed to send packet");
+                    }
+                    abandon_replies(&mut awaiting);
+                    continue;
+                };
+                if let Some((key, codes)) = acknowledgement(&p) {
+                    if let Some(reply) = awaiting.remove(&key) {
+                        let _ = reply.send(Ok(Reply::Acknowledged(codes)));
+                    }
+                }
+                let actions = script.as_mut().map(|s| s.on_packet(&p));
+                let sequence = sink.forward(Exchange::Receive, p).await.expect("Failed to send packet");
+                match actions {
+                    Some(Ok(actions)) => {
+                        apply_script_actions(actions, sequence, client.as_mut(), &mut sink, &mut script_packet_id).await;
+                    }
+                    Some(Err(e)) => {
+                        eprintln!("Script error: {e}");
+                        let _ = event_tx.send(Event::Script(ScriptEvent::Error(e))).await;
+                    }
+                    None => {}
                 }
             }
-            command = cmd_channel.recv() => {                
+            request = cmd_channel.recv() => {
+                let Some(Request { command, reply }) = request else {
+                    // Channel closed, exit loop
+                    eprintln!("Command channel closed, exiting");
+                    running = false;
+                    continue;
+                };
+                let mut result: CommandResult = Ok(Reply::Done);
+                // set when the reply waits for the broker's acknowledgement
+                let mut ack: Option<(AckKind, u16)> = None;
                 match command {
-                    Some(Command::ResumeSession(conn)) => {
+                    Command::ResumeSession(conn, relay, keep_alive) => {
                         // Logic to resume the session
-                        println!("Resuming session");
-                        let mut c = vaux_client::ClientBuilder::default()
-                            .with_state(conn, session.take()
-                            .expect("No session to resume"))
-                            .build().await.expect("Failed to build client");
+                        eprintln!("Resuming session");
+                        let Some(state) = session.take() else {
+                            if let Some(reply) = reply {
+                                let _ = reply.send(failed("No session to resume".to_string()));
+                            }
+                            continue;
+                        };
+                        let snapshot = match SessionSnapshot::from_state(&state) {
+                            Ok(snapshot) => snapshot,
+                            Err(e) => {
+                                session = Some(state);
+                                if let Some(reply) = reply {
+                                    let _ = reply.send(failed(format!("Failed to read the session state: {e}")));
+                                }
+                                continue;
+                            }
+                        };
+                        let _ = event_tx.send(Event::Session(None)).await;
+                        start_relay(relay, &event_tx);
+                        let tracker = SessionTracker::new(snapshot.clone());
+                        let mut c = match vaux_client::ClientBuilder::default()
+                            .with_state(conn, state)
+                            .with_keep_alive(keep_alive)
+                            .build().await {
+                            Ok(c) => c,
+                            Err(e) => {
+                                // the session was never used, keep it for another try
+                                if let Ok(state) = snapshot.to_state() {
+                                    let _ = event_tx.send(Event::Session(Some(snapshot))).await;
+                                    session = Some(state);
+                                }
+                                if let Some(reply) = reply {
+                                    let _ = reply.send(failed(format!("Failed to start MQTT Client: {e}")));
+                                }
+                                continue;
+                            }
+                        };
                         match c.try_start(Duration::from_secs(10), false).await {
                             Ok(h) => {
                                 handle = Some(h);
                                 // take the packet consumer
                                 packet_consumer = c.take_packet_consumer().expect("Failed to take packet consumer");
                                 client = Some(c);
+                                sink.tracker = Some(tracker);
                             }
                             Err(e) => {
-                                eprintln!("Failed to connect MQTT Client: {e}");
+                                result = failed(format!("Failed to connect MQTT Client: {e}"));
                             }
                         }
                     }
-                    Some(Command::StartClient(builder)) => {
+                    Command::StartClient(builder, relay, session) => {
                         // Logic to start the client
-                        println!("MQTT Client started with builder");
+                        eprintln!("MQTT Client started with builder");
+                        start_relay(relay, &event_tx);
                         match builder.build().await {
                             Ok(mut c) => {
-                                println!("MQTT Client connected successfully");
+                                eprintln!("MQTT Client connected successfully");
                                 // attempt to connect the client
                                 match c.try_start(Duration::from_secs(10), true).await {
                                     Ok(h) => {
@@ -142,58 +776,76 @@ pub async fn run(
                                         // take the packet consumer
                                         packet_consumer = c.take_packet_consumer().expect("Failed to take packet consumer");
                                         client = Some(c);
+                                        sink.tracker = Some(SessionTracker::new(session));
                                     }
                                     Err(e) => {
-                                        eprintln!("Failed to connect MQTT Client: {e}");
+                                        // a refused CONNACK never connects the client, report it all the same
+                                        if let Some(mut refused) = c.take_packet_consumer() {
+                                            while let Ok(p) = refused.try_recv() {
+                                                let _ = sink.forward(Exchange::Receive, p).await;
+                                            }
+                                        }
+                                        result = failed(format!("Failed to connect MQTT Client: {e}"));
                                     }
                                 }
                             }
                             Err(e) => {
-                                eprintln!("Failed to start MQTT Client: {e}");
+                                result = failed(format!("Failed to start MQTT Client: {e}"));
                             }
                         }
                     }
-                    Some(Command::Ping) => {
-                        if let Some(ref mut c) = client {
-                            if let Err(e) = c.ping().await {
-                                eprintln!("Failed to send ping: {e}");
-                            } else {
-                                mqtt_tx.send((Exchange::Send, vaux_mqtt::Packet::PingRequest(vaux_mqtt::PingReq::default()))).await.expect("Failed to send ping packet");
-                            }
+                    Command::Ping => {
+                        if ping_schedule == PingSchedule::Suppressed {
+                            eprintln!("Pings are suppressed, not sending ping");
+                            result = Err("pings are suppressed".to_string());
+                        } else if let Some(ref mut c) = client {
+                            send_ping(c, &mut sink).await;
                         } else {
-                            eprintln!("Client not initialized, cannot send ping");
+                            result = failed("Client not initialized, cannot send ping".to_string());
                         }
                     }
-                    Some(Command::Publish(publish))=> {
+                    Command::Publish(publish) => {
+                        let packet_id = publish.packet_id();
                         let packet = vaux_mqtt::Packet::Publish(publish);
                         let _packet = packet.clone();
-                        println!("Publishing packet: {packet:?}");
+                        eprintln!("Publishing packet: {packet:?}");
                         if let Some(ref mut c) = client {
                             c.packet_producer()
                             .send(packet)
                             .await
                             .expect("Failed to send publish packet");
-                            mqtt_tx.send((Exchange::Send, _packet)).await.expect("Failed to send publish packet to UI");
+                            sink.forward(Exchange::Send, _packet).await.expect("Failed to send publish packet to UI");
+                            // QoS 0 has no packet ID and nothing comes back
+                            ack = packet_id.map(|id| (AckKind::Publish, id));
                         } else {
-                            eprintln!("Client not initialized, cannot publish");
+                            result = failed("Client not initialized, cannot publish".to_string());
                         }
                     }
-                    Some(Command::Subscribe(packet_id, qos_level, topic)) => {
+                    Command::Subscribe(packet_id, qos_level, topic) => {
                         // Logic to subscribe to a topic
-                        println!("Subscribed to topic '{topic}'" );
+                        eprintln!("Subscribed to topic '{topic}'" );
                         if let Some(ref mut c) = client {
                             let topic_list = vec![topic.as_str()];
                             match c.subscribe(packet_id, topic_list.as_slice(), qos_level).await {
-                                Ok(_) => println!("Subscription request sent for topic '{topic}'"),
-                                Err(e) => eprintln!("Failed to send subscription request: {e}"),
+                                Ok(_) => {
+                                    eprintln!("Subscription request sent for topic '{topic}'");
+                                    if let Some(tracker) = sink.tracker.as_mut() {
+                                        tracker.subscribing(packet_id, &topic, qos_level as u8);
+                                    }
+                                    ack = Some((AckKind::Subscribe, packet_id));
+                                }
+                                Err(e) => result = failed(format!("Failed to send subscription request: {e}")),
                             }
                         } else {
-                            eprintln!("Client not initialized, cannot subscribe");
+                            result = failed("Client not initialized, cannot subscribe".to_string());
                         }
                     }
-                    Some(Command::Unsubscribe(packet_id, topic)) => {
+                    Command::Unsubscribe(packet_id, topic) => {
                         // Logic to unsubscribe from a topic
-                        println!("Unsubscribed from topic '{topic}'");
+                        eprintln!("Unsubscribed from topic '{topic}'");
+                        if let Some(tracker) = sink.tracker.as_mut() {
+                            tracker.unsubscribing(packet_id, &topic);
+                        }
                         let unsub = unsubscribe::Unsubscribe::new(packet_id, vec![topic]);
                         let _unsub = unsub.clone();
                         if let Some(ref mut c) = client {
@@ -201,49 +853,183 @@ pub async fn run(
                             .send(vaux_mqtt::Packet::Unsubscribe(unsub))
                             .await
                             .expect("Failed to send unsubscribe packet");
-                            mqtt_tx.send((Exchange::Send, vaux_mqtt::Packet::Unsubscribe(_unsub))).await.expect("Failed to send unsubscribe packet to UI");
+                            sink.forward(Exchange::Send, vaux_mqtt::Packet::Unsubscribe(_unsub)).await.expect("Failed to send unsubscribe packet to UI");
+                            ack = Some((AckKind::Unsubscribe, packet_id));
                         } else {
-                            eprintln!("Client not initialized, cannot unsubscribe");
+                            result = failed("Client not initialized, cannot unsubscribe".to_string());
+                        }
+                    }
+                    Command::BrowseRetained(packet_id, filter) => {
+                        eprintln!("Browsing retained messages on '{filter}'");
+                        match (client.as_mut(), wire::subscribe(packet_id, &filter, 0, 0)) {
+                            (Some(c), Ok(packet)) => {
+                                c.packet_producer()
+                                .send(packet.clone())
+                                .await
+                                .expect("Failed to send subscribe packet");
+                                if let Some(tracker) = sink.tracker.as_mut() {
+                                    tracker.subscribing(packet_id, &filter, 0);
+                                }
+                                sink.forward(Exchange::Send, packet).await.expect("Failed to send subscribe packet to UI");
+                                ack = Some((AckKind::Subscribe, packet_id));
+                            }
+                            (Some(_), Err(e)) => result = failed(format!("Invalid topic filter '{filter}': {e}")),
+                            (None, _) => result = failed("Client not initialized, cannot subscribe".to_string()),
                         }
                     }
-                    Some(Command::StopClient) => {
+                    Command::StopClient => {
                         // Logic to stop the client
-                        println!("MQTT Client stopped");
+                        eprintln!("MQTT Client stopped");
                         // Reset the packet consumer to a dummy receiver
                         let (_dummy_tx, dummy_rx) = tokio::sync::mpsc::channel(1);
                         packet_consumer = dummy_rx; // Reset the packet consumer
-                        if let Some(mut c) = client.take() {
-                            if let Err(e) = c.stop().await {
-                                eprintln!("Failed to stop MQTT Client: {e}");
-                            } else {
-                                println!("MQTT Client stopped successfully");
+                        if let Some(c) = client.take() {
+                            let state = stop_client(c, handle.take()).await;
+                            if let Some((snapshot, s)) = resumable(state, sink.tracker.take()) {
+                                let _ = event_tx.send(Event::Session(Some(snapshot))).await;
+                                session = Some(s);
                             }
                         } else {
-                            println!("No MQTT Client to stop");
-                        }
-                        // join the handle if it exists
-                        if let Some(h) = handle.take() {
-                            match h.await {
-                                Ok(r) => {
-                                     println!("Client session ended successfully");
-                                     match r {
-                                        Ok(s) => session = Some(s),
-                                        Err(e) => eprintln!("Error in client session: {e}"),
-                                     }
+                            eprintln!("No MQTT Client to stop");
+                        }
+                        abandon_replies(&mut awaiting);
+                    }
+                    Command::Disconnect(options) => {
+                        let disconnect = match options.to_packet() {
+                            Ok(disconnect) => disconnect,
+                            Err(e) => {
+                                if let Some(reply) = reply {
+                                    let _ = reply.send(failed(e));
                                 }
-                                Err(e) => eprintln!("Error while ending client session: {e}"),
+                                continue;
+                            }
+                        };
+                        let (_dummy_tx, dummy_rx) = tokio::sync::mpsc::channel(1);
+                        packet_consumer = dummy_rx;
+                        let state = match client.take() {
+                            Some(c) if options.abrupt => {
+                                // dropping the session task closes the socket without a DISCONNECT,
+                                // the session to resume is the one the runner followed
+                                if let Some(h) = handle.take() {
+                                    h.abort();
+                                }
+                                drop(c);
+                                eprintln!("MQTT Client connection dropped");
+                                Some(None)
+                            }
+                            Some(mut c) => {
+                                match c.packet_producer().send(vaux_mqtt::Packet::Disconnect(disconnect.clone())).await {
+                                    Ok(_) => {
+                                        sink.forward(Exchange::Send, vaux_mqtt::Packet::Disconnect(disconnect)).await.expect("Failed to send disconnect packet to UI");
+                                    }
+                                    Err(e) => eprintln!("Failed to send disconnect packet: {e}"),
+                                }
+                                // the broker closes the connection on DISCONNECT, stopping the
+                                // client would send a second one
+                                let state = join_session(handle.take()).await;
+                                drop(c);
+                                Some(state)
+                            }
+                            None => {
+                                eprintln!("No MQTT Client to disconnect");
+                                result = Err("not connected".to_string());
+                                None
+                            }
+                        };
+                        if let Some((snapshot, s)) = state.and_then(|state| resumable(state, sink.tracker.take())) {
+                            let _ = event_tx.send(Event::Session(Some(snapshot))).await;
+                            session = Some(s);
+                        }
+                        abandon_replies(&mut awaiting);
+                    }
+                    Command::SaveSession(path) => {
+                        match &session {
+                            Some(s) => match SessionSnapshot::from_state(s).and_then(|snapshot| snapshot.save(&path)) {
+                                Ok(_) => eprintln!("Session saved to {}", path.display()),
+                                Err(e) => result = failed(format!("Failed to save session to {}: {e}", path.display())),
+                            },
+                            None => result = failed("No session to save".to_string()),
+                        }
+                    }
+                    Command::LoadSession(path) => {
+                        match SessionSnapshot::load(&path).and_then(|snapshot| {
+                            snapshot.to_state().map(|state| (snapshot, state))
+                        }) {
+                            Ok((snapshot, state)) => {
+                                eprintln!("Session loaded from {}", path.display());
+                                session = Some(state);
+                                let _ = event_tx.send(Event::Session(Some(snapshot))).await;
                             }
+                            Err(e) => result = failed(format!("Failed to load session from {}: {e}", path.display())),
                         }
                     }
-                    Some(Command::StopRunner) => {
+                    Command::StartRecording(path) => {
+                        match Recorder::open(&path) {
+                            Ok(r) => {
+                                eprintln!("Recording to {}", path.display());
+                                sink.recorder = Some(r);
+                                let _ = event_tx.send(Event::Recording(Some(path))).await;
+                            }
+                            Err(e) => {
+                                result = failed(format!("Failed to start recording: {e}"));
+                                let _ = event_tx.send(Event::Recording(None)).await;
+                            }
+                        }
+                    }
+                    Command::StopRecording => {
+                        if let Some(r) = sink.recorder.take() {
+                            if let Err(e) = r.sync() {
+                                eprintln!("Failed to sync recording: {e}");
+                            }
+                            eprintln!("Recorded {} packets to {}", r.count(), r.path().display());
+                        }
+                        let _ = event_tx.send(Event::Recording(None)).await;
+                    }
+                    Command::SetPingSchedule(schedule) => {
+                        eprintln!("Ping schedule set to {schedule:?}");
+                        if let PingSchedule::Every(period) = schedule {
+                            // first scheduled ping one period from now, late ticks are not bunched up
+                            ping_interval = tokio::time::interval_at(tokio::time::Instant::now() + period, period);
+                            ping_interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+                        }
+                        ping_schedule = schedule;
+                    }
+                    Command::LoadScript(source) => {
+                        match ScriptHost::load(&source) {
+                            Ok(host) => {
+                                eprintln!("Script loaded");
+                                script = Some(host);
+                                let _ = event_tx.send(Event::Script(ScriptEvent::Loaded)).await;
+                            }
+                            Err(e) => {
+                                // keep the previous script running
+                                result = failed(format!("Failed to load script: {e}"));
+                                let _ = event_tx.send(Event::Script(ScriptEvent::Error(e))).await;
+                            }
+                        }
+                    }
+                    Command::UnloadScript => {
+                        script = None;
+                        let _ = event_tx.send(Event::Script(ScriptEvent::Unloaded)).await;
+                    }
+                    Command::StopRunner => {
                         // Logic to stop the runner
-                        println!("Runner stopped");
+                        eprintln!("Runner stopped");
                         running = false;
                     }
-                    None => {
-                        // Channel closed, exit loop
-                        println!("Command channel closed, exiting");
-                        running = false;
+                }
+                if let Some(reply) = reply {
+                    match (ack, result) {
+                        // answered when the broker acknowledges, a reused
+                        // packet ID fails the earlier reply
+                        (Some(key), Ok(_)) => {
+                            if let Some(earlier) = awaiting.insert(key, reply) {
+                                let _ = earlier.send(Err(format!("packet ID {} reused before it was acknowledged", key.1)));
+                            }
+                        }
+                        (_, result) => {
+                            let _ = reply.send(result);
+                        }
                     }
                 }
             }
@@ -0,0 +1,503 @@
+//! Raw MQTT v5 frames as the embedded broker and the authentication relay
+//! read and write them.
+//!
+//! The broker works on wire bytes rather than vaux-mqtt packets so it can
+//! forward PUBLISH properties untouched and misbehave on purpose. The relay
+//! needs AUTH, which vaux-mqtt has no packet for.
+
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+
+use crate::properties::put_variable_byte_int;
+
+pub const CONNECT: u8 = 1;
+pub const CONNACK: u8 = 2;
+pub const PUBLISH: u8 = 3;
+pub const PUBACK: u8 = 4;
+pub const PUBREC: u8 = 5;
+pub const PUBREL: u8 = 6;
+pub const PUBCOMP: u8 = 7;
+pub const SUBSCRIBE: u8 = 8;
+pub const SUBACK: u8 = 9;
+pub const UNSUBSCRIBE: u8 = 10;
+pub const UNSUBACK: u8 = 11;
+pub const PINGREQ: u8 = 12;
+pub const PINGRESP: u8 = 13;
+pub const DISCONNECT: u8 = 14;
+pub const AUTH: u8 = 15;
+
+const SESSION_EXPIRY_INTERVAL: u8 = 0x11;
+const ASSIGNED_CLIENT_IDENTIFIER: u8 = 0x12;
+const TOPIC_ALIAS: u8 = 0x23;
+const WILL_DELAY_INTERVAL: u8 = 0x18;
+
+pub fn name(kind: u8) -> &'static str {
+    match kind {
+        CONNECT => "CONNECT",
+        CONNACK => "CONNACK",
+        PUBLISH => "PUBLISH",
+        PUBACK => "PUBACK",
+        PUBREC => "PUBREC",
+        PUBREL => "PUBREL",
+        PUBCOMP => "PUBCOMP",
+        SUBSCRIBE => "SUBSCRIBE",
+        SUBACK => "SUBACK",
+        UNSUBSCRIBE => "UNSUBSCRIBE",
+        UNSUBACK => "UNSUBACK",
+        PINGREQ => "PINGREQ",
+        PINGRESP => "PINGRESP",
+        DISCONNECT => "DISCONNECT",
+        AUTH => "AUTH",
+        _ => "reserved",
+    }
+}
+
+/// One control packet: the type and flags of the fixed header and the bytes
+/// after the remaining length.
+pub struct Frame {
+    pub kind: u8,
+    pub flags: u8,
+    pub body: Bytes,
+}
+
+/// Split the next complete frame off `buf`, `None` until all of it arrived.
+pub fn next_frame(buf: &mut BytesMut) -> Result<Option<Frame>, String> {
+    let mut length = 0usize;
+    let mut header_len = 1;
+    loop {
+        let Some(&byte) = buf.get(header_len) else {
+            return Ok(None);
+        };
+        length |= ((byte & 0x7F) as usize) << (7 * (header_len - 1));
+        header_len += 1;
+        if byte & 0x80 == 0 {
+            break;
+        }
+        if header_len > 4 {
+            return Err("malformed remaining length".to_string());
+        }
+    }
+    if buf.len() < header_len + length {
+        return Ok(None);
+    }
+    let first = buf[0];
+    buf.advance(header_len);
+    Ok(Some(Frame {
+        kind: first >> 4,
+        flags: first & 0x0F,
+        body: buf.split_to(length).freeze(),
+    }))
+}
+
+/// Reads the fields of a frame body in order.
+pub struct Reader {
+    body: Bytes,
+}
+
+impl Reader {
+    pub fn new(body: Bytes) -> Self {
+        Self { body }
+    }
+
+    fn need(&self, n: usize) -> Result<(), String> {
+        if self.body.remaining() < n {
+            return Err("packet too short".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn u8(&mut self) -> Result<u8, String> {
+        self.need(1)?;
+        Ok(self.body.get_u8())
+    }
+
+    pub fn u16(&mut self) -> Result<u16, String> {
+        self.need(2)?;
+        Ok(self.body.get_u16())
+    }
+
+    pub fn binary(&mut self) -> Result<Bytes, String> {
+        let length = self.u16()? as usize;
+        self.need(length)?;
+        Ok(self.body.split_to(length))
+    }
+
+    pub fn string(&mut self) -> Result<String, String> {
+        String::from_utf8(self.binary()?.to_vec()).map_err(|_| "invalid UTF-8 string".to_string())
+    }
+
+    pub fn variable_int(&mut self) -> Result<u32, String> {
+        let mut value = 0;
+        for shift in (0..28).step_by(7) {
+            let byte = self.u8()?;
+            value |= ((byte & 0x7F) as u32) << shift;
+            if byte & 0x80 == 0 {
+                return Ok(value);
+            }
+        }
+        Err("malformed variable byte integer".to_string())
+    }
+
+    /// A property block, without its length.
+    pub fn properties(&mut self) -> Result<Bytes, String> {
+        let length = self.variable_int()? as usize;
+        self.need(length)?;
+        let properties = self.body.split_to(length);
+        split_properties(&properties)?;
+        Ok(properties)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.body.is_empty()
+    }
+
+    pub fn rest(&mut self) -> Bytes {
+        self.body.split_off(0)
+    }
+}
+
+/// The properties of a block as (identifier, value bytes).
+pub fn split_properties(mut block: &[u8]) -> Result<Vec<(u8, &[u8])>, String> {
+    let mut properties = Vec::new();
+    while let Some((&id, rest)) = block.split_first() {
+        let length = match id {
+            0x01 | 0x17 | 0x19 | 0x24 | 0x25 | 0x28 | 0x29 | 0x2A => 1,
+            0x13 | 0x21 | 0x22 | 0x23 => 2,
+            0x02 | 0x11 | 0x18 | 0x27 => 4,
+            0x0B => match rest.iter().position(|byte| byte & 0x80 == 0) {
+                Some(end) if end < 4 => end + 1,
+                _ => return Err("malformed subscription identifier".to_string()),
+            },
+            0x03 | 0x08 | 0x09 | 0x12 | 0x15 | 0x16 | 0x1A | 0x1C | 0x1F => {
+                2 + u16::from_be_bytes([
+                    *rest.first().ok_or("property too short")?,
+                    *rest.get(1).ok_or("property too short")?,
+                ]) as usize
+            }
+            0x26 => {
+                let key = 2 + u16::from_be_bytes([
+                    *rest.first().ok_or("property too short")?,
+                    *rest.get(1).ok_or("property too short")?,
+                ]) as usize;
+                key + 2
+                    + u16::from_be_bytes([
+                        *rest.get(key).ok_or("property too short")?,
+                        *rest.get(key + 1).ok_or("property too short")?,
+                    ]) as usize
+            }
+            _ => return Err(format!("unknown property 0x{id:02X}")),
+        };
+        let value = rest.get(..length).ok_or("property too short")?;
+        properties.push((id, value));
+        block = &rest[length..];
+    }
+    Ok(properties)
+}
+
+/// The property block without the properties in `ids`.
+pub fn without(properties: &[u8], ids: &[u8]) -> Result<Bytes, String> {
+    let mut kept = BytesMut::new();
+    for (id, value) in split_properties(properties)? {
+        if !ids.contains(&id) {
+            kept.put_u8(id);
+            kept.extend_from_slice(value);
+        }
+    }
+    Ok(kept.freeze())
+}
+
+/// An application message as the broker stores and forwards it.
+#[derive(Debug, Clone)]
+pub struct Message {
+    pub topic: String,
+    pub payload: Bytes,
+    /// PUBLISH properties passed on to subscribers, without a topic alias.
+    pub properties: Bytes,
+    pub qos: u8,
+    pub retain: bool,
+}
+
+pub struct Connect {
+    pub client_id: String,
+    pub clean_start: bool,
+    pub keep_alive: u16,
+    pub session_expiry: u32,
+    pub will: Option<Message>,
+}
+
+/// Read a CONNECT, or the CONNACK reason code to refuse it with.
+pub fn connect(body: Bytes) -> Result<Connect, u8> {
+    let mut reader = Reader::new(body);
+    let malformed = |_: String| 0x81;
+    if reader.string().map_err(malformed)? != "MQTT" {
+        return Err(0x84);
+    }
+    if reader.u8().map_err(malformed)? != 5 {
+        return Err(0x84);
+    }
+    let flags = reader.u8().map_err(malformed)?;
+    let keep_alive = reader.u16().map_err(malformed)?;
+    let properties = reader.properties().map_err(malformed)?;
+    let session_expiry = split_properties(&properties)
+        .map_err(malformed)?
+        .into_iter()
+        .find(|(id, _)| *id == SESSION_EXPIRY_INTERVAL)
+        .map(|(_, value)| u32::from_be_bytes([value[0], value[1], value[2], value[3]]))
+        .unwrap_or(0);
+    let client_id = reader.string().map_err(malformed)?;
+    let will = if flags & 0x04 != 0 {
+        // the will is sent as a PUBLISH, without the delay
+        let properties = without(
+            &reader.properties().map_err(malformed)?,
+            &[WILL_DELAY_INTERVAL],
+        )
+        .map_err(malformed)?;
+        let topic = reader.string().map_err(malformed)?;
+        let payload = reader.binary().map_err(malformed)?;
+        Some(Message {
+            topic,
+            payload,
+            properties,
+            qos: (flags >> 3) & 0x03,
+            retain: flags & 0x20 != 0,
+        })
+    } else {
+        None
+    };
+    // credentials are accepted whatever they are
+    Ok(Connect {
+        client_id,
+        clean_start: flags & 0x02 != 0,
+        keep_alive,
+        session_expiry,
+        will,
+    })
+}
+
+/// Will properties of an encoded CONNECT packet, `None` without a will.
+pub fn will_properties(packet: &[u8]) -> Result<Option<Bytes>, String> {
+    let mut buf = BytesMut::from(packet);
+    let frame = match next_frame(&mut buf)? {
+        Some(frame) if frame.kind == CONNECT => frame,
+        _ => return Err("not a complete CONNECT".to_string()),
+    };
+    let mut reader = Reader::new(frame.body);
+    // protocol name and version
+    reader.string()?;
+    reader.u8()?;
+    let flags = reader.u8()?;
+    reader.u16()?;
+    reader.properties()?;
+    reader.string()?;
+    if flags & 0x04 == 0 {
+        return Ok(None);
+    }
+    reader.properties().map(Some)
+}
+
+/// Read a PUBLISH as its message and packet ID, 0 at QoS 0.
+pub fn publish(flags: u8, body: Bytes) -> Result<(Message, u16), String> {
+    let qos = (flags >> 1) & 0x03;
+    if qos == 3 {
+        return Err("QoS 3".to_string());
+    }
+    let mut reader = Reader::new(body);
+    let topic = reader.string()?;
+    let packet_id = if qos > 0 { reader.u16()? } else { 0 };
+    let properties = without(&reader.properties()?, &[TOPIC_ALIAS])?;
+    Ok((
+        Message {
+            topic,
+            payload: reader.rest(),
+            properties,
+            qos,
+            retain: flags & 0x01 != 0,
+        },
+        packet_id,
+    ))
+}
+
+/// Read a SUBSCRIBE as its packet ID and (filter, options) pairs.
+pub fn subscribe(body: Bytes) -> Result<(u16, Vec<(String, u8)>), String> {
+    let mut reader = Reader::new(body);
+    let packet_id = reader.u16()?;
+    reader.properties()?;
+    let mut filters = Vec::new();
+    while !reader.is_empty() {
+        let filter = reader.string()?;
+        filters.push((filter, reader.u8()?));
+    }
+    if filters.is_empty() {
+        return Err("SUBSCRIBE without topic filters".to_string());
+    }
+    Ok((packet_id, filters))
+}
+
+pub fn unsubscribe(body: Bytes) -> Result<(u16, Vec<String>), String> {
+    let mut reader = Reader::new(body);
+    let packet_id = reader.u16()?;
+    reader.properties()?;
+    let mut filters = Vec::new();
+    while !reader.is_empty() {
+        filters.push(reader.string()?);
+    }
+    if filters.is_empty() {
+        return Err("UNSUBSCRIBE without topic filters".to_string());
+    }
+    Ok((packet_id, filters))
+}
+
+/// Packet ID of a PUBACK, PUBREC, PUBREL or PUBCOMP.
+pub fn packet_id(body: Bytes) -> Result<u16, String> {
+    Reader::new(body).u16()
+}
+
+/// Reason code of a DISCONNECT, which is 0x00 when left out.
+pub fn disconnect_reason(body: Bytes) -> u8 {
+    Reader::new(body).u8().unwrap_or(0)
+}
+
+pub fn frame(first: u8, body: &[u8]) -> Bytes {
+    let mut dest = BytesMut::with_capacity(body.len() + 5);
+    dest.put_u8(first);
+    put_variable_byte_int(&mut dest, body.len() as u32);
+    dest.extend_from_slice(body);
+    dest.freeze()
+}
+
+pub fn put_string(dest: &mut BytesMut, value: &str) {
+    dest.put_u16(value.len() as u16);
+    dest.extend_from_slice(value.as_bytes());
+}
+
+pub fn connack(session_present: bool, reason: u8, assigned_client_id: Option<&str>) -> Bytes {
+    let mut properties = BytesMut::new();
+    if let Some(client_id) = assigned_client_id {
+        properties.put_u8(ASSIGNED_CLIENT_IDENTIFIER);
+        put_string(&mut properties, client_id);
+    }
+    let mut body = BytesMut::new();
+    body.put_u8(session_present as u8);
+    body.put_u8(reason);
+    put_variable_byte_int(&mut body, properties.len() as u32);
+    body.extend_from_slice(&properties);
+    frame(CONNACK << 4, &body)
+}
+
+pub fn publish_frame(message: &Message, qos: u8, packet_id: u16, retain: bool, dup: bool) -> Bytes {
+    let mut body = BytesMut::new();
+    put_string(&mut body, &message.topic);
+    if qos > 0 {
+        body.put_u16(packet_id);
+    }
+    put_variable_byte_int(&mut body, message.properties.len() as u32);
+    body.extend_from_slice(&message.properties);
+    body.extend_from_slice(&message.payload);
+    let flags = (dup as u8) << 3 | qos << 1 | retain as u8;
+    frame(PUBLISH << 4 | flags, &body)
+}
+
+/// PUBACK, PUBREC, PUBREL or PUBCOMP.
+pub fn ack(kind: u8, packet_id: u16, reason: u8) -> Bytes {
+    let mut body = BytesMut::with_capacity(4);
+    body.put_u16(packet_id);
+    body.put_u8(reason);
+    body.put_u8(0);
+    // PUBREL has the reserved flags 0010
+    let flags = if kind == PUBREL { 0x02 } else { 0x00 };
+    frame(kind << 4 | flags, &body)
+}
+
+/// SUBACK or UNSUBACK with one reason code per topic filter.
+pub fn suback(kind: u8, packet_id: u16, codes: &[u8]) -> Bytes {
+    let mut body = BytesMut::with_capacity(codes.len() + 3);
+    body.put_u16(packet_id);
+    body.put_u8(0);
+    body.extend_from_slice(codes);
+    frame(kind << 4, &body)
+}
+
+pub fn pingresp() -> Bytes {
+    frame(PINGRESP << 4, &[])
+}
+
+pub fn disconnect(reason: u8) -> Bytes {
+    frame(DISCONNECT << 4, &[reason, 0])
+}
+
+/// AUTH with a reason code and a property block without its length.
+pub fn auth(reason: u8, properties: &[u8]) -> Bytes {
+    let mut body = BytesMut::with_capacity(properties.len() + 5);
+    body.put_u8(reason);
+    put_variable_byte_int(&mut body, properties.len() as u32);
+    body.extend_from_slice(properties);
+    frame(AUTH << 4, &body)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// CONNECT with client ID "c" and, with `will`, a will on topic "t" with
+    /// a 5 second delay.
+    fn connect_packet(will: bool) -> Bytes {
+        let mut body = BytesMut::new();
+        put_string(&mut body, "MQTT");
+        body.put_u8(5);
+        body.put_u8(if will { 0x06 } else { 0x02 });
+        body.put_u16(60);
+        body.put_u8(0);
+        put_string(&mut body, "c");
+        if will {
+            body.extend_from_slice(&[5, 0x18, 0, 0, 0, 5]);
+            put_string(&mut body, "t");
+            put_string(&mut body, "p");
+        }
+        frame(CONNECT << 4, &body)
+    }
+
+    #[test]
+    fn will_properties_are_read() {
+        assert_eq!(
+            will_properties(&connect_packet(true)).unwrap().as_deref(),
+            Some(&[0x18, 0, 0, 0, 5][..])
+        );
+    }
+
+    #[test]
+    fn connect_without_will_has_no_will_properties() {
+        assert_eq!(will_properties(&connect_packet(false)), Ok(None));
+    }
+
+    #[test]
+    fn will_properties_need_a_connect() {
+        assert!(will_properties(&disconnect(0)).is_err());
+        assert!(will_properties(&connect_packet(true)[..10]).is_err());
+    }
+
+    #[test]
+    fn frames_split_once_complete() {
+        let publish = frame(PUBLISH << 4 | 0x01, &[0xAB; 200]);
+        // two byte remaining length
+        assert_eq!(&publish[..3], &[0x31, 0xC8, 0x01]);
+        let mut buf = BytesMut::from(&publish[..100]);
+        assert!(next_frame(&mut buf).unwrap().is_none());
+        assert_eq!(buf.len(), 100);
+        buf.extend_from_slice(&publish[100..]);
+        buf.extend_from_slice(&pingresp());
+        let first = next_frame(&mut buf).unwrap().unwrap();
+        assert_eq!((first.kind, first.flags), (PUBLISH, 0x01));
+        assert_eq!(first.body.as_ref(), &[0xAB; 200][..]);
+        let second = next_frame(&mut buf).unwrap().unwrap();
+        assert_eq!((second.kind, second.body.len()), (PINGRESP, 0));
+        assert!(buf.is_empty());
+        assert!(next_frame(&mut buf).unwrap().is_none());
+    }
+
+    #[test]
+    fn remaining_length_over_four_bytes_is_malformed() {
+        let mut buf = BytesMut::from(&[0x30, 0xFF, 0xFF, 0xFF, 0xFF, 0x01][..]);
+        assert!(next_frame(&mut buf).is_err());
+        let mut partial = BytesMut::from(&[0x30, 0xFF][..]);
+        assert!(next_frame(&mut partial).unwrap().is_none());
+    }
+}
@@ -0,0 +1,35 @@
+//! CSV export of the `PacketObject` columns.
+
+use std::io::Write;
+
+use crate::model::PacketObject;
+
+const HEADER: &str = "timestamp,direction,packet_type,packet_id,topic,detail,payload,size";
+
+pub fn write(packets: &[PacketObject], writer: &mut impl Write) -> std::io::Result<()> {
+    writeln!(writer, "{HEADER}")?;
+    for packet in packets {
+        writeln!(
+            writer,
+            "{},{},{},{},{},{},{},{}",
+            escape(&packet.timestamp()),
+            escape(&packet.exchange()),
+            escape(&packet.packet_type()),
+            packet.packet_id(),
+            escape(&packet.topic()),
+            escape(&packet.detail()),
+            escape(&packet.payload()),
+            packet.size()
+        )?;
+    }
+    writer.flush()
+}
+
+/// Quote a field when it contains a separator, quote or line break.
+fn escape(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
@@ -0,0 +1,90 @@
+//! JSON Lines export, one decoded packet per line with the payload and raw
+//! packet bytes as base64.
+
+use std::io::Write;
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use serde::{Deserialize, Serialize};
+use vaux_mqtt::PacketType;
+
+use crate::model::PacketObject;
+use crate::model::packet::Exchange;
+use crate::wire;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Record {
+    pub timestamp: String,
+    /// "send" or "receive".
+    pub direction: String,
+    pub packet_type: String,
+    pub packet_id: i32,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub topic: String,
+    #[serde(default, skip_serializing_if = "String::is_empty")]
+    pub detail: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payload: Option<String>,
+    /// Base64 of the packet as vaux-mqtt encodes it again after decoding,
+    /// not the bytes read from the socket.
+    #[serde(alias = "raw")]
+    pub reencoded: String,
+}
+
+impl Record {
+    pub fn from_packet_object(packet_obj: &PacketObject) -> Self {
+        let packet = packet_obj.packet();
+        let payload = match &packet {
+            Some(vaux_mqtt::Packet::Publish(publish)) => {
+                publish.payload.as_deref().map(|p| STANDARD.encode(p))
+            }
+            _ => None,
+        };
+        Self {
+            timestamp: packet_obj.timestamp(),
+            direction: packet_obj.exchange(),
+            packet_type: packet_obj.packet_type(),
+            packet_id: packet_obj.packet_id(),
+            topic: packet_obj.topic(),
+            detail: packet_obj.detail(),
+            payload,
+            reencoded: packet
+                .and_then(|p| wire::encode(&p).ok())
+                .map(|raw| STANDARD.encode(raw))
+                .unwrap_or_default(),
+        }
+    }
+
+    /// Build a record straight from a packet, for use off the UI thread.
+    pub fn from_packet(exchange: &Exchange, packet: &vaux_mqtt::Packet, timestamp: String) -> Self {
+        let payload = match packet {
+            vaux_mqtt::Packet::Publish(publish) => {
+                publish.payload.as_deref().map(|p| STANDARD.encode(p))
+            }
+            _ => None,
+        };
+        Self {
+            timestamp,
+            direction: match exchange {
+                Exchange::Send => "send",
+                Exchange::Receive => "receive",
+            }
+            .to_string(),
+            packet_type: PacketType::from(packet).to_string(),
+            packet_id: PacketObject::packet_id_from(packet),
+            topic: PacketObject::topic_from(packet).unwrap_or_default(),
+            detail: PacketObject::detail_from(packet),
+            payload,
+            reencoded: wire::encode(packet)
+                .map(|raw| STANDARD.encode(raw))
+                .unwrap_or_default(),
+        }
+    }
+}
+
+pub fn write(packets: &[PacketObject], writer: &mut impl Write) -> std::io::Result<()> {
+    for packet in packets {
+        serde_json::to_writer(&mut *writer, &Record::from_packet_object(packet))?;
+        writer.write_all(b"\n")?;
+    }
+    writer.flush()
+}
@@ -0,0 +1,48 @@
+//! Message log export.
+
+use std::{fs::File, io::BufWriter, path::Path};
+
+use crate::model::PacketObject;
+
+pub mod csv;
+pub mod jsonl;
+pub mod pcap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ExportFormat {
+    JsonLines,
+    Csv,
+    Pcap,
+}
+
+impl ExportFormat {
+    pub const ALL: [ExportFormat; 3] = [
+        ExportFormat::JsonLines,
+        ExportFormat::Csv,
+        ExportFormat::Pcap,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExportFormat::JsonLines => "JSON Lines",
+            ExportFormat::Csv => "CSV",
+            ExportFormat::Pcap => "PCAP",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|f| f.name() == name)
+    }
+}
+
+/// Write the packets to `path` in the given format.
+pub fn export(packets: &[PacketObject], format: ExportFormat, path: &Path) -> Result<(), String> {
+    let file = File::create(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    let mut writer = BufWriter::new(file);
+    match format {
+        ExportFormat::JsonLines => jsonl::write(packets, &mut writer),
+        ExportFormat::Csv => csv::write(packets, &mut writer),
+        ExportFormat::Pcap => pcap::write(packets, &mut writer),
+    }
+    .map_err(|e| format!("{}: {e}", path.display()))
+}
@@ -0,0 +1,289 @@
+//! PCAP export with synthetic Ethernet, IPv4 and TCP framing so the raw MQTT
+//! bytes open in Wireshark's MQTT dissector.
+//!
+//! The capture starts with a TCP handshake between a client at
+//! 10.0.0.1:50000 and a broker at 10.0.0.2:1883, then carries every packet in
+//! one or more PSH/ACK segments with consistent sequence numbers.
+
+use std::io::Write;
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+
+use crate::export::jsonl::Record;
+use crate::model::PacketObject;
+
+const PCAP_MAGIC: u32 = 0xa1b2_c3d4;
+const LINKTYPE_ETHERNET: u32 = 1;
+const SNAPLEN: u32 = 65535;
+
+const CLIENT_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 0x01];
+const BROKER_MAC: [u8; 6] = [0x02, 0, 0, 0, 0, 0x02];
+const CLIENT_IP: [u8; 4] = [10, 0, 0, 1];
+const BROKER_IP: [u8; 4] = [10, 0, 0, 2];
+const CLIENT_PORT: u16 = 50000;
+const BROKER_PORT: u16 = 1883;
+/// Segment size, keeps every frame within a standard Ethernet MTU.
+const MSS: usize = 1460;
+
+const TCP_FIN: u8 = 0x01;
+const TCP_SYN: u8 = 0x02;
+const TCP_PSH: u8 = 0x08;
+const TCP_ACK: u8 = 0x10;
+
+pub fn write(packets: &[PacketObject], writer: &mut impl Write) -> std::io::Result<()> {
+    let records: Vec<Record> = packets.iter().map(Record::from_packet_object).collect();
+    write_records(&records, writer)
+}
+
+pub fn write_records(records: &[Record], writer: &mut impl Write) -> std::io::Result<()> {
+    writer.write_all(&PCAP_MAGIC.to_le_bytes())?;
+    writer.write_all(&2u16.to_le_bytes())?;
+    writer.write_all(&4u16.to_le_bytes())?;
+    writer.write_all(&0i32.to_le_bytes())?;
+    writer.write_all(&0u32.to_le_bytes())?;
+    writer.write_all(&SNAPLEN.to_le_bytes())?;
+    writer.write_all(&LINKTYPE_ETHERNET.to_le_bytes())?;
+
+    let mut stream = TcpStream::default();
+    let start = records
+        .first()
+        .map(|r| timestamp_micros(&r.timestamp))
+        .unwrap_or_default();
+    stream.handshake(writer, start)?;
+    let mut last = start;
+    for record in records {
+        let raw = STANDARD.decode(&record.reencoded).unwrap_or_default();
+        if raw.is_empty() {
+            continue;
+        }
+        last = timestamp_micros(&record.timestamp);
+        stream.data(writer, last, record.direction == "send", &raw)?;
+    }
+    stream.close(writer, last)?;
+    writer.flush()
+}
+
+fn timestamp_micros(timestamp: &str) -> i64 {
+    chrono::DateTime::parse_from_rfc3339(timestamp)
+        .map(|t| t.timestamp_micros())
+        .unwrap_or_default()
+}
+
+/// Sequence numbers of the synthetic connection.
+#[derive(Default)]
+struct TcpStream {
+    client_seq: u32,
+    broker_seq: u32,
+    ip_id: u16,
+}
+
+impl TcpStream {
+    fn handshake(&mut self, writer: &mut impl Write, micros: i64) -> std::io::Result<()> {
+        self.segment(writer, micros, true, TCP_SYN, &[])?;
+        self.client_seq = self.client_seq.wrapping_add(1);
+        self.segment(writer, micros, false, TCP_SYN | TCP_ACK, &[])?;
+        self.broker_seq = self.broker_seq.wrapping_add(1);
+        self.segment(writer, micros, true, TCP_ACK, &[])
+    }
+
+    fn data(
+        &mut self,
+        writer: &mut impl Write,
+        micros: i64,
+        from_client: bool,
+        data: &[u8],
+    ) -> std::io::Result<()> {
+        for chunk in data.chunks(MSS) {
+            self.segment(writer, micros, from_client, TCP_PSH | TCP_ACK, chunk)?;
+            if from_client {
+                self.client_seq = self.client_seq.wrapping_add(chunk.len() as u32);
+            } else {
+                self.broker_seq = self.broker_seq.wrapping_add(chunk.len() as u32);
+            }
+        }
+        Ok(())
+    }
+
+    fn close(&mut self, writer: &mut impl Write, micros: i64) -> std::io::Result<()> {
+        self.segment(writer, micros, true, TCP_FIN | TCP_ACK, &[])?;
+        self.client_seq = self.client_seq.wrapping_add(1);
+        self.segment(writer, micros, false, TCP_FIN | TCP_ACK, &[])?;
+        self.broker_seq = self.broker_seq.wrapping_add(1);
+        self.segment(writer, micros, true, TCP_ACK, &[])
+    }
+
+    fn segment(
+        &mut self,
+        writer: &mut impl Write,
+        micros: i64,
+        from_client: bool,
+        flags: u8,
+        payload: &[u8],
+    ) -> std::io::Result<()> {
+        let (src_mac, dst_mac, src_ip, dst_ip, src_port, dst_port, seq, ack) = if from_client {
+            (
+                CLIENT_MAC,
+                BROKER_MAC,
+                CLIENT_IP,
+                BROKER_IP,
+                CLIENT_PORT,
+                BROKER_PORT,
+                self.client_seq,
+                self.broker_seq,
+            )
+        } else {
+            (
+                BROKER_MAC,
+                CLIENT_MAC,
+                BROKER_IP,
+                CLIENT_IP,
+                BROKER_PORT,
+                CLIENT_PORT,
+                self.broker_seq,
+                self.client_seq,
+            )
+        };
+        // no ACK number before the peer's SYN has been seen
+        let ack = if flags & TCP_ACK != 0 { ack } else { 0 };
+
+        let mut tcp = Vec::with_capacity(20 + payload.len());
+        tcp.extend_from_slice(&src_port.to_be_bytes());
+        tcp.extend_from_slice(&dst_port.to_be_bytes());
+        tcp.extend_from_slice(&seq.to_be_bytes());
+        tcp.extend_from_slice(&ack.to_be_bytes());
+        tcp.push(5 << 4); // data offset, 5 words
+        tcp.push(flags);
+        tcp.extend_from_slice(&u16::MAX.to_be_bytes()); // window
+        tcp.extend_from_slice(&[0, 0]); // checksum
+        tcp.extend_from_slice(&[0, 0]); // urgent pointer
+        tcp.extend_from_slice(payload);
+        let mut pseudo = Vec::with_capacity(12 + tcp.len());
+        pseudo.extend_from_slice(&src_ip);
+        pseudo.extend_from_slice(&dst_ip);
+        pseudo.push(0);
+        pseudo.push(6);
+        pseudo.extend_from_slice(&(tcp.len() as u16).to_be_bytes());
+        pseudo.extend_from_slice(&tcp);
+        let tcp_checksum = checksum(&pseudo);
+        tcp[16..18].copy_from_slice(&tcp_checksum.to_be_bytes());
+
+        let mut ip = Vec::with_capacity(20);
+        ip.push(0x45); // IPv4, 5 word header
+        ip.push(0);
+        ip.extend_from_slice(&((20 + tcp.len()) as u16).to_be_bytes());
+        ip.extend_from_slice(&self.ip_id.to_be_bytes());
+        self.ip_id = self.ip_id.wrapping_add(1);
+        ip.extend_from_slice(&0x4000u16.to_be_bytes()); // don't fragment
+        ip.push(64); // TTL
+        ip.push(6); // TCP
+        ip.extend_from_slice(&[0, 0]); // checksum
+        ip.extend_from_slice(&src_ip);
+        ip.extend_from_slice(&dst_ip);
+        let ip_checksum = checksum(&ip);
+        ip[10..12].copy_from_slice(&ip_checksum.to_be_bytes());
+
+        let mut frame = Vec::with_capacity(14 + ip.len() + tcp.len());
+        frame.extend_from_slice(&dst_mac);
+        frame.extend_from_slice(&src_mac);
+        frame.extend_from_slice(&0x0800u16.to_be_bytes());
+        frame.extend_from_slice(&ip);
+        frame.extend_from_slice(&tcp);
+
+        writer.write_all(&(micros.div_euclid(1_000_000) as u32).to_le_bytes())?;
+        writer.write_all(&(micros.rem_euclid(1_000_000) as u32).to_le_bytes())?;
+        writer.write_all(&(frame.len() as u32).to_le_bytes())?;
+        writer.write_all(&(frame.len() as u32).to_le_bytes())?;
+        writer.write_all(&frame)
+    }
+}
+
+/// Internet checksum (RFC 1071).
+fn checksum(data: &[u8]) -> u16 {
+    let mut sum: u32 = data
+        .chunks(2)
+        .map(|pair| u16::from_be_bytes([pair[0], *pair.get(1).unwrap_or(&0)]) as u32)
+        .sum();
+    while sum > 0xffff {
+        sum = (sum & 0xffff) + (sum >> 16);
+    }
+    !(sum as u16)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(timestamp: &str, direction: &str, raw: &[u8]) -> Record {
+        Record {
+            timestamp: timestamp.to_string(),
+            direction: direction.to_string(),
+            packet_type: String::new(),
+            packet_id: 0,
+            topic: String::new(),
+            detail: String::new(),
+            payload: None,
+            reencoded: STANDARD.encode(raw),
+        }
+    }
+
+    fn u32_at(bytes: &[u8], at: usize) -> u32 {
+        u32::from_le_bytes(bytes[at..at + 4].try_into().unwrap())
+    }
+
+    #[test]
+    fn two_packets_are_framed_in_one_connection() {
+        let records = [
+            record("2024-01-01T00:00:01Z", "send", &[0xC0, 0x00]),
+            record("2024-01-01T00:00:01.5Z", "receive", &[0xD0, 0x00]),
+        ];
+        let mut out = Vec::new();
+        write_records(&records, &mut out).unwrap();
+
+        // global header: magic, version 2.4, zone, accuracy, snaplen, link type
+        assert_eq!(u32_at(&out, 0), PCAP_MAGIC);
+        assert_eq!(&out[4..8], &[2, 0, 4, 0]);
+        assert_eq!(&out[8..16], &[0; 8]);
+        assert_eq!(u32_at(&out, 16), SNAPLEN);
+        assert_eq!(u32_at(&out, 20), LINKTYPE_ETHERNET);
+
+        // handshake, the two packets and the close, each with its record header
+        let mut frames = Vec::new();
+        let mut at = 24;
+        while at < out.len() {
+            let (included, original) = (u32_at(&out, at + 8), u32_at(&out, at + 12));
+            assert_eq!(included, original);
+            frames.push((
+                u32_at(&out, at),
+                u32_at(&out, at + 4),
+                &out[at + 16..at + 16 + included as usize],
+            ));
+            at += 16 + included as usize;
+        }
+        assert_eq!(at, out.len());
+        let lengths: Vec<usize> = frames.iter().map(|(_, _, frame)| frame.len()).collect();
+        assert_eq!(lengths, vec![54, 54, 54, 56, 56, 54, 54, 54]);
+
+        let (seconds, micros, ping) = frames[3];
+        assert_eq!((seconds, micros), (1_704_067_201, 0));
+        assert_eq!(&ping[12..14], &[0x08, 0x00]);
+        // IPv4 total length, addresses and a header checksum that verifies
+        assert_eq!(&ping[16..18], &42u16.to_be_bytes());
+        assert_eq!(&ping[26..30], &CLIENT_IP);
+        assert_eq!(&ping[30..34], &BROKER_IP);
+        assert_eq!(checksum(&ping[14..34]), 0);
+        // TCP ports, sequence 1 after the SYN, PSH/ACK and the MQTT bytes
+        assert_eq!(&ping[34..36], &CLIENT_PORT.to_be_bytes());
+        assert_eq!(&ping[36..38], &BROKER_PORT.to_be_bytes());
+        assert_eq!(&ping[38..42], &1u32.to_be_bytes());
+        assert_eq!(ping[47], TCP_PSH | TCP_ACK);
+        assert_eq!(&ping[54..], &[0xC0, 0x00]);
+
+        let (seconds, micros, pong) = frames[4];
+        assert_eq!((seconds, micros), (1_704_067_201, 500_000));
+        assert_eq!(&pong[34..36], &BROKER_PORT.to_be_bytes());
+        // acknowledges the client's two bytes
+        assert_eq!(&pong[38..42], &1u32.to_be_bytes());
+        assert_eq!(&pong[42..46], &3u32.to_be_bytes());
+        assert_eq!(&pong[54..], &[0xD0, 0x00]);
+    }
+}
@@ -0,0 +1,39 @@
+//! Keep-alive bookkeeping for the monitor panel: the server keep alive from
+//! the CONNACK and when control packets last went each way.
+
+use std::time::Instant;
+
+use crate::model::packet::Exchange;
+
+#[derive(Default)]
+pub struct KeepAliveMonitor {
+    /// Server Keep Alive from the CONNACK, overrides the requested value.
+    pub server_keep_alive: Option<u16>,
+    pub last_sent: Option<Instant>,
+    pub last_received: Option<Instant>,
+    pub last_pingresp: Option<Instant>,
+}
+
+impl KeepAliveMonitor {
+    pub fn record(&mut self, exchange: &Exchange, packet: &vaux_mqtt::Packet) {
+        let now = Instant::now();
+        match exchange {
+            Exchange::Send => self.last_sent = Some(now),
+            Exchange::Receive => self.last_received = Some(now),
+        }
+        match packet {
+            vaux_mqtt::Packet::ConnAck(ack) => {
+                // a new connection starts the timers over
+                self.server_keep_alive = ack.server_keep_alive;
+                self.last_pingresp = None;
+            }
+            vaux_mqtt::Packet::PingResponse(_) => self.last_pingresp = Some(now),
+            _ => {}
+        }
+    }
+
+    /// Keep alive in effect, the server value when it sent one.
+    pub fn negotiated(&self, requested: u16) -> u16 {
+        self.server_keep_alive.unwrap_or(requested)
+    }
+}
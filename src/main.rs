@@ -1,55 +1,60 @@
+mod auth;
+#[cfg(any(test, feature = "broker"))]
+#[cfg_attr(not(feature = "broker"), allow(dead_code))]
+mod broker;
+mod cli;
 mod client;
+#[cfg_attr(not(feature = "broker"), allow(dead_code))]
+mod codec;
+mod export;
+mod keepalive;
 mod model;
+mod payload;
+mod profile;
+mod properties;
+mod proxy;
+mod raw;
+mod reason;
+mod recording;
+mod replay;
+mod runtime;
+mod scenario;
+mod script;
+mod session;
+mod shared;
+mod stats;
+#[cfg(test)]
+mod tests;
 mod ui;
-
-use std::cell::RefCell;
-use std::rc::Rc;
+mod wire;
 
 use gtk4 as gtk;
 
 use gtk::prelude::*;
 use gtk::{Application, ApplicationWindow, glib};
-use vaux_mqtt::{ Packet, PingResp};
 
-use crate::client::ClientSetting;
-use crate::model::PacketObject;
-use crate::model::packet::Exchange;
-use crate::ui::{build_actions, build_message_view};
+use crate::runtime::SharedRuntime;
+use crate::ui::{build_connection_page, close_connection_page};
 
 fn main() -> glib::ExitCode {
-    let (cmd_tx, cmd_rx) = tokio::sync::mpsc::channel(32);
-    let (packet_tx, mut packet_rx) = tokio::sync::mpsc::channel(32);
-
-    let rt = tokio::runtime::Builder::new_current_thread()
-        .enable_all()
-        .build()
-        .unwrap();
-
-    let _packet_tx = packet_tx.clone();
-
-    std::thread::spawn(move || {
-        rt.block_on(async {
-            client::run(_packet_tx, cmd_rx).await;
-        });
-    });
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    if args.first().is_some_and(|arg| arg == "--headless") {
+        return match cli::run(&args[1..]) {
+            Ok(()) => glib::ExitCode::SUCCESS,
+            Err(e) => {
+                eprintln!("{e}");
+                glib::ExitCode::FAILURE
+            }
+        };
+    }
+
+    let runtime = SharedRuntime::start();
 
     let app = Application::builder()
         .application_id("org.bytetrail-rs.vaux")
         .build();
 
-    let list_model = gtk::gio::ListStore::new::<PacketObject>();
-    let message_model = Rc::new(RefCell::new(list_model));
-    let _message_model = Rc::clone(&message_model);
-    glib::spawn_future_local(async move {
-        while let Some((dir, packet)) = packet_rx.recv().await {
-            let packet_obj = PacketObject::new(dir, packet);
-            (*_message_model.borrow_mut()).append(&packet_obj);
-        }
-    });
-
     app.connect_activate(move |app| {
-        let client_setting = ClientSetting::new();
-
         // We create the main window.
         let window = ApplicationWindow::builder()
             .application(app)
@@ -58,34 +63,54 @@ fn main() -> glib::ExitCode {
             .title("Vaux Client")
             .build();
 
-        let main_box = gtk::Box::new(gtk::Orientation::Vertical, 0);
-        main_box.set_margin_start(10);
-        main_box.set_margin_end(10);
-        main_box.set_margin_top(10);
-        main_box.set_margin_bottom(10);
-        main_box.set_spacing(10);
-
-        let session_frame = gtk::Frame::new(Some("Session"));
-        session_frame.set_hexpand(true);
-        session_frame.set_vexpand(false);
-        let (connect_tabs, clean_start_check) = ui::build_connection_notebook(&client_setting);
-        session_frame.set_child(Some(&connect_tabs));
-        main_box.append(&session_frame);
-
-        let actions_frame = build_actions(&clean_start_check, cmd_tx.clone(), &client_setting);
-        main_box.append(&actions_frame);
-
-        let message_frame = build_message_view(Rc::clone(&message_model));
-        message_frame.set_vexpand(true);
-
-        main_box.append(&message_frame);
-
-        let packet = PacketObject::new(Exchange::Receive, Packet::PingResponse(PingResp::default()));
-        (*message_model.borrow_mut()).append(&packet);
-
-        window.set_child(Some(&main_box));
+        let connections = gtk::Notebook::new();
+        connections.set_scrollable(true);
+        let add_button = gtk::Button::from_icon_name("list-add-symbolic");
+        add_button.set_tooltip_text(Some("New connection"));
+        connections.set_action_widget(&add_button, gtk::PackType::End);
+        #[cfg(feature = "broker")]
+        connections.set_action_widget(&ui::broker::build_broker_button(), gtk::PackType::Start);
+        let runtime = runtime.clone();
+        let add_connection = glib::clone!(
+            #[weak]
+            connections,
+            move || add_connection_page(&connections, &runtime)
+        );
+        add_connection();
+        add_button.connect_clicked(move |_| add_connection());
+
+        window.set_child(Some(&connections));
         window.present();
     });
 
     app.run()
 }
+
+/// Add a connection tab with its own runner and select it.
+fn add_connection_page(connections: &gtk::Notebook, runtime: &SharedRuntime) {
+    let title = gtk::Label::new(Some(&format!("Connection {}", connections.n_pages() + 1)));
+    let (page, queue) = build_connection_page(runtime, &title);
+
+    let tab = gtk::Box::new(gtk::Orientation::Horizontal, 6);
+    tab.append(&title);
+    let close_button = gtk::Button::from_icon_name("window-close-symbolic");
+    close_button.set_has_frame(false);
+    close_button.set_tooltip_text(Some("Disconnect and close"));
+    tab.append(&close_button);
+
+    let index = connections.append_page(&page, Some(&tab));
+    connections.set_tab_reorderable(&page, true);
+    connections.set_current_page(Some(index));
+    close_button.connect_clicked(glib::clone!(
+        #[weak]
+        connections,
+        #[weak]
+        page,
+        move |_| {
+            close_connection_page(&queue);
+            if let Some(index) = connections.page_num(&page) {
+                connections.remove_page(Some(index));
+            }
+        }
+    ));
+}
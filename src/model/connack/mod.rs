@@ -0,0 +1,107 @@
+use glib::Object;
+use vaux_mqtt::{ConnAck, QoSLevel};
+
+mod objimpl;
+
+/// Placeholder shown for values the broker has not reported yet.
+const NOT_REPORTED: &str = "-";
+
+glib::wrapper! {
+    pub struct ConnAckObject(ObjectSubclass<objimpl::ConnAckObject>);
+}
+
+impl Default for ConnAckObject {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ConnAckObject {
+    pub fn new() -> Self {
+        Object::builder()
+            .property("reason", NOT_REPORTED)
+            .property("session-present", NOT_REPORTED)
+            .property("assigned-client-id", NOT_REPORTED)
+            .property("session-expiry", NOT_REPORTED)
+            .property("server-keep-alive", NOT_REPORTED)
+            .property("receive-maximum", NOT_REPORTED)
+            .property("maximum-qos", NOT_REPORTED)
+            .property("retain-available", NOT_REPORTED)
+            .property("wildcard-available", NOT_REPORTED)
+            .property("shared-available", NOT_REPORTED)
+            .property("subscription-id-available", NOT_REPORTED)
+            .property("topic-alias-maximum", NOT_REPORTED)
+            .property("maximum-packet-size", NOT_REPORTED)
+            .property("response-information", NOT_REPORTED)
+            .property("server-reference", NOT_REPORTED)
+            .build()
+    }
+
+    /// Update the summary from the CONNACK returned by the broker. Properties
+    /// absent from the CONNACK are shown with the MQTT v5 default value the
+    /// broker is implicitly granting.
+    pub fn update(&self, ack: &ConnAck) {
+        self.set_reason(match &ack.reason_str {
+            Some(reason_str) => format!("{:?} ({reason_str})", ack.reason),
+            None => format!("{:?}", ack.reason),
+        });
+        self.set_session_present(yes_no(ack.session_present));
+        self.set_assigned_client_id(
+            ack.assigned_client_id
+                .clone()
+                .unwrap_or_else(|| "not assigned".to_string()),
+        );
+        self.set_session_expiry(match ack.expiry_interval {
+            Some(expiry) => format!("{expiry} s"),
+            None => "as requested".to_string(),
+        });
+        self.set_server_keep_alive(match ack.server_keep_alive {
+            Some(keep_alive) => format!("{keep_alive} s"),
+            None => "as requested".to_string(),
+        });
+        self.set_receive_maximum(or_default(ack.receive_max, 65535));
+        self.set_maximum_qos(match ack.max_qos {
+            Some(QoSLevel::AtMostOnce) => "QoS 0".to_string(),
+            Some(QoSLevel::AtLeastOnce) => "QoS 1".to_string(),
+            Some(QoSLevel::ExactlyOnce) => "QoS 2".to_string(),
+            None => "QoS 2 (default)".to_string(),
+        });
+        self.set_retain_available(available(ack.retain_avail));
+        self.set_wildcard_available(available(ack.wildcard_sub_avail));
+        self.set_shared_available(available(ack.shared_sub_avail));
+        self.set_subscription_id_available(available(ack.sub_id_avail));
+        self.set_topic_alias_maximum(or_default(ack.topic_alias_max, 0));
+        self.set_maximum_packet_size(match ack.max_packet_size {
+            Some(size) => format!("{size} bytes"),
+            None => "unlimited (default)".to_string(),
+        });
+        self.set_response_information(
+            ack.response_info
+                .clone()
+                .unwrap_or_else(|| NOT_REPORTED.to_string()),
+        );
+        self.set_server_reference(
+            ack.server_ref
+                .clone()
+                .unwrap_or_else(|| NOT_REPORTED.to_string()),
+        );
+    }
+}
+
+fn yes_no(value: bool) -> &'static str {
+    if value { "yes" } else { "no" }
+}
+
+fn available(value: Option<bool>) -> String {
+    match value {
+        Some(v) => yes_no(v).to_string(),
+        None => "yes (default)".to_string(),
+    }
+}
+
+fn or_default<T: std::fmt::Display>(value: Option<T>, default: T) -> String {
+    match value {
+        Some(v) => v.to_string(),
+        None => format!("{default} (default)"),
+    }
+}
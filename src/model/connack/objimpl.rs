@@ -0,0 +1,51 @@
+use glib::{
+    object::ObjectExt,
+    subclass::{object::ObjectImpl, types::ObjectSubclass},
+};
+use gtk4::subclass::prelude::DerivedObjectProperties;
+use std::cell::RefCell;
+
+#[derive(glib::Properties, Default)]
+#[properties(wrapper_type = super::ConnAckObject)]
+pub struct ConnAckObject {
+    #[property(construct, get, set)]
+    pub reason: RefCell<String>,
+    #[property(construct, get, set)]
+    pub session_present: RefCell<String>,
+    #[property(construct, get, set)]
+    pub assigned_client_id: RefCell<String>,
+    #[property(construct, get, set)]
+    pub session_expiry: RefCell<String>,
+    #[property(construct, get, set)]
+    pub server_keep_alive: RefCell<String>,
+    #[property(construct, get, set)]
+    pub receive_maximum: RefCell<String>,
+    #[property(construct, get, set)]
+    pub maximum_qos: RefCell<String>,
+    #[property(construct, get, set)]
+    pub retain_available: RefCell<String>,
+    #[property(construct, get, set)]
+    pub wildcard_available: RefCell<String>,
+    #[property(construct, get, set)]
+    pub shared_available: RefCell<String>,
+    #[property(construct, get, set)]
+    pub subscription_id_available: RefCell<String>,
+    #[property(construct, get, set)]
+    pub topic_alias_maximum: RefCell<String>,
+    #[property(construct, get, set)]
+    pub maximum_packet_size: RefCell<String>,
+    #[property(construct, get, set)]
+    pub response_information: RefCell<String>,
+    #[property(construct, get, set)]
+    pub server_reference: RefCell<String>,
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for ConnAckObject {
+    const NAME: &'static str = "ConnAckObject";
+    type Type = super::ConnAckObject;
+    type ParentType = glib::Object;
+}
+
+#[glib::derived_properties]
+impl ObjectImpl for ConnAckObject {}
@@ -0,0 +1,149 @@
+//! Message log filter criteria.
+
+use std::collections::HashSet;
+
+use chrono::NaiveTime;
+
+use crate::model::PacketObject;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum DirectionFilter {
+    #[default]
+    All,
+    Send,
+    Receive,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct MessageFilter {
+    /// Packet types to hide, by `PacketObject::packet_type`.
+    pub hidden_types: HashSet<String>,
+    pub direction: DirectionFilter,
+    /// Topic glob where `*` matches any run of characters and `?` matches one.
+    pub topic_glob: String,
+    pub packet_id: Option<i32>,
+    pub from: Option<NaiveTime>,
+    pub to: Option<NaiveTime>,
+    /// Case insensitive text searched for in payload, topic and detail.
+    pub text: String,
+}
+
+impl MessageFilter {
+    pub fn matches(&self, packet: &PacketObject) -> bool {
+        if self.hidden_types.contains(&packet.packet_type()) {
+            return false;
+        }
+        match self.direction {
+            DirectionFilter::All => {}
+            DirectionFilter::Send if packet.exchange() == "send" => {}
+            DirectionFilter::Receive if packet.exchange() == "receive" => {}
+            _ => return false,
+        }
+        if !self.topic_glob.is_empty() && !glob_match(&self.topic_glob, &packet.topic()) {
+            return false;
+        }
+        if let Some(packet_id) = self.packet_id {
+            if packet.packet_id() != packet_id {
+                return false;
+            }
+        }
+        if self.from.is_some() || self.to.is_some() {
+            let Ok(timestamp) = chrono::DateTime::parse_from_rfc3339(&packet.timestamp()) else {
+                return false;
+            };
+            let time = timestamp.time();
+            if self.from.is_some_and(|from| time < from) || self.to.is_some_and(|to| time > to) {
+                return false;
+            }
+        }
+        if !self.text.is_empty() {
+            let text = self.text.to_lowercase();
+            if ![packet.payload(), packet.topic(), packet.detail()]
+                .iter()
+                .any(|field| field.to_lowercase().contains(&text))
+            {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Parse a time of day filter, accepting `HH:MM` or `HH:MM:SS`.
+pub fn parse_time(text: &str) -> Option<NaiveTime> {
+    NaiveTime::parse_from_str(text, "%H:%M:%S")
+        .or_else(|_| NaiveTime::parse_from_str(text, "%H:%M"))
+        .ok()
+}
+
+pub fn glob_match(pattern: &str, text: &str) -> bool {
+    let pattern: Vec<char> = pattern.chars().collect();
+    let text: Vec<char> = text.chars().collect();
+    // iterative matcher, backtracking to the last '*' on mismatch
+    let (mut p, mut t) = (0, 0);
+    let mut star: Option<(usize, usize)> = None;
+    while t < text.len() {
+        if p < pattern.len() && (pattern[p] == '?' || pattern[p] == text[t]) {
+            p += 1;
+            t += 1;
+        } else if p < pattern.len() && pattern[p] == '*' {
+            star = Some((p, t));
+            p += 1;
+        } else if let Some((star_p, star_t)) = star {
+            p = star_p + 1;
+            t = star_t + 1;
+            star = Some((star_p, star_t + 1));
+        } else {
+            return false;
+        }
+    }
+    pattern[p..].iter().all(|c| *c == '*')
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn star_matches_any_run() {
+        assert!(glob_match("sensors/*", "sensors/a/b"));
+        assert!(glob_match("sensors/*", "sensors/"));
+        assert!(glob_match("*/temp", "house/kitchen/temp"));
+        assert!(glob_match("a*b*c", "aXXbYYbc"));
+        assert!(!glob_match("a*b*c", "aXXbYYb"));
+        assert!(!glob_match("sensors/*", "sensor/a"));
+    }
+
+    #[test]
+    fn question_mark_matches_one_character() {
+        assert!(glob_match("t?", "t1"));
+        assert!(!glob_match("t?", "t"));
+        assert!(!glob_match("t?", "t12"));
+        assert!(glob_match("?/*", "a/"));
+    }
+
+    #[test]
+    fn empty_pattern_matches_only_empty_text() {
+        assert!(glob_match("", ""));
+        assert!(!glob_match("", "a"));
+        assert!(glob_match("*", ""));
+        assert!(glob_match("**", "anything"));
+    }
+
+    #[test]
+    fn non_ascii_topics_match_by_character() {
+        assert!(glob_match("caf?/*", "café/menu"));
+        assert!(glob_match("*温度", "部屋/温度"));
+        assert!(glob_match("部屋/??", "部屋/温度"));
+        assert!(!glob_match("部屋/?", "部屋/温度"));
+    }
+
+    #[test]
+    fn times_parse_with_or_without_seconds() {
+        assert_eq!(parse_time("09:30"), NaiveTime::from_hms_opt(9, 30, 0));
+        assert_eq!(parse_time("23:59:58"), NaiveTime::from_hms_opt(23, 59, 58));
+        assert_eq!(parse_time("24:00"), None);
+        assert_eq!(parse_time("9.30"), None);
+        assert_eq!(parse_time(""), None);
+    }
+}
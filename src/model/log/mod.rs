@@ -0,0 +1,107 @@
+use gio::prelude::*;
+use glib::Object;
+use glib::subclass::prelude::ObjectSubclassIsExt;
+use gtk4::gio;
+
+use crate::model::PacketObject;
+
+mod objimpl;
+
+/// Packet log with a retention policy. Rows are evicted oldest first once any
+/// of the non-zero limits (max rows, max age in seconds, max bytes) is
+/// exceeded.
+glib::wrapper! {
+    pub struct MessageLog(ObjectSubclass<objimpl::MessageLog>);
+}
+
+impl Default for MessageLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl MessageLog {
+    pub fn new() -> Self {
+        let log: Self = Object::builder()
+            .property("retained", 0u32)
+            .property("retained-bytes", 0u64)
+            .property("dropped", 0u64)
+            .property("paused", false)
+            .property("max-rows", 0u32)
+            .property("max-age", 0u32)
+            .property("max-bytes", 0u64)
+            .property("recording", String::new())
+            .build();
+        log.imp()
+            .store
+            .set(gio::ListStore::new::<PacketObject>())
+            .expect("message log store initialized twice");
+        log
+    }
+
+    pub fn store(&self) -> gio::ListStore {
+        self.imp().store.get().expect("message log store").clone()
+    }
+
+    /// Append a packet unless capture is paused. Paused packets are counted
+    /// as dropped.
+    pub fn append(&self, packet: &PacketObject) {
+        if self.paused() {
+            self.set_dropped(self.dropped() + 1);
+            return;
+        }
+        self.store().append(packet);
+        self.set_retained(self.retained() + 1);
+        self.set_retained_bytes(self.retained_bytes() + packet.size() as u64);
+        self.enforce();
+    }
+
+    /// Redraw the row for a packet whose properties changed after it was
+    /// appended.
+    pub fn refresh(&self, packet: &PacketObject) {
+        let store = self.store();
+        if let Some(position) = store.find(packet) {
+            store.items_changed(position, 1, 1);
+        }
+    }
+
+    /// Evict rows that exceed the retention policy.
+    pub fn enforce(&self) {
+        let store = self.store();
+        let oldest_allowed = (self.max_age() > 0)
+            .then(|| chrono::Local::now() - chrono::Duration::seconds(self.max_age() as i64));
+        let mut evict = 0u32;
+        let mut evicted_bytes = 0u64;
+        while evict < store.n_items() {
+            let rows = store.n_items() - evict;
+            let bytes = self.retained_bytes() - evicted_bytes;
+            let Some(packet) = store.item(evict).and_downcast::<PacketObject>() else {
+                break;
+            };
+            let too_many = self.max_rows() > 0 && rows > self.max_rows();
+            let too_large = self.max_bytes() > 0 && bytes > self.max_bytes();
+            let too_old = oldest_allowed.is_some_and(|oldest| {
+                chrono::DateTime::parse_from_rfc3339(&packet.timestamp())
+                    .is_ok_and(|timestamp| timestamp < oldest)
+            });
+            if !(too_many || too_large || too_old) {
+                break;
+            }
+            evict += 1;
+            evicted_bytes += packet.size() as u64;
+        }
+        if evict > 0 {
+            store.splice(0, evict, &[] as &[glib::Object]);
+            self.set_retained(self.retained() - evict);
+            self.set_retained_bytes(self.retained_bytes() - evicted_bytes);
+            self.set_dropped(self.dropped() + evict as u64);
+        }
+    }
+
+    pub fn clear(&self) {
+        self.store().remove_all();
+        self.set_retained(0);
+        self.set_retained_bytes(0);
+        self.set_dropped(0);
+    }
+}
@@ -0,0 +1,41 @@
+use glib::{
+    object::ObjectExt,
+    subclass::{object::ObjectImpl, types::ObjectSubclass},
+};
+use gtk4::{gio, subclass::prelude::DerivedObjectProperties};
+use std::cell::{Cell, OnceCell, RefCell};
+
+#[derive(glib::Properties, Default)]
+#[properties(wrapper_type = super::MessageLog)]
+pub struct MessageLog {
+    pub store: OnceCell<gio::ListStore>,
+    #[property(construct, get, set)]
+    pub retained: Cell<u32>,
+    #[property(construct, get, set)]
+    pub retained_bytes: Cell<u64>,
+    #[property(construct, get, set)]
+    pub dropped: Cell<u64>,
+    /// Stop appending to the log while the connection stays open.
+    #[property(construct, get, set)]
+    pub paused: Cell<bool>,
+    #[property(construct, get, set)]
+    pub max_rows: Cell<u32>,
+    /// Maximum age in seconds.
+    #[property(construct, get, set)]
+    pub max_age: Cell<u32>,
+    #[property(construct, get, set)]
+    pub max_bytes: Cell<u64>,
+    /// Path of the session recording in progress, empty when not recording.
+    #[property(construct, get, set)]
+    pub recording: RefCell<String>,
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for MessageLog {
+    const NAME: &'static str = "MessageLog";
+    type Type = super::MessageLog;
+    type ParentType = glib::Object;
+}
+
+#[glib::derived_properties]
+impl ObjectImpl for MessageLog {}
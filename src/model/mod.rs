@@ -1,4 +1,18 @@
+pub mod connack;
+pub mod filter;
+pub mod log;
 pub mod packet;
+pub mod queue;
+pub mod retained;
+pub mod script;
+pub mod session;
 pub mod subscription;
 
+pub use connack::ConnAckObject;
+pub use log::MessageLog;
 pub use packet::PacketObject;
+pub use queue::CommandQueue;
+pub use retained::RetainedMessage;
+pub use script::ScriptObject;
+pub use session::SessionObject;
+pub use subscription::Subscription;
@@ -1,8 +1,13 @@
 use glib::Object;
+use glib::subclass::prelude::ObjectSubclassIsExt;
 use vaux_mqtt::PacketType;
 
+use crate::reason;
+use crate::wire;
+
 mod objimpl;
 
+#[derive(Debug, Clone, Copy, PartialEq)]
 pub enum Exchange {
     Send,
     Receive,
@@ -13,17 +18,89 @@ glib::wrapper! {
 }
 
 impl PacketObject {
-    pub fn new(exchange: Exchange, packet: vaux_mqtt::Packet) -> Self {
+    /// Log row for `packet`, `size` bytes when encoded.
+    pub fn new(exchange: Exchange, packet: vaux_mqtt::Packet, size: usize) -> Self {
 
-        Object::builder()
+        let packet_obj: Self = Object::builder()
             .property("exchange",  if let Exchange::Receive = exchange { "receive" } else { "send" })
             .property("packet-type", PacketType::from(&packet).to_string())
             .property("packet-id", PacketObject::packet_id_from(&packet))
             .property("timestamp", chrono::Local::now().to_rfc3339())
+            .property("detail", PacketObject::detail_from(&packet))
+            .property("topic", PacketObject::topic_from(&packet).unwrap_or_default())
+            .property("payload", PacketObject::payload_from(&packet))
+            .property("size", size as u32)
+            .property("tag", "")
+            .build();
+        packet_obj.imp().packet.replace(Some(packet));
+        packet_obj
+    }
+
+    /// Log row for something that happened outside the MQTT exchange, such
+    /// as a fault injected by the proxy. It has no packet.
+    pub fn event(source: &str, detail: &str) -> Self {
+        Object::builder()
+            .property("exchange", "event")
+            .property("packet-type", source)
+            .property("packet-id", 0)
+            .property("timestamp", chrono::Local::now().to_rfc3339())
+            .property("detail", detail)
+            .property("topic", "")
+            .property("payload", "")
+            .property("size", 0u32)
+            .property("tag", "")
             .build()
     }
 
-    fn packet_id_from(packet: &vaux_mqtt::Packet) -> i32 {
+    /// The logged packet, used to export the raw bytes.
+    pub fn packet(&self) -> Option<vaux_mqtt::Packet> {
+        self.imp().packet.borrow().clone()
+    }
+
+    pub(crate) fn topic_from(packet: &vaux_mqtt::Packet) -> Option<String> {
+        match packet {
+            vaux_mqtt::Packet::Publish(publish) => publish.topic_name.clone(),
+            _ => None,
+        }
+    }
+
+    /// Publish payload as text, invalid UTF-8 is replaced.
+    pub(crate) fn payload_from(packet: &vaux_mqtt::Packet) -> String {
+        match packet {
+            vaux_mqtt::Packet::Publish(publish) => publish
+                .payload
+                .as_deref()
+                .map(|payload| String::from_utf8_lossy(payload).to_string())
+                .unwrap_or_default(),
+            _ => String::new(),
+        }
+    }
+
+    /// Short human readable summary of the packet contents for the log.
+    pub(crate) fn detail_from(packet: &vaux_mqtt::Packet) -> String {
+        match packet {
+            vaux_mqtt::Packet::Disconnect(disconnect) => {
+                let code = reason::code(&disconnect.reason);
+                let mut detail = format!("0x{code:02X} {}", reason::disconnect_name(code));
+                if let Some(reason_desc) = &disconnect.reason_desc {
+                    detail.push_str(&format!(": {reason_desc}"));
+                }
+                if let Some(server_ref) = &disconnect.server_ref {
+                    detail.push_str(&format!(" (server reference {server_ref})"));
+                }
+                detail
+            }
+            vaux_mqtt::Packet::SubAck(_) => wire::ack_reasons(packet)
+                .map(|reasons| reasons.summary(reason::suback_name))
+                .unwrap_or_default(),
+            vaux_mqtt::Packet::UnsubAck(_) => wire::ack_reasons(packet)
+                .map(|reasons| reasons.summary(reason::name))
+                .unwrap_or_default(),
+            _ => String::new(),
+        }
+    }
+
+    pub(crate) fn packet_id_from(packet: &vaux_mqtt::Packet) -> i32 {
         let id = match packet {
             vaux_mqtt::Packet::Connect(_) => None,
             vaux_mqtt::Packet::ConnAck(_) => None,
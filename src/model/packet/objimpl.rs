@@ -8,6 +8,7 @@ use std::cell::{Cell, RefCell};
 #[derive(glib::Properties, Default)]
 #[properties(wrapper_type = super::PacketObject)]
 pub struct PacketObject {
+    pub packet: RefCell<Option<vaux_mqtt::Packet>>,
     #[property(construct, get, set)]
     pub exchange: RefCell<String>,
     #[property(construct, get, set)]
@@ -16,6 +17,18 @@ pub struct PacketObject {
     pub packet_id: Cell<i32>,
     #[property(construct, get, set)]
     pub timestamp: RefCell<String>,
+    #[property(construct, get, set)]
+    pub detail: RefCell<String>,
+    #[property(construct, get, set)]
+    pub topic: RefCell<String>,
+    #[property(construct, get, set)]
+    pub payload: RefCell<String>,
+    /// Encoded size in bytes.
+    #[property(construct, get, set)]
+    pub size: Cell<u32>,
+    /// Tags added by script hooks, comma separated.
+    #[property(construct, get, set)]
+    pub tag: RefCell<String>,
 }
 
 
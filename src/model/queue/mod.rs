@@ -0,0 +1,88 @@
+use std::future::Future;
+
+use glib::Object;
+use glib::object::ObjectExt;
+use glib::subclass::prelude::ObjectSubclassIsExt;
+use tokio::sync::mpsc::{Sender, error::TrySendError};
+
+use crate::client::{Command, CommandResult, Request};
+
+mod objimpl;
+
+glib::wrapper! {
+    pub struct CommandQueue(ObjectSubclass<objimpl::CommandQueue>);
+}
+
+impl CommandQueue {
+    pub fn new(cmd_tx: Sender<Request>) -> Self {
+        let queue: Self = Object::builder()
+            .property("waiting", 0u32)
+            .property("status", "")
+            .build();
+        let (backlog_tx, mut backlog_rx) = tokio::sync::mpsc::unbounded_channel();
+        let _ = queue.imp().cmd_tx.set(cmd_tx.clone());
+        let _ = queue.imp().backlog.set(backlog_tx);
+        let weak = queue.downgrade();
+        glib::spawn_future_local(async move {
+            while let Some(request) = backlog_rx.recv().await {
+                let sent = cmd_tx.send(request).await;
+                let Some(queue) = weak.upgrade() else {
+                    break;
+                };
+                queue.set_waiting(queue.waiting() - 1);
+                queue.set_status(match (sent, queue.waiting()) {
+                    (Err(_), _) => "Runner stopped, command not sent".to_string(),
+                    (Ok(()), 0) => String::new(),
+                    (Ok(()), waiting) => format!("Runner busy, {waiting} waiting"),
+                });
+            }
+        });
+        queue
+    }
+
+    /// Queue `command` for the runner without blocking the main loop and
+    /// return its result. Subscribe, Unsubscribe and QoS 1 or 2 Publish
+    /// resolve once the broker acknowledges them. The command is sent whether
+    /// or not the future is awaited.
+    pub fn send(&self, command: Command) -> impl Future<Output = CommandResult> + use<> {
+        let (reply_tx, reply_rx) = tokio::sync::oneshot::channel();
+        self.queue(Request {
+            command,
+            reply: Some(reply_tx),
+        });
+        async move {
+            reply_rx
+                .await
+                .unwrap_or_else(|_| Err("no reply from the runner".to_string()))
+        }
+    }
+
+    /// Queue `command` when nothing needs to wait for it, failures only show
+    /// in `status` and the log.
+    pub fn dispatch(&self, command: Command) {
+        self.queue(command.into());
+    }
+
+    /// When the runner's channel is full the request joins the backlog and
+    /// is counted in `waiting`. Later requests join it too until it empties,
+    /// so the runner gets them in the order they were queued.
+    fn queue(&self, request: Request) {
+        let imp = self.imp();
+        let request = if self.waiting() == 0 {
+            match imp.cmd_tx.get().expect("set in new").try_send(request) {
+                Ok(()) => return,
+                Err(TrySendError::Closed(_)) => {
+                    self.set_status("Runner stopped, command not sent");
+                    return;
+                }
+                Err(TrySendError::Full(request)) => request,
+            }
+        } else {
+            request
+        };
+        self.set_waiting(self.waiting() + 1);
+        self.set_status(format!("Runner busy, {} waiting", self.waiting()));
+        // the backlog task runs as long as the queue
+        let _ = imp.backlog.get().expect("set in new").send(request);
+    }
+}
@@ -0,0 +1,33 @@
+use glib::{
+    object::ObjectExt,
+    subclass::{object::ObjectImpl, types::ObjectSubclass},
+};
+use gtk4::subclass::prelude::DerivedObjectProperties;
+use std::cell::{Cell, OnceCell, RefCell};
+
+use crate::client::Request;
+
+#[derive(glib::Properties, Default)]
+#[properties(wrapper_type = super::CommandQueue)]
+pub struct CommandQueue {
+    pub cmd_tx: OnceCell<tokio::sync::mpsc::Sender<Request>>,
+    /// Requests that found the runner's channel full, sent in order by one
+    /// local task.
+    pub backlog: OnceCell<tokio::sync::mpsc::UnboundedSender<Request>>,
+    /// Commands waiting for room in the runner's channel.
+    #[property(construct, get, set)]
+    pub waiting: Cell<u32>,
+    /// Back-pressure or the last send failure, empty when all is well.
+    #[property(construct, get, set)]
+    pub status: RefCell<String>,
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for CommandQueue {
+    const NAME: &'static str = "CommandQueue";
+    type Type = super::CommandQueue;
+    type ParentType = glib::Object;
+}
+
+#[glib::derived_properties]
+impl ObjectImpl for CommandQueue {}
@@ -0,0 +1,81 @@
+use gio::prelude::*;
+use glib::Object;
+use gtk4::gio;
+use vaux_mqtt::Packet;
+
+use crate::payload;
+use crate::wire::Encoded;
+
+mod objimpl;
+
+/// Payload bytes shown in the preview column.
+const PREVIEW_BYTES: usize = 64;
+
+glib::wrapper! {
+    pub struct RetainedMessage(ObjectSubclass<objimpl::RetainedMessage>);
+}
+
+impl RetainedMessage {
+    pub fn new(topic: &str, payload: &[u8], qos: u32) -> Self {
+        Object::builder()
+            .property("topic", topic)
+            .property("preview", preview(payload))
+            .property("size", payload.len() as u32)
+            .property("qos", qos)
+            .build()
+    }
+}
+
+fn preview(payload: &[u8]) -> String {
+    let head = &payload[..payload.len().min(PREVIEW_BYTES)];
+    let mut text = match std::str::from_utf8(head) {
+        Ok(text) => text.replace(['\n', '\r'], " "),
+        // a multi-byte character cut at the preview boundary is still text
+        Err(e) if e.error_len().is_none() => {
+            String::from_utf8_lossy(&head[..e.valid_up_to()]).replace(['\n', '\r'], " ")
+        }
+        Err(_) => payload::to_hex(head),
+    };
+    if payload.len() > PREVIEW_BYTES {
+        text.push('…');
+    }
+    text
+}
+
+/// List a received retained PUBLISH, replacing the earlier message for its
+/// topic. An empty retained payload means the topic was cleared.
+pub fn record(store: &gio::ListStore, packet: &Packet, encoded: &Encoded) {
+    let Packet::Publish(publish) = packet else {
+        return;
+    };
+    if !encoded.retain() {
+        return;
+    }
+    let topic = publish.topic_name.clone().unwrap_or_default();
+    let payload = publish.payload.as_deref().unwrap_or_default();
+    if let Some(message) = find(store, &topic) {
+        remove(store, &message);
+    }
+    if payload.is_empty() {
+        return;
+    }
+    let message = RetainedMessage::new(&topic, payload, encoded.qos() as u32);
+    store.insert_sorted(&message, |a, b| {
+        let a = a.downcast_ref::<RetainedMessage>().map(|m| m.topic());
+        let b = b.downcast_ref::<RetainedMessage>().map(|m| m.topic());
+        a.cmp(&b)
+    });
+}
+
+pub fn find(store: &gio::ListStore, topic: &str) -> Option<RetainedMessage> {
+    store
+        .iter::<RetainedMessage>()
+        .flatten()
+        .find(|message| message.topic() == topic)
+}
+
+pub fn remove(store: &gio::ListStore, message: &RetainedMessage) {
+    if let Some(position) = store.find(message) {
+        store.remove(position);
+    }
+}
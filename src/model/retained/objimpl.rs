@@ -0,0 +1,33 @@
+use glib::{
+    object::ObjectExt,
+    subclass::{object::ObjectImpl, types::ObjectSubclass},
+};
+use gtk4::subclass::prelude::DerivedObjectProperties;
+use std::cell::{Cell, RefCell};
+
+#[derive(glib::Properties, Default)]
+#[properties(wrapper_type = super::RetainedMessage)]
+pub struct RetainedMessage {
+    #[property(construct, get, set)]
+    pub topic: RefCell<String>,
+    /// Start of the payload as text, or as hex when it is not UTF-8.
+    #[property(construct, get, set)]
+    pub preview: RefCell<String>,
+    /// Payload size in bytes.
+    #[property(construct, get, set)]
+    pub size: Cell<u32>,
+    #[property(construct, get, set)]
+    pub qos: Cell<u32>,
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for RetainedMessage {
+    const NAME: &'static str = "RetainedMessage";
+    type Type = super::RetainedMessage;
+    type ParentType = glib::Object;
+}
+
+#[glib::derived_properties]
+impl ObjectImpl for RetainedMessage {}
+
+impl RetainedMessage {}
@@ -0,0 +1,48 @@
+use glib::Object;
+
+use crate::client::ScriptEvent;
+
+mod objimpl;
+
+glib::wrapper! {
+    pub struct ScriptObject(ObjectSubclass<objimpl::ScriptObject>);
+}
+
+impl Default for ScriptObject {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl ScriptObject {
+    pub fn new() -> Self {
+        Object::builder()
+            .property("loaded", false)
+            .property("status", "No script loaded")
+            .property("alert-count", 0u32)
+            .property("last-alert", "")
+            .build()
+    }
+
+    /// Show a script event reported by the runner.
+    pub fn update(&self, event: ScriptEvent) {
+        match event {
+            ScriptEvent::Loaded => {
+                self.set_loaded(true);
+                self.set_status(format!(
+                    "Loaded at {}",
+                    chrono::Local::now().format("%H:%M:%S")
+                ));
+            }
+            ScriptEvent::Unloaded => {
+                self.set_loaded(false);
+                self.set_status("No script loaded");
+            }
+            ScriptEvent::Error(e) => self.set_status(format!("Error: {e}")),
+            ScriptEvent::Alert(alert) => {
+                self.set_alert_count(self.alert_count() + 1);
+                self.set_last_alert(alert);
+            }
+        }
+    }
+}
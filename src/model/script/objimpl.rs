@@ -0,0 +1,30 @@
+use glib::{
+    object::ObjectExt,
+    subclass::{object::ObjectImpl, types::ObjectSubclass},
+};
+use gtk4::subclass::prelude::DerivedObjectProperties;
+use std::cell::{Cell, RefCell};
+
+#[derive(glib::Properties, Default)]
+#[properties(wrapper_type = super::ScriptObject)]
+pub struct ScriptObject {
+    #[property(construct, get, set)]
+    pub loaded: Cell<bool>,
+    /// Load state or the last script error.
+    #[property(construct, get, set)]
+    pub status: RefCell<String>,
+    #[property(construct, get, set)]
+    pub alert_count: Cell<u32>,
+    #[property(construct, get, set)]
+    pub last_alert: RefCell<String>,
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for ScriptObject {
+    const NAME: &'static str = "ScriptObject";
+    type Type = super::ScriptObject;
+    type ParentType = glib::Object;
+}
+
+#[glib::derived_properties]
+impl ObjectImpl for ScriptObject {}
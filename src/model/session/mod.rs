@@ -0,0 +1,72 @@
+use glib::Object;
+
+use crate::session::SessionSnapshot;
+
+mod objimpl;
+
+glib::wrapper! {
+    pub struct SessionObject(ObjectSubclass<objimpl::SessionObject>);
+}
+
+impl Default for SessionObject {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl SessionObject {
+    pub fn new() -> Self {
+        Object::builder()
+            .property("present", false)
+            .property("client-id", "-")
+            .property("expires-at", 0i64)
+            .property("unacked", "")
+            .property("pending-qos2", "")
+            .property("subscriptions", "")
+            .build()
+    }
+
+    /// Show the session state stored by the runner, `None` when there is no
+    /// session to resume.
+    pub fn update(&self, snapshot: Option<&SessionSnapshot>) {
+        let Some(snapshot) = snapshot else {
+            self.set_present(false);
+            self.set_client_id("-");
+            self.set_expires_at(0);
+            self.set_unacked("");
+            self.set_pending_qos2("");
+            self.set_subscriptions("");
+            return;
+        };
+        self.set_present(true);
+        self.set_client_id(snapshot.client_id.as_str());
+        self.set_expires_at(snapshot.expires_at());
+        self.set_unacked(
+            snapshot
+                .unacked_outgoing
+                .iter()
+                .map(|p| match &p.topic {
+                    Some(topic) => format!("{} {} {topic}", p.packet_id, p.packet_type),
+                    None => format!("{} {}", p.packet_id, p.packet_type),
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+        );
+        self.set_pending_qos2(
+            snapshot
+                .pending_incoming_qos2
+                .iter()
+                .map(u16::to_string)
+                .collect::<Vec<_>>()
+                .join(", "),
+        );
+        self.set_subscriptions(
+            snapshot
+                .subscriptions
+                .iter()
+                .map(|s| format!("{} (QoS {})", s.filter, s.qos))
+                .collect::<Vec<_>>()
+                .join("\n"),
+        );
+    }
+}
@@ -0,0 +1,33 @@
+use glib::{
+    object::ObjectExt,
+    subclass::{object::ObjectImpl, types::ObjectSubclass},
+};
+use gtk4::subclass::prelude::DerivedObjectProperties;
+use std::cell::{Cell, RefCell};
+
+#[derive(glib::Properties, Default)]
+#[properties(wrapper_type = super::SessionObject)]
+pub struct SessionObject {
+    #[property(construct, get, set)]
+    pub present: Cell<bool>,
+    #[property(construct, get, set)]
+    pub client_id: RefCell<String>,
+    #[property(construct, get, set)]
+    pub expires_at: Cell<i64>,
+    #[property(construct, get, set)]
+    pub unacked: RefCell<String>,
+    #[property(construct, get, set)]
+    pub pending_qos2: RefCell<String>,
+    #[property(construct, get, set)]
+    pub subscriptions: RefCell<String>,
+}
+
+#[glib::object_subclass]
+impl ObjectSubclass for SessionObject {
+    const NAME: &'static str = "SessionObject";
+    type Type = super::SessionObject;
+    type ParentType = glib::Object;
+}
+
+#[glib::derived_properties]
+impl ObjectImpl for SessionObject {}
@@ -1,4 +1,9 @@
+use gio::prelude::*;
 use glib::Object;
+use gtk4::gio;
+
+use crate::client::{CommandResult, Reply};
+use crate::reason;
 
 mod objimpl;
 
@@ -18,6 +23,75 @@ impl Subscription {
             .property("topic", "".to_string())
             .property("id", 0)
             .property("active", false)
+            .property("qos", 0u32)
+            .property("status", "")
+            .property("reason", "")
+            .property("failed", false)
             .build()
     }
+
+    /// Show a SUBSCRIBE sent with `packet_id` as waiting for the broker.
+    pub fn pending(&self, packet_id: u16, qos: u32) {
+        self.set_id(packet_id as u32);
+        self.set_qos(qos);
+        self.set_status("Pending");
+        self.set_reason("");
+        self.set_failed(false);
+    }
+
+    /// Show the result of a SUBSCRIBE or UNSUBSCRIBE for this filter, the
+    /// only one in its packet. Returns whether the broker accepted it.
+    pub fn acknowledged(&self, result: &CommandResult, name: fn(u8) -> &'static str) -> bool {
+        let (status, reason, failed) = match result {
+            Ok(Reply::Acknowledged(reasons)) => match reasons.codes.first() {
+                Some(&code) => (
+                    format!("0x{code:02X} {}", name(code)),
+                    reasons.properties_text(),
+                    reason::is_failure(code),
+                ),
+                None => (
+                    "No reason code".to_string(),
+                    reasons.properties_text(),
+                    true,
+                ),
+            },
+            Ok(Reply::Done) => ("Sent".to_string(), String::new(), false),
+            Err(e) => (e.clone(), String::new(), true),
+        };
+        self.set_status(status);
+        self.set_reason(reason);
+        self.set_failed(failed);
+        !failed
+    }
+}
+
+/// The listed subscription for `topic`, appended when it is not listed yet.
+pub fn find_or_add(store: &gio::ListStore, topic: &str) -> Subscription {
+    if let Some(subscription) = find(store, topic) {
+        return subscription;
+    }
+    let subscription = Subscription::new();
+    subscription.set_topic(topic);
+    store.append(&subscription);
+    subscription
+}
+
+pub fn find(store: &gio::ListStore, topic: &str) -> Option<Subscription> {
+    store
+        .iter::<Subscription>()
+        .flatten()
+        .find(|subscription| subscription.topic() == topic)
+}
+
+/// Redraw the row of a subscription whose properties changed.
+pub fn refresh(store: &gio::ListStore, subscription: &Subscription) {
+    if let Some(position) = store.find(subscription) {
+        store.items_changed(position, 1, 1);
+    }
+}
+
+pub fn remove(store: &gio::ListStore, subscription: &Subscription) {
+    if let Some(position) = store.find(subscription) {
+        store.remove(position);
+    }
 }
@@ -12,13 +12,25 @@ pub struct Subscription {
     pub topic: RefCell<String>,
     #[property(construct, get, set)]
     pub active: Cell<bool>,
+    /// Packet ID of the last SUBSCRIBE for the topic.
     #[property(construct, get, set)]
     pub id: Cell<u32>,
+    /// Requested QoS.
+    #[property(construct, get, set)]
+    pub qos: Cell<u32>,
+    /// Reason code name from the broker, or why the request failed.
+    #[property(construct, get, set)]
+    pub status: RefCell<String>,
+    /// Reason string and user properties from the acknowledgement.
+    #[property(construct, get, set)]
+    pub reason: RefCell<String>,
+    #[property(construct, get, set)]
+    pub failed: Cell<bool>,
 }
 
 #[glib::object_subclass]
 impl ObjectSubclass for Subscription {
-    const NAME: &'static str = "PacketObject";
+    const NAME: &'static str = "Subscription";
     type Type = super::Subscription;
     type ParentType = glib::Object;
 }
@@ -0,0 +1,112 @@
+//! Payload text encodings used by the payload editors.
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PayloadEncoding {
+    /// UTF-8 text sent as is.
+    #[default]
+    Text,
+    /// Hexadecimal bytes, whitespace between digit pairs is ignored.
+    Hex,
+    Base64,
+}
+
+impl PayloadEncoding {
+    pub const ALL: [PayloadEncoding; 3] = [
+        PayloadEncoding::Text,
+        PayloadEncoding::Hex,
+        PayloadEncoding::Base64,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            PayloadEncoding::Text => "Text",
+            PayloadEncoding::Hex => "Hex",
+            PayloadEncoding::Base64 => "Base64",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|e| e.name() == name)
+    }
+
+    /// Convert editor text to payload bytes.
+    pub fn decode(&self, text: &str) -> Result<Vec<u8>, String> {
+        match self {
+            PayloadEncoding::Text => Ok(text.as_bytes().to_vec()),
+            PayloadEncoding::Hex => decode_hex(text),
+            PayloadEncoding::Base64 => STANDARD
+                .decode(text.split_whitespace().collect::<String>())
+                .map_err(|e| format!("invalid base64: {e}")),
+        }
+    }
+
+    /// Convert payload bytes to editor text.
+    pub fn encode(&self, payload: &[u8]) -> String {
+        match self {
+            PayloadEncoding::Text => String::from_utf8_lossy(payload).to_string(),
+            PayloadEncoding::Hex => to_hex(payload),
+            PayloadEncoding::Base64 => STANDARD.encode(payload),
+        }
+    }
+}
+
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes
+        .iter()
+        .map(|b| format!("{b:02x}"))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+pub fn decode_hex(text: &str) -> Result<Vec<u8>, String> {
+    let digits: String = text.split_whitespace().collect();
+    if let Some(c) = digits.chars().find(|c| !c.is_ascii_hexdigit()) {
+        return Err(format!("invalid hex digit '{c}'"));
+    }
+    if digits.len() % 2 != 0 {
+        return Err("hex payload has an odd number of digits".to_string());
+    }
+    // only ASCII digits are left, so each byte is one digit
+    Ok(digits
+        .as_bytes()
+        .chunks(2)
+        .map(|pair| {
+            let digit = |d: u8| (d as char).to_digit(16).unwrap_or_default() as u8;
+            digit(pair[0]) << 4 | digit(pair[1])
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn hex_is_decoded_with_any_spacing() {
+        assert_eq!(decode_hex("0a FF\n1b"), Ok(vec![0x0A, 0xFF, 0x1B]));
+        assert_eq!(decode_hex(""), Ok(vec![]));
+    }
+
+    #[test]
+    fn odd_length_hex_is_refused() {
+        assert_eq!(
+            decode_hex("abc"),
+            Err("hex payload has an odd number of digits".to_string())
+        );
+    }
+
+    #[test]
+    fn non_hex_digits_are_refused() {
+        assert_eq!(decode_hex("0g"), Err("invalid hex digit 'g'".to_string()));
+        assert_eq!(
+            decode_hex("\u{e9}0"),
+            Err("invalid hex digit '\u{e9}'".to_string())
+        );
+        assert_eq!(
+            decode_hex("0\u{e9}"),
+            Err("invalid hex digit '\u{e9}'".to_string())
+        );
+    }
+}
@@ -0,0 +1,169 @@
+//! Connection profiles saved from the GUI and loaded by the headless mode.
+
+use std::path::Path;
+
+use serde::{Deserialize, Serialize};
+use vaux_mqtt::QoSLevel;
+
+use crate::auth::AuthMethod;
+use crate::client::ClientSetting;
+use crate::payload::PayloadEncoding;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct WillProfile {
+    pub topic: String,
+    pub payload: String,
+    /// Encoding name, see `PayloadEncoding::name`.
+    pub payload_encoding: String,
+    pub qos: u8,
+    pub retain: bool,
+    pub delay: u32,
+    pub expiry: u32,
+    pub payload_utf8: bool,
+    pub content_type: String,
+    pub response_topic: String,
+    /// Correlation data as hex.
+    pub correlation_data: String,
+    pub user_properties: Vec<(String, String)>,
+}
+
+impl Default for WillProfile {
+    fn default() -> Self {
+        Self::from_setting(&ClientSetting::new())
+    }
+}
+
+impl WillProfile {
+    fn from_setting(setting: &ClientSetting) -> Self {
+        Self {
+            topic: setting.will_topic.borrow().clone(),
+            payload: setting.will_payload.borrow().clone(),
+            payload_encoding: setting.will_payload_encoding.borrow().name().to_string(),
+            qos: setting.will_qos.borrow().clone() as u8,
+            retain: *setting.will_retain.borrow(),
+            delay: *setting.will_delay.borrow(),
+            expiry: *setting.will_expiry.borrow(),
+            payload_utf8: *setting.will_payload_utf8.borrow(),
+            content_type: setting.will_content_type.borrow().clone(),
+            response_topic: setting.will_response_topic.borrow().clone(),
+            correlation_data: setting.will_correlation_data.borrow().clone(),
+            user_properties: setting.will_user_properties.borrow().clone(),
+        }
+    }
+}
+
+/// The connection settings of a `ClientSetting`, missing fields take the
+/// GUI defaults.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Profile {
+    pub client_id: String,
+    pub host: String,
+    pub port: u16,
+    pub with_tls: bool,
+    pub ca_file: String,
+    pub client_cert: String,
+    pub session_expiry: u32,
+    pub keep_alive: u16,
+    pub auto_ack: bool,
+    pub auto_packet_id: bool,
+    pub with_ping_resp: bool,
+    pub with_credentials: bool,
+    pub username: String,
+    /// Stored in clear text, leave empty and pass it at run time for shared profiles.
+    pub password: String,
+    /// Method name, see `AuthMethod::name`.
+    pub auth_method: String,
+    pub will: Option<WillProfile>,
+}
+
+impl Default for Profile {
+    fn default() -> Self {
+        Self::from_setting(&ClientSetting::new())
+    }
+}
+
+impl Profile {
+    pub fn from_setting(setting: &ClientSetting) -> Self {
+        Self {
+            client_id: setting.client_id.borrow().clone(),
+            host: setting.host.borrow().clone(),
+            port: *setting.port.borrow(),
+            with_tls: *setting.with_tls.borrow(),
+            ca_file: setting.ca_file.borrow().clone(),
+            client_cert: setting.client_cert.borrow().clone(),
+            session_expiry: *setting.session_expiry.borrow(),
+            keep_alive: *setting.keep_alive.borrow(),
+            auto_ack: *setting.auto_ack.borrow(),
+            auto_packet_id: *setting.auto_packet_id.borrow(),
+            with_ping_resp: *setting.with_ping_resp.borrow(),
+            with_credentials: *setting.with_credentials.borrow(),
+            username: setting.username.borrow().clone(),
+            password: setting.password.borrow().clone(),
+            auth_method: setting.auth_method.borrow().name().to_string(),
+            will: (*setting.with_will.borrow()).then(|| WillProfile::from_setting(setting)),
+        }
+    }
+
+    pub fn to_setting(&self) -> Result<ClientSetting, String> {
+        let setting = ClientSetting::new();
+        setting.client_id.replace(self.client_id.clone());
+        setting.host.replace(self.host.clone());
+        setting.port.replace(self.port);
+        setting.with_tls.replace(self.with_tls);
+        setting.ca_file.replace(self.ca_file.clone());
+        setting.client_cert.replace(self.client_cert.clone());
+        setting.session_expiry.replace(self.session_expiry);
+        setting.keep_alive.replace(self.keep_alive);
+        setting.auto_ack.replace(self.auto_ack);
+        setting.auto_packet_id.replace(self.auto_packet_id);
+        setting.with_ping_resp.replace(self.with_ping_resp);
+        setting.with_credentials.replace(self.with_credentials);
+        setting.username.replace(self.username.clone());
+        setting.password.replace(self.password.clone());
+        setting.auth_method.replace(
+            AuthMethod::from_name(&self.auth_method)
+                .ok_or_else(|| format!("unknown auth method '{}'", self.auth_method))?,
+        );
+        if let Some(will) = &self.will {
+            setting.with_will.replace(true);
+            setting.will_topic.replace(will.topic.clone());
+            setting.will_payload.replace(will.payload.clone());
+            setting.will_payload_encoding.replace(
+                PayloadEncoding::from_name(&will.payload_encoding).ok_or_else(|| {
+                    format!("unknown payload encoding '{}'", will.payload_encoding)
+                })?,
+            );
+            setting.will_qos.replace(
+                QoSLevel::try_from(will.qos)
+                    .map_err(|_| format!("invalid will QoS {}", will.qos))?,
+            );
+            setting.will_retain.replace(will.retain);
+            setting.will_delay.replace(will.delay);
+            setting.will_expiry.replace(will.expiry);
+            setting.will_payload_utf8.replace(will.payload_utf8);
+            setting.will_content_type.replace(will.content_type.clone());
+            setting
+                .will_response_topic
+                .replace(will.response_topic.clone());
+            setting
+                .will_correlation_data
+                .replace(will.correlation_data.clone());
+            setting
+                .will_user_properties
+                .replace(will.user_properties.clone());
+        }
+        Ok(setting)
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        std::fs::write(path, json).map_err(|e| format!("{}: {e}", path.display()))
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let json = std::fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        serde_json::from_str(&json).map_err(|e| format!("{}: {e}", path.display()))
+    }
+}
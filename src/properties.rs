@@ -0,0 +1,88 @@
+//! Minimal MQTT v5 property block encoder.
+//!
+//! vaux-mqtt encodes properties internally; this encoder builds the property
+//! blocks of packets vaux cannot, such as the raw packets sent as typed.
+
+use bytes::{BufMut, BytesMut};
+
+#[derive(Default)]
+pub struct PropertyEncoder {
+    properties: BytesMut,
+}
+
+impl PropertyEncoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn byte(&mut self, id: u8, value: u8) -> &mut Self {
+        self.properties.put_u8(id);
+        self.properties.put_u8(value);
+        self
+    }
+
+    pub fn two_byte_int(&mut self, id: u8, value: u16) -> &mut Self {
+        self.properties.put_u8(id);
+        self.properties.put_u16(value);
+        self
+    }
+
+    pub fn four_byte_int(&mut self, id: u8, value: u32) -> &mut Self {
+        self.properties.put_u8(id);
+        self.properties.put_u32(value);
+        self
+    }
+
+    pub fn variable_byte_int(&mut self, id: u8, value: u32) -> &mut Self {
+        self.properties.put_u8(id);
+        put_variable_byte_int(&mut self.properties, value);
+        self
+    }
+
+    pub fn utf8(&mut self, id: u8, value: &str) -> &mut Self {
+        self.properties.put_u8(id);
+        put_binary(&mut self.properties, value.as_bytes());
+        self
+    }
+
+    pub fn binary(&mut self, id: u8, value: &[u8]) -> &mut Self {
+        self.properties.put_u8(id);
+        put_binary(&mut self.properties, value);
+        self
+    }
+
+    pub fn utf8_pair(&mut self, id: u8, key: &str, value: &str) -> &mut Self {
+        self.properties.put_u8(id);
+        put_binary(&mut self.properties, key.as_bytes());
+        put_binary(&mut self.properties, value.as_bytes());
+        self
+    }
+
+    /// The encoded block: property length as a variable byte integer followed
+    /// by the properties.
+    pub fn finish(&self) -> BytesMut {
+        let mut dest = BytesMut::with_capacity(self.properties.len() + 4);
+        put_variable_byte_int(&mut dest, self.properties.len() as u32);
+        dest.extend_from_slice(&self.properties);
+        dest
+    }
+}
+
+fn put_binary(dest: &mut BytesMut, value: &[u8]) {
+    dest.put_u16(value.len() as u16);
+    dest.extend_from_slice(value);
+}
+
+pub fn put_variable_byte_int(dest: &mut BytesMut, mut value: u32) {
+    loop {
+        let mut byte = (value % 128) as u8;
+        value /= 128;
+        if value > 0 {
+            byte |= 0x80;
+        }
+        dest.put_u8(byte);
+        if value == 0 {
+            break;
+        }
+    }
+}
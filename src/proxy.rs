@@ -0,0 +1,498 @@
+//! Fault-injection TCP proxy between the client and the broker.
+//!
+//! The client connects to a local port and each connection is forwarded to
+//! the broker. Both directions hold every chunk for the latency, pace it to
+//! the bandwidth limit and write it in small segments, while a schedule
+//! corrupts single bytes and resets connections. Faults can be changed
+//! while the proxy runs and each injected one is reported as a
+//! `ProxyEvent`.
+
+use std::{
+    net::SocketAddr,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU32, Ordering},
+    },
+    time::Duration,
+};
+
+use bytes::BytesMut;
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::{
+        TcpListener, TcpStream,
+        tcp::{OwnedReadHalf, OwnedWriteHalf},
+    },
+    sync::mpsc::UnboundedSender,
+    time::Instant,
+};
+
+/// Packet type shown for proxy events in the message log.
+pub const PROXY_EVENT_TYPE: &str = "Proxy";
+
+const READ_BUFFER: usize = 16 * 1024;
+/// Pause between the segments of a fragmented chunk, so each one leaves in
+/// its own TCP segment.
+const FRAGMENT_GAP: Duration = Duration::from_millis(2);
+/// How often the corruption and reset schedule is checked.
+const SCHEDULE_TICK: Duration = Duration::from_millis(100);
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ProxyFaults {
+    /// Added to every chunk in both directions.
+    pub latency: Duration,
+    /// Bytes per second in each direction, 0 for unlimited.
+    pub bandwidth: u32,
+    /// Largest segment written at once, 0 to forward chunks whole.
+    pub fragment: usize,
+    /// Flip one bit in the next chunk this often.
+    pub corrupt_every: Option<Duration>,
+    /// Close each connection without warning once it has been open this long.
+    pub reset_after: Option<Duration>,
+}
+
+impl ProxyFaults {
+    pub fn text(&self) -> String {
+        let mut faults = Vec::new();
+        if !self.latency.is_zero() {
+            faults.push(format!("latency {} ms", self.latency.as_millis()));
+        }
+        if self.bandwidth > 0 {
+            faults.push(format!("bandwidth {} bytes/s", self.bandwidth));
+        }
+        if self.fragment > 0 {
+            faults.push(format!("segments of {} bytes", self.fragment));
+        }
+        if let Some(every) = self.corrupt_every {
+            faults.push(format!("corrupt every {}s", every.as_secs()));
+        }
+        if let Some(after) = self.reset_after {
+            faults.push(format!("reset after {}s", after.as_secs()));
+        }
+        if faults.is_empty() {
+            "no faults".to_string()
+        } else {
+            faults.join(", ")
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Direction {
+    ToBroker,
+    ToClient,
+}
+
+impl Direction {
+    fn name(self) -> &'static str {
+        match self {
+            Direction::ToBroker => "to broker",
+            Direction::ToClient => "to client",
+        }
+    }
+}
+
+/// Something the proxy did, connections are numbered from 1.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProxyEvent {
+    Listening(SocketAddr, String),
+    FaultsChanged(ProxyFaults),
+    Opened(u32, SocketAddr),
+    Failed(String),
+    Fragmented {
+        connection: u32,
+        direction: Direction,
+        bytes: usize,
+        segments: usize,
+    },
+    Corrupted {
+        connection: u32,
+        direction: Direction,
+        offset: usize,
+        from: u8,
+        to: u8,
+    },
+    Reset(u32),
+    Closed {
+        connection: u32,
+        to_broker: u64,
+        to_client: u64,
+    },
+    Stopped,
+}
+
+impl ProxyEvent {
+    pub fn text(&self) -> String {
+        match self {
+            ProxyEvent::Listening(address, upstream) => {
+                format!("Listening on {address}, forwarding to {upstream}")
+            }
+            ProxyEvent::FaultsChanged(faults) => format!("Faults: {}", faults.text()),
+            ProxyEvent::Opened(connection, client) => {
+                format!("Connection {connection} opened from {client}")
+            }
+            ProxyEvent::Failed(e) => e.clone(),
+            ProxyEvent::Fragmented {
+                connection,
+                direction,
+                bytes,
+                segments,
+            } => format!(
+                "Connection {connection}: split {bytes} bytes {} into {segments} segments",
+                direction.name()
+            ),
+            ProxyEvent::Corrupted {
+                connection,
+                direction,
+                offset,
+                from,
+                to,
+            } => format!(
+                "Connection {connection}: corrupted byte {offset} {} from 0x{from:02X} to 0x{to:02X}",
+                direction.name()
+            ),
+            ProxyEvent::Reset(connection) => {
+                format!("Connection {connection} reset, both sockets closed without warning")
+            }
+            ProxyEvent::Closed {
+                connection,
+                to_broker,
+                to_client,
+            } => format!(
+                "Connection {connection} closed after {to_broker} bytes to broker and {to_client} bytes to client"
+            ),
+            ProxyEvent::Stopped => "Proxy stopped".to_string(),
+        }
+    }
+}
+
+/// Faults and event channel shared by every connection of a proxy.
+struct Shared {
+    faults: Mutex<ProxyFaults>,
+    events: UnboundedSender<ProxyEvent>,
+}
+
+impl Shared {
+    fn faults(&self) -> ProxyFaults {
+        self.faults.lock().unwrap().clone()
+    }
+
+    fn report(&self, event: ProxyEvent) {
+        let _ = self.events.send(event);
+    }
+}
+
+/// Flip one random bit of `chunk`, returning the offset and both values.
+fn corrupt(chunk: &mut [u8]) -> Option<(usize, u8, u8)> {
+    if chunk.is_empty() {
+        return None;
+    }
+    let random = uuid::Uuid::new_v4().as_u128();
+    let offset = (random % chunk.len() as u128) as usize;
+    let from = chunk[offset];
+    chunk[offset] ^= 1 << ((random >> 64) % 8);
+    Some((offset, from, chunk[offset]))
+}
+
+/// Bytes per write for a chunk of `len` bytes, the whole chunk when
+/// `fragment` is 0.
+fn segment_size(len: usize, fragment: usize) -> usize {
+    if fragment == 0 { len.max(1) } else { fragment }
+}
+
+/// Forward one direction until the reader closes or the writer fails,
+/// returning the bytes written.
+async fn pump(
+    connection: u32,
+    direction: Direction,
+    mut reader: OwnedReadHalf,
+    mut writer: OwnedWriteHalf,
+    shared: &Shared,
+    corrupt_next: &AtomicBool,
+) -> u64 {
+    let (queue_tx, mut queue_rx) = tokio::sync::mpsc::unbounded_channel::<(Instant, BytesMut)>();
+    let read = async move {
+        let mut buffer = BytesMut::with_capacity(READ_BUFFER);
+        loop {
+            buffer.reserve(READ_BUFFER);
+            match reader.read_buf(&mut buffer).await {
+                Ok(0) | Err(_) => break,
+                Ok(_) => {}
+            }
+            let due = Instant::now() + shared.faults().latency;
+            if queue_tx.send((due, buffer.split())).is_err() {
+                break;
+            }
+        }
+    };
+    let write = async move {
+        let mut written = 0u64;
+        while let Some((due, mut chunk)) = queue_rx.recv().await {
+            tokio::time::sleep_until(due).await;
+            let faults = shared.faults();
+            if corrupt_next.swap(false, Ordering::Relaxed)
+                && let Some((offset, from, to)) = corrupt(&mut chunk)
+            {
+                shared.report(ProxyEvent::Corrupted {
+                    connection,
+                    direction,
+                    offset,
+                    from,
+                    to,
+                });
+            }
+            let size = segment_size(chunk.len(), faults.fragment);
+            let segments = chunk.len().div_ceil(size);
+            if segments > 1 {
+                shared.report(ProxyEvent::Fragmented {
+                    connection,
+                    direction,
+                    bytes: chunk.len(),
+                    segments,
+                });
+            }
+            for (index, segment) in chunk.chunks(size).enumerate() {
+                if index > 0 {
+                    tokio::time::sleep(FRAGMENT_GAP).await;
+                }
+                if writer.write_all(segment).await.is_err() {
+                    return written;
+                }
+                written += segment.len() as u64;
+                if faults.bandwidth > 0 {
+                    let seconds = segment.len() as f64 / faults.bandwidth as f64;
+                    tokio::time::sleep(Duration::from_secs_f64(seconds)).await;
+                }
+            }
+        }
+        // pass the close on once everything queued is delivered
+        let _ = writer.shutdown().await;
+        written
+    };
+    let ((), written) = tokio::join!(read, write);
+    written
+}
+
+/// Flag the next chunk for corruption on schedule, returning when the
+/// connection is due to be reset.
+async fn schedule(shared: &Shared, corrupt_next: &AtomicBool) {
+    let opened = Instant::now();
+    let mut last_corrupt = opened;
+    let mut tick = tokio::time::interval(SCHEDULE_TICK);
+    loop {
+        tick.tick().await;
+        let faults = shared.faults();
+        if faults
+            .reset_after
+            .is_some_and(|after| opened.elapsed() >= after)
+        {
+            return;
+        }
+        match faults.corrupt_every {
+            Some(every) if last_corrupt.elapsed() >= every => {
+                corrupt_next.store(true, Ordering::Relaxed);
+                last_corrupt = Instant::now();
+            }
+            Some(_) => {}
+            // a period set later counts from then
+            None => last_corrupt = Instant::now(),
+        }
+    }
+}
+
+async fn forward(connection: u32, client: TcpStream, upstream: String, shared: Arc<Shared>) {
+    let broker = match tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect(&upstream)).await {
+        Ok(Ok(broker)) => broker,
+        Ok(Err(e)) => {
+            shared.report(ProxyEvent::Failed(format!(
+                "Connection {connection}: failed to connect to {upstream}: {e}"
+            )));
+            return;
+        }
+        Err(_) => {
+            shared.report(ProxyEvent::Failed(format!(
+                "Connection {connection}: no answer from {upstream} within {}s",
+                CONNECT_TIMEOUT.as_secs()
+            )));
+            return;
+        }
+    };
+    // segments are written as they are, not merged
+    let _ = client.set_nodelay(true);
+    let _ = broker.set_nodelay(true);
+    let (client_read, client_write) = client.into_split();
+    let (broker_read, broker_write) = broker.into_split();
+    let corrupt_next = AtomicBool::new(false);
+    let to_broker = pump(
+        connection,
+        Direction::ToBroker,
+        client_read,
+        broker_write,
+        &shared,
+        &corrupt_next,
+    );
+    let to_client = pump(
+        connection,
+        Direction::ToClient,
+        broker_read,
+        client_write,
+        &shared,
+        &corrupt_next,
+    );
+    tokio::select! {
+        (to_broker, to_client) = async { tokio::join!(to_broker, to_client) } => {
+            shared.report(ProxyEvent::Closed { connection, to_broker, to_client });
+        }
+        // dropping both pumps closes the sockets with data still queued
+        _ = schedule(&shared, &corrupt_next) => shared.report(ProxyEvent::Reset(connection)),
+    }
+}
+
+async fn serve(listener: TcpListener, upstream: String, shared: Arc<Shared>) {
+    let connections = AtomicU32::new(0);
+    loop {
+        match listener.accept().await {
+            Ok((client, address)) => {
+                let connection = connections.fetch_add(1, Ordering::Relaxed) + 1;
+                shared.report(ProxyEvent::Opened(connection, address));
+                tokio::spawn(forward(
+                    connection,
+                    client,
+                    upstream.clone(),
+                    shared.clone(),
+                ));
+            }
+            Err(e) => {
+                shared.report(ProxyEvent::Failed(format!("Proxy accept failed: {e}")));
+                return;
+            }
+        }
+    }
+}
+
+/// A proxy running on its own thread, stopped when dropped.
+pub struct ProxyHandle {
+    shared: Arc<Shared>,
+    address: SocketAddr,
+    shutdown: Option<tokio::sync::oneshot::Sender<()>>,
+}
+
+impl ProxyHandle {
+    /// Listen on localhost at `port`, 0 for any free port, and forward each
+    /// connection to `upstream` as `host:port`.
+    pub fn start(
+        port: u16,
+        upstream: String,
+        faults: ProxyFaults,
+        events: UnboundedSender<ProxyEvent>,
+    ) -> Result<Self, String> {
+        let listener = std::net::TcpListener::bind(("127.0.0.1", port))
+            .map_err(|e| format!("Failed to listen on port {port}: {e}"))?;
+        listener
+            .set_nonblocking(true)
+            .map_err(|e| format!("Failed to listen on port {port}: {e}"))?;
+        let address = listener
+            .local_addr()
+            .map_err(|e| format!("Failed to listen on port {port}: {e}"))?;
+        let shared = Arc::new(Shared {
+            faults: Mutex::new(faults.clone()),
+            events,
+        });
+        let (shutdown_tx, shutdown_rx) = tokio::sync::oneshot::channel();
+        let serving = shared.clone();
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .map_err(|e| format!("Failed to start the proxy runtime: {e}"))?;
+        std::thread::spawn(move || {
+            rt.block_on(async {
+                match TcpListener::from_std(listener) {
+                    Ok(listener) => {
+                        eprintln!("Proxy listening on {address}, forwarding to {upstream}");
+                        serving.report(ProxyEvent::Listening(address, upstream.clone()));
+                        serving.report(ProxyEvent::FaultsChanged(faults));
+                        tokio::select! {
+                            _ = serve(listener, upstream, serving.clone()) => {}
+                            _ = shutdown_rx => {}
+                        }
+                    }
+                    Err(e) => {
+                        serving.report(ProxyEvent::Failed(format!("Proxy failed to start: {e}")))
+                    }
+                }
+            });
+            // dropping the runtime closes every proxied connection
+            drop(rt);
+            eprintln!("Proxy on {address} stopped");
+            serving.report(ProxyEvent::Stopped);
+        });
+        Ok(Self {
+            shared,
+            address,
+            shutdown: Some(shutdown_tx),
+        })
+    }
+
+    pub fn address(&self) -> SocketAddr {
+        self.address
+    }
+
+    /// Apply `faults` to every connection, including open ones.
+    pub fn set_faults(&self, faults: ProxyFaults) {
+        let mut current = self.shared.faults.lock().unwrap();
+        if *current != faults {
+            *current = faults.clone();
+            self.shared.report(ProxyEvent::FaultsChanged(faults));
+        }
+    }
+}
+
+impl Drop for ProxyHandle {
+    fn drop(&mut self) {
+        if let Some(shutdown) = self.shutdown.take() {
+            let _ = shutdown.send(());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn corrupt_flips_exactly_one_bit() {
+        let original: Vec<u8> = (0..=255).collect();
+        for _ in 0..100 {
+            let mut chunk = original.clone();
+            let (offset, from, to) = corrupt(&mut chunk).unwrap();
+            assert_eq!(from, original[offset]);
+            assert_eq!(to, chunk[offset]);
+            assert_eq!((from ^ to).count_ones(), 1);
+            let changed = chunk.iter().zip(&original).filter(|(a, b)| a != b).count();
+            assert_eq!(changed, 1);
+        }
+    }
+
+    #[test]
+    fn corrupt_leaves_an_empty_chunk_alone() {
+        assert_eq!(corrupt(&mut []), None);
+    }
+
+    #[test]
+    fn fragments_into_segments_of_the_configured_size() {
+        let chunk = [0u8; 10];
+        let segments = |fragment| chunk.chunks(segment_size(chunk.len(), fragment)).count();
+        assert_eq!(segments(0), 1);
+        assert_eq!(segments(1), 10);
+        assert_eq!(segments(3), 4);
+        assert_eq!(segments(5), 2);
+        assert_eq!(segments(10), 1);
+        assert_eq!(segments(64), 1);
+        let sizes: Vec<usize> = chunk
+            .chunks(segment_size(chunk.len(), 4))
+            .map(<[u8]>::len)
+            .collect();
+        assert_eq!(sizes, [4, 4, 2]);
+    }
+}
@@ -0,0 +1,378 @@
+//! Raw packet sender for protocol fuzzing.
+//!
+//! Packets are assembled from their fields exactly as given, or taken as
+//! hex, and written to a TCP connection of their own with no validation, so
+//! a broker's handling of malformed input can be tried. vaux-mqtt only
+//! encodes valid packets, so it is not used for sending. Replies are split
+//! into frames and decoded where possible.
+
+use std::{
+    num::{IntErrorKind, ParseIntError},
+    str::FromStr,
+    time::Duration,
+};
+
+use bytes::{BufMut, BytesMut};
+use tokio::{
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+};
+use vaux_mqtt::{Packet, PacketType};
+
+use crate::model::PacketObject;
+use crate::payload::{self, decode_hex};
+use crate::properties::{PropertyEncoder, put_variable_byte_int};
+use crate::reason;
+use crate::wire;
+
+/// Control packet type names by type number, 0 is reserved.
+pub const PACKET_TYPES: [&str; 16] = [
+    "Reserved",
+    "CONNECT",
+    "CONNACK",
+    "PUBLISH",
+    "PUBACK",
+    "PUBREC",
+    "PUBREL",
+    "PUBCOMP",
+    "SUBSCRIBE",
+    "SUBACK",
+    "UNSUBSCRIBE",
+    "UNSUBACK",
+    "PINGREQ",
+    "PINGRESP",
+    "DISCONNECT",
+    "AUTH",
+];
+
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+/// How long to wait for more of the broker's reply.
+pub const REPLY_WINDOW: Duration = Duration::from_secs(3);
+
+/// A control packet by its fields, encoded as given.
+#[derive(Debug, Clone, Default)]
+pub struct RawPacket {
+    pub packet_type: u8,
+    /// Low four bits of the fixed header.
+    pub flags: u8,
+    /// Written instead of the actual length when set.
+    pub remaining_length: Option<u32>,
+    pub variable_header: Vec<u8>,
+    /// Encoded property block with its length, left out when `None`.
+    pub properties: Option<Vec<u8>>,
+    pub payload: Vec<u8>,
+}
+
+impl RawPacket {
+    pub fn encode(&self) -> Vec<u8> {
+        let mut body = BytesMut::new();
+        body.extend_from_slice(&self.variable_header);
+        if let Some(properties) = &self.properties {
+            body.extend_from_slice(properties);
+        }
+        body.extend_from_slice(&self.payload);
+        let mut packet = BytesMut::with_capacity(body.len() + 5);
+        packet.put_u8((self.packet_type << 4) | (self.flags & 0x0F));
+        put_variable_byte_int(
+            &mut packet,
+            self.remaining_length.unwrap_or(body.len() as u32),
+        );
+        packet.extend_from_slice(&body);
+        packet.to_vec()
+    }
+}
+
+/// Parse a decimal property value that has to fit in `T`.
+fn parse_number<T: FromStr<Err = ParseIntError>>(value: &str) -> Result<T, String> {
+    value.parse().map_err(|e: ParseIntError| match e.kind() {
+        IntErrorKind::PosOverflow | IntErrorKind::NegOverflow => {
+            format!("value out of range '{value}'")
+        }
+        _ => format!("invalid number '{value}'"),
+    })
+}
+
+/// Parse one property per line as `<id> <kind> <value>`, with the id in
+/// decimal or `0x` hex. Kinds are byte, int2, int4, varint, utf8, binary
+/// (hex) and pair (`key=value`). Ids and repeats are not checked.
+pub fn parse_properties(text: &str) -> Result<Vec<u8>, String> {
+    let mut encoder = PropertyEncoder::new();
+    for (number, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        let error = |message: &str| format!("property line {}: {message}", number + 1);
+        let mut fields = line.splitn(3, char::is_whitespace);
+        let id = fields.next().unwrap_or_default();
+        let id = match id.strip_prefix("0x").or_else(|| id.strip_prefix("0X")) {
+            Some(hex) => u8::from_str_radix(hex, 16),
+            None => id.parse(),
+        }
+        .map_err(|_| error(&format!("invalid property id '{id}'")))?;
+        let kind = fields.next().unwrap_or_default();
+        let value = fields.next().unwrap_or_default().trim();
+        match kind {
+            "byte" => encoder.byte(id, parse_number(value).map_err(|e| error(&e))?),
+            "int2" => encoder.two_byte_int(id, parse_number(value).map_err(|e| error(&e))?),
+            "int4" => encoder.four_byte_int(id, parse_number(value).map_err(|e| error(&e))?),
+            "varint" => encoder.variable_byte_int(id, parse_number(value).map_err(|e| error(&e))?),
+            "utf8" => encoder.utf8(id, value),
+            "binary" => encoder.binary(id, &decode_hex(value).map_err(|e| error(&e))?),
+            "pair" => {
+                let (key, value) = value
+                    .split_once('=')
+                    .ok_or_else(|| error("pair values are written key=value"))?;
+                encoder.utf8_pair(id, key, value)
+            }
+            _ => return Err(error(&format!("unknown kind '{kind}'"))),
+        };
+    }
+    Ok(encoder.finish().to_vec())
+}
+
+/// A valid MQTT v5 CONNECT with Clean Start, sent first so the broker reads
+/// the test packet as part of a session.
+pub fn connect_packet(
+    client_id: &str,
+    keep_alive: u16,
+    credentials: Option<(&str, &str)>,
+) -> Vec<u8> {
+    let put_string = |dest: &mut Vec<u8>, value: &[u8]| {
+        dest.extend_from_slice(&(value.len() as u16).to_be_bytes());
+        dest.extend_from_slice(value);
+    };
+    let mut variable_header = Vec::new();
+    put_string(&mut variable_header, b"MQTT");
+    variable_header.push(5);
+    // Clean Start, plus User Name and Password
+    let flags = if credentials.is_some() { 0xC2 } else { 0x02 };
+    variable_header.push(flags);
+    variable_header.extend_from_slice(&keep_alive.to_be_bytes());
+    let mut payload = Vec::new();
+    put_string(&mut payload, client_id.as_bytes());
+    if let Some((username, password)) = credentials {
+        put_string(&mut payload, username.as_bytes());
+        put_string(&mut payload, password.as_bytes());
+    }
+    RawPacket {
+        packet_type: 1,
+        flags: 0,
+        remaining_length: None,
+        variable_header,
+        properties: Some(PropertyEncoder::new().finish().to_vec()),
+        payload,
+    }
+    .encode()
+}
+
+/// Take the next complete frame from `buffer`, by its remaining length.
+fn next_frame(buffer: &mut BytesMut) -> Option<Vec<u8>> {
+    let mut length = 0usize;
+    for (index, byte) in buffer.iter().skip(1).take(4).enumerate() {
+        length += ((byte & 0x7F) as usize) << (7 * index);
+        if byte & 0x80 == 0 {
+            let total = 2 + index + length;
+            return (buffer.len() >= total).then(|| buffer.split_to(total).to_vec());
+        }
+    }
+    None
+}
+
+/// Packet name and detail for a frame the broker sent, with its bytes.
+pub fn describe(frame: &[u8]) -> String {
+    let hex = payload::to_hex(frame);
+    match wire::decode(frame) {
+        Ok(packet) => {
+            let detail = match &packet {
+                Packet::ConnAck(ack) => {
+                    let code = reason::code(&ack.reason);
+                    format!("0x{code:02X} {}", reason::name(code))
+                }
+                _ => PacketObject::detail_from(&packet),
+            };
+            let name = PacketType::from(&packet).to_string();
+            if detail.is_empty() {
+                format!("{name} [{hex}]")
+            } else {
+                format!("{name} {detail} [{hex}]")
+            }
+        }
+        Err(e) => format!("undecodable, {e} [{hex}]"),
+    }
+}
+
+/// What happened while sending, in order.
+#[derive(Debug, Clone, PartialEq)]
+pub enum RawUpdate {
+    Connected(String),
+    Sent(Vec<u8>),
+    /// One frame from the broker, or the incomplete rest when it closed.
+    Received(Vec<u8>),
+    /// The broker closed the connection, with the socket error if any.
+    Closed(Option<String>),
+    /// Nothing more arrived within `REPLY_WINDOW`.
+    Quiet,
+    Failed(String),
+}
+
+impl RawUpdate {
+    pub fn text(&self) -> String {
+        match self {
+            RawUpdate::Connected(address) => format!("Connected to {address}"),
+            RawUpdate::Sent(bytes) => {
+                format!("→ {} bytes [{}]", bytes.len(), payload::to_hex(bytes))
+            }
+            RawUpdate::Received(frame) => format!("← {}", describe(frame)),
+            RawUpdate::Closed(None) => "Connection closed by the broker".to_string(),
+            RawUpdate::Closed(Some(e)) => format!("Connection closed: {e}"),
+            RawUpdate::Quiet => format!("No reply within {}s", REPLY_WINDOW.as_secs()),
+            RawUpdate::Failed(e) => e.clone(),
+        }
+    }
+}
+
+/// Report frames from `stream` until none arrive for `REPLY_WINDOW`, or
+/// just the first one when `first_only`. Returns false once the broker
+/// closed the connection.
+async fn receive(
+    stream: &mut TcpStream,
+    buffer: &mut BytesMut,
+    first_only: bool,
+    on_update: &mut dyn FnMut(RawUpdate),
+) -> bool {
+    loop {
+        while let Some(frame) = next_frame(buffer) {
+            on_update(RawUpdate::Received(frame));
+            if first_only {
+                return true;
+            }
+        }
+        let closed = match tokio::time::timeout(REPLY_WINDOW, stream.read_buf(buffer)).await {
+            Ok(Ok(0)) => None,
+            Ok(Ok(_)) => continue,
+            Ok(Err(e)) => Some(e.to_string()),
+            Err(_) => {
+                on_update(RawUpdate::Quiet);
+                return true;
+            }
+        };
+        if !buffer.is_empty() {
+            on_update(RawUpdate::Received(buffer.split().to_vec()));
+        }
+        on_update(RawUpdate::Closed(closed));
+        return false;
+    }
+}
+
+/// Connect to `address`, send `connect` and wait for its answer when given,
+/// then send `packet` and report the broker's replies.
+pub async fn send(
+    address: &str,
+    connect: Option<Vec<u8>>,
+    packet: Vec<u8>,
+    on_update: &mut dyn FnMut(RawUpdate),
+) {
+    let mut stream = match tokio::time::timeout(CONNECT_TIMEOUT, TcpStream::connect(address)).await
+    {
+        Ok(Ok(stream)) => stream,
+        Ok(Err(e)) => {
+            on_update(RawUpdate::Failed(format!(
+                "Failed to connect to {address}: {e}"
+            )));
+            return;
+        }
+        Err(_) => {
+            on_update(RawUpdate::Failed(format!(
+                "No answer from {address} within {}s",
+                CONNECT_TIMEOUT.as_secs()
+            )));
+            return;
+        }
+    };
+    let _ = stream.set_nodelay(true);
+    on_update(RawUpdate::Connected(address.to_string()));
+    let mut buffer = BytesMut::new();
+    for (bytes, first_only) in connect
+        .into_iter()
+        .map(|c| (c, true))
+        .chain([(packet, false)])
+    {
+        if let Err(e) = stream.write_all(&bytes).await {
+            on_update(RawUpdate::Failed(format!("Failed to send: {e}")));
+            return;
+        }
+        on_update(RawUpdate::Sent(bytes));
+        if !receive(&mut stream, &mut buffer, first_only, on_update).await {
+            return;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn properties_of_each_kind() {
+        let text = "\
+            0x01 byte 1\n\
+            \n\
+            35 int2 10\n\
+            0x11 int4 300\n\
+            0x0B varint 200\n\
+            0x03 utf8 text/plain\n\
+            0x09 binary 0a 0B\n\
+            0x26 pair k=v\n";
+        let mut expected = vec![
+            0x01, 1, 35, 0, 10, 0x11, 0, 0, 0x01, 0x2C, 0x0B, 0xC8, 0x01, 0x03, 0, 10,
+        ];
+        expected.extend_from_slice(b"text/plain");
+        expected.extend_from_slice(&[0x09, 0, 2, 0x0A, 0x0B, 0x26, 0, 1, b'k', 0, 1, b'v']);
+        expected.insert(0, expected.len() as u8);
+        assert_eq!(parse_properties(text), Ok(expected));
+    }
+
+    #[test]
+    fn no_properties_is_an_empty_block() {
+        assert_eq!(parse_properties(""), Ok(vec![0]));
+        assert_eq!(parse_properties("  \n\n"), Ok(vec![0]));
+    }
+
+    #[test]
+    fn property_errors_name_the_line() {
+        assert_eq!(
+            parse_properties("0x01 byte 1\n0xZZ byte 1"),
+            Err("property line 2: invalid property id '0xZZ'".to_string())
+        );
+        assert_eq!(
+            parse_properties("0x01 word 1"),
+            Err("property line 1: unknown kind 'word'".to_string())
+        );
+        assert_eq!(
+            parse_properties("0x02 int4 soon"),
+            Err("property line 1: invalid number 'soon'".to_string())
+        );
+        assert_eq!(
+            parse_properties("0x01 byte 255\n0x01 byte 256"),
+            Err("property line 2: value out of range '256'".to_string())
+        );
+        assert_eq!(
+            parse_properties("0x21 int2 70000"),
+            Err("property line 1: value out of range '70000'".to_string())
+        );
+        assert_eq!(
+            parse_properties("0x11 int4 4294967296"),
+            Err("property line 1: value out of range '4294967296'".to_string())
+        );
+        assert_eq!(
+            parse_properties("0x01 byte -1"),
+            Err("property line 1: invalid number '-1'".to_string())
+        );
+        assert_eq!(
+            parse_properties("0x26 pair novalue"),
+            Err("property line 1: pair values are written key=value".to_string())
+        );
+        assert!(parse_properties("0x09 binary abc").is_err());
+    }
+}
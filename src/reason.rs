@@ -0,0 +1,92 @@
+//! MQTT v5 reason codes and their names.
+//!
+//! Reason code 0x00 and a few others mean different things depending on the
+//! packet they are carried in, so the names are looked up per packet type.
+
+use vaux_mqtt::Reason;
+
+/// Reason codes a client may send in a DISCONNECT packet.
+pub const CLIENT_DISCONNECT_REASONS: [u8; 14] = [
+    0x00, 0x04, 0x80, 0x81, 0x82, 0x83, 0x90, 0x93, 0x94, 0x95, 0x96, 0x97, 0x98, 0x99,
+];
+
+pub fn code(reason: &Reason) -> u8 {
+    reason.clone() as u8
+}
+
+pub fn from_code(code: u8) -> Option<Reason> {
+    Reason::try_from(code).ok()
+}
+
+/// Name of a reason code carried in a DISCONNECT packet.
+pub fn disconnect_name(code: u8) -> &'static str {
+    match code {
+        0x00 => "Normal disconnection",
+        0x04 => "Disconnect with Will Message",
+        _ => name(code),
+    }
+}
+
+/// Name of a reason code carried in a SUBACK, where 0x00 to 0x02 are the
+/// QoS the broker granted.
+pub fn suback_name(code: u8) -> &'static str {
+    match code {
+        0x00 => "Granted QoS 0",
+        _ => name(code),
+    }
+}
+
+/// Reason codes from 0x80 up are failures in every packet type.
+pub fn is_failure(code: u8) -> bool {
+    code >= 0x80
+}
+
+/// Name of a reason code as used in acknowledgement packets.
+pub fn name(code: u8) -> &'static str {
+    match code {
+        0x00 => "Success",
+        0x01 => "Granted QoS 1",
+        0x02 => "Granted QoS 2",
+        0x04 => "Disconnect with Will Message",
+        0x10 => "No matching subscribers",
+        0x11 => "No subscription existed",
+        0x18 => "Continue authentication",
+        0x19 => "Re-authenticate",
+        0x80 => "Unspecified error",
+        0x81 => "Malformed Packet",
+        0x82 => "Protocol Error",
+        0x83 => "Implementation specific error",
+        0x84 => "Unsupported Protocol Version",
+        0x85 => "Client Identifier not valid",
+        0x86 => "Bad User Name or Password",
+        0x87 => "Not authorized",
+        0x88 => "Server unavailable",
+        0x89 => "Server busy",
+        0x8a => "Banned",
+        0x8b => "Server shutting down",
+        0x8c => "Bad authentication method",
+        0x8d => "Keep Alive timeout",
+        0x8e => "Session taken over",
+        0x8f => "Topic Filter invalid",
+        0x90 => "Topic Name invalid",
+        0x91 => "Packet Identifier in use",
+        0x92 => "Packet Identifier not found",
+        0x93 => "Receive Maximum exceeded",
+        0x94 => "Topic Alias invalid",
+        0x95 => "Packet too large",
+        0x96 => "Message rate too high",
+        0x97 => "Quota exceeded",
+        0x98 => "Administrative action",
+        0x99 => "Payload format invalid",
+        0x9a => "Retain not supported",
+        0x9b => "QoS not supported",
+        0x9c => "Use another server",
+        0x9d => "Server moved",
+        0x9e => "Shared Subscriptions not supported",
+        0x9f => "Connection rate exceeded",
+        0xa0 => "Maximum connect time",
+        0xa1 => "Subscription Identifiers not supported",
+        0xa2 => "Wildcard Subscriptions not supported",
+        _ => "Unknown reason code",
+    }
+}
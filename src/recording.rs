@@ -0,0 +1,88 @@
+//! Append-only session recording. Each packet is written as one JSON line as
+//! soon as it passes through the runner, so a crash loses at most the line
+//! being written. The lines are a superset of the JSON Lines export and can be
+//! loaded for replay.
+//!
+//! The recording is taken in the runner, not at the socket. It holds the
+//! packets the runner sends and those vaux-mqtt hands it, so acknowledgements
+//! and pings vaux sends on its own are missing, and each packet's bytes are
+//! vaux's encoding of the decoded packet rather than what was on the wire.
+
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::export::jsonl::Record;
+use crate::model::packet::Exchange;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct RecordedPacket {
+    /// Microseconds since the recording started, unaffected by clock changes.
+    pub monotonic_us: u64,
+    #[serde(flatten)]
+    pub record: Record,
+}
+
+pub struct Recorder {
+    path: PathBuf,
+    file: File,
+    started: Instant,
+    count: u64,
+}
+
+impl Recorder {
+    /// Open `path` for appending, creating it if needed.
+    pub fn open(path: &Path) -> Result<Self, String> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| format!("{}: {e}", path.display()))?;
+        Ok(Self {
+            path: path.to_path_buf(),
+            file,
+            started: Instant::now(),
+            count: 0,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// Append one packet. The line goes to the file unbuffered in a single
+    /// write so it is on disk even if the app is killed straight after.
+    pub fn record(
+        &mut self,
+        exchange: &Exchange,
+        packet: &vaux_mqtt::Packet,
+    ) -> Result<(), String> {
+        let entry = RecordedPacket {
+            monotonic_us: self.started.elapsed().as_micros() as u64,
+            record: Record::from_packet(exchange, packet, chrono::Local::now().to_rfc3339()),
+        };
+        let mut line = serde_json::to_vec(&entry).map_err(|e| e.to_string())?;
+        line.push(b'\n');
+        self.file
+            .write_all(&line)
+            .map_err(|e| format!("{}: {e}", self.path.display()))?;
+        self.count += 1;
+        Ok(())
+    }
+
+    /// Flush file data to the storage device, called when recording stops.
+    pub fn sync(&self) -> Result<(), String> {
+        self.file
+            .sync_data()
+            .map_err(|e| format!("{}: {e}", self.path.display()))
+    }
+}
@@ -0,0 +1,315 @@
+//! Replay of a recorded JSON Lines capture through the runner commands.
+
+use std::{
+    collections::{HashMap, HashSet},
+    path::Path,
+    time::Duration,
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+
+use crate::client::Command;
+use crate::export::jsonl::Record;
+use crate::wire;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Timing {
+    /// Keep the gaps between packets from the capture.
+    Original,
+    /// Multiply the original gaps, 0.5 replays twice as fast.
+    Scaled(f64),
+    AsFastAsPossible,
+}
+
+#[derive(Debug, Clone)]
+pub struct ReplayOptions {
+    pub timing: Timing,
+    /// Replace a leading topic prefix, (from, to).
+    pub topic_rewrite: Option<(String, String)>,
+    /// Renumber packet IDs from 1 in replay order, keeping repeated IDs
+    /// mapped to the same new ID.
+    pub remap_packet_ids: bool,
+}
+
+/// Packet IDs of the replayed commands.
+struct PacketIds {
+    remap: bool,
+    /// Recorded ID to replayed ID, when remapping.
+    mapped: HashMap<u16, u16>,
+    /// IDs in the capture, which new IDs avoid when not remapping.
+    recorded: HashSet<u16>,
+    next: u16,
+}
+
+impl PacketIds {
+    fn new(remap: bool, recorded: HashSet<u16>) -> Self {
+        Self {
+            remap,
+            mapped: HashMap::new(),
+            recorded,
+            next: 0,
+        }
+    }
+
+    fn replayed(&mut self, id: u16) -> u16 {
+        if !self.remap {
+            return id;
+        }
+        if let Some(&mapped) = self.mapped.get(&id) {
+            return mapped;
+        }
+        let new = self.fresh();
+        self.mapped.insert(id, new);
+        new
+    }
+
+    /// The next ID no other replayed packet uses, wrapping past 65535 to 1.
+    fn fresh(&mut self) -> u16 {
+        loop {
+            self.next = self.next.wrapping_add(1).max(1);
+            if self.remap || !self.recorded.contains(&self.next) {
+                return self.next;
+            }
+        }
+    }
+}
+
+/// A command to send after waiting `delay` from the previous one.
+pub struct ReplayStep {
+    pub delay: Duration,
+    pub description: String,
+    pub command: Command,
+}
+
+/// Read a JSON Lines capture, skipping lines that do not parse.
+pub fn load(path: &Path) -> Result<Vec<Record>, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+    let mut records = Vec::new();
+    for (line_number, line) in text.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+        match serde_json::from_str::<Record>(line) {
+            Ok(record) => records.push(record),
+            Err(e) => eprintln!(
+                "Skipping line {} of {}: {e}",
+                line_number + 1,
+                path.display()
+            ),
+        }
+    }
+    Ok(records)
+}
+
+/// Build the replay steps for the sent packets in the capture. Packets the
+/// runner produces itself (CONNECT, acknowledgements, DISCONNECT) are skipped.
+/// Each filter of a SUBSCRIBE or UNSUBSCRIBE is its own command, the ones
+/// after the first with a new packet ID.
+pub fn plan(records: &[Record], options: &ReplayOptions) -> Vec<ReplayStep> {
+    let mut steps = Vec::new();
+    let sent = records.iter().filter(|r| r.direction == "send");
+    let recorded = sent
+        .clone()
+        .filter_map(|r| u16::try_from(r.packet_id).ok())
+        .filter(|id| *id != 0)
+        .collect();
+    let mut packet_ids = PacketIds::new(options.remap_packet_ids, recorded);
+    let rewrite = |topic: &str| -> String {
+        match &options.topic_rewrite {
+            Some((from, to)) if topic.starts_with(from.as_str()) => {
+                format!("{to}{}", &topic[from.len()..])
+            }
+            _ => topic.to_string(),
+        }
+    };
+
+    let mut previous: Option<chrono::DateTime<chrono::FixedOffset>> = None;
+    for record in sent {
+        let Some(packet) = STANDARD
+            .decode(&record.reencoded)
+            .ok()
+            .and_then(|raw| wire::decode(&raw).ok())
+        else {
+            eprintln!("Skipping undecodable {} packet", record.packet_type);
+            continue;
+        };
+        let timestamp = chrono::DateTime::parse_from_rfc3339(&record.timestamp).ok();
+        let gap = match (previous, timestamp) {
+            (Some(previous), Some(timestamp)) => {
+                (timestamp - previous).to_std().unwrap_or_default()
+            }
+            _ => Duration::ZERO,
+        };
+        let delay = match options.timing {
+            Timing::Original => gap,
+            Timing::Scaled(factor) => gap.mul_f64(factor.max(0.0)),
+            Timing::AsFastAsPossible => Duration::ZERO,
+        };
+
+        let mut commands = Vec::new();
+        match packet {
+            vaux_mqtt::Packet::Publish(mut publish) => {
+                if let Some(topic) = publish.topic_name.as_deref() {
+                    publish.topic_name = Some(rewrite(topic));
+                }
+                if let Some(id) = publish.packet_id() {
+                    publish.set_packet_id(packet_ids.replayed(id));
+                }
+                commands.push((
+                    format!("PUBLISH {}", publish.topic_name.clone().unwrap_or_default()),
+                    Command::Publish(publish),
+                ));
+            }
+            vaux_mqtt::Packet::Subscribe(subscribe) => {
+                let first = packet_ids.replayed(subscribe.packet_id);
+                for (i, subscription) in subscribe.filters.into_iter().enumerate() {
+                    let packet_id = if i == 0 { first } else { packet_ids.fresh() };
+                    let filter = rewrite(&subscription.filter);
+                    commands.push((
+                        format!("SUBSCRIBE {filter}"),
+                        Command::Subscribe(packet_id, subscription.qos, filter),
+                    ));
+                }
+            }
+            vaux_mqtt::Packet::Unsubscribe(unsubscribe) => {
+                let first = packet_ids.replayed(unsubscribe.packet_id);
+                for (i, topic) in unsubscribe.topics.into_iter().enumerate() {
+                    let packet_id = if i == 0 { first } else { packet_ids.fresh() };
+                    let topic = rewrite(&topic);
+                    commands.push((
+                        format!("UNSUBSCRIBE {topic}"),
+                        Command::Unsubscribe(packet_id, topic),
+                    ));
+                }
+            }
+            vaux_mqtt::Packet::PingRequest(_) => {
+                commands.push(("PINGREQ".to_string(), Command::Ping));
+            }
+            _ => continue,
+        }
+        previous = timestamp.or(previous);
+        for (i, (description, command)) in commands.into_iter().enumerate() {
+            steps.push(ReplayStep {
+                delay: if i == 0 { delay } else { Duration::ZERO },
+                description,
+                command,
+            });
+        }
+    }
+    steps
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(timestamp: &str, packet_id: i32, raw: &[u8]) -> Record {
+        Record {
+            timestamp: timestamp.to_string(),
+            direction: "send".to_string(),
+            packet_type: String::new(),
+            packet_id,
+            topic: String::new(),
+            detail: String::new(),
+            payload: None,
+            reencoded: STANDARD.encode(raw),
+        }
+    }
+
+    /// QoS 1 PUBLISH of "x" to "a/b".
+    fn publish(timestamp: &str, packet_id: u8) -> Record {
+        let raw = [0x32, 0x09, 0, 3, b'a', b'/', b'b', 0, packet_id, 0, b'x'];
+        record(timestamp, packet_id as i32, &raw)
+    }
+
+    /// SUBSCRIBE to "a/#" at QoS 1 and "c" at QoS 0.
+    fn subscribe(timestamp: &str, packet_id: u8) -> Record {
+        let raw = [
+            0x82, 0x0D, 0, packet_id, 0, 0, 3, b'a', b'/', b'#', 1, 0, 1, b'c', 0,
+        ];
+        record(timestamp, packet_id as i32, &raw)
+    }
+
+    fn options(remap_packet_ids: bool) -> ReplayOptions {
+        ReplayOptions {
+            timing: Timing::Original,
+            topic_rewrite: None,
+            remap_packet_ids,
+        }
+    }
+
+    fn packet_ids(steps: &[ReplayStep]) -> Vec<u16> {
+        steps
+            .iter()
+            .filter_map(|step| match &step.command {
+                Command::Publish(publish) => publish.packet_id(),
+                Command::Subscribe(packet_id, _, _) | Command::Unsubscribe(packet_id, _) => {
+                    Some(*packet_id)
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn filters_get_their_own_packet_ids() {
+        let records = [
+            subscribe("2024-01-01T00:00:00Z", 1),
+            publish("2024-01-01T00:00:01Z", 2),
+        ];
+        let steps = plan(&records, &options(false));
+        let descriptions: Vec<&str> = steps.iter().map(|s| s.description.as_str()).collect();
+        assert_eq!(
+            descriptions,
+            ["SUBSCRIBE a/#", "SUBSCRIBE c", "PUBLISH a/b"]
+        );
+        // the new ID avoids those in the capture
+        assert_eq!(packet_ids(&steps), [1, 3, 2]);
+    }
+
+    #[test]
+    fn remapped_ids_count_from_one() {
+        let records = [
+            publish("2024-01-01T00:00:00Z", 40),
+            subscribe("2024-01-01T00:00:00Z", 41),
+            publish("2024-01-01T00:00:00Z", 40),
+        ];
+        assert_eq!(packet_ids(&plan(&records, &options(true))), [1, 2, 3, 1]);
+    }
+
+    #[test]
+    fn remapped_ids_wrap_past_zero() {
+        let mut packet_ids = PacketIds::new(true, HashSet::new());
+        packet_ids.next = u16::MAX - 1;
+        assert_eq!(packet_ids.replayed(7), u16::MAX);
+        assert_eq!(packet_ids.replayed(8), 1);
+        assert_eq!(packet_ids.replayed(7), u16::MAX);
+    }
+
+    #[test]
+    fn gaps_follow_the_timing() {
+        let records = [
+            publish("2024-01-01T00:00:00Z", 1),
+            subscribe("2024-01-01T00:00:02Z", 2),
+        ];
+        let mut options = options(false);
+        options.timing = Timing::Scaled(0.5);
+        options.topic_rewrite = Some(("a/".to_string(), "z/".to_string()));
+        let steps = plan(&records, &options);
+        let delays: Vec<Duration> = steps.iter().map(|s| s.delay).collect();
+        assert_eq!(
+            delays,
+            [Duration::ZERO, Duration::from_secs(1), Duration::ZERO]
+        );
+        assert_eq!(steps[1].description, "SUBSCRIBE z/#");
+    }
+
+    #[test]
+    fn received_and_unknown_packets_are_skipped() {
+        let mut received = publish("2024-01-01T00:00:00Z", 1);
+        received.direction = "receive".to_string();
+        let garbled = record("2024-01-01T00:00:00Z", 0, &[0x32, 0x7F]);
+        let disconnect = record("2024-01-01T00:00:00Z", 0, &[0xE0, 0x00]);
+        assert!(plan(&[received, garbled, disconnect], &options(false)).is_empty());
+    }
+}
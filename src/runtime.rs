@@ -0,0 +1,72 @@
+//! The tokio runtime shared by every connection tab.
+//!
+//! Runners hold script engines and other state that cannot move between
+//! threads, so they all run as local tasks on one runtime thread. Scenario
+//! runs and the other tabs' background work share it the same way.
+
+use std::{future::Future, pin::Pin};
+
+use tokio::sync::mpsc::{Receiver, Sender, UnboundedSender};
+
+use crate::client::{self, Event, Request};
+use crate::model::packet::Exchange;
+
+/// Builds a local task on the runtime thread.
+type Task = Box<dyn FnOnce() -> Pin<Box<dyn Future<Output = ()>>> + Send>;
+
+/// Handle for starting local tasks on the shared runtime thread.
+#[derive(Clone)]
+pub struct SharedRuntime {
+    task_tx: UnboundedSender<Task>,
+}
+
+impl SharedRuntime {
+    pub fn start() -> Self {
+        let (task_tx, mut task_rx) = tokio::sync::mpsc::unbounded_channel::<Task>();
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        std::thread::spawn(move || {
+            let local = tokio::task::LocalSet::new();
+            rt.block_on(local.run_until(async move {
+                while let Some(task) = task_rx.recv().await {
+                    tokio::task::spawn_local(task());
+                }
+            }));
+        });
+        Self { task_tx }
+    }
+
+    /// Run the future `task` builds as a local task. `task` is called on the
+    /// runtime thread, so the future may hold state that cannot move there.
+    pub fn spawn_local<F, Fut>(&self, task: F)
+    where
+        F: FnOnce() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + 'static,
+    {
+        if self
+            .task_tx
+            .send(Box::new(move || Box::pin(task())))
+            .is_err()
+        {
+            eprintln!("Runtime thread has stopped, task will not run");
+        }
+    }
+
+    /// Start a runner for one connection. It stops when every command
+    /// sender is dropped or it receives `Command::StopRunner`.
+    pub fn spawn_runner(
+        &self,
+    ) -> (
+        Sender<Request>,
+        Receiver<(Exchange, vaux_mqtt::Packet)>,
+        Receiver<Event>,
+    ) {
+        let (cmd_tx, cmd_rx) = tokio::sync::mpsc::channel(32);
+        let (packet_tx, packet_rx) = tokio::sync::mpsc::channel(32);
+        let (event_tx, event_rx) = tokio::sync::mpsc::channel(8);
+        self.spawn_local(move || client::run(packet_tx, event_tx, cmd_rx));
+        (cmd_tx, packet_rx, event_rx)
+    }
+}
@@ -0,0 +1,352 @@
+//! Test scenarios: a TOML file of steps (connect, subscribe, publish, expect a
+//! matching packet within a timeout, ...) run against a broker by
+//! `runner::execute`.
+//!
+//! ```toml
+//! name = "retained round trip"
+//! profile = "local.json"
+//!
+//! [[step]]
+//! action = "connect"
+//!
+//! [[step]]
+//! action = "subscribe"
+//! filter = "test/#"
+//! qos = 1
+//!
+//! [[step]]
+//! action = "publish"
+//! topic = "test/a"
+//! payload = "hello"
+//!
+//! [[step]]
+//! action = "expect"
+//! packet = "PUBLISH"
+//! topic = "test/*"
+//! payload = "hello"
+//! timeout = 2
+//! ```
+//!
+//! Each `expect` only looks at packets after the one the previous `expect`
+//! matched, so a sequence of expects also asserts their order.
+
+use std::{path::Path, path::PathBuf, time::Duration};
+
+use serde::Deserialize;
+use vaux_mqtt::PacketType;
+
+use crate::model::PacketObject;
+use crate::model::filter::glob_match;
+use crate::model::packet::Exchange;
+use crate::reason;
+
+pub mod runner;
+
+const DEFAULT_TIMEOUT_SECONDS: f64 = 5.0;
+
+fn default_timeout() -> f64 {
+    DEFAULT_TIMEOUT_SECONDS
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Scenario {
+    pub name: String,
+    /// Profile to connect with, relative to the scenario file.
+    #[serde(default)]
+    pub profile: Option<PathBuf>,
+    /// Default timeout in seconds for `connect` and `expect`.
+    #[serde(default = "default_timeout")]
+    pub timeout: f64,
+    #[serde(rename = "step", default)]
+    pub steps: Vec<Step>,
+}
+
+impl Scenario {
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {e}", path.display()))?;
+        let mut scenario: Scenario =
+            toml::from_str(&text).map_err(|e| format!("{}: {e}", path.display()))?;
+        if let (Some(profile), Some(dir)) = (&scenario.profile, path.parent()) {
+            scenario.profile = Some(dir.join(profile));
+        }
+        Ok(scenario)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+#[serde(tag = "action", rename_all = "snake_case")]
+pub enum Step {
+    /// Connect and wait for the CONNACK, by default expecting success.
+    Connect {
+        reason: Option<u8>,
+        timeout: Option<f64>,
+    },
+    Subscribe {
+        filter: String,
+        #[serde(default)]
+        qos: u8,
+        packet_id: Option<u16>,
+    },
+    Unsubscribe {
+        filter: String,
+        packet_id: Option<u16>,
+    },
+    Publish {
+        topic: String,
+        #[serde(default)]
+        payload: String,
+        #[serde(default)]
+        qos: u8,
+        #[serde(default)]
+        retain: bool,
+        packet_id: Option<u16>,
+    },
+    /// Wait for a packet matching all the given fields.
+    Expect {
+        #[serde(flatten)]
+        matcher: PacketMatcher,
+        timeout: Option<f64>,
+    },
+    /// Fail if a matching packet arrives within `within` seconds.
+    ExpectNone {
+        #[serde(flatten)]
+        matcher: PacketMatcher,
+        within: f64,
+    },
+    Sleep {
+        seconds: f64,
+    },
+    Disconnect {
+        #[serde(default)]
+        reason: u8,
+    },
+}
+
+impl Step {
+    pub fn describe(&self) -> String {
+        match self {
+            Step::Connect { .. } => "connect".to_string(),
+            Step::Subscribe { filter, qos, .. } => format!("subscribe {filter} (QoS {qos})"),
+            Step::Unsubscribe { filter, .. } => format!("unsubscribe {filter}"),
+            Step::Publish { topic, qos, .. } => format!("publish {topic} (QoS {qos})"),
+            Step::Expect { matcher, .. } => format!("expect {}", matcher.describe()),
+            Step::ExpectNone { matcher, within } => {
+                format!("expect no {} within {within}s", matcher.describe())
+            }
+            Step::Sleep { seconds } => format!("sleep {seconds}s"),
+            Step::Disconnect { reason } => format!("disconnect 0x{reason:02X}"),
+        }
+    }
+}
+
+/// Fields a packet has to match, unset fields match anything.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(default)]
+pub struct PacketMatcher {
+    /// Packet type name such as "PUBLISH" or "SUBACK", case insensitive.
+    pub packet: Option<String>,
+    /// "receive" (default) or "send".
+    pub direction: Option<String>,
+    /// Topic glob, `*` and `?`.
+    pub topic: Option<String>,
+    pub payload: Option<String>,
+    pub payload_contains: Option<String>,
+    pub packet_id: Option<u16>,
+    /// Reason code of a CONNACK, DISCONNECT or the first code of a SUBACK or
+    /// UNSUBACK.
+    pub reason: Option<u8>,
+}
+
+impl PacketMatcher {
+    pub fn describe(&self) -> String {
+        let mut parts = vec![self.packet.clone().unwrap_or_else(|| "packet".to_string())];
+        if let Some(topic) = &self.topic {
+            parts.push(format!("topic {topic}"));
+        }
+        if let Some(payload) = &self.payload {
+            parts.push(format!("payload '{payload}'"));
+        }
+        if let Some(payload) = &self.payload_contains {
+            parts.push(format!("payload containing '{payload}'"));
+        }
+        if let Some(packet_id) = self.packet_id {
+            parts.push(format!("#{packet_id}"));
+        }
+        if let Some(reason) = self.reason {
+            parts.push(format!("reason 0x{reason:02X}"));
+        }
+        parts.join(" ")
+    }
+
+    pub fn matches(&self, exchange: &Exchange, packet: &vaux_mqtt::Packet) -> bool {
+        let send = matches!(exchange, Exchange::Send);
+        let want_send = self.direction.as_deref() == Some("send");
+        if send != want_send {
+            return false;
+        }
+        if let Some(name) = &self.packet {
+            if !PacketType::from(packet)
+                .to_string()
+                .eq_ignore_ascii_case(name)
+            {
+                return false;
+            }
+        }
+        if let Some(pattern) = &self.topic {
+            match PacketObject::topic_from(packet) {
+                Some(topic) if glob_match(pattern, &topic) => {}
+                _ => return false,
+            }
+        }
+        let payload = PacketObject::payload_from(packet);
+        if self.payload.as_ref().is_some_and(|p| *p != payload) {
+            return false;
+        }
+        if self
+            .payload_contains
+            .as_ref()
+            .is_some_and(|p| !payload.contains(p.as_str()))
+        {
+            return false;
+        }
+        if self
+            .packet_id
+            .is_some_and(|id| PacketObject::packet_id_from(packet) != id as i32)
+        {
+            return false;
+        }
+        if let Some(code) = self.reason {
+            if reason_code(packet) != Some(code) {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+/// Reason code carried by the packet, when it has one.
+pub fn reason_code(packet: &vaux_mqtt::Packet) -> Option<u8> {
+    match packet {
+        vaux_mqtt::Packet::ConnAck(ack) => Some(reason::code(&ack.reason)),
+        vaux_mqtt::Packet::Disconnect(disconnect) => Some(reason::code(&disconnect.reason)),
+        vaux_mqtt::Packet::SubAck(ack) => ack.reason_codes().first().map(reason::code),
+        vaux_mqtt::Packet::UnsubAck(ack) => ack.reason_code.first().map(reason::code),
+        _ => None,
+    }
+}
+
+pub fn seconds(value: f64) -> Duration {
+    Duration::from_secs_f64(value.max(0.0))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> Result<Scenario, toml::de::Error> {
+        toml::from_str(text)
+    }
+
+    #[test]
+    fn steps_parse_with_defaults() {
+        let scenario = parse(
+            r#"
+            name = "round trip"
+
+            [[step]]
+            action = "connect"
+
+            [[step]]
+            action = "subscribe"
+            filter = "test/#"
+
+            [[step]]
+            action = "publish"
+            topic = "test/a"
+            qos = 1
+            retain = true
+            "#,
+        )
+        .unwrap();
+        assert_eq!(scenario.name, "round trip");
+        assert_eq!(scenario.profile, None);
+        assert_eq!(scenario.timeout, DEFAULT_TIMEOUT_SECONDS);
+        assert!(matches!(
+            scenario.steps[0],
+            Step::Connect {
+                reason: None,
+                timeout: None
+            }
+        ));
+        assert!(matches!(
+            &scenario.steps[1],
+            Step::Subscribe { filter, qos: 0, packet_id: None } if filter == "test/#"
+        ));
+        assert!(matches!(
+            &scenario.steps[2],
+            Step::Publish { topic, payload, qos: 1, retain: true, packet_id: None }
+                if topic == "test/a" && payload.is_empty()
+        ));
+    }
+
+    #[test]
+    fn expect_steps_take_matcher_fields() {
+        let scenario = parse(
+            r#"
+            name = "expects"
+            timeout = 1.5
+
+            [[step]]
+            action = "expect"
+            packet = "SUBACK"
+            reason = 0x80
+            timeout = 2
+
+            [[step]]
+            action = "expect_none"
+            packet = "PUBLISH"
+            direction = "send"
+            topic = "a/*"
+            within = 0.5
+            "#,
+        )
+        .unwrap();
+        assert_eq!(scenario.timeout, 1.5);
+        let Step::Expect { matcher, timeout } = &scenario.steps[0] else {
+            panic!("expected an expect step, got {:?}", scenario.steps[0]);
+        };
+        assert_eq!(matcher.packet.as_deref(), Some("SUBACK"));
+        assert_eq!(matcher.reason, Some(0x80));
+        assert_eq!(*timeout, Some(2.0));
+        let Step::ExpectNone { matcher, within } = &scenario.steps[1] else {
+            panic!("expected an expect_none step, got {:?}", scenario.steps[1]);
+        };
+        assert_eq!(matcher.direction.as_deref(), Some("send"));
+        assert_eq!(matcher.topic.as_deref(), Some("a/*"));
+        assert_eq!(*within, 0.5);
+        assert_eq!(
+            scenario.steps[1].describe(),
+            "expect no PUBLISH topic a/* within 0.5s"
+        );
+    }
+
+    #[test]
+    fn invalid_steps_are_rejected() {
+        let unknown = "name = \"x\"\n[[step]]\naction = \"teleport\"\n";
+        assert!(parse(unknown).is_err());
+        let missing = "name = \"x\"\n[[step]]\naction = \"subscribe\"\n";
+        assert!(parse(missing).is_err());
+        assert!(parse("[[step]]\naction = \"connect\"\n").is_err());
+    }
+
+    #[test]
+    fn profile_is_relative_to_the_scenario() {
+        let dir = std::env::temp_dir().join(format!("vaux-scenario-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let path = dir.join("scenario.toml");
+        std::fs::write(&path, "name = \"x\"\nprofile = \"local.json\"\n").unwrap();
+        let scenario = Scenario::load(&path);
+        let _ = std::fs::remove_dir_all(&dir);
+        assert_eq!(scenario.unwrap().profile, Some(dir.join("local.json")));
+    }
+}
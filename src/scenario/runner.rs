@@ -0,0 +1,259 @@
+//! Executes a scenario with its own `client::run` instance.
+
+use std::{
+    collections::VecDeque,
+    time::{Duration, Instant},
+};
+
+use tokio::sync::mpsc::{Receiver, Sender};
+use vaux_mqtt::QoSLevel;
+
+use super::{PacketMatcher, Scenario, Step, reason_code, seconds};
+use crate::client::{self, ClientSetting, Command, DisconnectOptions, Request};
+use crate::model::packet::Exchange;
+use crate::reason;
+
+#[derive(Debug, Clone)]
+pub struct StepResult {
+    pub index: usize,
+    pub description: String,
+    pub passed: bool,
+    pub detail: String,
+    pub elapsed: Duration,
+}
+
+struct Session<'a> {
+    cmd_tx: Sender<Request>,
+    packet_rx: Receiver<(Exchange, vaux_mqtt::Packet)>,
+    /// Packets not yet consumed by an `expect`, oldest first.
+    buffer: VecDeque<(Exchange, vaux_mqtt::Packet)>,
+    on_packet: &'a mut dyn FnMut(&Exchange, &vaux_mqtt::Packet),
+    next_packet_id: u16,
+    connected: bool,
+}
+
+impl Session<'_> {
+    async fn send(&self, command: Command) -> Result<(), String> {
+        self.cmd_tx
+            .send(command.into())
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    fn packet_id(&mut self, packet_id: Option<u16>) -> u16 {
+        packet_id.unwrap_or_else(|| {
+            self.next_packet_id = self.next_packet_id.wrapping_add(1).max(1);
+            self.next_packet_id
+        })
+    }
+
+    /// Buffer the next packet, false once the deadline passes or the runner stops.
+    async fn receive(&mut self, deadline: Instant) -> bool {
+        let timeout = deadline.saturating_duration_since(Instant::now());
+        match tokio::time::timeout(timeout, self.packet_rx.recv()).await {
+            Ok(Some((exchange, packet))) => {
+                (self.on_packet)(&exchange, &packet);
+                self.buffer.push_back((exchange, packet));
+                true
+            }
+            _ => false,
+        }
+    }
+
+    /// Consume packets up to and including the first match.
+    async fn expect(
+        &mut self,
+        matcher: &PacketMatcher,
+        timeout: Duration,
+    ) -> Result<vaux_mqtt::Packet, String> {
+        let deadline = Instant::now() + timeout;
+        loop {
+            if let Some(position) = self
+                .buffer
+                .iter()
+                .position(|(exchange, packet)| matcher.matches(exchange, packet))
+            {
+                let (_, packet) = self
+                    .buffer
+                    .drain(..=position)
+                    .last()
+                    .expect("matched packet");
+                return Ok(packet);
+            }
+            if !self.receive(deadline).await {
+                return Err(format!(
+                    "no {} within {:.1}s",
+                    matcher.describe(),
+                    timeout.as_secs_f64()
+                ));
+            }
+        }
+    }
+
+    async fn run_step(
+        &mut self,
+        step: &Step,
+        setting: &ClientSetting,
+        default_timeout: Duration,
+    ) -> Result<String, String> {
+        match step {
+            Step::Connect { reason, timeout } => {
+                self.send(setting.start_client()?).await?;
+                let matcher = PacketMatcher {
+                    packet: Some("CONNACK".to_string()),
+                    ..Default::default()
+                };
+                let timeout = timeout.map(seconds).unwrap_or(default_timeout);
+                let packet = self.expect(&matcher, timeout).await?;
+                let code = reason_code(&packet).unwrap_or_default();
+                let ok = match reason {
+                    Some(expected) => code == *expected,
+                    None => code < 0x80,
+                };
+                self.connected = code < 0x80;
+                let text = format!("CONNACK 0x{code:02X} {}", reason::name(code));
+                if ok { Ok(text) } else { Err(text) }
+            }
+            Step::Subscribe {
+                filter,
+                qos,
+                packet_id,
+            } => {
+                let packet_id = self.packet_id(*packet_id);
+                self.send(Command::Subscribe(
+                    packet_id,
+                    qos_level(*qos)?,
+                    filter.clone(),
+                ))
+                .await?;
+                Ok(format!("packet ID {packet_id}"))
+            }
+            Step::Unsubscribe { filter, packet_id } => {
+                let packet_id = self.packet_id(*packet_id);
+                self.send(Command::Unsubscribe(packet_id, filter.clone()))
+                    .await?;
+                Ok(format!("packet ID {packet_id}"))
+            }
+            Step::Publish {
+                topic,
+                payload,
+                qos,
+                retain,
+                packet_id,
+            } => {
+                let packet_id = self.packet_id(*packet_id);
+                let publish = vaux_mqtt::publish::Publish::new_with_message(
+                    packet_id,
+                    topic,
+                    qos_level(*qos)?,
+                    payload.as_str(),
+                )
+                .map(|p| p.with_retain(*retain))
+                .map_err(|e| e.to_string())?;
+                self.send(Command::Publish(publish)).await?;
+                Ok(format!("packet ID {packet_id}"))
+            }
+            Step::Expect { matcher, timeout } => {
+                let timeout = timeout.map(seconds).unwrap_or(default_timeout);
+                let packet = self.expect(matcher, timeout).await?;
+                Ok(format!("{packet:?}"))
+            }
+            Step::ExpectNone { matcher, within } => {
+                let deadline = Instant::now() + seconds(*within);
+                // packets buffered before this step are not part of its window
+                let start = self.buffer.len();
+                loop {
+                    if let Some((_, packet)) = self
+                        .buffer
+                        .iter()
+                        .skip(start)
+                        .find(|(exchange, packet)| matcher.matches(exchange, packet))
+                    {
+                        return Err(format!("unexpected {packet:?}"));
+                    }
+                    if !self.receive(deadline).await {
+                        return Ok("none received".to_string());
+                    }
+                }
+            }
+            Step::Sleep { seconds: s } => {
+                let deadline = Instant::now() + seconds(*s);
+                while self.receive(deadline).await {}
+                Ok(String::new())
+            }
+            Step::Disconnect { reason } => {
+                self.send(Command::Disconnect(DisconnectOptions {
+                    reason_code: *reason,
+                    ..Default::default()
+                }))
+                .await?;
+                self.connected = false;
+                Ok(String::new())
+            }
+        }
+    }
+}
+
+fn qos_level(qos: u8) -> Result<QoSLevel, String> {
+    QoSLevel::try_from(qos).map_err(|_| format!("invalid QoS {qos}"))
+}
+
+/// Run the scenario on the current tokio runtime, stopping at the first
+/// failed step. `on_result` is called as each step finishes and `on_packet`
+/// for every packet sent or received.
+pub async fn execute(
+    scenario: &Scenario,
+    setting: &ClientSetting,
+    on_result: &mut dyn FnMut(&StepResult),
+    on_packet: &mut dyn FnMut(&Exchange, &vaux_mqtt::Packet),
+) -> Vec<StepResult> {
+    let (cmd_tx, cmd_rx) = tokio::sync::mpsc::channel(32);
+    let (packet_tx, packet_rx) = tokio::sync::mpsc::channel(32);
+    // session snapshots are not used by scenarios
+    let (event_tx, _) = tokio::sync::mpsc::channel(1);
+
+    let driver = async {
+        let mut session = Session {
+            cmd_tx,
+            packet_rx,
+            buffer: VecDeque::new(),
+            on_packet,
+            next_packet_id: 0,
+            connected: false,
+        };
+        let default_timeout = seconds(scenario.timeout);
+        let mut results = Vec::new();
+        for (index, step) in scenario.steps.iter().enumerate() {
+            let started = Instant::now();
+            let outcome = session.run_step(step, setting, default_timeout).await;
+            let result = StepResult {
+                index: index + 1,
+                description: step.describe(),
+                passed: outcome.is_ok(),
+                detail: outcome.unwrap_or_else(|e| e),
+                elapsed: started.elapsed(),
+            };
+            on_result(&result);
+            let passed = result.passed;
+            results.push(result);
+            if !passed {
+                break;
+            }
+        }
+
+        if session.connected {
+            let _ = session
+                .send(Command::Disconnect(DisconnectOptions::default()))
+                .await;
+        }
+        let _ = session.send(Command::StopRunner).await;
+        // keep receiving until the runner exits so its last sends succeed
+        while let Some((exchange, packet)) = session.packet_rx.recv().await {
+            (session.on_packet)(&exchange, &packet);
+        }
+        results
+    };
+
+    let (_, results) = tokio::join!(client::run(packet_tx, event_tx, cmd_rx), driver);
+    results
+}
@@ -0,0 +1,227 @@
+//! Rhai scripting hooks run by the client runner for each received packet.
+//!
+//! A script may define any of these functions:
+//!
+//! ```rhai
+//! fn on_publish(topic, payload) { }      // return a string to replace the displayed payload
+//! fn on_connack(reason, session_present) { }
+//! fn on_disconnect(reason) { }
+//! ```
+//!
+//! and call `publish(topic, payload)`, `publish(topic, payload, qos)`,
+//! `alert(message)` and `tag(text)` from them. Top level statements run once
+//! when the script is loaded.
+
+use std::{cell::RefCell, rc::Rc};
+
+use rhai::{AST, Dynamic, Engine, Scope};
+
+use crate::reason;
+
+/// Stops runaway scripts, a hook gives up after this many operations.
+const MAX_OPERATIONS: u64 = 1_000_000;
+
+/// What the hooks asked for while handling one packet.
+#[derive(Debug, Default)]
+pub struct ScriptActions {
+    /// (topic, payload, QoS)
+    pub publish: Vec<(String, String, u8)>,
+    pub alerts: Vec<String>,
+    pub tags: Vec<String>,
+    /// Payload text to show in the log instead of the received payload.
+    pub display: Option<String>,
+}
+
+pub struct ScriptHost {
+    engine: Engine,
+    ast: AST,
+    scope: Scope<'static>,
+    actions: Rc<RefCell<ScriptActions>>,
+}
+
+impl ScriptHost {
+    /// Compile the script and run its top level statements.
+    pub fn load(source: &str) -> Result<Self, String> {
+        let actions = Rc::new(RefCell::new(ScriptActions::default()));
+        let mut engine = Engine::new();
+        engine.set_max_operations(MAX_OPERATIONS);
+        engine.on_print(|text| eprintln!("[script] {text}"));
+
+        let a = actions.clone();
+        engine.register_fn("publish", move |topic: &str, payload: &str| {
+            a.borrow_mut()
+                .publish
+                .push((topic.to_string(), payload.to_string(), 0));
+        });
+        let a = actions.clone();
+        engine.register_fn("publish", move |topic: &str, payload: &str, qos: i64| {
+            a.borrow_mut().publish.push((
+                topic.to_string(),
+                payload.to_string(),
+                qos.clamp(0, 2) as u8,
+            ));
+        });
+        let a = actions.clone();
+        engine.register_fn("alert", move |message: &str| {
+            a.borrow_mut().alerts.push(message.to_string());
+        });
+        let a = actions.clone();
+        engine.register_fn("tag", move |text: &str| {
+            a.borrow_mut().tags.push(text.to_string());
+        });
+
+        let ast = engine.compile(source).map_err(|e| e.to_string())?;
+        let mut scope = Scope::new();
+        engine
+            .run_ast_with_scope(&mut scope, &ast)
+            .map_err(|e| e.to_string())?;
+        // actions from the top level statements are dropped, there is no packet yet
+        actions.take();
+        Ok(Self {
+            engine,
+            ast,
+            scope,
+            actions,
+        })
+    }
+
+    fn has_fn(&self, name: &str, params: usize) -> bool {
+        self.ast
+            .iter_functions()
+            .any(|f| f.name == name && f.params.len() == params)
+    }
+
+    fn call(&mut self, name: &str, args: impl rhai::FuncArgs) -> Result<Dynamic, String> {
+        self.engine
+            .call_fn::<Dynamic>(&mut self.scope, &self.ast, name, args)
+            .map_err(|e| format!("{name}: {e}"))
+    }
+
+    /// Run the hook for a received packet, if the script defines one.
+    pub fn on_packet(&mut self, packet: &vaux_mqtt::Packet) -> Result<ScriptActions, String> {
+        // discard anything left by a hook that failed part way
+        self.actions.take();
+        match packet {
+            vaux_mqtt::Packet::Publish(publish) if self.has_fn("on_publish", 2) => {
+                let topic = publish.topic_name.clone().unwrap_or_default();
+                let payload = publish
+                    .payload
+                    .as_deref()
+                    .map(|p| String::from_utf8_lossy(p).to_string())
+                    .unwrap_or_default();
+                let result = self.call("on_publish", (topic, payload))?;
+                if result.is_string() {
+                    self.actions.borrow_mut().display = result.into_string().ok();
+                }
+            }
+            vaux_mqtt::Packet::ConnAck(ack) if self.has_fn("on_connack", 2) => {
+                self.call(
+                    "on_connack",
+                    (reason::code(&ack.reason) as i64, ack.session_present),
+                )?;
+            }
+            vaux_mqtt::Packet::Disconnect(disconnect) if self.has_fn("on_disconnect", 1) => {
+                self.call("on_disconnect", (reason::code(&disconnect.reason) as i64,))?;
+            }
+            _ => {}
+        }
+        Ok(self.actions.take())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use vaux_mqtt::{QoSLevel, publish::Publish};
+
+    fn publish(topic: &str, payload: &str) -> vaux_mqtt::Packet {
+        vaux_mqtt::Packet::Publish(
+            Publish::new_with_message(0, topic, QoSLevel::AtMostOnce, payload).unwrap(),
+        )
+    }
+
+    /// CONNACK with Session Present and reason Success.
+    fn connack() -> vaux_mqtt::Packet {
+        crate::wire::decode(&[0x20, 0x03, 0x01, 0x00, 0x00]).unwrap()
+    }
+
+    #[test]
+    fn returned_string_replaces_the_payload() {
+        let mut host =
+            ScriptHost::load(r#"fn on_publish(topic, payload) { payload + "!" }"#).unwrap();
+        let actions = host.on_packet(&publish("t/a", "hi")).unwrap();
+        assert_eq!(actions.display.as_deref(), Some("hi!"));
+
+        let mut host = ScriptHost::load("fn on_publish(topic, payload) { 42 }").unwrap();
+        assert_eq!(host.on_packet(&publish("t/a", "hi")).unwrap().display, None);
+    }
+
+    #[test]
+    fn hook_calls_are_collected() {
+        let mut host = ScriptHost::load(
+            r#"
+            fn on_publish(topic, payload) {
+                publish("reply/" + topic, payload);
+                publish("audit", payload, 1);
+                alert("got " + topic);
+                tag("seen");
+            }
+            "#,
+        )
+        .unwrap();
+        let actions = host.on_packet(&publish("t/a", "hi")).unwrap();
+        assert_eq!(
+            actions.publish,
+            vec![
+                ("reply/t/a".to_string(), "hi".to_string(), 0),
+                ("audit".to_string(), "hi".to_string(), 1),
+            ]
+        );
+        assert_eq!(actions.alerts, vec!["got t/a".to_string()]);
+        assert_eq!(actions.tags, vec!["seen".to_string()]);
+        // each packet starts with no actions
+        assert_eq!(host.on_packet(&connack()).unwrap().publish, vec![]);
+    }
+
+    #[test]
+    fn publish_qos_is_clamped() {
+        let mut host = ScriptHost::load(
+            r#"fn on_publish(topic, payload) { publish("high", "", 7); publish("low", "", -1); }"#,
+        )
+        .unwrap();
+        let qos: Vec<u8> = host
+            .on_packet(&publish("t", ""))
+            .unwrap()
+            .publish
+            .into_iter()
+            .map(|(_, _, qos)| qos)
+            .collect();
+        assert_eq!(qos, vec![2, 0]);
+    }
+
+    #[test]
+    fn runaway_scripts_are_stopped() {
+        let mut host = ScriptHost::load("fn on_publish(topic, payload) { loop {} }").unwrap();
+        assert!(host.on_packet(&publish("t", "")).is_err());
+        assert!(ScriptHost::load("loop {}").is_err());
+    }
+
+    #[test]
+    fn top_level_actions_are_dropped() {
+        let mut host = ScriptHost::load(
+            r#"
+            publish("boot", "x");
+            alert("loaded");
+            fn on_connack(reason, session_present) {
+                if session_present { tag("resumed " + reason); }
+            }
+            "#,
+        )
+        .unwrap();
+        let actions = host.on_packet(&connack()).unwrap();
+        assert!(actions.publish.is_empty());
+        assert!(actions.alerts.is_empty());
+        assert_eq!(actions.tags, vec!["resumed 0".to_string()]);
+    }
+}
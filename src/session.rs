@@ -0,0 +1,371 @@
+//! Serializable snapshot of the vaux-client session state.
+//!
+//! The runner keeps the `SessionState` returned when a client stops so the
+//! next connect can resume it. A snapshot is what the session panel shows and
+//! what is written to disk to resume a session after the app restarts.
+
+use std::{collections::HashMap, path::Path, time::Duration};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use serde::{Deserialize, Serialize};
+use vaux_client::session::SessionState;
+use vaux_mqtt::{Packet, PacketType, QoSLevel, subscribe::Subscription};
+
+use crate::model::packet::Exchange;
+use crate::reason;
+use crate::wire;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingPacket {
+    pub packet_id: u16,
+    pub packet_type: String,
+    pub topic: Option<String>,
+    /// Base64 of the encoded packet.
+    pub raw: String,
+}
+
+impl PendingPacket {
+    fn new(packet_id: u16, packet: &Packet) -> Result<Self, String> {
+        Ok(Self {
+            packet_id,
+            packet_type: PacketType::from(packet).to_string(),
+            topic: match packet {
+                Packet::Publish(publish) => publish.topic_name.clone(),
+                _ => None,
+            },
+            raw: STANDARD
+                .encode(wire::encode(packet).map_err(|e| format!("packet {packet_id}: {e}"))?),
+        })
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SubscriptionSnapshot {
+    pub filter: String,
+    pub qos: u8,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SessionSnapshot {
+    pub client_id: String,
+    pub session_expiry: u32,
+    /// Unix time in seconds when the client disconnected; the broker discards
+    /// the session `session_expiry` seconds later.
+    pub ended_at: i64,
+    pub unacked_outgoing: Vec<PendingPacket>,
+    pub pending_incoming_qos2: Vec<u16>,
+    pub subscriptions: Vec<SubscriptionSnapshot>,
+}
+
+impl SessionSnapshot {
+    /// A session with nothing in flight and no subscriptions.
+    pub fn new(client_id: &str, session_expiry: u32) -> Self {
+        Self {
+            client_id: client_id.to_string(),
+            session_expiry,
+            ended_at: 0,
+            unacked_outgoing: Vec::new(),
+            pending_incoming_qos2: Vec::new(),
+            subscriptions: Vec::new(),
+        }
+    }
+
+    pub fn from_state(state: &SessionState) -> Result<Self, String> {
+        let mut unacked_outgoing = state
+            .unacked
+            .iter()
+            .map(|(packet_id, packet)| PendingPacket::new(*packet_id, packet))
+            .collect::<Result<Vec<_>, _>>()?;
+        unacked_outgoing.sort_by_key(|p| p.packet_id);
+        let mut pending_incoming_qos2: Vec<u16> = state.qos2_incoming.iter().copied().collect();
+        pending_incoming_qos2.sort();
+        Ok(Self {
+            client_id: state.client_id.clone(),
+            session_expiry: state.session_expiry.as_secs() as u32,
+            ended_at: chrono::Utc::now().timestamp(),
+            unacked_outgoing,
+            pending_incoming_qos2,
+            subscriptions: state
+                .subscriptions
+                .iter()
+                .map(|s| SubscriptionSnapshot {
+                    filter: s.filter.clone(),
+                    qos: s.qos as u8,
+                })
+                .collect(),
+        })
+    }
+
+    /// Rebuild the session state so it can be resumed.
+    pub fn to_state(&self) -> Result<SessionState, String> {
+        let mut state = SessionState::new(
+            &self.client_id,
+            Duration::from_secs(self.session_expiry as u64),
+        );
+        let mut unacked = HashMap::new();
+        for pending in &self.unacked_outgoing {
+            let raw = STANDARD
+                .decode(&pending.raw)
+                .map_err(|e| format!("packet {}: {e}", pending.packet_id))?;
+            let packet =
+                wire::decode(&raw).map_err(|e| format!("packet {}: {e}", pending.packet_id))?;
+            unacked.insert(pending.packet_id, packet);
+        }
+        state.unacked = unacked;
+        state.qos2_incoming = self.pending_incoming_qos2.iter().copied().collect();
+        state.subscriptions = self
+            .subscriptions
+            .iter()
+            .map(|s| {
+                let qos = QoSLevel::try_from(s.qos).unwrap_or(QoSLevel::AtMostOnce);
+                Subscription::new(&s.filter, qos)
+            })
+            .collect();
+        Ok(state)
+    }
+
+    /// Unix time in seconds when the broker discards the session.
+    pub fn expires_at(&self) -> i64 {
+        self.ended_at + self.session_expiry as i64
+    }
+
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let json = serde_json::to_string_pretty(self).map_err(|e| e.to_string())?;
+        std::fs::write(path, json).map_err(|e| e.to_string())
+    }
+
+    pub fn load(path: &Path) -> Result<Self, String> {
+        let json = std::fs::read_to_string(path).map_err(|e| e.to_string())?;
+        serde_json::from_str(&json).map_err(|e| e.to_string())
+    }
+}
+
+/// Session state followed from the packets the runner exchanges, for when
+/// the session task cannot hand back its own. An abrupt drop aborts the
+/// task, so this is what the next connect resumes. vaux acknowledges
+/// incoming QoS 2 messages on its own, so those are not followed.
+pub struct SessionTracker {
+    snapshot: SessionSnapshot,
+    /// Filter and QoS of each SUBSCRIBE waiting for its SUBACK.
+    subscribing: HashMap<u16, (String, u8)>,
+    /// Filter of each UNSUBSCRIBE waiting for its UNSUBACK.
+    unsubscribing: HashMap<u16, String>,
+}
+
+impl SessionTracker {
+    pub fn new(snapshot: SessionSnapshot) -> Self {
+        Self {
+            snapshot,
+            subscribing: HashMap::new(),
+            unsubscribing: HashMap::new(),
+        }
+    }
+
+    /// A subscription joins the session once the broker grants it.
+    pub fn subscribing(&mut self, packet_id: u16, filter: &str, qos: u8) {
+        self.subscribing
+            .insert(packet_id, (filter.to_string(), qos));
+    }
+
+    pub fn unsubscribing(&mut self, packet_id: u16, filter: &str) {
+        self.unsubscribing.insert(packet_id, filter.to_string());
+    }
+
+    /// Whether `packet_id` is held by a publish, subscribe or unsubscribe the
+    /// broker has not finished acknowledging.
+    pub fn in_flight(&self, packet_id: u16) -> bool {
+        self.snapshot
+            .unacked_outgoing
+            .iter()
+            .any(|p| p.packet_id == packet_id)
+            || self.subscribing.contains_key(&packet_id)
+            || self.unsubscribing.contains_key(&packet_id)
+    }
+
+    pub fn exchanged(&mut self, exchange: &Exchange, packet: &Packet) {
+        let unacked = &mut self.snapshot.unacked_outgoing;
+        match (exchange, packet) {
+            (Exchange::Send, Packet::Publish(publish)) => {
+                let Some(packet_id) = publish.packet_id() else {
+                    return;
+                };
+                match PendingPacket::new(packet_id, packet) {
+                    Ok(pending) => {
+                        unacked.retain(|p| p.packet_id != packet_id);
+                        unacked.push(pending);
+                    }
+                    Err(e) => eprintln!("Failed to follow publish {packet_id}: {e}"),
+                }
+            }
+            (Exchange::Send, Packet::Disconnect(disconnect)) => {
+                if let Some(expiry) = disconnect.session_expiry {
+                    self.snapshot.session_expiry = expiry;
+                }
+            }
+            (Exchange::Receive, Packet::ConnAck(ack)) => {
+                if let Some(client_id) = &ack.assigned_client_id {
+                    self.snapshot.client_id = client_id.clone();
+                }
+            }
+            (Exchange::Receive, Packet::PubAck(ack)) => {
+                unacked.retain(|p| p.packet_id != ack.packet_id);
+            }
+            (Exchange::Receive, Packet::PubComp(comp)) => {
+                unacked.retain(|p| p.packet_id != comp.packet_id);
+            }
+            (Exchange::Receive, Packet::SubAck(ack)) => {
+                let Some((filter, _)) = self.subscribing.remove(&ack.packet_id()) else {
+                    return;
+                };
+                let granted = wire::ack_reasons(packet)
+                    .and_then(|reasons| reasons.codes.first().copied())
+                    .filter(|code| !reason::is_failure(*code));
+                if let Some(qos) = granted {
+                    let subscriptions = &mut self.snapshot.subscriptions;
+                    subscriptions.retain(|s| s.filter != filter);
+                    subscriptions.push(SubscriptionSnapshot { filter, qos });
+                }
+            }
+            (Exchange::Receive, Packet::UnsubAck(ack)) => {
+                if let Some(filter) = self.unsubscribing.remove(&ack.packet_id) {
+                    self.snapshot.subscriptions.retain(|s| s.filter != filter);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// The followed session as it stands now that the client has stopped.
+    pub fn finish(self) -> Result<(SessionSnapshot, SessionState), String> {
+        let mut snapshot = self.snapshot;
+        snapshot.unacked_outgoing.sort_by_key(|p| p.packet_id);
+        snapshot.ended_at = chrono::Utc::now().timestamp();
+        let state = snapshot.to_state()?;
+        Ok((snapshot, state))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn publish(packet_id: u16, topic: &str) -> Packet {
+        Packet::Publish(
+            vaux_mqtt::publish::Publish::new_with_message(
+                packet_id,
+                topic,
+                QoSLevel::AtLeastOnce,
+                "x",
+            )
+            .unwrap(),
+        )
+    }
+
+    fn snapshot() -> SessionSnapshot {
+        let mut snapshot = SessionSnapshot::new("resumer", 300);
+        snapshot.unacked_outgoing = vec![
+            PendingPacket::new(3, &publish(3, "a/b")).unwrap(),
+            PendingPacket::new(9, &publish(9, "c")).unwrap(),
+        ];
+        snapshot.pending_incoming_qos2 = vec![4, 7];
+        snapshot.subscriptions = vec![SubscriptionSnapshot {
+            filter: "a/#".to_string(),
+            qos: 1,
+        }];
+        snapshot
+    }
+
+    fn unacked(snapshot: &SessionSnapshot) -> Vec<(u16, Option<String>)> {
+        snapshot
+            .unacked_outgoing
+            .iter()
+            .map(|p| (p.packet_id, p.topic.clone()))
+            .collect()
+    }
+
+    #[test]
+    fn snapshot_survives_the_session_state() {
+        let snapshot = snapshot();
+        let state = snapshot.to_state().unwrap();
+        assert_eq!(state.client_id, "resumer");
+        assert_eq!(state.session_expiry, Duration::from_secs(300));
+        assert_eq!(state.unacked.len(), 2);
+        let back = SessionSnapshot::from_state(&state).unwrap();
+        assert_eq!(back.client_id, snapshot.client_id);
+        assert_eq!(back.session_expiry, snapshot.session_expiry);
+        assert_eq!(unacked(&back), unacked(&snapshot));
+        assert_eq!(
+            back.unacked_outgoing[0].raw,
+            snapshot.unacked_outgoing[0].raw
+        );
+        assert_eq!(back.pending_incoming_qos2, vec![4, 7]);
+        assert_eq!(back.subscriptions.len(), 1);
+        assert_eq!(back.subscriptions[0].filter, "a/#");
+        assert_eq!(back.subscriptions[0].qos, 1);
+    }
+
+    #[test]
+    fn snapshot_survives_a_file() {
+        let path = std::env::temp_dir().join(format!("vaux-session-{}.json", std::process::id()));
+        let snapshot = snapshot();
+        snapshot.save(&path).unwrap();
+        let loaded = SessionSnapshot::load(&path);
+        let _ = std::fs::remove_file(&path);
+        let loaded = loaded.unwrap();
+        assert_eq!(unacked(&loaded), unacked(&snapshot));
+        assert_eq!(loaded.pending_incoming_qos2, snapshot.pending_incoming_qos2);
+        assert!(loaded.to_state().is_ok());
+    }
+
+    #[test]
+    fn corrupt_pending_packet_is_refused() {
+        let mut snapshot = snapshot();
+        snapshot.unacked_outgoing[0].raw = "not base64!".to_string();
+        assert!(snapshot.to_state().is_err());
+    }
+
+    #[test]
+    fn tracker_follows_acknowledgements() {
+        let mut tracker = SessionTracker::new(SessionSnapshot::new("tracked", 60));
+        tracker.exchanged(&Exchange::Send, &publish(5, "a/b"));
+        tracker.exchanged(&Exchange::Send, &publish(6, "a/c"));
+        assert!(tracker.in_flight(5));
+        // PUBACK 5
+        let puback = wire::decode(&[0x40, 0x03, 0x00, 0x05, 0x00]).unwrap();
+        tracker.exchanged(&Exchange::Receive, &puback);
+        assert!(!tracker.in_flight(5));
+        assert!(tracker.in_flight(6));
+
+        tracker.subscribing(7, "granted/#", 1);
+        tracker.subscribing(8, "refused/#", 1);
+        tracker.unsubscribing(10, "old");
+        assert!(tracker.in_flight(7) && tracker.in_flight(10));
+        // SUBACK 7 granting QoS 1, SUBACK 8 Not authorized, UNSUBACK 10
+        for raw in [
+            &[0x90, 0x04, 0x00, 0x07, 0x00, 0x01][..],
+            &[0x90, 0x04, 0x00, 0x08, 0x00, 0x87][..],
+            &[0xB0, 0x04, 0x00, 0x0A, 0x00, 0x00][..],
+        ] {
+            tracker.exchanged(&Exchange::Receive, &wire::decode(raw).unwrap());
+        }
+        assert!(!tracker.in_flight(7) && !tracker.in_flight(8) && !tracker.in_flight(10));
+        // CONNACK assigning the client ID "abc"
+        let connack = wire::decode(&[
+            0x20, 0x09, 0x00, 0x00, 0x06, 0x12, 0x00, 0x03, b'a', b'b', b'c',
+        ])
+        .unwrap();
+        tracker.exchanged(&Exchange::Receive, &connack);
+
+        let (snapshot, state) = tracker.finish().unwrap();
+        assert_eq!(snapshot.client_id, "abc");
+        assert_eq!(unacked(&snapshot), vec![(6, Some("a/c".to_string()))]);
+        let filters: Vec<&str> = snapshot
+            .subscriptions
+            .iter()
+            .map(|s| s.filter.as_str())
+            .collect();
+        assert_eq!(filters, vec!["granted/#"]);
+        assert!(snapshot.ended_at > 0);
+        assert_eq!(state.unacked.len(), 1);
+    }
+}
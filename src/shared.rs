@@ -0,0 +1,476 @@
+//! Shared subscription load-balancing test.
+//!
+//! Several members with generated client IDs subscribe to the same
+//! `$share/<group>/<filter>`, each with its own `client::run` instance, and a
+//! publisher sends numbered messages. The deliveries show how the broker
+//! spread them across the group, whether each member saw them in order and
+//! where the messages of a dropped member went.
+
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use tokio::sync::mpsc::{Sender, UnboundedReceiver, UnboundedSender};
+use vaux_mqtt::{Packet, QoSLevel, publish::Publish};
+
+use crate::client::{self, Command, CommandResult, DisconnectOptions, Reply, Request};
+use crate::model::packet::Exchange;
+use crate::profile::Profile;
+use crate::reason;
+
+/// How long to wait for stragglers once every message is published.
+const SETTLE: Duration = Duration::from_secs(2);
+/// Upper bound on the wait for deliveries after the last publish.
+const SETTLE_LIMIT: Duration = Duration::from_secs(10);
+
+#[derive(Debug, Clone)]
+pub struct SharedTest {
+    pub group: String,
+    pub filter: String,
+    /// Topic the publisher sends to, matching `filter`.
+    pub topic: String,
+    pub members: usize,
+    pub messages: u32,
+    pub qos: u8,
+    /// Pause between publishes.
+    pub interval: Duration,
+    /// Drop the first member without DISCONNECT after this many messages
+    /// are published. It never acknowledges what it receives, so the broker
+    /// has its unacknowledged messages to send to the other members.
+    pub drop_after: Option<u32>,
+}
+
+impl SharedTest {
+    pub fn share_filter(&self) -> String {
+        format!("$share/{}/{}", self.group, self.filter)
+    }
+}
+
+/// Deliveries to one member of the group.
+#[derive(Debug, Clone, Default)]
+pub struct MemberStats {
+    pub client_id: String,
+    pub received: u32,
+    /// Messages numbered lower than one this member received before.
+    pub out_of_order: u32,
+    /// Messages this member received more than once.
+    pub duplicates: u32,
+    /// Messages delivered to a dropped member first, then to this one.
+    pub redelivered: u32,
+    pub last: Option<u32>,
+    pub dropped: bool,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Summary {
+    pub published: u32,
+    /// Messages delivered to at least one member.
+    pub delivered: u32,
+    pub missing: Vec<u32>,
+    pub duplicates: u32,
+    pub redelivered: u32,
+}
+
+impl Summary {
+    pub fn text(&self) -> String {
+        let mut text = format!(
+            "{} published, {} delivered, {} missing, {} redelivered, {} duplicates",
+            self.published,
+            self.delivered,
+            self.missing.len(),
+            self.redelivered,
+            self.duplicates
+        );
+        if !self.missing.is_empty() {
+            let first: Vec<String> = self.missing.iter().take(10).map(u32::to_string).collect();
+            text.push_str(&format!(" (missing {}", first.join(", ")));
+            if self.missing.len() > first.len() {
+                text.push_str(", ...");
+            }
+            text.push(')');
+        }
+        text
+    }
+}
+
+pub enum Update {
+    /// A packet sent or received by one of the test connections.
+    Packet(Exchange, Packet),
+    Member(usize, MemberStats),
+    Published(u32),
+    Finished(Summary),
+    Failed(String),
+}
+
+/// Which members each message was delivered to.
+struct Distribution {
+    members: Vec<MemberStats>,
+    deliveries: HashMap<u32, Vec<usize>>,
+}
+
+impl Distribution {
+    fn record(&mut self, member: usize, number: u32) {
+        let seen = self.deliveries.entry(number).or_default();
+        let duplicate = seen.contains(&member);
+        let redelivered = !duplicate && seen.iter().any(|m| self.members[*m].dropped);
+        seen.push(member);
+        let stats = &mut self.members[member];
+        stats.received += 1;
+        if duplicate {
+            stats.duplicates += 1;
+        }
+        if redelivered {
+            stats.redelivered += 1;
+        }
+        if !duplicate && stats.last.is_some_and(|last| number < last) {
+            stats.out_of_order += 1;
+        }
+        stats.last = Some(stats.last.map_or(number, |last| last.max(number)));
+    }
+
+    fn summary(&self, published: u32) -> Summary {
+        Summary {
+            published,
+            delivered: self.deliveries.len() as u32,
+            missing: (1..=published)
+                .filter(|number| !self.deliveries.contains_key(number))
+                .collect(),
+            duplicates: self.members.iter().map(|m| m.duplicates).sum(),
+            redelivered: self.members.iter().map(|m| m.redelivered).sum(),
+        }
+    }
+}
+
+/// Packets of every test connection, tagged with the member index. The
+/// publisher has none.
+type Tagged = (Option<usize>, Exchange, Packet);
+
+/// One test connection running on its own `client::run` instance.
+struct Connection {
+    client_id: String,
+    cmd_tx: Sender<Request>,
+}
+
+impl Connection {
+    /// Start the runner and connect with `profile` under `client_id`.
+    async fn connect(
+        profile: &Profile,
+        client_id: String,
+        hold_acks: bool,
+        index: Option<usize>,
+        forward: &UnboundedSender<Tagged>,
+    ) -> Result<Self, String> {
+        let mut profile = profile.clone();
+        profile.client_id = client_id.clone();
+        if hold_acks {
+            profile.auto_ack = false;
+        }
+        let start = profile.to_setting()?.start_client()?;
+
+        let (cmd_tx, cmd_rx) = tokio::sync::mpsc::channel(32);
+        let (packet_tx, mut packet_rx) = tokio::sync::mpsc::channel(32);
+        // session snapshots are not used by the test
+        let (event_tx, _) = tokio::sync::mpsc::channel(1);
+        tokio::task::spawn_local(client::run(packet_tx, event_tx, cmd_rx));
+        let forward = forward.clone();
+        tokio::task::spawn_local(async move {
+            while let Some((exchange, packet)) = packet_rx.recv().await {
+                if forward.send((index, exchange, packet)).is_err() {
+                    break;
+                }
+            }
+        });
+
+        let connection = Self { client_id, cmd_tx };
+        connection
+            .request(start)
+            .await
+            .map_err(|e| format!("{}: {e}", connection.client_id))?;
+        Ok(connection)
+    }
+
+    async fn request(&self, command: Command) -> CommandResult {
+        let (reply_tx, reply_rx) = tokio::sync::oneshot::channel();
+        self.cmd_tx
+            .send(Request {
+                command,
+                reply: Some(reply_tx),
+            })
+            .await
+            .map_err(|_| "runner stopped".to_string())?;
+        reply_rx
+            .await
+            .unwrap_or_else(|_| Err("no reply from the runner".to_string()))
+    }
+
+    async fn subscribe(&self, filter: &str, qos: QoSLevel) -> Result<(), String> {
+        match self
+            .request(Command::Subscribe(1, qos, filter.to_string()))
+            .await
+        {
+            Ok(Reply::Acknowledged(reasons)) if reasons.failed() => Err(format!(
+                "{}: SUBACK {}",
+                self.client_id,
+                reasons.summary(reason::suback_name)
+            )),
+            Ok(_) => Ok(()),
+            Err(e) => Err(format!("{}: {e}", self.client_id)),
+        }
+    }
+
+    async fn stop(&self, disconnect: bool) {
+        if disconnect {
+            let _ = self
+                .cmd_tx
+                .send(Command::Disconnect(DisconnectOptions::default()).into())
+                .await;
+        }
+        let _ = self.cmd_tx.send(Command::StopRunner.into()).await;
+    }
+}
+
+/// Test state shared by the steps of a run.
+struct Run<'a> {
+    id: String,
+    packets: UnboundedReceiver<Tagged>,
+    distribution: Distribution,
+    on_update: &'a mut dyn FnMut(Update),
+}
+
+impl Run<'_> {
+    fn handle(&mut self, (index, exchange, packet): Tagged) {
+        if let (Some(member), Exchange::Receive, Packet::Publish(publish)) =
+            (index, &exchange, &packet)
+        {
+            let number = publish
+                .payload
+                .as_deref()
+                .and_then(|payload| std::str::from_utf8(payload).ok())
+                .and_then(|text| text.split_once('/'))
+                .filter(|(id, _)| *id == self.id)
+                .and_then(|(_, number)| number.parse::<u32>().ok());
+            if let Some(number) = number {
+                self.distribution.record(member, number);
+                (self.on_update)(Update::Member(
+                    member,
+                    self.distribution.members[member].clone(),
+                ));
+            }
+        }
+        (self.on_update)(Update::Packet(exchange, packet));
+    }
+
+    /// Handle packets until `deadline`, or until none arrive for `idle`.
+    async fn receive(&mut self, deadline: Instant, idle: Option<Duration>) {
+        loop {
+            let mut timeout = deadline.saturating_duration_since(Instant::now());
+            if let Some(idle) = idle {
+                timeout = timeout.min(idle);
+            }
+            match tokio::time::timeout(timeout, self.packets.recv()).await {
+                Ok(Some(tagged)) => self.handle(tagged),
+                _ => return,
+            }
+        }
+    }
+
+    fn set_dropped(&mut self, member: usize) {
+        self.distribution.members[member].dropped = true;
+        (self.on_update)(Update::Member(
+            member,
+            self.distribution.members[member].clone(),
+        ));
+    }
+}
+
+fn packet_id(number: u32) -> u16 {
+    ((number - 1) % u16::MAX as u32 + 1) as u16
+}
+
+async fn drive(
+    test: &SharedTest,
+    profile: &Profile,
+    run: &mut Run<'_>,
+    forward: &UnboundedSender<Tagged>,
+    connections: &mut Vec<(Connection, bool)>,
+) -> Result<Summary, String> {
+    let qos = QoSLevel::try_from(test.qos).map_err(|_| format!("invalid QoS {}", test.qos))?;
+    let share_filter = test.share_filter();
+    for member in 0..test.members {
+        let client_id = format!("share-{}-{}", run.id, member + 1);
+        run.distribution.members.push(MemberStats {
+            client_id: client_id.clone(),
+            ..Default::default()
+        });
+        (run.on_update)(Update::Member(
+            member,
+            run.distribution.members[member].clone(),
+        ));
+        let hold_acks = member == 0 && test.drop_after.is_some();
+        let connection =
+            Connection::connect(profile, client_id, hold_acks, Some(member), forward).await?;
+        connections.push((connection, true));
+        connections[member]
+            .0
+            .subscribe(&share_filter, qos.clone())
+            .await?;
+    }
+    let publisher = Connection::connect(
+        profile,
+        format!("share-{}-pub", run.id),
+        false,
+        None,
+        forward,
+    )
+    .await?;
+    connections.push((publisher, true));
+
+    for number in 1..=test.messages {
+        let packet_id = if test.qos == 0 { 0 } else { packet_id(number) };
+        let payload = format!("{}/{number}", run.id);
+        let publish =
+            Publish::new_with_message(packet_id, &test.topic, qos.clone(), payload.as_str())
+                .map_err(|e| format!("invalid publish to '{}': {e}", test.topic))?;
+        let (publisher, _) = connections.last().expect("publisher connection");
+        if let Err(e) = publisher.request(Command::Publish(publish)).await {
+            return Err(format!("publish {number}: {e}"));
+        }
+        (run.on_update)(Update::Published(number));
+        run.receive(Instant::now() + test.interval, None).await;
+
+        if test.drop_after == Some(number) {
+            let (member, connected) = &mut connections[0];
+            let _ = member
+                .request(Command::Disconnect(DisconnectOptions {
+                    abrupt: true,
+                    ..Default::default()
+                }))
+                .await;
+            *connected = false;
+            run.set_dropped(0);
+        }
+    }
+
+    run.receive(Instant::now() + SETTLE_LIMIT, Some(SETTLE))
+        .await;
+    Ok(run.distribution.summary(test.messages))
+}
+
+/// Run the test on the current tokio runtime. Must run inside a `LocalSet`
+/// as every connection is a local task. `on_update` is called for every
+/// packet and delivery as they happen.
+pub async fn execute(test: &SharedTest, profile: &Profile, on_update: &mut dyn FnMut(Update)) {
+    let (forward, packets) = tokio::sync::mpsc::unbounded_channel();
+    let id = uuid::Uuid::new_v4().simple().to_string()[..8].to_string();
+    let mut run = Run {
+        id,
+        packets,
+        distribution: Distribution {
+            members: Vec::new(),
+            deliveries: HashMap::new(),
+        },
+        on_update,
+    };
+    let mut connections = Vec::new();
+    let outcome = drive(test, profile, &mut run, &forward, &mut connections).await;
+
+    for (connection, connected) in &connections {
+        connection.stop(*connected).await;
+    }
+    drop(connections);
+    drop(forward);
+    // keep receiving until every runner exits so its last sends succeed
+    while let Some(tagged) = run.packets.recv().await {
+        run.handle(tagged);
+    }
+    (run.on_update)(match outcome {
+        Ok(summary) => Update::Finished(summary),
+        Err(e) => Update::Failed(e),
+    });
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn distribution(members: usize) -> Distribution {
+        Distribution {
+            members: (0..members)
+                .map(|i| MemberStats {
+                    client_id: format!("member-{i}"),
+                    ..MemberStats::default()
+                })
+                .collect(),
+            deliveries: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn counts_duplicates_and_out_of_order_per_member() {
+        let mut d = distribution(2);
+        for (member, number) in [(0, 1), (1, 2), (0, 3), (0, 3), (1, 5), (1, 4)] {
+            d.record(member, number);
+        }
+        let first = &d.members[0];
+        assert_eq!(first.received, 3);
+        assert_eq!(first.duplicates, 1);
+        assert_eq!(first.out_of_order, 0);
+        assert_eq!(first.last, Some(3));
+        let second = &d.members[1];
+        assert_eq!(second.received, 3);
+        assert_eq!(second.duplicates, 0);
+        assert_eq!(second.out_of_order, 1);
+        assert_eq!(second.last, Some(5));
+
+        let summary = d.summary(6);
+        assert_eq!(summary.published, 6);
+        assert_eq!(summary.delivered, 5);
+        assert_eq!(summary.missing, vec![6]);
+        assert_eq!(summary.duplicates, 1);
+        assert_eq!(summary.redelivered, 0);
+    }
+
+    #[test]
+    fn counts_messages_of_a_dropped_member_as_redelivered() {
+        let mut d = distribution(3);
+        d.record(0, 1);
+        d.record(1, 2);
+        d.record(1, 3);
+        d.members[1].dropped = true;
+        // The broker hands the dropped member's messages to the others.
+        d.record(0, 2);
+        d.record(2, 3);
+        d.record(2, 4);
+        // A second copy to the same survivor is a duplicate, not a redelivery.
+        d.record(2, 3);
+
+        assert_eq!(d.members[0].redelivered, 1);
+        assert_eq!(d.members[0].out_of_order, 0);
+        assert_eq!(d.members[2].redelivered, 1);
+        assert_eq!(d.members[2].duplicates, 1);
+        assert_eq!(d.members[1].redelivered, 0);
+
+        let summary = d.summary(5);
+        assert_eq!(summary.delivered, 4);
+        assert_eq!(summary.missing, vec![5]);
+        assert_eq!(summary.redelivered, 2);
+        assert_eq!(summary.duplicates, 1);
+        assert_eq!(
+            summary.text(),
+            "5 published, 4 delivered, 1 missing, 2 redelivered, 1 duplicates (missing 5)"
+        );
+    }
+
+    #[test]
+    fn summary_without_deliveries_lists_every_message_missing() {
+        let summary = distribution(1).summary(12);
+        assert_eq!(summary.delivered, 0);
+        assert_eq!(summary.missing, (1..=12).collect::<Vec<_>>());
+        assert!(
+            summary
+                .text()
+                .ends_with("(missing 1, 2, 3, 4, 5, 6, 7, 8, 9, 10, ...)")
+        );
+    }
+}
@@ -0,0 +1,267 @@
+//! Rolling traffic statistics fed from the packet stream: message and byte
+//! rates by direction, ping round trip and QoS acknowledgement latency.
+
+use std::{
+    collections::{BTreeMap, HashMap, VecDeque},
+    time::{Duration, Instant},
+};
+
+use crate::model::packet::Exchange;
+
+/// Length of the rolling window the rates and percentiles cover.
+pub const WINDOW: Duration = Duration::from_secs(60);
+
+struct Sample {
+    at: Instant,
+    send: bool,
+    bytes: usize,
+}
+
+/// Messages and bytes per second for one second of the window.
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Rate {
+    pub messages_sent: u32,
+    pub messages_received: u32,
+    pub bytes_sent: u64,
+    pub bytes_received: u64,
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct Percentiles {
+    pub count: usize,
+    pub p50: Duration,
+    pub p90: Duration,
+    pub p99: Duration,
+    pub max: Duration,
+}
+
+#[derive(Debug, Default, Clone)]
+pub struct TopicCounter {
+    pub sent: u64,
+    pub received: u64,
+    pub bytes: u64,
+}
+
+#[derive(Default)]
+pub struct Statistics {
+    samples: VecDeque<Sample>,
+    pings: VecDeque<Instant>,
+    ping_rtt: VecDeque<(Instant, Duration)>,
+    /// Outgoing QoS 1/2 publishes waiting for PUBACK or PUBCOMP.
+    unacked: HashMap<u16, Instant>,
+    ack_latency: VecDeque<(Instant, Duration)>,
+    topics: BTreeMap<String, TopicCounter>,
+}
+
+impl Statistics {
+    pub fn record(&mut self, exchange: &Exchange, packet: &vaux_mqtt::Packet, bytes: usize) {
+        let now = Instant::now();
+        let send = matches!(exchange, Exchange::Send);
+        self.samples.push_back(Sample {
+            at: now,
+            send,
+            bytes,
+        });
+        match packet {
+            vaux_mqtt::Packet::PingRequest(_) if send => self.pings.push_back(now),
+            vaux_mqtt::Packet::PingResponse(_) if !send => {
+                if let Some(sent) = self.pings.pop_front() {
+                    self.ping_rtt.push_back((now, now - sent));
+                }
+            }
+            vaux_mqtt::Packet::Publish(publish) => {
+                if let Some(topic) = &publish.topic_name {
+                    let counter = self.topics.entry(topic.clone()).or_default();
+                    if send {
+                        counter.sent += 1;
+                    } else {
+                        counter.received += 1;
+                    }
+                    counter.bytes += bytes as u64;
+                }
+                // only QoS 1 and 2 publishes carry a packet ID
+                if let (true, Some(id)) = (send, publish.packet_id()) {
+                    self.unacked.insert(id, now);
+                }
+            }
+            vaux_mqtt::Packet::PubAck(ack) if !send => self.acked(ack.packet_id, now),
+            vaux_mqtt::Packet::PubComp(comp) if !send => self.acked(comp.packet_id, now),
+            _ => {}
+        }
+        self.expire(now);
+    }
+
+    fn acked(&mut self, packet_id: u16, now: Instant) {
+        if let Some(sent) = self.unacked.remove(&packet_id) {
+            self.ack_latency.push_back((now, now - sent));
+        }
+    }
+
+    /// Drop everything older than the window.
+    pub fn expire(&mut self, now: Instant) {
+        let Some(start) = now.checked_sub(WINDOW) else {
+            return;
+        };
+        while self.samples.front().is_some_and(|s| s.at < start) {
+            self.samples.pop_front();
+        }
+        while self.ping_rtt.front().is_some_and(|(at, _)| *at < start) {
+            self.ping_rtt.pop_front();
+        }
+        while self.ack_latency.front().is_some_and(|(at, _)| *at < start) {
+            self.ack_latency.pop_front();
+        }
+        // a ping or publish unanswered for a whole window is not coming back
+        while self.pings.front().is_some_and(|at| *at < start) {
+            self.pings.pop_front();
+        }
+        self.unacked.retain(|_, at| *at >= start);
+    }
+
+    /// One rate per second of the window, oldest first.
+    pub fn rates(&self, now: Instant) -> Vec<Rate> {
+        let seconds = WINDOW.as_secs() as usize;
+        let mut rates = vec![Rate::default(); seconds];
+        for sample in &self.samples {
+            let age = now.saturating_duration_since(sample.at).as_secs() as usize;
+            if age >= seconds {
+                continue;
+            }
+            let rate = &mut rates[seconds - 1 - age];
+            if sample.send {
+                rate.messages_sent += 1;
+                rate.bytes_sent += sample.bytes as u64;
+            } else {
+                rate.messages_received += 1;
+                rate.bytes_received += sample.bytes as u64;
+            }
+        }
+        rates
+    }
+
+    /// Ping round trip times in the window, oldest first.
+    pub fn ping_rtt(&self) -> Vec<Duration> {
+        self.ping_rtt.iter().map(|(_, rtt)| *rtt).collect()
+    }
+
+    pub fn ack_latency(&self) -> Percentiles {
+        percentiles(
+            self.ack_latency
+                .iter()
+                .map(|(_, latency)| *latency)
+                .collect(),
+        )
+    }
+
+    pub fn topics(&self) -> &BTreeMap<String, TopicCounter> {
+        &self.topics
+    }
+
+    pub fn clear(&mut self) {
+        *self = Self::default();
+    }
+}
+
+/// Nearest-rank percentiles.
+fn percentiles(mut values: Vec<Duration>) -> Percentiles {
+    if values.is_empty() {
+        return Percentiles::default();
+    }
+    values.sort();
+    let rank =
+        |p: f64| values[((p * values.len() as f64).ceil() as usize).clamp(1, values.len()) - 1];
+    Percentiles {
+        count: values.len(),
+        p50: rank(0.5),
+        p90: rank(0.9),
+        p99: rank(0.99),
+        max: values[values.len() - 1],
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn millis(values: impl IntoIterator<Item = u64>) -> Vec<Duration> {
+        values.into_iter().map(Duration::from_millis).collect()
+    }
+
+    fn ping(exchange: Exchange, stats: &mut Statistics) {
+        let raw: &[u8] = match exchange {
+            Exchange::Send => &[0xC0, 0x00],
+            Exchange::Receive => &[0xD0, 0x00],
+        };
+        stats.record(&exchange, &crate::wire::decode(raw).unwrap(), raw.len());
+    }
+
+    #[test]
+    fn empty_window_has_no_traffic() {
+        let stats = Statistics::default();
+        let rates = stats.rates(Instant::now());
+        assert_eq!(rates.len(), WINDOW.as_secs() as usize);
+        assert!(
+            rates
+                .iter()
+                .all(|r| r.messages_sent + r.messages_received == 0)
+        );
+        assert!(stats.ping_rtt().is_empty());
+        assert_eq!(stats.ack_latency().count, 0);
+        assert_eq!(stats.ack_latency().max, Duration::ZERO);
+    }
+
+    #[test]
+    fn single_sample_fills_the_last_second() {
+        let mut stats = Statistics::default();
+        ping(Exchange::Send, &mut stats);
+        let rates = stats.rates(Instant::now());
+        let last = rates.last().unwrap();
+        assert_eq!((last.messages_sent, last.bytes_sent), (1, 2));
+        assert_eq!(last.messages_received, 0);
+        assert!(
+            rates[..rates.len() - 1]
+                .iter()
+                .all(|r| r.messages_sent == 0)
+        );
+
+        ping(Exchange::Receive, &mut stats);
+        assert_eq!(stats.ping_rtt().len(), 1);
+        let single = percentiles(millis([7]));
+        assert_eq!(single.count, 1);
+        assert_eq!(
+            (single.p50, single.p99, single.max),
+            (
+                Duration::from_millis(7),
+                Duration::from_millis(7),
+                Duration::from_millis(7)
+            )
+        );
+    }
+
+    #[test]
+    fn samples_leave_the_window() {
+        let mut stats = Statistics::default();
+        ping(Exchange::Send, &mut stats);
+        let later = Instant::now() + WINDOW + Duration::from_secs(1);
+        assert!(stats.rates(later).iter().all(|r| r.messages_sent == 0));
+        stats.expire(later);
+        // the unanswered ping is dropped, so a late PINGRESP has no round trip
+        ping(Exchange::Receive, &mut stats);
+        assert!(stats.ping_rtt().is_empty());
+    }
+
+    #[test]
+    fn nearest_rank_at_the_boundaries() {
+        let ten = percentiles(millis(1..=10));
+        assert_eq!(ten.p50, Duration::from_millis(5));
+        assert_eq!(ten.p90, Duration::from_millis(9));
+        assert_eq!(ten.p99, Duration::from_millis(10));
+        // one more value moves the 90th rank up to the 10th
+        let eleven = percentiles(millis(1..=11));
+        assert_eq!(eleven.p90, Duration::from_millis(10));
+        let hundred = percentiles(millis((1..=100).rev()));
+        assert_eq!(hundred.p90, Duration::from_millis(90));
+        assert_eq!(hundred.p99, Duration::from_millis(99));
+        assert_eq!(hundred.max, Duration::from_millis(100));
+    }
+}
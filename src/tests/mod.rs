@@ -0,0 +1,214 @@
+//! End to end tests of the client runner.
+//!
+//! Each test starts the embedded broker on a free port, drives one or more
+//! `client::run` instances through their command channel and checks the
+//! `(Exchange, Packet)` stream they report. The runners are local tasks on a
+//! current thread runtime, the broker runs on its own thread.
+
+mod runner;
+
+use std::{future::Future, time::Duration};
+
+use tokio::sync::mpsc::{Receiver, Sender};
+use tokio::sync::oneshot;
+use vaux_mqtt::{ConnAck, Packet, QoSLevel, publish::Publish};
+
+use crate::broker::{BrokerHandle, Faults};
+use crate::client::{self, Command, CommandResult, Event, Reply, Request};
+use crate::model::packet::Exchange;
+use crate::profile::Profile;
+
+/// Longest wait for a packet or a reply before a test fails.
+const TIMEOUT: Duration = Duration::from_secs(15);
+/// How long to wait to be sure a packet does not arrive.
+const QUIET: Duration = Duration::from_millis(500);
+
+/// Run `test` to completion on a current thread runtime.
+pub(crate) fn run_local<F: Future>(test: F) -> F::Output {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    tokio::task::LocalSet::new().block_on(&rt, test)
+}
+
+fn start_broker(faults: Faults) -> BrokerHandle {
+    BrokerHandle::start(0, faults).expect("Failed to start the test broker")
+}
+
+/// Settings for a connection to `broker` with a generated client ID.
+fn profile(broker: &BrokerHandle, name: &str) -> Profile {
+    Profile {
+        client_id: format!(
+            "test-{name}-{}",
+            &uuid::Uuid::new_v4().simple().to_string()[..8]
+        ),
+        host: "127.0.0.1".to_string(),
+        port: broker.address().port(),
+        ..Default::default()
+    }
+}
+
+fn publish(packet_id: u16, topic: &str, qos: QoSLevel, payload: &str) -> Publish {
+    Publish::new_with_message(packet_id, topic, qos, payload).expect("Failed to build publish")
+}
+
+/// Reason codes of an acknowledged command.
+fn codes(result: CommandResult) -> Vec<u8> {
+    match result {
+        Ok(Reply::Acknowledged(reasons)) => reasons.codes,
+        other => panic!("expected an acknowledgement, got {other:?}"),
+    }
+}
+
+fn is_publish_to(packet: &Packet, topic: &str) -> bool {
+    matches!(packet, Packet::Publish(publish) if publish.topic_name.as_deref() == Some(topic))
+}
+
+/// One `client::run` instance and the streams it reports on.
+struct Runner {
+    cmd_tx: Sender<Request>,
+    packets: Receiver<(Exchange, Packet)>,
+    events: Receiver<Event>,
+    /// Reported packets no expectation has taken yet.
+    backlog: Vec<(Exchange, Packet)>,
+}
+
+impl Runner {
+    fn start() -> Self {
+        let (cmd_tx, cmd_rx) = tokio::sync::mpsc::channel(32);
+        let (packet_tx, packets) = tokio::sync::mpsc::channel(64);
+        let (event_tx, events) = tokio::sync::mpsc::channel(8);
+        tokio::task::spawn_local(client::run(packet_tx, event_tx, cmd_rx));
+        Self {
+            cmd_tx,
+            packets,
+            events,
+            backlog: Vec::new(),
+        }
+    }
+
+    /// Queue `command` without waiting for its result.
+    async fn send(&self, command: Command) -> oneshot::Receiver<CommandResult> {
+        let (reply_tx, reply_rx) = oneshot::channel();
+        self.cmd_tx
+            .send(Request {
+                command,
+                reply: Some(reply_tx),
+            })
+            .await
+            .expect("runner stopped");
+        reply_rx
+    }
+
+    async fn reply(reply: oneshot::Receiver<CommandResult>) -> CommandResult {
+        tokio::time::timeout(TIMEOUT, reply)
+            .await
+            .expect("no reply from the runner")
+            .expect("runner dropped the reply")
+    }
+
+    async fn request(&self, command: Command) -> CommandResult {
+        Self::reply(self.send(command).await).await
+    }
+
+    /// Start a client with `profile` and wait for its CONNACK.
+    async fn connect(&mut self, profile: &Profile) -> ConnAck {
+        let start = profile
+            .to_setting()
+            .and_then(|setting| setting.start_client())
+            .expect("invalid profile");
+        if let Err(e) = self.request(start).await {
+            panic!("failed to connect: {e}");
+        }
+        self.connack().await
+    }
+
+    async fn connack(&mut self) -> ConnAck {
+        match self
+            .expect(Exchange::Receive, "CONNACK", |p| {
+                matches!(p, Packet::ConnAck(_))
+            })
+            .await
+        {
+            Packet::ConnAck(ack) => ack,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Take the first reported packet that `matches`, waiting for it when
+    /// none has arrived yet. Others stay for later expectations.
+    async fn expect(
+        &mut self,
+        exchange: Exchange,
+        what: &str,
+        matches: impl Fn(&Packet) -> bool,
+    ) -> Packet {
+        if let Some(index) = self
+            .backlog
+            .iter()
+            .position(|(e, packet)| *e == exchange && matches(packet))
+        {
+            return self.backlog.remove(index).1;
+        }
+        let deadline = tokio::time::Instant::now() + TIMEOUT;
+        loop {
+            match tokio::time::timeout_at(deadline, self.packets.recv()).await {
+                Ok(Some((e, packet))) if e == exchange && matches(&packet) => return packet,
+                Ok(Some(reported)) => self.backlog.push(reported),
+                Ok(None) => panic!(
+                    "runner stopped before {exchange:?} {what}, unmatched {:?}",
+                    self.backlog
+                ),
+                Err(_) => panic!(
+                    "no {exchange:?} {what} within {TIMEOUT:?}, unmatched {:?}",
+                    self.backlog
+                ),
+            }
+        }
+    }
+
+    /// Fail if a packet that `matches` is reported, waiting `QUIET` for it.
+    async fn expect_none(
+        &mut self,
+        exchange: Exchange,
+        what: &str,
+        matches: impl Fn(&Packet) -> bool,
+    ) {
+        let deadline = tokio::time::Instant::now() + QUIET;
+        while let Ok(Some(reported)) = tokio::time::timeout_at(deadline, self.packets.recv()).await
+        {
+            self.backlog.push(reported);
+        }
+        if let Some((_, packet)) = self
+            .backlog
+            .iter()
+            .find(|(e, packet)| *e == exchange && matches(packet))
+        {
+            panic!("unexpected {exchange:?} {what}: {packet:?}");
+        }
+    }
+
+    /// Wait for an event that `matches`, skipping any other.
+    async fn event(&mut self, what: &str, matches: impl Fn(&Event) -> bool) -> Event {
+        let deadline = tokio::time::Instant::now() + TIMEOUT;
+        loop {
+            match tokio::time::timeout_at(deadline, self.events.recv()).await {
+                Ok(Some(event)) if matches(&event) => return event,
+                Ok(Some(_)) => {}
+                Ok(None) => panic!("runner stopped before the {what} event"),
+                Err(_) => panic!("no {what} event within {TIMEOUT:?}"),
+            }
+        }
+    }
+
+    /// Stop the runner and wait for it to exit.
+    async fn stop(mut self) {
+        let _ = self.cmd_tx.send(Command::StopRunner.into()).await;
+        let closed = tokio::time::timeout(TIMEOUT, async {
+            while self.packets.recv().await.is_some() {}
+        })
+        .await;
+        assert!(closed.is_ok(), "runner did not exit after StopRunner");
+    }
+}
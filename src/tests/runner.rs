@@ -0,0 +1,630 @@
+use bytes::BytesMut;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+use super::*;
+use crate::client::{DisconnectOptions, PingSchedule};
+use crate::codec;
+use crate::reason;
+
+#[test]
+fn connect_reports_connack() {
+    run_local(async {
+        let broker = start_broker(Faults::default());
+        let mut runner = Runner::start();
+        let ack = runner.connect(&profile(&broker, "connect")).await;
+        assert_eq!(reason::code(&ack.reason), 0x00);
+        assert!(!ack.session_present);
+        assert_eq!(broker.broker().clients(), 1);
+        runner.stop().await;
+    });
+}
+
+#[test]
+fn connect_without_broker_fails() {
+    run_local(async {
+        // a port nothing listens on
+        let port = std::net::TcpListener::bind("127.0.0.1:0")
+            .and_then(|listener| listener.local_addr())
+            .expect("Failed to find a free port")
+            .port();
+        let profile = Profile {
+            host: "127.0.0.1".to_string(),
+            port,
+            ..Default::default()
+        };
+        let start = profile.to_setting().unwrap().start_client().unwrap();
+        let runner = Runner::start();
+        let result = runner.request(start).await;
+        assert!(result.is_err(), "connected to a closed port: {result:?}");
+        runner.stop().await;
+    });
+}
+
+#[test]
+fn refused_connack_is_reported() {
+    run_local(async {
+        // stand-in server refusing every CONNECT with Not authorized
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::task::spawn_local(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut connect = [0u8; 256];
+            let _ = socket.read(&mut connect).await;
+            let _ = socket.write_all(&[0x20, 0x03, 0x00, 0x87, 0x00]).await;
+            while socket.read(&mut connect).await.is_ok_and(|n| n > 0) {}
+        });
+        let profile = Profile {
+            host: "127.0.0.1".to_string(),
+            port,
+            ..Default::default()
+        };
+        let start = profile.to_setting().unwrap().start_client().unwrap();
+        let mut runner = Runner::start();
+        // the start fails and the refusal still shows in the packet stream
+        let result = runner.request(start).await;
+        assert!(
+            result
+                .as_ref()
+                .is_err_and(|e| e.starts_with("Failed to connect")),
+            "{result:?}"
+        );
+        let ack = runner.connack().await;
+        assert_eq!(reason::code(&ack.reason), 0x87);
+        runner.stop().await;
+    });
+}
+
+#[test]
+fn commands_without_connection_fail() {
+    run_local(async {
+        let runner = Runner::start();
+        let publish = publish(1, "vaux/test", QoSLevel::AtLeastOnce, "no connection");
+        assert!(runner.request(Command::Publish(publish)).await.is_err());
+        let subscribe = Command::Subscribe(1, QoSLevel::AtMostOnce, "vaux/#".to_string());
+        assert!(runner.request(subscribe).await.is_err());
+        let unsubscribe = Command::Unsubscribe(1, "vaux/#".to_string());
+        assert!(runner.request(unsubscribe).await.is_err());
+        assert!(runner.request(Command::Ping).await.is_err());
+        let disconnect = Command::Disconnect(DisconnectOptions::default());
+        assert_eq!(
+            runner.request(disconnect).await,
+            Err("not connected".to_string())
+        );
+        let unknown_reason = Command::Disconnect(DisconnectOptions {
+            reason_code: 0x03,
+            ..Default::default()
+        });
+        assert!(runner.request(unknown_reason).await.is_err());
+        let resume = Profile::default()
+            .to_setting()
+            .unwrap()
+            .resume_session()
+            .unwrap();
+        assert!(runner.request(resume).await.is_err());
+        runner.stop().await;
+    });
+}
+
+#[test]
+fn proxy_is_refused_with_tls() {
+    let setting = Profile {
+        with_tls: true,
+        ..Default::default()
+    }
+    .to_setting()
+    .unwrap();
+    setting.proxy_port.replace(Some(1883));
+    assert!(setting.start_client().is_err());
+    assert!(setting.resume_session().is_err());
+}
+
+#[test]
+fn publish_qos0_is_delivered() {
+    run_local(async {
+        let broker = start_broker(Faults::default());
+        let mut runner = Runner::start();
+        runner.connect(&profile(&broker, "qos0")).await;
+        let topic = "vaux/test/qos0";
+        let subscribe = Command::Subscribe(1, QoSLevel::AtMostOnce, topic.to_string());
+        assert_eq!(codes(runner.request(subscribe).await), vec![0x00]);
+
+        let publish = publish(0, topic, QoSLevel::AtMostOnce, "hello");
+        assert_eq!(
+            runner.request(Command::Publish(publish)).await,
+            Ok(Reply::Done)
+        );
+        runner
+            .expect(Exchange::Send, "PUBLISH", |p| is_publish_to(p, topic))
+            .await;
+        let Packet::Publish(received) = runner
+            .expect(Exchange::Receive, "PUBLISH", |p| is_publish_to(p, topic))
+            .await
+        else {
+            unreachable!()
+        };
+        assert_eq!(received.payload.as_deref(), Some(&b"hello"[..]));
+        runner.stop().await;
+    });
+}
+
+#[test]
+fn publish_qos1_is_acknowledged() {
+    run_local(async {
+        let broker = start_broker(Faults::default());
+        let mut runner = Runner::start();
+        runner.connect(&profile(&broker, "qos1")).await;
+        let topic = "vaux/test/qos1";
+        let subscribe = Command::Subscribe(1, QoSLevel::AtLeastOnce, topic.to_string());
+        assert_eq!(codes(runner.request(subscribe).await), vec![0x01]);
+
+        let publish = publish(2, topic, QoSLevel::AtLeastOnce, "hello");
+        assert_eq!(
+            codes(runner.request(Command::Publish(publish)).await),
+            vec![0x00]
+        );
+        runner
+            .expect(
+                Exchange::Receive,
+                "PUBACK",
+                |p| matches!(p, Packet::PubAck(ack) if ack.packet_id == 2),
+            )
+            .await;
+        runner
+            .expect(Exchange::Receive, "PUBLISH", |p| is_publish_to(p, topic))
+            .await;
+        runner.stop().await;
+    });
+}
+
+#[test]
+fn publish_qos2_completes() {
+    run_local(async {
+        let broker = start_broker(Faults::default());
+        let mut runner = Runner::start();
+        runner.connect(&profile(&broker, "qos2")).await;
+        let topic = "vaux/test/qos2";
+        let subscribe = Command::Subscribe(1, QoSLevel::ExactlyOnce, topic.to_string());
+        assert_eq!(codes(runner.request(subscribe).await), vec![0x02]);
+
+        // answered by PUBREC, the flow goes on to PUBCOMP
+        let publish = publish(3, topic, QoSLevel::ExactlyOnce, "hello");
+        assert_eq!(
+            codes(runner.request(Command::Publish(publish)).await),
+            vec![0x00]
+        );
+        runner
+            .expect(
+                Exchange::Receive,
+                "PUBCOMP",
+                |p| matches!(p, Packet::PubComp(comp) if comp.packet_id == 3),
+            )
+            .await;
+        runner
+            .expect(Exchange::Receive, "PUBLISH", |p| is_publish_to(p, topic))
+            .await;
+        runner.stop().await;
+    });
+}
+
+#[test]
+fn unsubscribe_stops_delivery() {
+    run_local(async {
+        let broker = start_broker(Faults::default());
+        let mut runner = Runner::start();
+        runner.connect(&profile(&broker, "unsubscribe")).await;
+        let topic = "vaux/test/unsubscribe";
+        let subscribe = Command::Subscribe(1, QoSLevel::AtLeastOnce, topic.to_string());
+        assert_eq!(codes(runner.request(subscribe).await), vec![0x01]);
+        let unsubscribe = Command::Unsubscribe(2, topic.to_string());
+        assert_eq!(codes(runner.request(unsubscribe).await), vec![0x00]);
+        // No subscription existed
+        let unsubscribe = Command::Unsubscribe(3, topic.to_string());
+        assert_eq!(codes(runner.request(unsubscribe).await), vec![0x11]);
+
+        let publish = publish(4, topic, QoSLevel::AtLeastOnce, "hello");
+        assert_eq!(
+            codes(runner.request(Command::Publish(publish)).await),
+            vec![0x00]
+        );
+        runner
+            .expect_none(Exchange::Receive, "PUBLISH", |p| is_publish_to(p, topic))
+            .await;
+        runner.stop().await;
+    });
+}
+
+#[test]
+fn invalid_filter_is_refused() {
+    run_local(async {
+        let broker = start_broker(Faults::default());
+        let mut runner = Runner::start();
+        runner.connect(&profile(&broker, "filter")).await;
+        let subscribe = Command::Subscribe(1, QoSLevel::AtMostOnce, "vaux/#/test".to_string());
+        // refused by the client before sending, or by the broker
+        match runner.request(subscribe).await {
+            Ok(Reply::Acknowledged(reasons)) => assert_eq!(reasons.codes, vec![0x8F]),
+            Ok(reply) => panic!("invalid filter accepted: {reply:?}"),
+            Err(_) => {}
+        }
+        runner.stop().await;
+    });
+}
+
+#[test]
+fn ping_reports_pingresp() {
+    run_local(async {
+        let broker = start_broker(Faults::default());
+        let mut runner = Runner::start();
+        runner.connect(&profile(&broker, "ping")).await;
+        assert_eq!(runner.request(Command::Ping).await, Ok(Reply::Done));
+        runner
+            .expect(Exchange::Send, "PINGREQ", |p| {
+                matches!(p, Packet::PingRequest(_))
+            })
+            .await;
+        runner
+            .expect(Exchange::Receive, "PINGRESP", |p| {
+                matches!(p, Packet::PingResponse(_))
+            })
+            .await;
+
+        let suppress = Command::SetPingSchedule(PingSchedule::Suppressed);
+        assert_eq!(runner.request(suppress).await, Ok(Reply::Done));
+        assert!(runner.request(Command::Ping).await.is_err());
+        runner.stop().await;
+    });
+}
+
+#[test]
+fn suppressed_pings_connect_without_keep_alive() {
+    run_local(async {
+        // stand-in server reporting the keep alive of the CONNECT
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (keep_alive_tx, keep_alive_rx) = oneshot::channel();
+        tokio::task::spawn_local(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = BytesMut::new();
+            while socket.read_buf(&mut buf).await.is_ok_and(|n| n > 0) {
+                if let Ok(Some(frame)) = codec::next_frame(&mut buf) {
+                    let connect = codec::connect(frame.body).unwrap();
+                    let _ = keep_alive_tx.send(connect.keep_alive);
+                    let _ = socket.write_all(&codec::connack(false, 0x00, None)).await;
+                    break;
+                }
+            }
+            while socket.read_buf(&mut buf).await.is_ok_and(|n| n > 0) {}
+        });
+        let setting = Profile {
+            host: "127.0.0.1".to_string(),
+            port,
+            keep_alive: 30,
+            ..Default::default()
+        }
+        .to_setting()
+        .unwrap();
+        setting.pings_suppressed.replace(true);
+        let mut runner = Runner::start();
+        assert_eq!(
+            runner.request(setting.start_client().unwrap()).await,
+            Ok(Reply::Done)
+        );
+        runner.connack().await;
+        let keep_alive = tokio::time::timeout(TIMEOUT, keep_alive_rx)
+            .await
+            .expect("no CONNECT")
+            .unwrap();
+        assert_eq!(keep_alive, 0);
+        runner.stop().await;
+    });
+}
+
+#[test]
+fn scheduled_pings_are_sent() {
+    run_local(async {
+        let broker = start_broker(Faults::default());
+        let mut runner = Runner::start();
+        runner.connect(&profile(&broker, "schedule")).await;
+        let schedule = Command::SetPingSchedule(PingSchedule::Every(Duration::from_millis(200)));
+        assert_eq!(runner.request(schedule).await, Ok(Reply::Done));
+        for _ in 0..2 {
+            runner
+                .expect(Exchange::Send, "PINGREQ", |p| {
+                    matches!(p, Packet::PingRequest(_))
+                })
+                .await;
+        }
+        runner.stop().await;
+    });
+}
+
+#[test]
+fn disconnect_sends_reason() {
+    run_local(async {
+        // stand-in server reporting the type and reason code of every packet
+        // after CONNECT; it closes its side on DISCONNECT like a broker
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let (received_tx, received_rx) = oneshot::channel();
+        tokio::task::spawn_local(async move {
+            let (socket, _) = listener.accept().await.unwrap();
+            let (mut reader, mut writer) = socket.into_split();
+            let mut buf = BytesMut::new();
+            let mut received = Vec::new();
+            let mut connected = false;
+            while reader.read_buf(&mut buf).await.is_ok_and(|n| n > 0) {
+                while let Ok(Some(frame)) = codec::next_frame(&mut buf) {
+                    match frame.kind {
+                        codec::CONNECT if !connected => {
+                            connected = true;
+                            let _ = writer.write_all(&codec::connack(false, 0x00, None)).await;
+                        }
+                        codec::DISCONNECT => {
+                            received.push((frame.kind, codec::disconnect_reason(frame.body)));
+                            let _ = writer.shutdown().await;
+                        }
+                        kind => received.push((kind, 0)),
+                    }
+                }
+            }
+            let _ = received_tx.send(received);
+        });
+        let server = Profile {
+            host: "127.0.0.1".to_string(),
+            port,
+            ..Default::default()
+        };
+        let mut runner = Runner::start();
+        runner.connect(&server).await;
+        let disconnect = Command::Disconnect(DisconnectOptions {
+            reason_code: 0x04,
+            ..Default::default()
+        });
+        assert_eq!(runner.request(disconnect).await, Ok(Reply::Done));
+        let Packet::Disconnect(sent) = runner
+            .expect(Exchange::Send, "DISCONNECT", |p| {
+                matches!(p, Packet::Disconnect(_))
+            })
+            .await
+        else {
+            unreachable!()
+        };
+        // Disconnect with Will Message
+        assert_eq!(reason::code(&sent.reason), 0x04);
+        let received = tokio::time::timeout(TIMEOUT, received_rx)
+            .await
+            .expect("the connection was not closed")
+            .unwrap();
+        assert_eq!(received, vec![(codec::DISCONNECT, 0x04)]);
+        runner.stop().await;
+    });
+}
+
+#[test]
+fn abrupt_drop_keeps_the_session() {
+    run_local(async {
+        let broker = start_broker(Faults::default());
+        let topic = "vaux/test/abrupt";
+        let subscriber = profile(&broker, "abrupt");
+        let mut runner = Runner::start();
+        runner.connect(&subscriber).await;
+        let subscribe = Command::Subscribe(1, QoSLevel::AtLeastOnce, topic.to_string());
+        assert_eq!(codes(runner.request(subscribe).await), vec![0x01]);
+        let abrupt = Command::Disconnect(DisconnectOptions {
+            abrupt: true,
+            ..Default::default()
+        });
+        assert_eq!(runner.request(abrupt).await, Ok(Reply::Done));
+        let Event::Session(Some(snapshot)) = runner
+            .event("session", |e| matches!(e, Event::Session(Some(_))))
+            .await
+        else {
+            unreachable!()
+        };
+        assert_eq!(snapshot.client_id, subscriber.client_id);
+        assert!(snapshot.subscriptions.iter().any(|s| s.filter == topic));
+
+        let resume = subscriber.to_setting().unwrap().resume_session().unwrap();
+        assert_eq!(runner.request(resume).await, Ok(Reply::Done));
+        assert!(runner.connack().await.session_present);
+        runner.stop().await;
+    });
+}
+
+#[test]
+fn script_publish_skips_packet_ids_in_flight() {
+    run_local(async {
+        // stand-in server that never acknowledges a PUBLISH and answers the
+        // first one with a PUBLISH to "in"
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        tokio::task::spawn_local(async move {
+            let (mut socket, _) = listener.accept().await.unwrap();
+            let mut buf = BytesMut::new();
+            let mut answered = false;
+            while socket.read_buf(&mut buf).await.is_ok_and(|n| n > 0) {
+                while let Ok(Some(frame)) = codec::next_frame(&mut buf) {
+                    let reply = match frame.kind {
+                        codec::CONNECT => codec::connack(false, 0x00, None),
+                        codec::PUBLISH if !answered => {
+                            answered = true;
+                            let message = codec::Message {
+                                topic: "in".to_string(),
+                                payload: "x".into(),
+                                properties: Default::default(),
+                                qos: 0,
+                                retain: false,
+                            };
+                            codec::publish_frame(&message, 0, 0, false, false)
+                        }
+                        _ => continue,
+                    };
+                    let _ = socket.write_all(&reply).await;
+                }
+            }
+        });
+        let server = Profile {
+            host: "127.0.0.1".to_string(),
+            port,
+            ..Default::default()
+        };
+        let mut runner = Runner::start();
+        runner.connect(&server).await;
+        let script = r#"fn on_publish(topic, payload) { publish("reply", payload, 1); }"#;
+        assert_eq!(
+            runner
+                .request(Command::LoadScript(script.to_string()))
+                .await,
+            Ok(Reply::Done)
+        );
+        // 60000 is the first script packet ID, held here by an unacknowledged publish
+        let held = publish(60000, "held", QoSLevel::AtLeastOnce, "x");
+        let _reply = runner.send(Command::Publish(held)).await;
+        let Packet::Publish(reply) = runner
+            .expect(Exchange::Send, "script PUBLISH", |p| {
+                is_publish_to(p, "reply")
+            })
+            .await
+        else {
+            unreachable!()
+        };
+        assert_eq!(reply.packet_id(), Some(60001));
+        runner.stop().await;
+    });
+}
+
+#[test]
+fn broker_disconnect_is_reported() {
+    run_local(async {
+        let broker = start_broker(Faults::default());
+        let mut runner = Runner::start();
+        runner.connect(&profile(&broker, "kicked")).await;
+        broker.broker().disconnect_all();
+        let Packet::Disconnect(received) = runner
+            .expect(Exchange::Receive, "DISCONNECT", |p| {
+                matches!(p, Packet::Disconnect(_))
+            })
+            .await
+        else {
+            unreachable!()
+        };
+        // Administrative action
+        assert_eq!(reason::code(&received.reason), 0x98);
+        // the runner lets go of the closed connection and keeps its session
+        runner
+            .event("session", |e| matches!(e, Event::Session(Some(_))))
+            .await;
+        assert_eq!(
+            runner.request(Command::Ping).await,
+            Err("Client not initialized, cannot send ping".to_string())
+        );
+        runner.stop().await;
+    });
+}
+
+#[test]
+fn pending_reply_fails_on_disconnect() {
+    run_local(async {
+        let broker = start_broker(Faults::default());
+        let mut runner = Runner::start();
+        runner.connect(&profile(&broker, "pending")).await;
+        broker.broker().set_faults(Faults {
+            ack_delay: Duration::from_secs(5),
+            ..Default::default()
+        });
+        let publish = publish(5, "vaux/test/pending", QoSLevel::AtLeastOnce, "hello");
+        let reply = runner.send(Command::Publish(publish)).await;
+        let disconnect = Command::Disconnect(DisconnectOptions::default());
+        assert_eq!(runner.request(disconnect).await, Ok(Reply::Done));
+        let result = Runner::reply(reply).await;
+        assert!(
+            result
+                .as_ref()
+                .is_err_and(|e| e.contains("connection closed")),
+            "{result:?}"
+        );
+        runner.stop().await;
+    });
+}
+
+#[test]
+fn reused_packet_id_fails_the_earlier_reply() {
+    run_local(async {
+        let broker = start_broker(Faults::default());
+        let mut runner = Runner::start();
+        runner.connect(&profile(&broker, "reused")).await;
+        broker.broker().set_faults(Faults {
+            ack_delay: Duration::from_secs(5),
+            ..Default::default()
+        });
+        let first = publish(5, "vaux/test/reused", QoSLevel::AtLeastOnce, "first");
+        let first = runner.send(Command::Publish(first)).await;
+        let second = publish(5, "vaux/test/reused", QoSLevel::AtLeastOnce, "second");
+        let _second = runner.send(Command::Publish(second)).await;
+        assert_eq!(
+            Runner::reply(first).await,
+            Err("packet ID 5 reused before it was acknowledged".to_string())
+        );
+        runner.stop().await;
+    });
+}
+
+#[test]
+fn resume_session_receives_queued_messages() {
+    run_local(async {
+        let broker = start_broker(Faults::default());
+        let topic = "vaux/test/resume";
+        let subscriber = profile(&broker, "resume");
+        let mut runner = Runner::start();
+        runner.connect(&subscriber).await;
+        let subscribe = Command::Subscribe(1, QoSLevel::AtLeastOnce, topic.to_string());
+        assert_eq!(codes(runner.request(subscribe).await), vec![0x01]);
+        assert_eq!(runner.request(Command::StopClient).await, Ok(Reply::Done));
+        runner
+            .event("session", |e| matches!(e, Event::Session(Some(_))))
+            .await;
+
+        // published while the subscriber is away
+        let mut publisher = Runner::start();
+        publisher.connect(&profile(&broker, "publisher")).await;
+        let publish = publish(1, topic, QoSLevel::AtLeastOnce, "queued");
+        assert_eq!(
+            codes(publisher.request(Command::Publish(publish)).await),
+            vec![0x00]
+        );
+        publisher.stop().await;
+
+        let resume = subscriber.to_setting().unwrap().resume_session().unwrap();
+        assert_eq!(runner.request(resume).await, Ok(Reply::Done));
+        let ack = runner.connack().await;
+        assert!(ack.session_present);
+        let Packet::Publish(received) = runner
+            .expect(Exchange::Receive, "PUBLISH", |p| is_publish_to(p, topic))
+            .await
+        else {
+            unreachable!()
+        };
+        assert_eq!(received.payload.as_deref(), Some(&b"queued"[..]));
+        // the session was taken
+        assert!(
+            runner
+                .request(subscriber.to_setting().unwrap().resume_session().unwrap())
+                .await
+                .is_err()
+        );
+        runner.stop().await;
+    });
+}
+
+#[test]
+fn stop_runner_ends_the_streams() {
+    run_local(async {
+        let broker = start_broker(Faults::default());
+        let mut runner = Runner::start();
+        runner.connect(&profile(&broker, "stop")).await;
+        let cmd_tx = runner.cmd_tx.clone();
+        runner.stop().await;
+        // the command channel is closed once the runner exits
+        assert!(cmd_tx.send(Command::Ping.into()).await.is_err());
+    });
+}
@@ -1,23 +1,71 @@
-use std::{cell::RefCell, rc::Rc, time::Duration};
+use std::{cell::RefCell, rc::Rc};
 
 use bytes::BytesMut;
 use glib::clone;
-use gtk4::{self as gtk};
+use gtk4::{self as gtk, gio};
 
 use gtk::prelude::*;
-use vaux_mqtt::{WillMessage, codec::Encode};
+use vaux_mqtt::codec::Encode;
 
-use crate::client::{self, ClientSetting, Command};
+use crate::client::{self, ClientSetting, Command, CommandResult, Reply};
+use crate::model::{CommandQueue, MessageLog};
+use crate::reason;
+use crate::ui::disconnect::show_disconnect_dialog;
+use crate::ui::file::choose_file;
+use crate::ui::replay::show_replay_window;
+use crate::ui::subscriptions::{subscribe, unsubscribe};
 
 const FRAME_MARGIN: i32 = 6;
 const TOPIC_ENTRY_WIDTH_CHARS: i32 = 80;
 const PUBLISH_TEXT_WIDTH_REQUEST: i32 = 300;
 const PUBLISH_TEXT_HEIGHT_REQUEST: i32 = 120;
 
+/// Show the result of a command in its tab, failures in red. `name` names
+/// the reason codes of the packet that answers it.
+fn show_result(
+    label: &gtk::Label,
+    action: &str,
+    name: fn(u8) -> &'static str,
+    result: CommandResult,
+) {
+    let (text, failed) = match result {
+        Ok(Reply::Done) => (format!("{action} sent"), false),
+        Ok(Reply::Acknowledged(reasons)) => (
+            format!("{action} acknowledged: {}", reasons.summary(name)),
+            reasons.failed(),
+        ),
+        Err(e) => (format!("{action} failed: {e}"), true),
+    };
+    label.set_text(&text);
+    if failed {
+        label.add_css_class("error");
+    } else {
+        label.remove_css_class("error");
+    }
+}
+
+/// Run `result` to completion and show it in `label`.
+fn await_result(
+    label: &gtk::Label,
+    action: &'static str,
+    name: fn(u8) -> &'static str,
+    result: impl std::future::Future<Output = CommandResult> + 'static,
+) {
+    label.set_text(&format!("{action} pending..."));
+    label.remove_css_class("error");
+    glib::spawn_future_local(clone!(
+        #[weak]
+        label,
+        async move { show_result(&label, action, name, result.await) }
+    ));
+}
+
 pub fn build_actions(
     clean_start_check: &gtk::CheckButton,
-    cmd_tx: tokio::sync::mpsc::Sender<Command>,
+    queue: &CommandQueue,
     client_settings: &ClientSetting,
+    message_log: &MessageLog,
+    subscriptions: &gio::ListStore,
 ) -> gtk::Frame {
     let frame = gtk::Frame::new(Some("Actions"));
     let grid = gtk::Grid::new();
@@ -37,12 +85,7 @@ pub fn build_actions(
     ping_button.set_vexpand(false);
     // Make Ping button same width as Connect/Disconnect button
     ping_button.set_sensitive(false); // Initially disabled
-    let conn_button = build_connect(
-        &ping_button,
-        clean_start_check,
-        client_settings,
-        cmd_tx.clone(),
-    );
+    let conn_button = build_connect(&ping_button, clean_start_check, client_settings, queue);
     conn_button.set_width_request(120);
     conn_button.set_valign(gtk::Align::Center);
     conn_button.set_hexpand(true);
@@ -50,44 +93,154 @@ pub fn build_actions(
     grid.attach(&conn_button, 0, row, 1, 1);
     row += 1;
     grid.attach(&ping_button, 0, row, 1, 1);
-    let _cmd_tx = cmd_tx.clone();
-    ping_button.connect_clicked(move |_| {
-        let rt = tokio::runtime::Builder::new_current_thread()
-            .enable_all()
-            .build()
-            .unwrap();
-        match rt.block_on(async { _cmd_tx.send(client::Command::Ping).await }) {
-            Ok(_) => {}
-            Err(e) => {
-                println!("Failed to send ping command: {e}");
+    row += 1;
+    let disconnect_button = gtk::Button::with_label("Disconnect...");
+    disconnect_button.set_valign(gtk::Align::Center);
+    disconnect_button.set_tooltip_text(Some(
+        "Disconnect with a reason code, properties or an abrupt socket drop",
+    ));
+    // available whenever the client is connected, same as Ping
+    ping_button
+        .bind_property("sensitive", &disconnect_button, "sensitive")
+        .sync_create()
+        .build();
+    grid.attach(&disconnect_button, 0, row, 1, 1);
+    disconnect_button.connect_clicked(clone!(
+        #[weak]
+        queue,
+        #[weak]
+        conn_button,
+        #[weak]
+        ping_button,
+        move |b| {
+            show_disconnect_dialog(
+                b,
+                clone!(
+                    #[weak]
+                    queue,
+                    #[weak]
+                    conn_button,
+                    #[weak]
+                    ping_button,
+                    move |options| {
+                        queue.dispatch(client::Command::Disconnect(options));
+                        // set_active does not emit "clicked", so reset the connect state here
+                        conn_button.set_active(false);
+                        conn_button.set_label("Connect");
+                        ping_button.set_sensitive(false);
+                    }
+                ),
+            );
+        }
+    ));
+    row += 1;
+    let replay_button = gtk::Button::with_label("Replay...");
+    replay_button.set_valign(gtk::Align::Center);
+    replay_button.set_tooltip_text(Some(
+        "Replay the sent packets of an exported JSON Lines capture",
+    ));
+    ping_button
+        .bind_property("sensitive", &replay_button, "sensitive")
+        .sync_create()
+        .build();
+    grid.attach(&replay_button, 0, row, 1, 1);
+    replay_button.connect_clicked(clone!(
+        #[weak]
+        message_log,
+        #[weak]
+        queue,
+        move |b| show_replay_window(b, &message_log, &queue)
+    ));
+    row += 1;
+    let record_button = gtk::ToggleButton::with_label("Record...");
+    record_button.set_valign(gtk::Align::Center);
+    record_button.set_tooltip_text(Some(
+        "Append every sent and received packet to a JSON Lines file as it happens",
+    ));
+    grid.attach(&record_button, 0, row, 1, 1);
+    // the runner reports when recording starts or stops, including on write errors
+    message_log
+        .bind_property("recording", &record_button, "active")
+        .transform_to(|_, path: String| Some(!path.is_empty()))
+        .sync_create()
+        .build();
+    message_log
+        .bind_property("recording", &record_button, "tooltip-text")
+        .transform_to(|_, path: String| {
+            Some(if path.is_empty() {
+                "Append every sent and received packet to a JSON Lines file as it happens"
+                    .to_string()
+            } else {
+                format!("Recording to {path}")
+            })
+        })
+        .sync_create()
+        .build();
+    record_button.connect_clicked(clone!(
+        #[weak]
+        queue,
+        move |b| {
+            if b.is_active() {
+                // stay inactive until the runner confirms the file is open
+                b.set_active(false);
+                choose_file(
+                    b,
+                    "Record Session",
+                    gtk::FileChooserAction::Save,
+                    "Record",
+                    move |path| queue.dispatch(Command::StartRecording(path)),
+                );
+            } else {
+                // stays active until the runner confirms the recording stopped
+                b.set_active(true);
+                queue.dispatch(Command::StopRecording);
             }
         }
-    });
+    ));
+    ping_button.connect_clicked(clone!(
+        #[weak]
+        queue,
+        move |_| queue.dispatch(client::Command::Ping)
+    ));
+    row += 1;
+    // back-pressure from the runner, empty while commands go straight through
+    let queue_label = gtk::Label::new(None);
+    queue_label.set_halign(gtk::Align::Start);
+    queue_label.set_wrap(true);
+    queue_label.set_max_width_chars(16);
+    queue
+        .bind_property("status", &queue_label, "label")
+        .sync_create()
+        .build();
+    grid.attach(&queue_label, 0, row, 1, 1);
     // attach the subscribe frame to column 1, row, 0, 3 rows height
-    let notebook = build_action_notebook(cmd_tx);
+    let notebook = build_action_notebook(queue, subscriptions);
     grid.attach(&notebook, 1, 0, 1, 8);
 
     frame.set_child(Some(&grid));
     frame
 }
 
-pub fn build_action_notebook(cmd_tx: tokio::sync::mpsc::Sender<Command>) -> gtk::Notebook {
+pub fn build_action_notebook(
+    queue: &CommandQueue,
+    subscriptions: &gio::ListStore,
+) -> gtk::Notebook {
     let notebook = gtk::Notebook::new();
     notebook.set_tab_pos(gtk::PositionType::Top);
     notebook.set_hexpand(true);
     notebook.set_vexpand(false);
 
-    let pub_frame = build_publish(cmd_tx.clone());
+    let pub_frame = build_publish(queue);
     notebook.append_page(&pub_frame, Some(&gtk::Label::new(Some("Publish"))));
-    let sub_frame = build_subscribe(cmd_tx.clone());
+    let sub_frame = build_subscribe(queue, subscriptions);
     notebook.append_page(&sub_frame, Some(&gtk::Label::new(Some("Subscribe"))));
-    let unsub_frame = build_unsubscribe(cmd_tx);
+    let unsub_frame = build_unsubscribe(queue, subscriptions);
     notebook.append_page(&unsub_frame, Some(&gtk::Label::new(Some("Unsubscribe"))));
 
     notebook
 }
 
-pub(crate) fn build_subscribe(cmd_tx: tokio::sync::mpsc::Sender<Command>) -> gtk::Frame {
+pub(crate) fn build_subscribe(queue: &CommandQueue, subscriptions: &gio::ListStore) -> gtk::Frame {
     let frame = gtk::Frame::new(Some("Subscribe"));
     let grid = gtk::Grid::new();
     grid.set_column_spacing(4);
@@ -137,12 +290,18 @@ pub(crate) fn build_subscribe(cmd_tx: tokio::sync::mpsc::Sender<Command>) -> gtk
     topic_entry.set_width_chars(TOPIC_ENTRY_WIDTH_CHARS);
     grid.attach(&topic_entry, 0, row, 2, 1);
     row += 1;
+    let subscribe_result = gtk::Label::new(None);
+    subscribe_result.set_halign(gtk::Align::Start);
+    subscribe_result.set_wrap(true);
+    grid.attach(&subscribe_result, 0, row, 1, 1);
     let subscribe_button = gtk::Button::with_label("Subscribe");
     subscribe_button.set_halign(gtk::Align::End);
     grid.attach(&subscribe_button, 1, row, 1, 1);
 
     let topic_entry_clone = topic_entry.clone();
     let qos_combo_clone = qos_combo.clone();
+    let queue = queue.clone();
+    let subscriptions = subscriptions.clone();
     subscribe_button.connect_clicked(move |_| {
         let topic = topic_entry_clone.text().to_string();
         let qos = match qos_combo_clone.active_text().as_deref() {
@@ -159,28 +318,21 @@ pub(crate) fn build_subscribe(cmd_tx: tokio::sync::mpsc::Sender<Command>) -> gtk
         }
         println!("Subscribing to topic: {} with QoS: {:?}", topic, qos);
         // create a subscribe command and send it
-        let rt = tokio::runtime::Builder::new_current_thread()
-            .enable_all()
-            .build()
-            .unwrap();
-        match rt.block_on(async {
-            cmd_tx
-                .send(client::Command::Subscribe(packet_id, qos, topic))
-                .await
-        }) {
-            Ok(_) => {
-                println!("Subscribe command sent");
-            }
-            Err(e) => {
-                println!("Failed to send subscribe command: {e}");
-            }
-        }
+        await_result(
+            &subscribe_result,
+            "Subscribe",
+            reason::suback_name,
+            subscribe(&queue, &subscriptions, packet_id, qos, topic),
+        );
     });
 
     frame
 }
 
-pub(crate) fn build_unsubscribe(cmd_tx: tokio::sync::mpsc::Sender<Command>) -> gtk::Frame {
+pub(crate) fn build_unsubscribe(
+    queue: &CommandQueue,
+    subscriptions: &gio::ListStore,
+) -> gtk::Frame {
     let frame = gtk::Frame::new(Some("Unsubscribe"));
     let grid = gtk::Grid::new();
     grid.set_column_spacing(4);
@@ -216,11 +368,17 @@ pub(crate) fn build_unsubscribe(cmd_tx: tokio::sync::mpsc::Sender<Command>) -> g
     topic_entry.set_width_chars(TOPIC_ENTRY_WIDTH_CHARS);
     grid.attach(&topic_entry, 0, row, 2, 1);
     row += 1;
+    let unsubscribe_result = gtk::Label::new(None);
+    unsubscribe_result.set_halign(gtk::Align::Start);
+    unsubscribe_result.set_wrap(true);
+    grid.attach(&unsubscribe_result, 0, row, 1, 1);
     let unsubscribe_button = gtk::Button::with_label("Unsubscribe");
     unsubscribe_button.set_halign(gtk::Align::End);
     grid.attach(&unsubscribe_button, 1, row, 1, 1);
 
     let topic_entry_clone = topic_entry.clone();
+    let queue = queue.clone();
+    let subscriptions = subscriptions.clone();
     unsubscribe_button.connect_clicked(move |_| {
         let topic = topic_entry_clone.text().to_string();
         let packet_id = *packet_id.borrow();
@@ -231,22 +389,12 @@ pub(crate) fn build_unsubscribe(cmd_tx: tokio::sync::mpsc::Sender<Command>) -> g
         }
         println!("Unsubscribing from topic: {}", topic);
         // create an unsubscribe command and send it
-        let rt = tokio::runtime::Builder::new_current_thread()
-            .enable_all()
-            .build()
-            .unwrap();
-        match rt.block_on(async {
-            cmd_tx
-                .send(client::Command::Unsubscribe(packet_id, topic))
-                .await
-        }) {
-            Ok(_) => {
-                println!("Unsubscribe command sent");
-            }
-            Err(e) => {
-                println!("Failed to send unsubscribe command: {e}");
-            }
-        }
+        await_result(
+            &unsubscribe_result,
+            "Unsubscribe",
+            reason::name,
+            unsubscribe(&queue, &subscriptions, packet_id, topic),
+        );
     });
     frame
 }
@@ -255,29 +403,14 @@ fn build_connect(
     ping: &gtk::Button,
     clean_start_check: &gtk::CheckButton,
     client_setting: &ClientSetting,
-    cmd_tx: tokio::sync::mpsc::Sender<Command>,
+    queue: &CommandQueue,
 ) -> gtk::ToggleButton {
     let button = gtk::ToggleButton::with_label("Connect");
-    let host = Rc::clone(&client_setting.host);
-    let port = Rc::clone(&client_setting.port);
-    let username = Rc::clone(&client_setting.username);
-    let password = Rc::clone(&client_setting.password);
-    let with_credentials = Rc::clone(&client_setting.with_credentials);
-    let client_id = Rc::clone(&client_setting.client_id);
-    let session_expiry = Rc::clone(&client_setting.session_expiry);
-    let auto_ack = Rc::clone(&client_setting.auto_ack);
-    let auto_packet_id = Rc::clone(&client_setting.auto_packet_id);
-    let with_ping_resp = Rc::clone(&client_setting.with_ping_resp);
-    let with_tls = Rc::clone(&client_setting.with_tls);
-    let with_will = Rc::clone(&client_setting.with_will);
-    let will_topic = Rc::clone(&client_setting.will_topic);
-    let will_payload = Rc::clone(&client_setting.will_payload);
-    let will_qos = Rc::clone(&client_setting.will_qos);
-    let will_retain = Rc::clone(&client_setting.will_retain);
-    let will_delay = Rc::clone(&client_setting.will_delay);
-    let will_expiry = Rc::clone(&client_setting.will_expiry);
+    let client_setting = client_setting.clone();
 
     let click_handler = clone!(
+        #[weak]
+        queue,
         #[weak]
         clean_start_check,
         #[weak]
@@ -285,89 +418,45 @@ fn build_connect(
         move |b: &gtk::ToggleButton| {
             if b.is_active() {
                 b.set_label("Disconnect");
-                let mut connection = vaux_client::MqttConnection::new()
-                    .with_host((*host).borrow().as_str())
-                    .with_port(*port.borrow());
-                if *with_tls.borrow() {
-                    connection = connection.with_tls();
-                }
-                if *with_credentials.borrow() {
-                    connection = connection
-                        .with_credentials(username.borrow().as_str(), password.borrow().as_str());
-                }
-
-                if clean_start_check.is_active() {
-                    let rt = tokio::runtime::Builder::new_current_thread()
-                        .enable_all()
-                        .build()
-                        .unwrap();
-                    match rt.block_on(async {
-                        cmd_tx
-                            .send(client::Command::ResumeSession(connection))
-                            .await
-                    }) {
-                        Ok(_) => {
-                            println!("MQTT Client connected successfully");
-                            ping.set_sensitive(true);
-                        }
-                        Err(e) => {
-                            println!("Failed to connect MQTT Client: {e}");
-                            b.set_active(false); // Reset button state on failure
-                            return;
-                        }
-                    }
+                // the check is insensitive while there is no session to resume
+                let command = if clean_start_check.is_active() && clean_start_check.is_sensitive() {
+                    client_setting.resume_session()
                 } else {
-                    let mut builder = vaux_client::ClientBuilder::new(connection)
-                        .with_client_id((*client_id).borrow().as_str())
-                        .with_session_expiry(Duration::from_secs(*session_expiry.borrow() as u64))
-                        .with_auto_ack(*auto_ack.borrow())
-                        .with_auto_packet_id(*auto_packet_id.borrow())
-                        .with_pingresp(*with_ping_resp.borrow());
-                    if *with_will.borrow() {
-                        let will_message = 
-                            WillMessage::new((*will_topic).borrow().to_string(), 
-                                (*will_payload).borrow().as_bytes(),
-                                (*will_qos).borrow().clone(), *will_retain.borrow())
-                                .with_delay(*will_delay.borrow())
-                                .with_message_expiry(*will_expiry.borrow());
-                        builder = builder.with_will_message(will_message);
-                    };
-
-                    let rt = tokio::runtime::Builder::new_current_thread()
-                        .enable_all()
-                        .build()
-                        .unwrap();
-                    match rt.block_on(async {
-                        cmd_tx.send(client::Command::StartClient(builder)).await
-                    }) {
-                        Ok(_) => {
-                            println!("MQTT Client connected successfully");
-                            ping.set_sensitive(true);
-                        }
-                        Err(e) => {
-                            println!("Failed to connect MQTT Client: {e}");
-                            b.set_active(false); // Reset button state on failure
-                            return;
+                    client_setting.start_client()
+                };
+                let command = match command {
+                    Ok(command) => command,
+                    Err(e) => {
+                        println!("{e}");
+                        b.set_active(false); // Reset button state on failure
+                        return;
+                    }
+                };
+
+                let sent = queue.send(command);
+                glib::spawn_future_local(clone!(
+                    #[weak]
+                    b,
+                    #[weak]
+                    ping,
+                    async move {
+                        match sent.await {
+                            Ok(_) => {
+                                println!("MQTT Client connected successfully");
+                                ping.set_sensitive(true);
+                            }
+                            Err(e) => {
+                                println!("Failed to connect MQTT Client: {e}");
+                                b.set_label("Connect");
+                                b.set_active(false); // Reset button state on failure
+                            }
                         }
                     }
-                }
+                ));
             } else {
                 b.set_label("Connect");
                 ping.set_sensitive(false);
-                let rt = tokio::runtime::Builder::new_current_thread()
-                    .enable_all()
-                    .build()
-                    .unwrap();
-                match rt.block_on(async { cmd_tx.send(client::Command::StopClient).await }) {
-                    Ok(_) => {
-                        println!("MQTT Client disconnected successfully");
-                    }
-                    Err(e) => {
-                        println!("Failed to disconnect MQTT Client: {e}");
-                    }
-                }
-                // Reset the clean start checkbox
-                clean_start_check.set_sensitive(true);
+                queue.dispatch(client::Command::StopClient);
             }
         }
     );
@@ -376,7 +465,7 @@ fn build_connect(
     button
 }
 
-fn build_publish(cmd_tx: tokio::sync::mpsc::Sender<Command>) -> gtk::Frame {
+fn build_publish(queue: &CommandQueue) -> gtk::Frame {
     let frame = gtk::Frame::new(Some("Publish"));
 
     // frame grid layout
@@ -426,10 +515,16 @@ fn build_publish(cmd_tx: tokio::sync::mpsc::Sender<Command>) -> gtk::Frame {
     message_entry.set_size_request(PUBLISH_TEXT_WIDTH_REQUEST, PUBLISH_TEXT_HEIGHT_REQUEST);
     grid.attach(&message_entry, 0, 3, 3, 1);
     // publish button
+    // publish result
+    let publish_result = gtk::Label::new(None);
+    publish_result.set_halign(gtk::Align::Start);
+    publish_result.set_wrap(true);
+    grid.attach(&publish_result, 0, 4, 2, 1);
     let publish_button = gtk::Button::with_label("Publish");
     publish_button.set_halign(gtk::Align::End);
     grid.attach(&publish_button, 2, 4, 1, 1);
     // publish button click handler
+    let queue = queue.clone();
     publish_button.connect_clicked(move |_| {
         let topic = topic_entry.text().to_string();
         let buffer = message_entry.buffer();
@@ -474,18 +569,12 @@ fn build_publish(cmd_tx: tokio::sync::mpsc::Sender<Command>) -> gtk::Frame {
             print!("{:02x} ", byte);
         }
         println!();
-        let rt = tokio::runtime::Builder::new_current_thread()
-            .enable_all()
-            .build()
-            .unwrap();
-        match rt.block_on(async { cmd_tx.send(client::Command::Publish(publish)).await }) {
-            Ok(_) => {
-                println!("Publish command sent");
-            }
-            Err(e) => {
-                println!("Failed to send publish command: {e}");
-            }
-        }
+        await_result(
+            &publish_result,
+            "Publish",
+            reason::name,
+            queue.send(client::Command::Publish(publish)),
+        );
     });
 
     frame
@@ -0,0 +1,174 @@
+//! Local Broker popover: start and stop the embedded broker and change its
+//! injected faults while it runs.
+
+use std::{cell::RefCell, rc::Rc, time::Duration};
+
+use glib::clone;
+use gtk4::{self as gtk};
+
+use gtk::prelude::*;
+
+use crate::broker::{BrokerHandle, DEFAULT_PORT, Faults};
+
+const STATUS_SECONDS: u32 = 1;
+
+fn spin(max: f64, value: f64, tooltip: &str) -> gtk::SpinButton {
+    let spin = gtk::SpinButton::with_range(0.0, max, 1.0);
+    spin.set_value(value);
+    spin.set_tooltip_text(Some(tooltip));
+    spin
+}
+
+pub fn build_broker_button() -> gtk::MenuButton {
+    let grid = gtk::Grid::new();
+    grid.set_column_spacing(4);
+    grid.set_row_spacing(4);
+
+    let port_spin = spin(
+        u16::MAX as f64,
+        DEFAULT_PORT as f64,
+        "Port on localhost, 0 for any free port",
+    );
+    let ack_delay_spin = spin(
+        60_000.0,
+        0.0,
+        "Wait before each acknowledgement, in milliseconds",
+    );
+    let drop_spin = spin(
+        10_000.0,
+        0.0,
+        "Ignore every Nth packet received from clients, 0 for none",
+    );
+    let disconnect_spin = spin(
+        86_400.0,
+        0.0,
+        "Disconnect each client this many seconds after it connects, 0 for never",
+    );
+    for (row, (name, widget)) in [
+        ("Port:", &port_spin),
+        ("Ack Delay (ms):", &ack_delay_spin),
+        ("Drop Every Nth Packet:", &drop_spin),
+        ("Disconnect After (s):", &disconnect_spin),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let label = gtk::Label::new(Some(name));
+        label.set_halign(gtk::Align::End);
+        grid.attach(&label, 0, row as i32, 1, 1);
+        grid.attach(widget, 1, row as i32, 1, 1);
+    }
+
+    let start_button = gtk::Button::with_label("Start");
+    grid.attach(&start_button, 0, 4, 1, 1);
+    let disconnect_button = gtk::Button::with_label("Disconnect All");
+    disconnect_button.set_tooltip_text(Some(
+        "Send every connected client DISCONNECT with Administrative action",
+    ));
+    disconnect_button.set_sensitive(false);
+    grid.attach(&disconnect_button, 1, 4, 1, 1);
+    let status_label = gtk::Label::new(Some("Stopped"));
+    status_label.set_halign(gtk::Align::Start);
+    grid.attach(&status_label, 0, 5, 2, 1);
+
+    let popover = gtk::Popover::new();
+    popover.set_child(Some(&grid));
+    let menu_button = gtk::MenuButton::new();
+    menu_button.set_label("Local Broker");
+    menu_button.set_tooltip_text(Some("Embedded MQTT v5 broker for offline testing"));
+    menu_button.set_popover(Some(&popover));
+
+    let faults = clone!(
+        #[weak]
+        ack_delay_spin,
+        #[weak]
+        drop_spin,
+        #[weak]
+        disconnect_spin,
+        #[upgrade_or_default]
+        move || Faults {
+            ack_delay: Duration::from_millis(ack_delay_spin.value_as_int() as u64),
+            drop_every: drop_spin.value_as_int() as u32,
+            disconnect_after: Some(disconnect_spin.value_as_int() as u64)
+                .filter(|seconds| *seconds > 0)
+                .map(Duration::from_secs),
+        }
+    );
+    let handle: Rc<RefCell<Option<BrokerHandle>>> = Rc::new(RefCell::new(None));
+    for spin in [&ack_delay_spin, &drop_spin, &disconnect_spin] {
+        spin.connect_value_changed(clone!(
+            #[strong]
+            handle,
+            #[strong]
+            faults,
+            move |_| {
+                if let Some(handle) = handle.borrow().as_ref() {
+                    handle.broker().set_faults(faults());
+                }
+            }
+        ));
+    }
+
+    start_button.connect_clicked(clone!(
+        #[strong]
+        handle,
+        #[weak]
+        port_spin,
+        #[weak]
+        disconnect_button,
+        #[weak]
+        status_label,
+        move |button| {
+            if handle.borrow_mut().take().is_some() {
+                button.set_label("Start");
+                port_spin.set_sensitive(true);
+                disconnect_button.set_sensitive(false);
+                status_label.set_text("Stopped");
+                return;
+            }
+            match BrokerHandle::start(port_spin.value_as_int() as u16, faults()) {
+                Ok(started) => {
+                    status_label.set_text(&format!("Listening on {}", started.address()));
+                    handle.replace(Some(started));
+                    button.set_label("Stop");
+                    port_spin.set_sensitive(false);
+                    disconnect_button.set_sensitive(true);
+                }
+                Err(e) => status_label.set_text(&e),
+            }
+        }
+    ));
+    disconnect_button.connect_clicked(clone!(
+        #[strong]
+        handle,
+        move |_| {
+            if let Some(handle) = handle.borrow().as_ref() {
+                handle.broker().disconnect_all();
+            }
+        }
+    ));
+
+    glib::timeout_add_seconds_local(
+        STATUS_SECONDS,
+        clone!(
+            #[weak]
+            status_label,
+            #[strong]
+            handle,
+            #[upgrade_or]
+            glib::ControlFlow::Break,
+            move || {
+                if let Some(handle) = handle.borrow().as_ref() {
+                    status_label.set_text(&format!(
+                        "Listening on {}, {} clients connected",
+                        handle.address(),
+                        handle.broker().clients()
+                    ));
+                }
+                glib::ControlFlow::Continue
+            }
+        ),
+    );
+
+    menu_button
+}
@@ -0,0 +1,61 @@
+use gtk4::{self as gtk};
+
+use gtk::prelude::*;
+
+use crate::model::ConnAckObject;
+
+const FRAME_MARGIN: i32 = 6;
+
+/// Rows of the CONNACK summary as (label, ConnAckObject property name).
+const SUMMARY_ROWS: [(&str, &str); 15] = [
+    ("Reason:", "reason"),
+    ("Session Present:", "session-present"),
+    ("Assigned Client ID:", "assigned-client-id"),
+    ("Session Expiry:", "session-expiry"),
+    ("Server Keep Alive:", "server-keep-alive"),
+    ("Receive Maximum:", "receive-maximum"),
+    ("Maximum QoS:", "maximum-qos"),
+    ("Retain Available:", "retain-available"),
+    ("Wildcard Subscriptions:", "wildcard-available"),
+    ("Shared Subscriptions:", "shared-available"),
+    ("Subscription Identifiers:", "subscription-id-available"),
+    ("Topic Alias Maximum:", "topic-alias-maximum"),
+    ("Maximum Packet Size:", "maximum-packet-size"),
+    ("Response Information:", "response-information"),
+    ("Server Reference:", "server-reference"),
+];
+
+/// Panel showing the session limits granted by the broker in the last CONNACK.
+pub fn build_connack_summary(connack: &ConnAckObject) -> gtk::Frame {
+    let frame = gtk::Frame::new(Some("Broker Limits"));
+    let grid = gtk::Grid::new();
+    grid.set_column_spacing(12);
+    grid.set_row_spacing(2);
+    grid.set_margin_start(FRAME_MARGIN);
+    grid.set_margin_end(FRAME_MARGIN);
+    grid.set_margin_top(FRAME_MARGIN);
+    grid.set_margin_bottom(FRAME_MARGIN);
+
+    // two label/value column pairs keep the panel no taller than the other tabs
+    let rows_per_column = SUMMARY_ROWS.len().div_ceil(2);
+    for (i, (name, property)) in SUMMARY_ROWS.iter().enumerate() {
+        let column = (i / rows_per_column) as i32 * 2;
+        let row = (i % rows_per_column) as i32;
+
+        let label = gtk::Label::new(Some(name));
+        label.set_halign(gtk::Align::End);
+        grid.attach(&label, column, row, 1, 1);
+
+        let value = gtk::Label::new(None);
+        value.set_halign(gtk::Align::Start);
+        value.set_selectable(true);
+        connack
+            .bind_property(property, &value, "label")
+            .sync_create()
+            .build();
+        grid.attach(&value, column + 1, row, 1, 1);
+    }
+
+    frame.set_child(Some(&grid));
+    frame
+}
@@ -5,7 +5,12 @@ use gtk4::{self as gtk};
 use glib_macros::clone;
 use gtk::prelude::*;
 
+use crate::auth::AuthMethod;
 use crate::client::ClientSetting;
+use crate::payload::{PayloadEncoding, to_hex};
+use crate::profile::Profile;
+use crate::ui::file::choose_file;
+use crate::ui::properties::{build_user_properties_editor, user_properties};
 
 const FRAME_MARGIN: i32 = 6;
 const FILE_ENTRY_WIDTH_CHARS: i32 = 50;
@@ -17,6 +22,7 @@ const WILL_DELAY_MIN: f64 = 0.0; // 1 second
 const WILL_DELAY_MAX: f64 = 120.0; // 2 minutes
 const WILL_EXPIRY_MIN: f64 = 0.0; // 0 seconds
 const WILL_EXPIRY_MAX: f64 = 3600.0; // 1 hour
+const PREVIEW_WIDTH_CHARS: i32 = 48;
 
 pub fn build_connection_notebook(
     client_setting: &ClientSetting,
@@ -39,6 +45,7 @@ pub fn build_connection_notebook(
         Rc::clone(&client_setting.with_credentials),
         Rc::clone(&client_setting.username),
         Rc::clone(&client_setting.password),
+        Rc::clone(&client_setting.auth_method),
     );
     notebook.append_page(&cred_frame, Some(&gtk::Label::new(Some("Credentials"))));
 
@@ -67,6 +74,25 @@ pub fn build_will(client_setting: &ClientSetting) -> gtk::Frame {
 
     let mut row = 0;
 
+    // live preview of the encoded will properties, refreshed on every edit
+    let preview_label = gtk::Label::new(None);
+    preview_label.set_halign(gtk::Align::Start);
+    preview_label.set_valign(gtk::Align::Start);
+    preview_label.set_selectable(true);
+    preview_label.set_wrap(true);
+    preview_label.set_wrap_mode(gtk::pango::WrapMode::Char);
+    preview_label.set_max_width_chars(PREVIEW_WIDTH_CHARS);
+    preview_label.add_css_class("monospace");
+    let _client_setting = client_setting.clone();
+    let _preview_label = preview_label.clone();
+    let refresh_preview: Rc<dyn Fn()> = Rc::new(move || match _client_setting.will_properties() {
+        Ok(Some(encoded)) => {
+            _preview_label.set_text(&format!("{} bytes: {}", encoded.len(), to_hex(&encoded)))
+        }
+        Ok(None) => _preview_label.set_text("no will message"),
+        Err(e) => _preview_label.set_text(&format!("invalid: {e}")),
+    });
+
     let will_enable = Rc::clone(&client_setting.with_will);
     let will_enable_toggle = gtk::CheckButton::new();
     will_enable_toggle.set_active(*will_enable.borrow());
@@ -135,11 +161,14 @@ pub fn build_will(client_setting: &ClientSetting) -> gtk::Frame {
     will_payload_text.set_size_request(MESSAGE_TEXT_WIDTH_REQUEST, MESSAGE_TEXT_HEIGHT_REQUEST);
     will_payload_text.set_wrap_mode(gtk::WrapMode::Word);
     let will_payload = Rc::clone(&client_setting.will_payload);
+    // the preview comes from the encoded will, which needs a valid payload
+    let _refresh_preview = Rc::clone(&refresh_preview);
     will_payload_text.buffer().connect_changed(move |b| {
         let start = b.start_iter();
         let end = b.end_iter();
         let text = b.text(&start, &end, false);
         *(*will_payload).borrow_mut() = text.to_string();
+        _refresh_preview();
     });
     let will_payload_scrolled = gtk::ScrolledWindow::new();
     will_payload_scrolled.set_child(Some(&will_payload_text));
@@ -166,9 +195,11 @@ pub fn build_will(client_setting: &ClientSetting) -> gtk::Frame {
     will_delay_entry.set_tooltip_text(Some("Delay before the Will Message is sent"));
     will_delay_entry.set_sensitive(*will_enable.borrow());
     let will_delay = Rc::clone(&client_setting.will_delay);
+    let _refresh_preview = Rc::clone(&refresh_preview);
     will_delay_entry.connect_value_changed(move |spin_button| {
         let value = spin_button.value() as u32;
         *(*will_delay).borrow_mut() = value;
+        _refresh_preview();
     });
     grid.attach(&will_delay_entry, 1, row, 1, 1);
     row += 1;
@@ -190,12 +221,24 @@ pub fn build_will(client_setting: &ClientSetting) -> gtk::Frame {
     will_expiry_entry.set_tooltip_text(Some("Expiry time for the Will Message"));
     will_expiry_entry.set_sensitive(*will_enable.borrow());
     let will_expiry = Rc::clone(&client_setting.will_expiry);
+    let _refresh_preview = Rc::clone(&refresh_preview);
     will_expiry_entry.connect_value_changed(move |spin_button| {
         let value = spin_button.value() as u32;
         *(*will_expiry).borrow_mut() = value;
+        _refresh_preview();
     });
     grid.attach(&will_expiry_entry, 1, row, 1, 1);
 
+    let properties_grid =
+        build_will_properties(client_setting, &will_payload_text, &refresh_preview);
+    properties_grid.set_sensitive(*will_enable.borrow());
+    let label = gtk::Label::new(Some("Encoded Will Properties:"));
+    label.set_halign(gtk::Align::Start);
+    properties_grid.attach(&label, 0, 20, 2, 1);
+    properties_grid.attach(&preview_label, 0, 21, 2, 1);
+    grid.attach(&properties_grid, 2, 0, 1, row + 1);
+    refresh_preview();
+
     let will_toggle_handler = clone!(
         #[weak]
         will_qos_combo,
@@ -209,6 +252,10 @@ pub fn build_will(client_setting: &ClientSetting) -> gtk::Frame {
         will_delay_entry,
         #[weak]
         will_expiry_entry,
+        #[weak]
+        properties_grid,
+        #[strong]
+        refresh_preview,
         move |b: &gtk::CheckButton| {
             println!("Will Message toggled: {}", b.is_active());
             *(*will_enable).borrow_mut() = b.is_active();
@@ -218,6 +265,8 @@ pub fn build_will(client_setting: &ClientSetting) -> gtk::Frame {
             will_payload_text.set_sensitive(b.is_active());
             will_delay_entry.set_sensitive(b.is_active());
             will_expiry_entry.set_sensitive(b.is_active());
+            properties_grid.set_sensitive(b.is_active());
+            refresh_preview();
         }
     );
     will_enable_toggle.connect_toggled(will_toggle_handler);
@@ -227,6 +276,137 @@ pub fn build_will(client_setting: &ClientSetting) -> gtk::Frame {
     frame
 }
 
+/// Payload encoding and MQTT v5 will properties, laid out beside the basic
+/// will settings.
+fn build_will_properties(
+    client_setting: &ClientSetting,
+    will_payload_text: &gtk::TextView,
+    refresh_preview: &Rc<dyn Fn()>,
+) -> gtk::Grid {
+    let grid = gtk::Grid::new();
+    grid.set_column_spacing(4);
+    grid.set_row_spacing(4);
+    grid.set_margin_start(FRAME_MARGIN * 2);
+
+    let mut row = 0;
+
+    let label = gtk::Label::new(Some("Payload Encoding:"));
+    label.set_halign(gtk::Align::End);
+    grid.attach(&label, 0, row, 1, 1);
+    let encoding_box = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+    let encoding_combo = gtk::ComboBoxText::new();
+    for encoding in PayloadEncoding::ALL {
+        encoding_combo.append(Some(encoding.name()), encoding.name());
+    }
+    encoding_combo.set_active_id(Some(client_setting.will_payload_encoding.borrow().name()));
+    encoding_combo.set_tooltip_text(Some("How the will payload text is converted to bytes"));
+    let will_payload_encoding = Rc::clone(&client_setting.will_payload_encoding);
+    encoding_combo.connect_changed(move |combo| {
+        if let Some(encoding) = combo
+            .active_id()
+            .as_deref()
+            .and_then(PayloadEncoding::from_name)
+        {
+            *will_payload_encoding.borrow_mut() = encoding;
+        }
+    });
+    encoding_box.append(&encoding_combo);
+    let load_button = gtk::Button::with_label("Load File...");
+    load_button.set_tooltip_text(Some("Load a binary payload (e.g. CBOR) as base64"));
+    load_button.connect_clicked(clone!(
+        #[weak]
+        encoding_combo,
+        #[weak]
+        will_payload_text,
+        move |b| {
+            choose_file(
+                b,
+                "Load Will Payload",
+                gtk::FileChooserAction::Open,
+                "Load",
+                move |path| match std::fs::read(&path) {
+                    Ok(payload) => {
+                        encoding_combo.set_active_id(Some(PayloadEncoding::Base64.name()));
+                        will_payload_text
+                            .buffer()
+                            .set_text(&PayloadEncoding::Base64.encode(&payload));
+                    }
+                    Err(e) => println!("Failed to read {}: {e}", path.display()),
+                },
+            );
+        }
+    ));
+    encoding_box.append(&load_button);
+    grid.attach(&encoding_box, 1, row, 1, 1);
+    row += 1;
+
+    let payload_utf8_check = gtk::CheckButton::with_label("Payload is UTF-8 (format indicator)");
+    payload_utf8_check.set_active(*client_setting.will_payload_utf8.borrow());
+    payload_utf8_check.set_tooltip_text(Some("Set the Payload Format Indicator property to 1"));
+    let will_payload_utf8 = Rc::clone(&client_setting.will_payload_utf8);
+    let _refresh_preview = Rc::clone(refresh_preview);
+    payload_utf8_check.connect_toggled(move |b| {
+        *will_payload_utf8.borrow_mut() = b.is_active();
+        _refresh_preview();
+    });
+    grid.attach(&payload_utf8_check, 1, row, 1, 1);
+    row += 1;
+
+    let entries = [
+        (
+            "Content Type:",
+            "MIME type of the payload, e.g. application/cbor",
+            Rc::clone(&client_setting.will_content_type),
+        ),
+        (
+            "Response Topic:",
+            "Response Topic property of the will",
+            Rc::clone(&client_setting.will_response_topic),
+        ),
+        (
+            "Correlation Data:",
+            "Correlation Data property as hex bytes",
+            Rc::clone(&client_setting.will_correlation_data),
+        ),
+    ];
+    for (name, tooltip, value) in entries {
+        let label = gtk::Label::new(Some(name));
+        label.set_halign(gtk::Align::End);
+        grid.attach(&label, 0, row, 1, 1);
+        let entry = gtk::Entry::new();
+        entry.set_text(value.borrow().as_str());
+        entry.set_tooltip_text(Some(tooltip));
+        entry.set_width_chars(TOPIC_ENTRY_WIDTH_CHARS / 2);
+        let _refresh_preview = Rc::clone(refresh_preview);
+        entry.connect_changed(move |entry| {
+            *value.borrow_mut() = entry.text().to_string();
+            _refresh_preview();
+        });
+        grid.attach(&entry, 1, row, 1, 1);
+        row += 1;
+    }
+
+    let label = gtk::Label::new(Some("User Properties:"));
+    label.set_halign(gtk::Align::End);
+    label.set_valign(gtk::Align::Start);
+    grid.attach(&label, 0, row, 1, 1);
+    let (properties_scrolled, properties_text) =
+        build_user_properties_editor("Will user properties, one key=value per line");
+    let will_user_properties = Rc::clone(&client_setting.will_user_properties);
+    let _refresh_preview = Rc::clone(refresh_preview);
+    properties_text.buffer().connect_changed(clone!(
+        #[weak]
+        properties_text,
+        move |_| {
+            *will_user_properties.borrow_mut() = user_properties(&properties_text);
+            _refresh_preview();
+        }
+    ));
+    grid.attach(&properties_scrolled, 1, row, 1, 1);
+
+    grid
+}
+
 pub fn build_settings(client_setting: &ClientSetting) -> (gtk::Frame, gtk::CheckButton) {
     // Grid for MQTT client settings controls
 
@@ -248,7 +428,6 @@ pub fn build_settings(client_setting: &ClientSetting) -> (gtk::Frame, gtk::Check
     clean_start_check.set_tooltip_text(Some(
         "Resume prior session when present, otherwise clean start",
     ));
-    clean_start_check.set_sensitive((*client_setting.clean_start.borrow()).is_some());
     let _clean_start = Rc::clone(&client_setting.clean_start);
     clean_start_check.connect_toggled(move |button: &gtk::CheckButton| {
         if button.is_active() {
@@ -319,6 +498,24 @@ pub fn build_settings(client_setting: &ClientSetting) -> (gtk::Frame, gtk::Check
     grid.attach(&session_expiry_entry, 1, row, 1, 1);
     row += 1;
 
+    let label = gtk::Label::new(Some("Keep Alive:"));
+    label.set_halign(gtk4::Align::End);
+    let gtk_adjustment = gtk::Adjustment::new(60.0, 0.0, 65535.0, 1.0, 15.0, 1.0);
+    let keep_alive_entry = gtk::SpinButton::new(Some(&gtk_adjustment), 1.0, 0);
+    keep_alive_entry.set_value(*client_setting.keep_alive.borrow() as f64);
+    keep_alive_entry.set_tooltip_text(Some(
+        "Keep alive in seconds requested in CONNECT, 0 disables it",
+    ));
+    let _keep_alive = Rc::clone(&client_setting.keep_alive);
+    keep_alive_entry.connect_value_changed(move |spin_button| {
+        let value = spin_button.value() as u16;
+        *(*_keep_alive).borrow_mut() = value;
+    });
+    keep_alive_entry.set_margin_end(4);
+    grid.attach(&label, 0, row, 1, 1);
+    grid.attach(&keep_alive_entry, 1, row, 1, 1);
+    row += 1;
+
     let auto_ack = gtk::CheckButton::with_label("Enable Auto Ack");
     auto_ack.set_halign(gtk::Align::Start);
     auto_ack.set_tooltip_text(Some("Enable automatic acknowledgment of MQTT messages"));
@@ -351,6 +548,28 @@ pub fn build_settings(client_setting: &ClientSetting) -> (gtk::Frame, gtk::Check
         *(*_with_ping_resp).borrow_mut() = button.is_active();
     });
     grid.attach(&with_ping_resp, 1, row, 1, 1);
+    row += 1;
+
+    let save_profile_button = gtk::Button::with_label("Save Profile...");
+    save_profile_button.set_halign(gtk::Align::Start);
+    save_profile_button.set_tooltip_text(Some(
+        "Save the connection settings for use with --headless --profile",
+    ));
+    let _client_setting = client_setting.clone();
+    save_profile_button.connect_clicked(move |b| {
+        let client_setting = _client_setting.clone();
+        choose_file(
+            b,
+            "Save Profile",
+            gtk::FileChooserAction::Save,
+            "Save",
+            move |path| match Profile::from_setting(&client_setting).save(&path) {
+                Ok(()) => println!("Profile saved to {}", path.display()),
+                Err(e) => eprintln!("Failed to save profile: {e}"),
+            },
+        );
+    });
+    grid.attach(&save_profile_button, 1, row, 1, 1);
 
     (frame, clean_start_check)
 }
@@ -443,6 +662,7 @@ pub fn build_credentials(
     with_cred: Rc<RefCell<bool>>,
     username: Rc<RefCell<String>>,
     password: Rc<RefCell<String>>,
+    auth_method: Rc<RefCell<AuthMethod>>,
 ) -> gtk::Frame {
     let frame = gtk::Frame::new(Some("Credentials"));
     let grid = gtk::Grid::new();
@@ -454,6 +674,7 @@ pub fn build_credentials(
 
     let username_entry = gtk::Entry::new();
     let password_entry = gtk::Entry::new();
+    let auth_method_combo = gtk::ComboBoxText::new();
 
     let cred_check = gtk::CheckButton::with_label("Use Credentials");
     cred_check.set_halign(gtk::Align::Start);
@@ -469,15 +690,19 @@ pub fn build_credentials(
         username_entry,
         #[weak]
         password_entry,
+        #[weak]
+        auth_method_combo,
         move |button: &gtk::CheckButton| {
             println!("Credentials toggled: {}", button.is_active());
             *(*_with_cred).borrow_mut() = button.is_active();
             if button.is_active() {
                 username_entry.set_sensitive(true);
                 password_entry.set_sensitive(true);
+                auth_method_combo.set_sensitive(true);
             } else {
                 username_entry.set_sensitive(false);
                 password_entry.set_sensitive(false);
+                auth_method_combo.set_sensitive(false);
             }
         }
     );
@@ -504,6 +729,23 @@ pub fn build_credentials(
         *password.borrow_mut() = entry.text().to_string();
     });
     grid.attach(&password_entry, 1, 2, 1, 1);
+    let label = gtk::Label::new(Some("Method:"));
+    label.set_halign(gtk4::Align::End);
+    grid.attach(&label, 0, 3, 1, 1);
+    for method in AuthMethod::ALL {
+        auth_method_combo.append(Some(method.name()), method.name());
+    }
+    auth_method_combo.set_active_id(Some(auth_method.borrow().name()));
+    auth_method_combo.set_tooltip_text(Some(
+        "Username/password in CONNECT or an MQTT v5 enhanced authentication method",
+    ));
+    auth_method_combo.set_sensitive(*with_cred.borrow());
+    auth_method_combo.connect_changed(move |combo| {
+        if let Some(method) = combo.active_id().as_deref().and_then(AuthMethod::from_name) {
+            *auth_method.borrow_mut() = method;
+        }
+    });
+    grid.attach(&auth_method_combo, 1, 3, 1, 1);
 
     frame.set_child(Some(&grid));
     frame
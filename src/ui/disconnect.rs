@@ -0,0 +1,148 @@
+use std::rc::Rc;
+
+use gtk4::{self as gtk};
+
+use glib_macros::clone;
+use gtk::prelude::*;
+
+use crate::client::DisconnectOptions;
+use crate::reason;
+use crate::ui::properties::{build_user_properties_editor, user_properties};
+
+const DIALOG_MARGIN: i32 = 10;
+const SESSION_EXPIRY_MAX: f64 = u32::MAX as f64;
+
+/// Show the modal disconnect dialog. `on_disconnect` is called with the chosen
+/// options when the user confirms.
+pub fn show_disconnect_dialog(
+    parent: &impl IsA<gtk::Widget>,
+    on_disconnect: impl Fn(DisconnectOptions) + 'static,
+) {
+    let dialog = gtk::Window::builder()
+        .title("Disconnect")
+        .modal(true)
+        .resizable(false)
+        .build();
+    if let Some(window) = parent.root().and_downcast::<gtk::Window>() {
+        dialog.set_transient_for(Some(&window));
+    }
+
+    let grid = gtk::Grid::new();
+    grid.set_column_spacing(4);
+    grid.set_row_spacing(4);
+    grid.set_margin_start(DIALOG_MARGIN);
+    grid.set_margin_end(DIALOG_MARGIN);
+    grid.set_margin_top(DIALOG_MARGIN);
+    grid.set_margin_bottom(DIALOG_MARGIN);
+
+    let mut row = 0;
+
+    let label = gtk::Label::new(Some("Reason Code:"));
+    label.set_halign(gtk::Align::End);
+    grid.attach(&label, 0, row, 1, 1);
+    let reason_combo = gtk::ComboBoxText::new();
+    for code in reason::CLIENT_DISCONNECT_REASONS {
+        reason_combo.append(
+            Some(&code.to_string()),
+            &format!("0x{code:02X} {}", reason::disconnect_name(code)),
+        );
+    }
+    reason_combo.set_active(Some(0));
+    reason_combo.set_tooltip_text(Some("0x04 asks the broker to publish the Will Message"));
+    grid.attach(&reason_combo, 1, row, 1, 1);
+    row += 1;
+
+    let expiry_check = gtk::CheckButton::with_label("Session Expiry Override (seconds):");
+    expiry_check.set_tooltip_text(Some("Replace the session expiry interval sent in CONNECT"));
+    grid.attach(&expiry_check, 0, row, 1, 1);
+    let gtk_adjustment = gtk::Adjustment::new(0.0, 0.0, SESSION_EXPIRY_MAX, 1.0, 60.0, 1.0);
+    let expiry_entry = gtk::SpinButton::new(Some(&gtk_adjustment), 1.0, 0);
+    expiry_entry.set_sensitive(false);
+    expiry_check.connect_toggled(clone!(
+        #[weak]
+        expiry_entry,
+        move |b| expiry_entry.set_sensitive(b.is_active())
+    ));
+    grid.attach(&expiry_entry, 1, row, 1, 1);
+    row += 1;
+
+    let label = gtk::Label::new(Some("Reason String:"));
+    label.set_halign(gtk::Align::End);
+    grid.attach(&label, 0, row, 1, 1);
+    let reason_string_entry = gtk::Entry::new();
+    reason_string_entry.set_placeholder_text(Some("Optional reason string"));
+    grid.attach(&reason_string_entry, 1, row, 1, 1);
+    row += 1;
+
+    let label = gtk::Label::new(Some("User Properties:"));
+    label.set_halign(gtk::Align::End);
+    label.set_valign(gtk::Align::Start);
+    grid.attach(&label, 0, row, 1, 1);
+    let (properties_scrolled, properties_text) =
+        build_user_properties_editor("User properties, one key=value per line");
+    grid.attach(&properties_scrolled, 1, row, 1, 1);
+    row += 1;
+
+    let abrupt_check = gtk::CheckButton::with_label("Abrupt drop (close socket, no DISCONNECT)");
+    abrupt_check.set_tooltip_text(Some(
+        "Simulate a network failure so the broker publishes the Will Message",
+    ));
+    abrupt_check.connect_toggled(clone!(
+        #[weak]
+        reason_combo,
+        #[weak]
+        expiry_check,
+        #[weak]
+        reason_string_entry,
+        #[weak]
+        properties_text,
+        move |b| {
+            let send_packet = !b.is_active();
+            reason_combo.set_sensitive(send_packet);
+            expiry_check.set_sensitive(send_packet);
+            reason_string_entry.set_sensitive(send_packet);
+            properties_text.set_sensitive(send_packet);
+        }
+    ));
+    grid.attach(&abrupt_check, 0, row, 2, 1);
+    row += 1;
+
+    let button_box = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+    button_box.set_halign(gtk::Align::End);
+    let cancel_button = gtk::Button::with_label("Cancel");
+    let disconnect_button = gtk::Button::with_label("Disconnect");
+    button_box.append(&cancel_button);
+    button_box.append(&disconnect_button);
+    grid.attach(&button_box, 0, row, 2, 1);
+
+    cancel_button.connect_clicked(clone!(
+        #[weak]
+        dialog,
+        move |_| dialog.close()
+    ));
+    let on_disconnect = Rc::new(on_disconnect);
+    disconnect_button.connect_clicked(clone!(
+        #[weak]
+        dialog,
+        move |_| {
+            let reason_string = reason_string_entry.text().to_string();
+            let options = DisconnectOptions {
+                reason_code: reason_combo
+                    .active_id()
+                    .and_then(|id| id.parse().ok())
+                    .unwrap_or_default(),
+                session_expiry: expiry_check
+                    .is_active()
+                    .then(|| expiry_entry.value() as u32),
+                reason_string: (!reason_string.is_empty()).then_some(reason_string),
+                user_properties: user_properties(&properties_text),
+                abrupt: abrupt_check.is_active(),
+            };
+            on_disconnect(options);
+            dialog.close();
+        }
+    ));
+
+    dialog.set_child(Some(&grid));
+    dialog.present();
+}
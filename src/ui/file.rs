@@ -0,0 +1,33 @@
+use std::path::PathBuf;
+
+use gtk4::{self as gtk};
+
+use gtk::prelude::*;
+
+/// Show a native file chooser and call `on_path` with the chosen file.
+pub fn choose_file(
+    parent: &impl IsA<gtk::Widget>,
+    title: &str,
+    action: gtk::FileChooserAction,
+    accept_label: &str,
+    on_path: impl Fn(PathBuf) + 'static,
+) {
+    let chooser = gtk::FileChooserNative::new(
+        Some(title),
+        parent.root().and_downcast_ref::<gtk::Window>(),
+        action,
+        Some(accept_label),
+        Some("Cancel"),
+    );
+    // the native dialog is not owned by a window, keep it alive until it responds
+    let keep_alive = std::cell::RefCell::new(Some(chooser.clone()));
+    chooser.connect_response(move |chooser, response| {
+        if response == gtk::ResponseType::Accept {
+            if let Some(path) = chooser.file().and_then(|f| f.path()) {
+                on_path(path);
+            }
+        }
+        keep_alive.borrow_mut().take();
+    });
+    chooser.show();
+}
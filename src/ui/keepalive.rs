@@ -0,0 +1,192 @@
+//! Keep-alive monitor: negotiated keep alive, time since the last control
+//! packets, ping round trip chart and the ping schedule.
+
+use std::{cell::RefCell, rc::Rc, time::Duration, time::Instant};
+
+use glib::clone;
+use gtk4::{self as gtk};
+
+use gtk::prelude::*;
+
+use crate::client::{self, ClientSetting, Command, PingSchedule};
+use crate::keepalive::KeepAliveMonitor;
+use crate::model::CommandQueue;
+use crate::stats::Statistics;
+use crate::ui::stats::{RTT_COLOR, build_chart, draw_series, millis};
+
+const FRAME_MARGIN: i32 = 6;
+const SCHEDULES: [&str; 3] = ["Manual (Ping button)", "Every", "Suppress all pings"];
+
+fn since(instant: Option<Instant>, now: Instant) -> String {
+    match instant {
+        Some(instant) => format!("{:.1} s", now.duration_since(instant).as_secs_f64()),
+        None => "-".to_string(),
+    }
+}
+
+pub fn build_keep_alive_panel(
+    monitor: &Rc<RefCell<KeepAliveMonitor>>,
+    statistics: &Rc<RefCell<Statistics>>,
+    client_setting: &ClientSetting,
+    queue: &CommandQueue,
+) -> gtk::Frame {
+    let frame = gtk::Frame::new(Some("Keep Alive"));
+    let grid = gtk::Grid::new();
+    grid.set_column_spacing(4);
+    grid.set_row_spacing(4);
+    grid.set_margin_bottom(FRAME_MARGIN);
+    grid.set_margin_start(FRAME_MARGIN);
+    grid.set_margin_end(FRAME_MARGIN);
+
+    let mut row = 0;
+    let mut value_label = |title: &str, tooltip: &str| {
+        let label = gtk::Label::new(Some(title));
+        label.set_halign(gtk::Align::End);
+        grid.attach(&label, 0, row, 1, 1);
+        let value = gtk::Label::new(Some("-"));
+        value.set_halign(gtk::Align::Start);
+        value.set_tooltip_text(Some(tooltip));
+        grid.attach(&value, 1, row, 2, 1);
+        row += 1;
+        value
+    };
+    let negotiated_label = value_label(
+        "Negotiated Keep Alive:",
+        "Server Keep Alive from the CONNACK, otherwise the value requested in CONNECT",
+    );
+    let sent_label = value_label(
+        "Since Last Sent:",
+        "The broker may disconnect once this exceeds 1.5 times the keep alive",
+    );
+    let received_label = value_label("Since Last Received:", "Any control packet from the broker");
+    let pingresp_label = value_label("Since Last PINGRESP:", "Since the last ping response");
+    let rtt_label = value_label(
+        "Ping Round Trip:",
+        "Last and average over the statistics window",
+    );
+
+    let label = gtk::Label::new(Some("Ping Schedule:"));
+    label.set_halign(gtk::Align::End);
+    grid.attach(&label, 0, row, 1, 1);
+    let schedule_combo = gtk::DropDown::from_strings(&SCHEDULES);
+    schedule_combo.set_selected(0);
+    grid.attach(&schedule_combo, 1, row, 1, 1);
+    let interval_spin = gtk::SpinButton::with_range(1.0, 3600.0, 1.0);
+    interval_spin.set_value(client::DEFAULT_KEEP_ALIVE_SECONDS as f64 / 2.0);
+    interval_spin.set_tooltip_text(Some("Seconds between scheduled pings"));
+    interval_spin.set_sensitive(false);
+    grid.attach(&interval_spin, 2, row, 1, 1);
+    let apply_button = gtk::Button::with_label("Apply");
+    apply_button.set_tooltip_text(Some(
+        "Suppressing pings stops every PINGREQ from the next connect on",
+    ));
+    grid.attach(&apply_button, 3, row, 1, 1);
+    row += 1;
+    // vaux sends its own pings whenever keep alive is set
+    let suppressed_label = gtk::Label::new(Some(
+        "Pings suppressed: connects ask for keep alive 0, so the client sends none of its own. \
+         A connection made before applying keeps its keep alive.",
+    ));
+    suppressed_label.set_halign(gtk::Align::Start);
+    suppressed_label.set_wrap(true);
+    suppressed_label.set_visible(*client_setting.pings_suppressed.borrow());
+    grid.attach(&suppressed_label, 1, row, 3, 1);
+    row += 1;
+    schedule_combo.connect_selected_notify(clone!(
+        #[weak]
+        interval_spin,
+        move |combo| interval_spin.set_sensitive(combo.selected() == 1)
+    ));
+    let pings_suppressed = Rc::clone(&client_setting.pings_suppressed);
+    apply_button.connect_clicked(clone!(
+        #[weak]
+        queue,
+        #[weak]
+        schedule_combo,
+        #[weak]
+        interval_spin,
+        #[weak]
+        suppressed_label,
+        move |_| {
+            let schedule = match schedule_combo.selected() {
+                1 => PingSchedule::Every(Duration::from_secs(interval_spin.value() as u64)),
+                2 => PingSchedule::Suppressed,
+                _ => PingSchedule::Manual,
+            };
+            let suppressed = schedule == PingSchedule::Suppressed;
+            pings_suppressed.replace(suppressed);
+            suppressed_label.set_visible(suppressed);
+            queue.dispatch(Command::SetPingSchedule(schedule));
+        }
+    ));
+
+    let rtt_chart = build_chart(statistics, |stats, cr, width, height| {
+        let rtt = stats
+            .ping_rtt()
+            .iter()
+            .map(|rtt| rtt.as_secs_f64() * 1000.0)
+            .collect();
+        draw_series(
+            cr,
+            width,
+            height,
+            "Ping round trip",
+            "ms",
+            &[(RTT_COLOR, rtt)],
+        );
+    });
+    grid.attach(&rtt_chart, 0, row, 4, 1);
+
+    let requested = Rc::clone(&client_setting.keep_alive);
+    glib::timeout_add_local(
+        Duration::from_millis(500),
+        clone!(
+            #[strong]
+            monitor,
+            #[strong]
+            statistics,
+            #[weak]
+            frame,
+            #[weak]
+            rtt_chart,
+            #[upgrade_or]
+            glib::ControlFlow::Break,
+            move || {
+                if !frame.is_mapped() {
+                    return glib::ControlFlow::Continue;
+                }
+                let now = Instant::now();
+                let monitor = monitor.borrow();
+                let keep_alive = monitor.negotiated(*requested.borrow());
+                negotiated_label.set_text(&match (keep_alive, monitor.server_keep_alive) {
+                    (0, _) => "disabled".to_string(),
+                    (k, Some(_)) => format!("{k} s (set by server)"),
+                    (k, None) => format!("{k} s (requested)"),
+                });
+                sent_label.set_text(&since(monitor.last_sent, now));
+                // past 1.5 times keep alive the broker should have closed the connection
+                let overdue = keep_alive > 0
+                    && monitor.last_sent.is_some_and(|sent| {
+                        now.duration_since(sent).as_secs_f64() > keep_alive as f64 * 1.5
+                    });
+                sent_label.set_css_classes(if overdue { &["error"] } else { &[] });
+                received_label.set_text(&since(monitor.last_received, now));
+                pingresp_label.set_text(&since(monitor.last_pingresp, now));
+                let rtt = statistics.borrow().ping_rtt();
+                rtt_label.set_text(&match rtt.last() {
+                    Some(last) => format!(
+                        "{} (average {})",
+                        millis(*last),
+                        millis(rtt.iter().sum::<Duration>() / rtt.len() as u32)
+                    ),
+                    None => "-".to_string(),
+                });
+                rtt_chart.queue_draw();
+                glib::ControlFlow::Continue
+            }
+        ),
+    );
+
+    frame.set_child(Some(&grid));
+    frame
+}
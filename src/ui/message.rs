@@ -1,74 +1,497 @@
 use std::{cell::RefCell, rc::Rc};
 
+use glib_macros::clone;
 use gtk::prelude::*;
-use gtk4::{
-    self as gtk,
-    gio::{self},
-};
+use gtk4::{self as gtk};
+use vaux_mqtt::PacketType;
 
-use crate::model::PacketObject;
+use crate::export::{self, ExportFormat};
+use crate::model::filter::{DirectionFilter, MessageFilter, parse_time};
+use crate::model::{MessageLog, PacketObject};
+use crate::auth::AUTH_EVENT_TYPE;
+use crate::proxy::PROXY_EVENT_TYPE;
+use crate::ui::file::choose_file;
 
-pub fn build_message_view(message_model: Rc<RefCell<gio::ListStore>>) -> gtk::Frame {
+const FILTER_ENTRY_WIDTH_CHARS: i32 = 16;
+const TIME_ENTRY_WIDTH_CHARS: i32 = 8;
+/// Interval for evicting rows that exceeded the maximum age.
+const RETENTION_CHECK_SECONDS: u32 = 1;
 
+/// Packet types offered in the filter bar, in control packet type order.
+const FILTER_PACKET_TYPES: [PacketType; 14] = [
+    PacketType::Connect,
+    PacketType::ConnAck,
+    PacketType::Publish,
+    PacketType::PubAck,
+    PacketType::PubRec,
+    PacketType::PubRel,
+    PacketType::PubComp,
+    PacketType::Subscribe,
+    PacketType::SubAck,
+    PacketType::Unsubscribe,
+    PacketType::UnsubAck,
+    PacketType::PingReq,
+    PacketType::PingResp,
+    PacketType::Disconnect,
+];
+
+pub fn build_message_view(message_log: &MessageLog) -> gtk::Frame {
     let frame = gtk::Frame::new(Some("Messages"));
-    // Create a vertical box to hold headers and the list
-    let vbox = gtk::Box::new(gtk::Orientation::Vertical, 0);
-    // Create header row with direction column
-    let header_box = gtk::Box::new(gtk::Orientation::Horizontal, 5);
-    let direction_header = gtk::Label::new(Some(""));
-    direction_header.set_hexpand(false);
-    direction_header.set_margin_bottom(4);
-    direction_header.set_markup("<b>Dir</b>");
-    header_box.append(&direction_header);
-    let packet_header = gtk::Label::new(Some("Type"));
-    packet_header.set_hexpand(false);
-    packet_header.set_margin_bottom(4);
-    packet_header.set_markup("<b>Type</b>");
-    header_box.append(&packet_header);
-    let timestamp_header = gtk::Label::new(Some("Timestamp"));
-    timestamp_header.set_hexpand(false);
-    timestamp_header.set_margin_bottom(4);
-    timestamp_header.set_markup("<b>Timestamp</b>");
-    header_box.append(&timestamp_header);
-    let id_header = gtk::Label::new(Some("ID"));
-    id_header.set_hexpand(true);
-    id_header.set_margin_start(10);
-    id_header.set_margin_bottom(4);
-    id_header.set_markup("<b>ID</b>");
-    header_box.append(&id_header);
-
-    vbox.append(&header_box);
+    // Create a vertical box to hold the filter bar and the log
+    let vbox = gtk::Box::new(gtk::Orientation::Vertical, 4);
+
+    let message_filter = Rc::new(RefCell::new(MessageFilter::default()));
+    let _message_filter = Rc::clone(&message_filter);
+    let filter = gtk::CustomFilter::new(move |obj| {
+        obj.downcast_ref::<PacketObject>()
+            .is_some_and(|packet| _message_filter.borrow().matches(packet))
+    });
+    let bar_box = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+    let filter_bar = build_filter_bar(&filter, message_filter);
+    filter_bar.set_hexpand(true);
+    bar_box.append(&filter_bar);
+    bar_box.append(&build_retention_bar(message_log));
+    vbox.append(&bar_box);
 
     let scrolled_window = gtk::ScrolledWindow::new();
     scrolled_window.set_policy(gtk::PolicyType::Automatic, gtk::PolicyType::Automatic);
     scrolled_window.set_hexpand(true);
     scrolled_window.set_vexpand(true);
 
-    // create a model for the message view
-    //let message_model = gio::ListStore::new::<PacketObject>();
+    let column_view = gtk::ColumnView::new(None::<gtk::SelectionModel>);
+    column_view.set_hexpand(true);
+    column_view.set_show_column_separators(true);
+
+    let direction_column = gtk::ColumnViewColumn::new(Some("Dir"), Some(build_direction_factory()));
+    direction_column.set_sorter(Some(&string_sorter("exchange")));
+    column_view.append_column(&direction_column);
+    column_view.append_column(&label_column("Type", "packet-type", |p| p.packet_type()));
+    column_view.append_column(&label_column("Timestamp", "timestamp", |p| p.timestamp()));
+    let id_column = label_column("ID", "packet-id", |p| {
+        if p.packet_id() == 0 {
+            "-".to_string()
+        } else {
+            p.packet_id().to_string()
+        }
+    });
+    id_column.set_sorter(Some(&gtk::NumericSorter::new(Some(packet_expression(
+        "packet-id",
+    )))));
+    column_view.append_column(&id_column);
+    column_view.append_column(&label_column("Topic", "topic", |p| p.topic()));
+    column_view.append_column(&label_column("Tag", "tag", |p| p.tag()));
+    let detail_column = label_column("Detail", "detail", |p| {
+        if p.detail().is_empty() {
+            p.payload()
+        } else {
+            p.detail()
+        }
+    });
+    detail_column.set_expand(true);
+    column_view.append_column(&detail_column);
+
+    let filter_model = gtk::FilterListModel::new(Some(message_log.store()), Some(filter));
+    let sort_model = gtk::SortListModel::new(Some(filter_model), column_view.sorter());
+    let selection_model = gtk::MultiSelection::new(Some(sort_model));
+    column_view.set_model(Some(&selection_model));
+    bar_box.append(&build_export_button(message_log, &selection_model));
+
+    scrolled_window.set_child(Some(&column_view));
+    vbox.append(&scrolled_window);
+    frame.set_child(Some(&vbox));
+
+    frame
+}
+
+fn build_filter_bar(
+    filter: &gtk::CustomFilter,
+    message_filter: Rc<RefCell<MessageFilter>>,
+) -> gtk::Box {
+    let filter_box = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+    filter_box.set_margin_start(4);
+    filter_box.set_margin_end(4);
+    filter_box.set_margin_top(4);
+
+    // notify the filter model after every edit of the criteria
+    let refilter = {
+        let filter = filter.clone();
+        Rc::new(move || filter.changed(gtk::FilterChange::Different))
+    };
+
+    // packet type check boxes live in a popover to keep the bar on one line
+    let types_box = gtk::Box::new(gtk::Orientation::Vertical, 2);
+    // proxy and authentication events are logged alongside the packets
+    let type_names = FILTER_PACKET_TYPES
+        .iter()
+        .map(PacketType::to_string)
+        .chain([PROXY_EVENT_TYPE.to_string(), AUTH_EVENT_TYPE.to_string()]);
+    for name in type_names {
+        let check = gtk::CheckButton::with_label(&name);
+        check.set_active(true);
+        let _message_filter = Rc::clone(&message_filter);
+        let _refilter = Rc::clone(&refilter);
+        check.connect_toggled(move |b| {
+            if b.is_active() {
+                _message_filter.borrow_mut().hidden_types.remove(&name);
+            } else {
+                _message_filter
+                    .borrow_mut()
+                    .hidden_types
+                    .insert(name.clone());
+            }
+            _refilter();
+        });
+        types_box.append(&check);
+    }
+    let types_popover = gtk::Popover::new();
+    types_popover.set_child(Some(&types_box));
+    let types_button = gtk::MenuButton::new();
+    types_button.set_label("Types");
+    types_button.set_tooltip_text(Some("Packet types shown in the log"));
+    types_button.set_popover(Some(&types_popover));
+    filter_box.append(&types_button);
+
+    let direction_combo = gtk::ComboBoxText::new();
+    direction_combo.append(Some("all"), "Both");
+    direction_combo.append(Some("send"), "Sent");
+    direction_combo.append(Some("receive"), "Received");
+    direction_combo.set_active(Some(0));
+    direction_combo.set_tooltip_text(Some("Packet direction"));
+    let _message_filter = Rc::clone(&message_filter);
+    let _refilter = Rc::clone(&refilter);
+    direction_combo.connect_changed(move |combo| {
+        _message_filter.borrow_mut().direction = match combo.active_id().as_deref() {
+            Some("send") => DirectionFilter::Send,
+            Some("receive") => DirectionFilter::Receive,
+            _ => DirectionFilter::All,
+        };
+        _refilter();
+    });
+    filter_box.append(&direction_combo);
+
+    let topic_entry = gtk::SearchEntry::new();
+    topic_entry.set_placeholder_text(Some("Topic glob"));
+    topic_entry.set_tooltip_text(Some("Topic glob, * matches any text and ? one character"));
+    topic_entry.set_width_chars(FILTER_ENTRY_WIDTH_CHARS);
+    let _message_filter = Rc::clone(&message_filter);
+    let _refilter = Rc::clone(&refilter);
+    topic_entry.connect_search_changed(move |entry| {
+        _message_filter.borrow_mut().topic_glob = entry.text().to_string();
+        _refilter();
+    });
+    filter_box.append(&topic_entry);
+
+    let packet_id_entry = gtk::Entry::new();
+    packet_id_entry.set_placeholder_text(Some("Packet ID"));
+    packet_id_entry.set_width_chars(TIME_ENTRY_WIDTH_CHARS);
+    packet_id_entry.set_input_purpose(gtk::InputPurpose::Digits);
+    let _message_filter = Rc::clone(&message_filter);
+    let _refilter = Rc::clone(&refilter);
+    packet_id_entry.connect_changed(move |entry| {
+        _message_filter.borrow_mut().packet_id = entry.text().trim().parse().ok();
+        _refilter();
+    });
+    filter_box.append(&packet_id_entry);
+
+    let from_entry = gtk::Entry::new();
+    from_entry.set_placeholder_text(Some("From"));
+    from_entry.set_tooltip_text(Some("Earliest time of day, HH:MM[:SS]"));
+    from_entry.set_width_chars(TIME_ENTRY_WIDTH_CHARS);
+    let to_entry = gtk::Entry::new();
+    to_entry.set_placeholder_text(Some("To"));
+    to_entry.set_tooltip_text(Some("Latest time of day, HH:MM[:SS]"));
+    to_entry.set_width_chars(TIME_ENTRY_WIDTH_CHARS);
+    for (entry, is_from) in [(&from_entry, true), (&to_entry, false)] {
+        let _message_filter = Rc::clone(&message_filter);
+        let _refilter = Rc::clone(&refilter);
+        entry.connect_changed(move |entry| {
+            let text = entry.text();
+            let time = parse_time(text.trim());
+            // flag text that is not empty and not a time
+            if time.is_none() && !text.trim().is_empty() {
+                entry.add_css_class("error");
+            } else {
+                entry.remove_css_class("error");
+            }
+            if is_from {
+                _message_filter.borrow_mut().from = time;
+            } else {
+                _message_filter.borrow_mut().to = time;
+            }
+            _refilter();
+        });
+        filter_box.append(entry);
+    }
+
+    let text_entry = gtk::SearchEntry::new();
+    text_entry.set_placeholder_text(Some("Search payload"));
+    text_entry.set_tooltip_text(Some("Case insensitive search of payload, topic and detail"));
+    text_entry.set_hexpand(true);
+    text_entry.connect_search_changed(clone!(
+        #[strong]
+        message_filter,
+        move |entry| {
+            message_filter.borrow_mut().text = entry.text().to_string();
+            refilter();
+        }
+    ));
+    filter_box.append(&text_entry);
+
+    filter_box
+}
+
+/// Capture pause toggle, retained/dropped counters and the retention policy.
+fn build_retention_bar(message_log: &MessageLog) -> gtk::Box {
+    let retention_box = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+    retention_box.set_margin_end(4);
+    retention_box.set_margin_top(4);
+
+    let counter_label = gtk::Label::new(None);
+    counter_label.set_tooltip_text(Some("Rows retained in the log / packets dropped"));
+    let update_counter = clone!(
+        #[weak]
+        counter_label,
+        move |log: &MessageLog| {
+            counter_label.set_text(&format!(
+                "{} rows ({} KiB) / {} dropped",
+                log.retained(),
+                log.retained_bytes() / 1024,
+                log.dropped()
+            ));
+        }
+    );
+    update_counter(message_log);
+    for property in ["retained", "retained-bytes", "dropped"] {
+        let update_counter = update_counter.clone();
+        message_log.connect_notify_local(Some(property), move |log, _| update_counter(log));
+    }
+    retention_box.append(&counter_label);
+
+    let pause_button = gtk::ToggleButton::with_label("Pause Capture");
+    pause_button.set_tooltip_text(Some(
+        "Keep the connection open but stop appending packets to the log",
+    ));
+    message_log
+        .bind_property("paused", &pause_button, "active")
+        .bidirectional()
+        .sync_create()
+        .build();
+    retention_box.append(&pause_button);
+
+    let policy_grid = gtk::Grid::new();
+    policy_grid.set_column_spacing(4);
+    policy_grid.set_row_spacing(4);
+    for (row, (name, property, max, tooltip)) in [
+        (
+            "Max Rows:",
+            "max-rows",
+            u32::MAX as f64,
+            "Maximum rows kept, 0 for no limit",
+        ),
+        (
+            "Max Age (seconds):",
+            "max-age",
+            u32::MAX as f64,
+            "Maximum row age, 0 for no limit",
+        ),
+        (
+            "Max Bytes:",
+            "max-bytes",
+            u64::MAX as f64,
+            "Maximum encoded bytes kept, 0 for no limit",
+        ),
+    ]
+    .into_iter()
+    .enumerate()
+    {
+        let label = gtk::Label::new(Some(name));
+        label.set_halign(gtk::Align::End);
+        policy_grid.attach(&label, 0, row as i32, 1, 1);
+        let gtk_adjustment = gtk::Adjustment::new(0.0, 0.0, max, 1.0, 100.0, 0.0);
+        let spin = gtk::SpinButton::new(Some(&gtk_adjustment), 1.0, 0);
+        spin.set_tooltip_text(Some(tooltip));
+        message_log
+            .bind_property(property, &spin, "value")
+            .bidirectional()
+            .sync_create()
+            .build();
+        policy_grid.attach(&spin, 1, row as i32, 1, 1);
+    }
+    let clear_button = gtk::Button::with_label("Clear Log");
+    clear_button.set_tooltip_text(Some("Remove all rows and reset the counters"));
+    clear_button.connect_clicked(clone!(
+        #[weak]
+        message_log,
+        move |_| message_log.clear()
+    ));
+    policy_grid.attach(&clear_button, 1, 3, 1, 1);
+    // a tighter limit applies to the rows already in the log
+    for property in ["max-rows", "max-age", "max-bytes"] {
+        message_log.connect_notify_local(Some(property), |log, _| log.enforce());
+    }
+    let policy_popover = gtk::Popover::new();
+    policy_popover.set_child(Some(&policy_grid));
+    let policy_button = gtk::MenuButton::new();
+    policy_button.set_label("Retention");
+    policy_button.set_tooltip_text(Some("Retention policy, oldest rows are evicted first"));
+    policy_button.set_popover(Some(&policy_popover));
+    retention_box.append(&policy_button);
+
+    glib::timeout_add_seconds_local(
+        RETENTION_CHECK_SECONDS,
+        clone!(
+            #[weak]
+            message_log,
+            #[upgrade_or]
+            glib::ControlFlow::Break,
+            move || {
+                if message_log.max_age() > 0 {
+                    message_log.enforce();
+                }
+                glib::ControlFlow::Continue
+            }
+        ),
+    );
+
+    retention_box
+}
+
+/// Export of the whole log or the selected rows to a file.
+fn build_export_button(
+    message_log: &MessageLog,
+    selection: &gtk::MultiSelection,
+) -> gtk::MenuButton {
+    let grid = gtk::Grid::new();
+    grid.set_column_spacing(4);
+    grid.set_row_spacing(4);
+
+    let label = gtk::Label::new(Some("Format:"));
+    label.set_halign(gtk::Align::End);
+    grid.attach(&label, 0, 0, 1, 1);
+    let format_combo = gtk::ComboBoxText::new();
+    for format in ExportFormat::ALL {
+        format_combo.append(Some(format.name()), format.name());
+    }
+    format_combo.set_active(Some(0));
+    format_combo.set_tooltip_text(Some(
+        "JSON Lines with base64 payloads, CSV of the log columns or PCAP for Wireshark",
+    ));
+    grid.attach(&format_combo, 1, 0, 1, 1);
+    let selected_check = gtk::CheckButton::with_label("Selected rows only");
+    grid.attach(&selected_check, 1, 1, 1, 1);
+    let export_button = gtk::Button::with_label("Export...");
+    export_button.set_halign(gtk::Align::End);
+    grid.attach(&export_button, 1, 2, 1, 1);
+
+    let popover = gtk::Popover::new();
+    popover.set_child(Some(&grid));
+    let menu_button = gtk::MenuButton::new();
+    menu_button.set_label("Export");
+    menu_button.set_tooltip_text(Some("Export the message log"));
+    menu_button.set_popover(Some(&popover));
 
+    export_button.connect_clicked(clone!(
+        #[weak]
+        message_log,
+        #[weak]
+        selection,
+        #[weak]
+        popover,
+        move |b| {
+            let format = format_combo
+                .active_id()
+                .as_deref()
+                .and_then(ExportFormat::from_name)
+                .unwrap_or(ExportFormat::JsonLines);
+            // selected rows in view order, otherwise the whole log in arrival order
+            let packets: Vec<PacketObject> = if selected_check.is_active() {
+                (0..selection.n_items())
+                    .filter(|i| selection.is_selected(*i))
+                    .filter_map(|i| selection.item(i).and_downcast::<PacketObject>())
+                    .collect()
+            } else {
+                let store = message_log.store();
+                (0..store.n_items())
+                    .filter_map(|i| store.item(i).and_downcast::<PacketObject>())
+                    .collect()
+            };
+            popover.popdown();
+            choose_file(
+                b,
+                "Export Message Log",
+                gtk::FileChooserAction::Save,
+                "Export",
+                move |path| match export::export(&packets, format, &path) {
+                    Ok(_) => println!("Exported {} packets to {}", packets.len(), path.display()),
+                    Err(e) => println!("Failed to export message log: {e}"),
+                },
+            );
+        }
+    ));
+
+    menu_button
+}
+
+fn packet_expression(property: &str) -> gtk::PropertyExpression {
+    gtk::PropertyExpression::new(
+        PacketObject::static_type(),
+        None::<&gtk::Expression>,
+        property,
+    )
+}
+
+fn string_sorter(property: &str) -> gtk::StringSorter {
+    gtk::StringSorter::new(Some(packet_expression(property)))
+}
+
+/// Resizable text column sorted by `property`, showing `text` for each packet.
+fn label_column(
+    title: &str,
+    property: &str,
+    text: impl Fn(&PacketObject) -> String + 'static,
+) -> gtk::ColumnViewColumn {
+    let factory = gtk::SignalListItemFactory::new();
+    factory.connect_setup(move |_, item| {
+        let label = gtk::Label::new(None);
+        label.set_halign(gtk::Align::Start);
+        label.set_ellipsize(gtk::pango::EllipsizeMode::End);
+        item.downcast_ref::<gtk::ListItem>()
+            .expect("Failed to downcast")
+            .set_child(Some(&label));
+    });
+    factory.connect_bind(move |_, item| {
+        let item = item
+            .downcast_ref::<gtk::ListItem>()
+            .expect("Failed to downcast");
+        let packet = item
+            .item()
+            .and_downcast::<PacketObject>()
+            .expect("Failed to downcast to PacketObject");
+        let label = item
+            .child()
+            .and_downcast::<gtk::Label>()
+            .expect("Failed to downcast to Label");
+        let text = text(&packet);
+        label.set_tooltip_text(Some(&text));
+        label.set_text(&text);
+    });
+
+    let column = gtk::ColumnViewColumn::new(Some(title), Some(factory));
+    column.set_resizable(true);
+    column.set_sorter(Some(&string_sorter(property)));
+    column
+}
+
+fn build_direction_factory() -> gtk::SignalListItemFactory {
     let factory = gtk::SignalListItemFactory::new();
     factory.connect_setup(move |_, item| {
-        let hbox = gtk::Box::new(gtk::Orientation::Horizontal, 5);
         // Direction indicator
         let direction_icon = gtk::DrawingArea::new();
         direction_icon.set_content_width(24);
         direction_icon.set_content_height(18);
-        hbox.append(&direction_icon);
-        let packet_label = gtk::Label::new(None);
-        packet_label.set_hexpand(false);
-        hbox.append(&packet_label);
-        let timestamp_label = gtk::Label::new(None);
-        timestamp_label.set_hexpand(false);
-        hbox.append(&timestamp_label);
-        let id_label = gtk::Label::new(None);
-        id_label.set_margin_start(10);
-        id_label.set_hexpand(true);
-        hbox.append(&id_label);
-
         item.downcast_ref::<gtk::ListItem>()
             .expect("Failed to downcast")
-            .set_child(Some(&hbox));
+            .set_child(Some(&direction_icon));
     });
     factory.connect_bind(move |_, item| {
         let packet = item
@@ -78,23 +501,16 @@ pub fn build_message_view(message_model: Rc<RefCell<gio::ListStore>>) -> gtk::Fr
             .and_downcast::<PacketObject>()
             .expect("Failed to downcast to PacketObject");
 
-        let hbox = item
+        let direction_icon = item
             .downcast_ref::<gtk::ListItem>()
             .expect("Failed to downcast")
             .child()
-            .and_downcast::<gtk::Box>()
-            .expect("Failed to downcast to Box");
-
-        // Direction icon is the first child
-        let direction_icon = hbox
-            .first_child()
             .and_downcast::<gtk::DrawingArea>()
             .expect("Failed to get direction_icon");
 
         // Draw the direction icon
         let exchange = packet.exchange();
         direction_icon.set_draw_func(move |_, cr, width, height| {
-            use gtk4::gdk::RGBA;
             use std::f64::consts::PI;
             // Layout constants
             let bar_x = 4.0;
@@ -109,7 +525,12 @@ pub fn build_message_view(message_model: Rc<RefCell<gio::ListStore>>) -> gtk::Fr
             cr.line_to(bar_x, height as f64 - 2.0);
             cr.stroke().unwrap();
 
-            if exchange == "send" {
+            if exchange == "event" {
+                // dot beside the bar, nothing crossed the connection
+                cr.set_source_rgba(1.0, 0.6, 0.0, 1.0); // orange
+                cr.arc(bar_x + arrow_len / 2.0, arrow_y, 4.0, 0.0, 2.0 * PI);
+                cr.fill().unwrap();
+            } else if exchange == "send" {
                 // Arrowhead to the right of the bar, pointing left
                 cr.set_source_rgba(0.0, 0.5, 1.0, 1.0); // blue
                 cr.set_line_width(2.0);
@@ -119,9 +540,7 @@ pub fn build_message_view(message_model: Rc<RefCell<gio::ListStore>>) -> gtk::Fr
                 // Shaft (hidden, just head)
                 // Arrowhead
                 cr.move_to(arrow_base_x, arrow_y);
-                cr.line_to(
-                    arrow_tip_x, arrow_y,
-                );
+                cr.line_to(arrow_tip_x, arrow_y);
                 cr.move_to(arrow_tip_x, arrow_y);
                 cr.line_to(
                     arrow_tip_x - head_size * (-(PI / 6.0)).cos(),
@@ -141,9 +560,7 @@ pub fn build_message_view(message_model: Rc<RefCell<gio::ListStore>>) -> gtk::Fr
                 let arrow_tip_x = bar_x + 2.0;
                 let arrow_base_x = bar_x + arrow_len;
                 cr.move_to(arrow_base_x, arrow_y);
-                cr.line_to(
-                    arrow_tip_x, arrow_y,
-                );
+                cr.line_to(arrow_tip_x, arrow_y);
                 cr.move_to(arrow_tip_x, arrow_y);
                 cr.line_to(
                     arrow_tip_x + head_size * (-(PI / 6.0)).cos(),
@@ -157,41 +574,6 @@ pub fn build_message_view(message_model: Rc<RefCell<gio::ListStore>>) -> gtk::Fr
                 cr.stroke().unwrap();
             }
         });
-
-        let packet_label = direction_icon
-            .next_sibling()
-            .and_downcast::<gtk::Label>()
-            .expect("Failed to get packet_label");
-
-        let timestamp_label = packet_label
-            .next_sibling()
-            .and_downcast::<gtk::Label>()
-            .expect("Failed to get timestamp_label");
-
-        let id_label = hbox
-            .last_child()
-            .and_downcast::<gtk::Label>()
-            .expect("Failed to get id_label");
-
-        let id_str: String;
-        packet_label.set_text(&packet.packet_type().to_string());
-        timestamp_label.set_text(&packet.timestamp().to_string());   
-        id_label.set_text(if &packet.packet_id() == &0 {
-            "-"
-        } else {
-            id_str = packet.packet_id().to_string();
-            id_str.as_str()
-        });
     });
-
-    let model = (*message_model.clone()).borrow().clone();
-    let selection_model = gtk::SingleSelection::new(Some(model));
-    let list_view = gtk::ListView::new(Some(selection_model), Some(factory));
-    list_view.set_hexpand(true);
-
-    scrolled_window.set_child(Some(&list_view));
-    vbox.append(&scrolled_window);
-    frame.set_child(Some(&vbox));
-
-    frame
+    factory
 }
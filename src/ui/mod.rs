@@ -1,7 +1,37 @@
 pub mod action;
+#[cfg(feature = "broker")]
+pub mod broker;
+pub mod connack;
 pub mod connection;
+pub mod disconnect;
+pub mod file;
+pub mod keepalive;
 pub mod message;
+pub mod page;
+pub mod properties;
+pub mod proxy;
+pub mod raw;
+pub mod replay;
+pub mod retained;
+pub mod scenario;
+pub mod script;
+pub mod session;
+pub mod shared;
+pub mod stats;
+pub mod subscriptions;
 
 pub use action::*;
+pub use connack::*;
 pub use connection::*;
+pub use keepalive::*;
 pub use message::*;
+pub use page::*;
+pub use proxy::build_proxy_panel;
+pub use raw::build_raw_panel;
+pub use retained::build_retained_panel;
+pub use scenario::*;
+pub use script::*;
+pub use session::*;
+pub use shared::build_shared_panel;
+pub use stats::*;
+pub use subscriptions::build_subscription_panel;
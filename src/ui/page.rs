@@ -0,0 +1,209 @@
+use std::{cell::RefCell, collections::VecDeque, rc::Rc};
+
+use gtk::glib;
+use gtk::prelude::*;
+use gtk4::{self as gtk, gio};
+use vaux_mqtt::Packet;
+
+use crate::auth::AUTH_EVENT_TYPE;
+use crate::client::{ClientSetting, Command, DisconnectOptions, Event};
+use crate::keepalive::KeepAliveMonitor;
+use crate::model::packet::Exchange;
+use crate::model::{
+    CommandQueue, ConnAckObject, MessageLog, PacketObject, RetainedMessage, ScriptObject,
+    SessionObject, Subscription, retained,
+};
+use crate::runtime::SharedRuntime;
+use crate::stats::Statistics;
+use crate::wire;
+
+use super::{
+    build_actions, build_connack_summary, build_connection_notebook, build_keep_alive_panel,
+    build_message_view, build_proxy_panel, build_raw_panel, build_retained_panel,
+    build_scenario_panel, build_script_panel, build_session_panel, build_shared_panel,
+    build_statistics_view, build_subscription_panel,
+};
+
+/// How many recent packets script annotations can still find.
+const RECENT_PACKETS: usize = 256;
+
+/// Build the page for one broker connection, with its own settings, runner
+/// and message log. `title` is renamed to the broker once it answers.
+pub fn build_connection_page(
+    runtime: &SharedRuntime,
+    title: &gtk::Label,
+) -> (gtk::Box, CommandQueue) {
+    let client_setting = ClientSetting::new();
+    let (cmd_tx, mut packet_rx, mut event_rx) = runtime.spawn_runner();
+    let queue = CommandQueue::new(cmd_tx);
+
+    let message_log = MessageLog::new();
+    let connack = ConnAckObject::new();
+    let session = SessionObject::new();
+    let statistics = Rc::new(RefCell::new(Statistics::default()));
+    let keep_alive = Rc::new(RefCell::new(KeepAliveMonitor::default()));
+    let script = ScriptObject::new();
+    let subscriptions = gio::ListStore::new::<Subscription>();
+    let retained_messages = gio::ListStore::new::<RetainedMessage>();
+
+    glib::spawn_future_local(glib::clone!(
+        #[strong]
+        message_log,
+        #[strong]
+        connack,
+        #[strong]
+        session,
+        #[strong]
+        statistics,
+        #[strong]
+        keep_alive,
+        #[strong]
+        script,
+        #[strong]
+        client_setting,
+        #[strong]
+        retained_messages,
+        #[weak]
+        title,
+        async move {
+            // packets by runner sequence number, for script annotations that
+            // arrive after the packet
+            let mut sequence = 0u64;
+            let mut recent: VecDeque<(u64, PacketObject)> = VecDeque::new();
+            loop {
+                tokio::select! {
+                    // packets first so events always follow the packets sent before them
+                    biased;
+                    Some((dir, packet)) = packet_rx.recv() => {
+                        if let Packet::ConnAck(ack) = &packet {
+                            connack.update(ack);
+                            title.set_label(&format!(
+                                "{}:{}",
+                                client_setting.host.borrow(),
+                                client_setting.port.borrow()
+                            ));
+                        }
+                        // counted even while the log capture is paused
+                        let encoded = wire::Encoded::of(&packet);
+                        statistics.borrow_mut().record(&dir, &packet, encoded.size);
+                        keep_alive.borrow_mut().record(&dir, &packet);
+                        if dir == Exchange::Receive {
+                            retained::record(&retained_messages, &packet, &encoded);
+                        }
+                        let packet_obj = PacketObject::new(dir, packet, encoded.size);
+                        message_log.append(&packet_obj);
+                        sequence += 1;
+                        recent.push_back((sequence, packet_obj));
+                        if recent.len() > RECENT_PACKETS {
+                            recent.pop_front();
+                        }
+                    }
+                    Some(event) = event_rx.recv() => match event {
+                        Event::Session(snapshot) => session.update(snapshot.as_ref()),
+                        Event::Script(event) => script.update(event),
+                        Event::Annotate { sequence: annotated, tags, display } => {
+                            if let Some((_, packet_obj)) =
+                                recent.iter().find(|(s, _)| *s == annotated)
+                            {
+                                if !tags.is_empty() {
+                                    packet_obj.set_tag(tags.join(", "));
+                                }
+                                if let Some(display) = display {
+                                    packet_obj.set_payload(display);
+                                }
+                                message_log.refresh(packet_obj);
+                            }
+                        }
+                        Event::Recording(path) => message_log.set_recording(
+                            path.map(|p| p.display().to_string()).unwrap_or_default(),
+                        ),
+                        Event::Auth(event) => message_log
+                            .append(&PacketObject::event(AUTH_EVENT_TYPE, &event.text())),
+                    },
+                    else => break,
+                }
+            }
+        }
+    ));
+
+    let page = gtk::Box::new(gtk::Orientation::Vertical, 0);
+    page.set_margin_start(10);
+    page.set_margin_end(10);
+    page.set_margin_top(10);
+    page.set_margin_bottom(10);
+    page.set_spacing(10);
+
+    let session_frame = gtk::Frame::new(Some("Session"));
+    session_frame.set_hexpand(true);
+    session_frame.set_vexpand(false);
+    let (connect_tabs, clean_start_check) = build_connection_notebook(&client_setting);
+    let connack_frame = build_connack_summary(&connack);
+    connect_tabs.append_page(&connack_frame, Some(&gtk::Label::new(Some("Broker"))));
+    let session_panel = build_session_panel(&session, &queue);
+    connect_tabs.append_page(
+        &session_panel,
+        Some(&gtk::Label::new(Some("Session State"))),
+    );
+    // a stored or loaded session is what makes resuming possible, nothing
+    // else sets whether it can be chosen
+    session
+        .bind_property("present", &clean_start_check, "sensitive")
+        .sync_create()
+        .build();
+    session_frame.set_child(Some(&connect_tabs));
+    page.append(&session_frame);
+
+    let actions_frame = build_actions(
+        &clean_start_check,
+        &queue,
+        &client_setting,
+        &message_log,
+        &subscriptions,
+    );
+    page.append(&actions_frame);
+
+    let message_frame = build_message_view(&message_log);
+    let statistics_frame = build_statistics_view(&statistics);
+    let views = gtk::Notebook::new();
+    views.append_page(&message_frame, Some(&gtk::Label::new(Some("Messages"))));
+    let subscription_frame = build_subscription_panel(&subscriptions, &queue);
+    views.append_page(
+        &subscription_frame,
+        Some(&gtk::Label::new(Some("Subscriptions"))),
+    );
+    let retained_frame = build_retained_panel(&retained_messages, &queue);
+    views.append_page(&retained_frame, Some(&gtk::Label::new(Some("Retained"))));
+    views.append_page(
+        &statistics_frame,
+        Some(&gtk::Label::new(Some("Statistics"))),
+    );
+    let keep_alive_frame =
+        build_keep_alive_panel(&keep_alive, &statistics, &client_setting, &queue);
+    views.append_page(
+        &keep_alive_frame,
+        Some(&gtk::Label::new(Some("Keep Alive"))),
+    );
+    let proxy_frame = build_proxy_panel(&client_setting, &message_log);
+    views.append_page(&proxy_frame, Some(&gtk::Label::new(Some("Proxy"))));
+    let scenario_frame = build_scenario_panel(&client_setting, &message_log, runtime);
+    views.append_page(&scenario_frame, Some(&gtk::Label::new(Some("Scenarios"))));
+    let shared_frame = build_shared_panel(&client_setting, &message_log, runtime);
+    views.append_page(
+        &shared_frame,
+        Some(&gtk::Label::new(Some("Shared Subscriptions"))),
+    );
+    let raw_frame = build_raw_panel(&client_setting, runtime);
+    views.append_page(&raw_frame, Some(&gtk::Label::new(Some("Raw Packet"))));
+    let script_frame = build_script_panel(&script, &queue);
+    views.append_page(&script_frame, Some(&gtk::Label::new(Some("Scripts"))));
+    views.set_vexpand(true);
+    page.append(&views);
+
+    (page, queue)
+}
+
+/// Disconnect and stop the runner of a page that is being closed.
+pub fn close_connection_page(queue: &CommandQueue) {
+    queue.dispatch(Command::Disconnect(DisconnectOptions::default()));
+    queue.dispatch(Command::StopRunner);
+}
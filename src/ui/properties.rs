@@ -0,0 +1,37 @@
+use gtk4::{self as gtk};
+
+use gtk::prelude::*;
+
+const PROPERTIES_TEXT_WIDTH_REQUEST: i32 = 300;
+const PROPERTIES_TEXT_HEIGHT_REQUEST: i32 = 60;
+
+/// Text editor for MQTT user properties, one `key=value` pair per line.
+pub fn build_user_properties_editor(tooltip: &str) -> (gtk::ScrolledWindow, gtk::TextView) {
+    let text_view = gtk::TextView::new();
+    text_view.set_tooltip_text(Some(tooltip));
+    text_view.set_monospace(true);
+    let scrolled = gtk::ScrolledWindow::new();
+    scrolled.set_child(Some(&text_view));
+    scrolled.set_min_content_width(PROPERTIES_TEXT_WIDTH_REQUEST);
+    scrolled.set_min_content_height(PROPERTIES_TEXT_HEIGHT_REQUEST);
+    scrolled.set_policy(gtk::PolicyType::Automatic, gtk::PolicyType::Automatic);
+    (scrolled, text_view)
+}
+
+/// Parse `key=value` lines into user properties. Blank lines are skipped and a
+/// line without '=' is taken as a key with an empty value.
+pub fn parse_user_properties(text: &str) -> Vec<(String, String)> {
+    text.lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty())
+        .map(|line| match line.split_once('=') {
+            Some((key, value)) => (key.trim().to_string(), value.trim().to_string()),
+            None => (line.to_string(), String::new()),
+        })
+        .collect()
+}
+
+pub fn user_properties(text_view: &gtk::TextView) -> Vec<(String, String)> {
+    let buffer = text_view.buffer();
+    parse_user_properties(&buffer.text(&buffer.start_iter(), &buffer.end_iter(), false))
+}
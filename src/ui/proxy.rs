@@ -0,0 +1,199 @@
+//! Proxy tab: run the fault-injection proxy in front of the broker and route
+//! the connection through it. Proxy events are added to the message log.
+
+use std::{cell::RefCell, rc::Rc, time::Duration};
+
+use glib::clone;
+use gtk4::{self as gtk};
+
+use gtk::prelude::*;
+
+use crate::client::ClientSetting;
+use crate::model::{MessageLog, PacketObject};
+use crate::proxy::{PROXY_EVENT_TYPE, ProxyEvent, ProxyFaults, ProxyHandle};
+
+const FRAME_MARGIN: i32 = 6;
+
+fn spin(max: f64, value: f64, tooltip: &str) -> gtk::SpinButton {
+    let spin = gtk::SpinButton::with_range(0.0, max, 1.0);
+    spin.set_value(value);
+    spin.set_tooltip_text(Some(tooltip));
+    spin
+}
+
+fn attach_labeled(
+    grid: &gtk::Grid,
+    label: &str,
+    widget: &impl IsA<gtk::Widget>,
+    column: i32,
+    row: i32,
+) {
+    let label = gtk::Label::new(Some(label));
+    label.set_halign(gtk::Align::End);
+    grid.attach(&label, column, row, 1, 1);
+    grid.attach(widget, column + 1, row, 1, 1);
+}
+
+/// Seconds from a spin button, `None` for 0.
+fn seconds(spin: &gtk::SpinButton) -> Option<Duration> {
+    Some(spin.value_as_int() as u64)
+        .filter(|seconds| *seconds > 0)
+        .map(Duration::from_secs)
+}
+
+pub fn build_proxy_panel(client_setting: &ClientSetting, message_log: &MessageLog) -> gtk::Frame {
+    let frame = gtk::Frame::new(Some("Fault Injection Proxy"));
+    let grid = gtk::Grid::new();
+    grid.set_column_spacing(4);
+    grid.set_row_spacing(4);
+    grid.set_margin_bottom(FRAME_MARGIN);
+    grid.set_margin_start(FRAME_MARGIN);
+    grid.set_margin_end(FRAME_MARGIN);
+
+    let port_spin = spin(
+        u16::MAX as f64,
+        0.0,
+        "Local port the client connects to, 0 for any free port",
+    );
+    attach_labeled(&grid, "Listen Port:", &port_spin, 0, 0);
+    let start_button = gtk::Button::with_label("Start");
+    start_button.set_tooltip_text(Some(
+        "Forward to the broker in the connection settings and connect through the proxy",
+    ));
+    grid.attach(&start_button, 2, 0, 1, 1);
+    let status_label = gtk::Label::new(Some("Stopped"));
+    status_label.set_halign(gtk::Align::Start);
+    status_label.set_hexpand(true);
+    status_label.set_wrap(true);
+    grid.attach(&status_label, 3, 0, 3, 1);
+
+    let latency_spin = spin(60_000.0, 0.0, "Added to every chunk in both directions");
+    attach_labeled(&grid, "Latency (ms):", &latency_spin, 0, 1);
+    let bandwidth_spin = spin(
+        1_000_000.0,
+        0.0,
+        "Limit in each direction in KB per second, 0 for unlimited",
+    );
+    attach_labeled(&grid, "Bandwidth (KB/s):", &bandwidth_spin, 2, 1);
+    let fragment_spin = spin(
+        65_535.0,
+        0.0,
+        "Write chunks in segments of at most this many bytes, 0 to forward them whole",
+    );
+    attach_labeled(&grid, "Fragment (bytes):", &fragment_spin, 4, 1);
+    let corrupt_spin = spin(
+        86_400.0,
+        0.0,
+        "Flip one bit in the next chunk this often, 0 for never",
+    );
+    attach_labeled(&grid, "Corrupt Every (s):", &corrupt_spin, 0, 2);
+    let reset_spin = spin(
+        86_400.0,
+        0.0,
+        "Close each connection without warning once it has been open this long, 0 for never",
+    );
+    attach_labeled(&grid, "Reset After (s):", &reset_spin, 2, 2);
+
+    let faults = clone!(
+        #[weak]
+        latency_spin,
+        #[weak]
+        bandwidth_spin,
+        #[weak]
+        fragment_spin,
+        #[weak]
+        corrupt_spin,
+        #[weak]
+        reset_spin,
+        #[upgrade_or_default]
+        move || ProxyFaults {
+            latency: Duration::from_millis(latency_spin.value_as_int() as u64),
+            bandwidth: bandwidth_spin.value_as_int() as u32 * 1024,
+            fragment: fragment_spin.value_as_int() as usize,
+            corrupt_every: seconds(&corrupt_spin),
+            reset_after: seconds(&reset_spin),
+        }
+    );
+    let handle: Rc<RefCell<Option<ProxyHandle>>> = Rc::new(RefCell::new(None));
+    for spin in [
+        &latency_spin,
+        &bandwidth_spin,
+        &fragment_spin,
+        &corrupt_spin,
+        &reset_spin,
+    ] {
+        spin.connect_value_changed(clone!(
+            #[strong]
+            handle,
+            #[strong]
+            faults,
+            move |_| {
+                if let Some(handle) = handle.borrow().as_ref() {
+                    handle.set_faults(faults());
+                }
+            }
+        ));
+    }
+
+    let (events_tx, mut events_rx) = tokio::sync::mpsc::unbounded_channel::<ProxyEvent>();
+    glib::spawn_future_local(clone!(
+        #[weak]
+        message_log,
+        async move {
+            while let Some(event) = events_rx.recv().await {
+                message_log.append(&PacketObject::event(PROXY_EVENT_TYPE, &event.text()));
+            }
+        }
+    ));
+
+    let client_setting = client_setting.clone();
+    start_button.connect_clicked(clone!(
+        #[strong]
+        handle,
+        #[weak]
+        port_spin,
+        #[weak]
+        status_label,
+        move |button| {
+            if handle.borrow_mut().take().is_some() {
+                client_setting.proxy_port.replace(None);
+                button.set_label("Start");
+                port_spin.set_sensitive(true);
+                status_label.set_text("Stopped, the next connection goes to the broker directly");
+                return;
+            }
+            if *client_setting.with_tls.borrow() {
+                status_label.set_text("The proxy forwards plain TCP only, turn off TLS");
+                return;
+            }
+            let upstream = format!(
+                "{}:{}",
+                client_setting.host.borrow(),
+                client_setting.port.borrow()
+            );
+            match ProxyHandle::start(
+                port_spin.value_as_int() as u16,
+                upstream.clone(),
+                faults(),
+                events_tx.clone(),
+            ) {
+                Ok(started) => {
+                    client_setting
+                        .proxy_port
+                        .replace(Some(started.address().port()));
+                    status_label.set_text(&format!(
+                        "Forwarding {} to {upstream}, the next connection goes through the proxy",
+                        started.address()
+                    ));
+                    handle.replace(Some(started));
+                    button.set_label("Stop");
+                    port_spin.set_sensitive(false);
+                }
+                Err(e) => status_label.set_text(&e),
+            }
+        }
+    ));
+
+    frame.set_child(Some(&grid));
+    frame
+}
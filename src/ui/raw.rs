@@ -0,0 +1,366 @@
+//! Raw Packet tab: build a control packet field by field or paste its bytes
+//! as hex, send it unvalidated on a connection of its own and show what the
+//! broker replies.
+
+use glib::clone;
+use gtk4::{self as gtk};
+
+use gtk::prelude::*;
+
+use crate::client::ClientSetting;
+use crate::payload::{PayloadEncoding, decode_hex, to_hex};
+use crate::raw::{self, PACKET_TYPES, RawPacket, RawUpdate};
+use crate::runtime::SharedRuntime;
+
+use super::properties::build_user_properties_editor;
+
+const FRAME_MARGIN: i32 = 6;
+const PROPERTIES_TOOLTIP: &str = "One property per line as <id> <kind> <value>, \
+    kinds are byte, int2, int4, varint, utf8, binary (hex) and pair (key=value). \
+    Ids and repeats are not checked, e.g. 0x01 byte 3";
+
+fn buffer_text(buffer: &gtk::TextBuffer) -> String {
+    buffer
+        .text(&buffer.start_iter(), &buffer.end_iter(), false)
+        .to_string()
+}
+
+fn attach_labeled(grid: &gtk::Grid, label: &str, widget: &impl IsA<gtk::Widget>, row: i32) {
+    let label = gtk::Label::new(Some(label));
+    label.set_halign(gtk::Align::End);
+    grid.attach(&label, 0, row, 1, 1);
+    grid.attach(widget, 1, row, 1, 1);
+}
+
+/// Fixed header flags the specification requires for `packet_type`.
+fn required_flags(packet_type: u8) -> u8 {
+    match packet_type {
+        // PUBREL, SUBSCRIBE and UNSUBSCRIBE
+        6 | 8 | 10 => 0x02,
+        _ => 0x00,
+    }
+}
+
+/// The Fields and Hex editors a packet is read from.
+#[derive(Clone)]
+struct PacketEditor {
+    stack: gtk::Stack,
+    hex_text: gtk::TextView,
+    type_combo: gtk::ComboBoxText,
+    flags_spin: gtk::SpinButton,
+    length_check: gtk::CheckButton,
+    length_spin: gtk::SpinButton,
+    header_entry: gtk::Entry,
+    properties_check: gtk::CheckButton,
+    properties_text: gtk::TextView,
+    payload_entry: gtk::Entry,
+    encoding_combo: gtk::ComboBoxText,
+}
+
+impl PacketEditor {
+    /// Packet bytes from whichever editor is showing.
+    fn packet(&self) -> Result<Vec<u8>, String> {
+        if self.stack.visible_child_name().as_deref() == Some("hex") {
+            let bytes = decode_hex(&buffer_text(&self.hex_text.buffer()))?;
+            if bytes.is_empty() {
+                return Err("Enter the packet bytes as hex".to_string());
+            }
+            return Ok(bytes);
+        }
+        let properties = if self.properties_check.is_active() {
+            Some(raw::parse_properties(&buffer_text(
+                &self.properties_text.buffer(),
+            ))?)
+        } else {
+            None
+        };
+        let encoding = self
+            .encoding_combo
+            .active_id()
+            .as_deref()
+            .and_then(PayloadEncoding::from_name)
+            .unwrap_or_default();
+        Ok(RawPacket {
+            packet_type: self
+                .type_combo
+                .active_id()
+                .and_then(|id| id.parse().ok())
+                .unwrap_or(3),
+            flags: self.flags_spin.value_as_int() as u8,
+            remaining_length: self
+                .length_check
+                .is_active()
+                .then(|| self.length_spin.value() as u32),
+            variable_header: decode_hex(&self.header_entry.text())
+                .map_err(|e| format!("variable header: {e}"))?,
+            properties,
+            payload: encoding
+                .decode(&self.payload_entry.text())
+                .map_err(|e| format!("payload: {e}"))?,
+        }
+        .encode())
+    }
+}
+
+/// Show the packet bytes, or why they can't be built, in `label`.
+fn show_preview(editor: &PacketEditor, label: &gtk::Label) -> Option<Vec<u8>> {
+    match editor.packet() {
+        Ok(bytes) => {
+            label.remove_css_class("error");
+            label.set_text(&format!("{} bytes: {}", bytes.len(), to_hex(&bytes)));
+            Some(bytes)
+        }
+        Err(e) => {
+            label.add_css_class("error");
+            label.set_text(&e);
+            None
+        }
+    }
+}
+
+/// Send on its own connection from the shared runtime, reporting through
+/// `updates`.
+fn spawn_send(
+    runtime: &SharedRuntime,
+    address: String,
+    connect: Option<Vec<u8>>,
+    packet: Vec<u8>,
+    updates: tokio::sync::mpsc::UnboundedSender<RawUpdate>,
+) {
+    runtime.spawn_local(move || async move {
+        let mut on_update = move |update| {
+            let _ = updates.send(update);
+        };
+        raw::send(&address, connect, packet, &mut on_update).await;
+    });
+}
+
+pub fn build_raw_panel(client_setting: &ClientSetting, runtime: &SharedRuntime) -> gtk::Frame {
+    let frame = gtk::Frame::new(Some("Raw Packet"));
+    let grid = gtk::Grid::new();
+    grid.set_column_spacing(4);
+    grid.set_row_spacing(4);
+    grid.set_margin_bottom(FRAME_MARGIN);
+    grid.set_margin_start(FRAME_MARGIN);
+    grid.set_margin_end(FRAME_MARGIN);
+
+    let fields_grid = gtk::Grid::new();
+    fields_grid.set_column_spacing(4);
+    fields_grid.set_row_spacing(4);
+    let type_box = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+    let type_combo = gtk::ComboBoxText::new();
+    for (number, name) in PACKET_TYPES.iter().enumerate() {
+        type_combo.append(Some(&number.to_string()), &format!("{number} {name}"));
+    }
+    type_combo.set_active_id(Some("3"));
+    type_box.append(&type_combo);
+    type_box.append(&gtk::Label::new(Some("Flags:")));
+    let flags_spin = gtk::SpinButton::with_range(0.0, 15.0, 1.0);
+    flags_spin.set_tooltip_text(Some(
+        "Low four bits of the fixed header: DUP (8), QoS (2 and 4) and RETAIN (1) for PUBLISH, \
+         2 for PUBREL, SUBSCRIBE and UNSUBSCRIBE",
+    ));
+    type_box.append(&flags_spin);
+    attach_labeled(&fields_grid, "Type:", &type_box, 0);
+    type_combo.connect_changed(clone!(
+        #[weak]
+        flags_spin,
+        move |combo| {
+            if let Some(packet_type) = combo.active_id().and_then(|id| id.parse().ok()) {
+                flags_spin.set_value(required_flags(packet_type) as f64);
+            }
+        }
+    ));
+
+    let length_box = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+    let length_check = gtk::CheckButton::with_label("Override");
+    length_check.set_tooltip_text(Some(
+        "Write this remaining length instead of the actual one. \
+         Values above 268435455 take five bytes",
+    ));
+    let length_spin = gtk::SpinButton::with_range(0.0, u32::MAX as f64, 1.0);
+    length_check
+        .bind_property("active", &length_spin, "sensitive")
+        .sync_create()
+        .build();
+    length_box.append(&length_check);
+    length_box.append(&length_spin);
+    attach_labeled(&fields_grid, "Remaining Length:", &length_box, 1);
+
+    let header_entry = gtk::Entry::new();
+    header_entry.set_hexpand(true);
+    header_entry.set_text("00 09 76 61 75 78 2f 74 65 73 74");
+    header_entry.set_tooltip_text(Some(
+        "Variable header bytes as hex, e.g. the topic name, then the packet ID when QoS is above 0, for PUBLISH",
+    ));
+    attach_labeled(&fields_grid, "Variable Header (hex):", &header_entry, 2);
+
+    let properties_check = gtk::CheckButton::with_label("Include");
+    properties_check.set_active(true);
+    properties_check.set_tooltip_text(Some(
+        "Leave out to send the packet without a property block",
+    ));
+    let (properties_scrolled, properties_text) = build_user_properties_editor(PROPERTIES_TOOLTIP);
+    properties_check
+        .bind_property("active", &properties_scrolled, "sensitive")
+        .sync_create()
+        .build();
+    let properties_box = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+    properties_box.append(&properties_check);
+    properties_scrolled.set_hexpand(true);
+    properties_box.append(&properties_scrolled);
+    attach_labeled(&fields_grid, "Properties:", &properties_box, 3);
+
+    let payload_box = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+    let payload_entry = gtk::Entry::new();
+    payload_entry.set_hexpand(true);
+    payload_box.append(&payload_entry);
+    let encoding_combo = gtk::ComboBoxText::new();
+    for encoding in PayloadEncoding::ALL {
+        encoding_combo.append(Some(encoding.name()), encoding.name());
+    }
+    encoding_combo.set_active_id(Some(PayloadEncoding::default().name()));
+    payload_box.append(&encoding_combo);
+    attach_labeled(&fields_grid, "Payload:", &payload_box, 4);
+
+    let hex_text = gtk::TextView::new();
+    hex_text.set_monospace(true);
+    hex_text.set_wrap_mode(gtk::WrapMode::Char);
+    hex_text.set_tooltip_text(Some("The whole packet as hex, sent exactly as written"));
+    let hex_scrolled = gtk::ScrolledWindow::new();
+    hex_scrolled.set_child(Some(&hex_text));
+    hex_scrolled.set_min_content_height(60);
+
+    let stack = gtk::Stack::new();
+    stack.add_titled(&fields_grid, Some("fields"), "Fields");
+    stack.add_titled(&hex_scrolled, Some("hex"), "Hex");
+    let switcher = gtk::StackSwitcher::new();
+    switcher.set_stack(Some(&stack));
+    switcher.set_halign(gtk::Align::Start);
+    grid.attach(&switcher, 0, 0, 4, 1);
+    grid.attach(&stack, 0, 1, 4, 1);
+
+    let connect_check = gtk::CheckButton::with_label("Connect first");
+    connect_check.set_active(true);
+    connect_check.set_tooltip_text(Some(
+        "Send a valid CONNECT with the connection's client ID and credentials and wait \
+         for CONNACK before the packet",
+    ));
+    grid.attach(&connect_check, 0, 2, 1, 1);
+    let preview_button = gtk::Button::with_label("Preview");
+    preview_button.set_tooltip_text(Some("Show the bytes that would be sent"));
+    grid.attach(&preview_button, 1, 2, 1, 1);
+    let send_button = gtk::Button::with_label("Send");
+    send_button.set_tooltip_text(Some(
+        "Send on a new connection to the broker and show the replies",
+    ));
+    grid.attach(&send_button, 2, 2, 1, 1);
+    let clear_button = gtk::Button::with_label("Clear");
+    grid.attach(&clear_button, 3, 2, 1, 1);
+
+    let preview_label = gtk::Label::new(None);
+    preview_label.set_halign(gtk::Align::Start);
+    preview_label.set_wrap(true);
+    preview_label.set_selectable(true);
+    grid.attach(&preview_label, 0, 3, 4, 1);
+
+    let results = gtk::TextView::new();
+    results.set_monospace(true);
+    results.set_editable(false);
+    results.set_wrap_mode(gtk::WrapMode::Char);
+    let results_scrolled = gtk::ScrolledWindow::new();
+    results_scrolled.set_child(Some(&results));
+    results_scrolled.set_vexpand(true);
+    results_scrolled.set_hexpand(true);
+    grid.attach(&results_scrolled, 0, 4, 4, 1);
+
+    let editor = PacketEditor {
+        stack,
+        hex_text,
+        type_combo,
+        flags_spin,
+        length_check,
+        length_spin,
+        header_entry,
+        properties_check,
+        properties_text,
+        payload_entry,
+        encoding_combo,
+    };
+    preview_button.connect_clicked(clone!(
+        #[strong]
+        editor,
+        #[weak]
+        preview_label,
+        move |_| {
+            show_preview(&editor, &preview_label);
+        }
+    ));
+    clear_button.connect_clicked(clone!(
+        #[weak]
+        results,
+        move |_| results.buffer().set_text("")
+    ));
+
+    let client_setting = client_setting.clone();
+    let runtime = runtime.clone();
+    send_button.connect_clicked(clone!(
+        #[strong]
+        editor,
+        #[weak]
+        connect_check,
+        #[weak]
+        preview_label,
+        #[weak]
+        results,
+        move |send_button| {
+            let Some(bytes) = show_preview(&editor, &preview_label) else {
+                return;
+            };
+            if *client_setting.with_tls.borrow() {
+                preview_label.add_css_class("error");
+                preview_label.set_text("Raw packets are sent over plain TCP, turn off TLS");
+                return;
+            }
+            let address = match *client_setting.proxy_port.borrow() {
+                Some(port) => format!("127.0.0.1:{port}"),
+                None => format!(
+                    "{}:{}",
+                    client_setting.host.borrow(),
+                    client_setting.port.borrow()
+                ),
+            };
+            let connect = connect_check.is_active().then(|| {
+                let username = client_setting.username.borrow();
+                let password = client_setting.password.borrow();
+                raw::connect_packet(
+                    &client_setting.client_id.borrow(),
+                    *client_setting.keep_alive.borrow(),
+                    (*client_setting.with_credentials.borrow())
+                        .then_some((username.as_str(), password.as_str())),
+                )
+            });
+
+            send_button.set_sensitive(false);
+            let (updates_tx, mut updates_rx) = tokio::sync::mpsc::unbounded_channel();
+            spawn_send(&runtime, address, connect, bytes, updates_tx);
+            glib::spawn_future_local(clone!(
+                #[weak]
+                send_button,
+                #[weak]
+                results,
+                async move {
+                    let buffer = results.buffer();
+                    while let Some(update) = updates_rx.recv().await {
+                        buffer.insert(&mut buffer.end_iter(), &format!("{}\n", update.text()));
+                    }
+                    buffer.insert(&mut buffer.end_iter(), "\n");
+                    send_button.set_sensitive(true);
+                }
+            ));
+        }
+    ));
+
+    frame.set_child(Some(&grid));
+    frame
+}
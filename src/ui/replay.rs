@@ -0,0 +1,331 @@
+//! Replay window: load a JSON Lines capture, send its packets again and show
+//! the recorded traffic next to the live traffic for comparison.
+
+use std::{cell::Cell, cell::RefCell, rc::Rc};
+
+use glib::clone;
+use gtk4::{self as gtk, gio};
+
+use gtk::prelude::*;
+
+use crate::export::jsonl::Record;
+use crate::model::{CommandQueue, MessageLog, PacketObject};
+use crate::replay::{self, ReplayOptions, Timing};
+use crate::ui::file::choose_file;
+
+const FRAME_MARGIN: i32 = 6;
+const TIMINGS: [&str; 3] = ["Original", "Scaled", "As fast as possible"];
+
+fn row_text(direction: &str, packet_type: &str, packet_id: i32, topic: &str) -> String {
+    let arrow = if direction == "receive" { "<-" } else { "->" };
+    let mut text = format!("{arrow} {packet_type}");
+    if packet_id != 0 {
+        text.push_str(&format!(" #{packet_id}"));
+    }
+    if !topic.is_empty() {
+        text.push_str(&format!(" {topic}"));
+    }
+    text
+}
+
+fn build_list(
+    title: &str,
+    model: &impl IsA<gio::ListModel>,
+    factory: &gtk::SignalListItemFactory,
+) -> gtk::Frame {
+    let frame = gtk::Frame::new(Some(title));
+    let list = gtk::ListView::new(
+        Some(gtk::NoSelection::new(Some(model.clone()))),
+        Some(factory.clone()),
+    );
+    let scrolled = gtk::ScrolledWindow::new();
+    scrolled.set_child(Some(&list));
+    scrolled.set_vexpand(true);
+    scrolled.set_hexpand(true);
+    frame.set_child(Some(&scrolled));
+    frame
+}
+
+fn label_factory(text: impl Fn(&glib::Object) -> String + 'static) -> gtk::SignalListItemFactory {
+    let factory = gtk::SignalListItemFactory::new();
+    factory.connect_setup(|_, item| {
+        let label = gtk::Label::new(None);
+        label.set_halign(gtk::Align::Start);
+        item.downcast_ref::<gtk::ListItem>()
+            .expect("list item")
+            .set_child(Some(&label));
+    });
+    factory.connect_bind(move |_, item| {
+        let item = item.downcast_ref::<gtk::ListItem>().expect("list item");
+        if let (Some(label), Some(object)) =
+            (item.child().and_downcast::<gtk::Label>(), item.item())
+        {
+            label.set_text(&text(&object));
+        }
+    });
+    factory
+}
+
+/// Open the replay window for `message_log`, sending through `queue`.
+pub fn show_replay_window(
+    parent: &impl IsA<gtk::Widget>,
+    message_log: &MessageLog,
+    queue: &CommandQueue,
+) {
+    let queue = queue.clone();
+    let window = gtk::Window::builder()
+        .title("Replay Capture")
+        .default_width(900)
+        .default_height(600)
+        .build();
+    if let Some(parent) = parent.root().and_downcast::<gtk::Window>() {
+        window.set_transient_for(Some(&parent));
+    }
+
+    let records: Rc<RefCell<Vec<Record>>> = Rc::new(RefCell::new(Vec::new()));
+    let running = Rc::new(Cell::new(false));
+    let started_at: Rc<RefCell<Option<chrono::DateTime<chrono::FixedOffset>>>> =
+        Rc::new(RefCell::new(None));
+
+    let grid = gtk::Grid::new();
+    grid.set_column_spacing(4);
+    grid.set_row_spacing(4);
+    grid.set_margin_top(FRAME_MARGIN);
+    grid.set_margin_bottom(FRAME_MARGIN);
+    grid.set_margin_start(FRAME_MARGIN);
+    grid.set_margin_end(FRAME_MARGIN);
+
+    let load_button = gtk::Button::with_label("Load Capture...");
+    load_button.set_tooltip_text(Some(
+        "Load a JSON Lines capture exported from the message log",
+    ));
+    grid.attach(&load_button, 0, 0, 1, 1);
+    let file_label = gtk::Label::new(Some("No capture loaded"));
+    file_label.set_halign(gtk::Align::Start);
+    grid.attach(&file_label, 1, 0, 5, 1);
+
+    let timing_label = gtk::Label::new(Some("Timing:"));
+    timing_label.set_halign(gtk::Align::End);
+    grid.attach(&timing_label, 0, 1, 1, 1);
+    let timing_combo = gtk::DropDown::from_strings(&TIMINGS);
+    timing_combo.set_selected(0);
+    grid.attach(&timing_combo, 1, 1, 1, 1);
+    let scale_label = gtk::Label::new(Some("Scale:"));
+    scale_label.set_halign(gtk::Align::End);
+    grid.attach(&scale_label, 2, 1, 1, 1);
+    let scale_spin = gtk::SpinButton::with_range(0.01, 100.0, 0.1);
+    scale_spin.set_digits(2);
+    scale_spin.set_value(1.0);
+    scale_spin.set_tooltip_text(Some(
+        "Multiplier for the recorded gaps, 0.5 is twice as fast",
+    ));
+    scale_spin.set_sensitive(false);
+    grid.attach(&scale_spin, 3, 1, 1, 1);
+    timing_combo.connect_selected_notify(clone!(
+        #[weak]
+        scale_spin,
+        move |combo| scale_spin.set_sensitive(combo.selected() == 1)
+    ));
+    let remap_check = gtk::CheckButton::with_label("Remap packet IDs");
+    remap_check.set_tooltip_text(Some("Renumber packet IDs from 1 in replay order"));
+    grid.attach(&remap_check, 4, 1, 1, 1);
+
+    let rewrite_label = gtk::Label::new(Some("Rewrite topic prefix:"));
+    rewrite_label.set_halign(gtk::Align::End);
+    grid.attach(&rewrite_label, 0, 2, 1, 1);
+    let from_entry = gtk::Entry::new();
+    from_entry.set_placeholder_text(Some("Recorded prefix"));
+    grid.attach(&from_entry, 1, 2, 1, 1);
+    let to_label = gtk::Label::new(Some("to"));
+    grid.attach(&to_label, 2, 2, 1, 1);
+    let to_entry = gtk::Entry::new();
+    to_entry.set_placeholder_text(Some("Replacement prefix"));
+    grid.attach(&to_entry, 3, 2, 1, 1);
+
+    let start_button = gtk::Button::with_label("Start Replay");
+    start_button.set_sensitive(false);
+    grid.attach(&start_button, 0, 3, 1, 1);
+    let stop_button = gtk::Button::with_label("Stop");
+    stop_button.set_sensitive(false);
+    grid.attach(&stop_button, 1, 3, 1, 1);
+    let progress_label = gtk::Label::new(None);
+    progress_label.set_halign(gtk::Align::Start);
+    grid.attach(&progress_label, 2, 3, 4, 1);
+
+    // recorded packets, both directions so responses can be compared
+    let recorded = gtk::StringList::new(&[]);
+    let recorded_factory = label_factory(|object| {
+        object
+            .downcast_ref::<gtk::StringObject>()
+            .map(|s| s.string().to_string())
+            .unwrap_or_default()
+    });
+    // live packets logged since the replay started
+    let live_filter = gtk::CustomFilter::new(clone!(
+        #[strong]
+        started_at,
+        move |object| {
+            let Some(start) = *started_at.borrow() else {
+                return false;
+            };
+            object
+                .downcast_ref::<PacketObject>()
+                .and_then(|p| chrono::DateTime::parse_from_rfc3339(&p.timestamp()).ok())
+                .is_some_and(|timestamp| timestamp >= start)
+        }
+    ));
+    let live = gtk::FilterListModel::new(Some(message_log.store()), Some(live_filter.clone()));
+    let live_factory = label_factory(|object| {
+        object
+            .downcast_ref::<PacketObject>()
+            .map(|p| row_text(&p.exchange(), &p.packet_type(), p.packet_id(), &p.topic()))
+            .unwrap_or_default()
+    });
+    let lists = gtk::Paned::new(gtk::Orientation::Horizontal);
+    lists.set_start_child(Some(&build_list("Recorded", &recorded, &recorded_factory)));
+    lists.set_end_child(Some(&build_list("Replay", &live, &live_factory)));
+    lists.set_position(440);
+    grid.attach(&lists, 0, 4, 6, 1);
+
+    load_button.connect_clicked(clone!(
+        #[strong]
+        records,
+        #[weak]
+        recorded,
+        #[weak]
+        file_label,
+        #[weak]
+        start_button,
+        move |b| {
+            let records = records.clone();
+            choose_file(
+                b,
+                "Load Capture",
+                gtk::FileChooserAction::Open,
+                "Load",
+                move |path| match replay::load(&path) {
+                    Ok(loaded) => {
+                        let rows: Vec<String> = loaded
+                            .iter()
+                            .map(|r| row_text(&r.direction, &r.packet_type, r.packet_id, &r.topic))
+                            .collect();
+                        let rows: Vec<&str> = rows.iter().map(String::as_str).collect();
+                        recorded.splice(0, recorded.n_items(), &rows);
+                        file_label.set_text(&format!(
+                            "{} ({} packets)",
+                            path.display(),
+                            loaded.len()
+                        ));
+                        start_button.set_sensitive(!loaded.is_empty());
+                        records.replace(loaded);
+                    }
+                    Err(e) => eprintln!("Failed to load capture: {e}"),
+                },
+            );
+        }
+    ));
+
+    stop_button.connect_clicked(clone!(
+        #[strong]
+        running,
+        move |_| running.set(false)
+    ));
+
+    start_button.connect_clicked(clone!(
+        #[strong]
+        records,
+        #[strong]
+        running,
+        #[strong]
+        started_at,
+        #[weak]
+        timing_combo,
+        #[weak]
+        scale_spin,
+        #[weak]
+        remap_check,
+        #[weak]
+        from_entry,
+        #[weak]
+        to_entry,
+        #[weak]
+        stop_button,
+        #[weak]
+        progress_label,
+        #[weak]
+        live_filter,
+        move |start_button| {
+            let timing = match timing_combo.selected() {
+                0 => Timing::Original,
+                1 => Timing::Scaled(scale_spin.value()),
+                _ => Timing::AsFastAsPossible,
+            };
+            let from = from_entry.text().to_string();
+            let options = ReplayOptions {
+                timing,
+                topic_rewrite: (!from.is_empty()).then(|| (from, to_entry.text().to_string())),
+                remap_packet_ids: remap_check.is_active(),
+            };
+            let steps = replay::plan(&records.borrow(), &options);
+            if steps.is_empty() {
+                progress_label.set_text("Nothing to replay, the capture has no sent packets");
+                return;
+            }
+            started_at.replace(Some(chrono::Local::now().fixed_offset()));
+            live_filter.changed(gtk::FilterChange::Different);
+
+            running.set(true);
+            start_button.set_sensitive(false);
+            stop_button.set_sensitive(true);
+            let total = steps.len();
+            let queue = queue.clone();
+            let running = running.clone();
+            glib::spawn_future_local(clone!(
+                #[weak]
+                start_button,
+                #[weak]
+                stop_button,
+                #[weak]
+                progress_label,
+                async move {
+                    let mut sent = 0;
+                    for step in steps {
+                        if !step.delay.is_zero() {
+                            glib::timeout_future(step.delay).await;
+                        }
+                        if !running.get() {
+                            break;
+                        }
+                        // not waiting for broker acknowledgements keeps the
+                        // recorded timing, a busy runner shows in the queue status
+                        queue.dispatch(step.command);
+                        sent += 1;
+                        progress_label
+                            .set_text(&format!("{sent}/{total} sent: {}", step.description));
+                    }
+                    if sent < total {
+                        progress_label.set_text(&format!("Stopped after {sent}/{total}"));
+                    } else {
+                        progress_label.set_text(&format!("Replay complete, {total} sent"));
+                    }
+                    running.set(false);
+                    start_button.set_sensitive(true);
+                    stop_button.set_sensitive(false);
+                }
+            ));
+        }
+    ));
+
+    // stop sending when the window goes away
+    window.connect_close_request(clone!(
+        #[strong]
+        running,
+        move |_| {
+            running.set(false);
+            glib::Propagation::Proceed
+        }
+    ));
+
+    window.set_child(Some(&grid));
+    window.present();
+}
@@ -0,0 +1,291 @@
+//! Retained message browser: subscribe to a filter with Retain Handling 0,
+//! list the retained message of every matching topic and clear selected
+//! topics on the broker.
+
+use std::{cell::RefCell, rc::Rc};
+
+use glib::clone;
+use gtk4::{self as gtk, gio};
+
+use gtk::prelude::*;
+use vaux_mqtt::{QoSLevel, publish::Publish};
+
+use crate::client::{Command, Reply};
+use crate::model::CommandQueue;
+use crate::model::retained::{self, RetainedMessage};
+use crate::reason;
+
+const FRAME_MARGIN: i32 = 6;
+/// Packet ID of the browse SUBSCRIBE and UNSUBSCRIBE, away from the IDs
+/// typed in the UI and below the script range.
+const BROWSE_PACKET_ID: u16 = 59999;
+
+fn column(
+    title: &str,
+    text: impl Fn(&RetainedMessage) -> String + 'static,
+) -> gtk::ColumnViewColumn {
+    let factory = gtk::SignalListItemFactory::new();
+    factory.connect_setup(move |_, item| {
+        let label = gtk::Label::new(None);
+        label.set_halign(gtk::Align::Start);
+        label.set_ellipsize(gtk::pango::EllipsizeMode::End);
+        item.downcast_ref::<gtk::ListItem>()
+            .expect("Failed to downcast")
+            .set_child(Some(&label));
+    });
+    factory.connect_bind(move |_, item| {
+        let item = item
+            .downcast_ref::<gtk::ListItem>()
+            .expect("Failed to downcast");
+        let message = item
+            .item()
+            .and_downcast::<RetainedMessage>()
+            .expect("Failed to downcast to RetainedMessage");
+        let label = item
+            .child()
+            .and_downcast::<gtk::Label>()
+            .expect("Failed to downcast to Label");
+        let text = text(&message);
+        label.set_tooltip_text(Some(&text));
+        label.set_text(&text);
+    });
+    let column = gtk::ColumnViewColumn::new(Some(title), Some(factory));
+    column.set_resizable(true);
+    column
+}
+
+fn show_status(label: &gtk::Label, text: &str, failed: bool) {
+    label.set_text(text);
+    if failed {
+        label.add_css_class("error");
+    } else {
+        label.remove_css_class("error");
+    }
+}
+
+/// Clear the retained message of each topic with a zero-length retained
+/// PUBLISH, dropping it from the list once sent. Returns the topics that
+/// could not be cleared, with the reason.
+async fn clear_topics(
+    queue: CommandQueue,
+    store: gio::ListStore,
+    messages: Vec<RetainedMessage>,
+) -> Vec<String> {
+    let mut failures = Vec::new();
+    for message in messages {
+        let topic = message.topic();
+        let publish = match Publish::new_with_message(0, &topic, QoSLevel::AtMostOnce, "") {
+            Ok(publish) => publish.with_retain(true),
+            Err(e) => {
+                failures.push(format!("{topic}: {e}"));
+                continue;
+            }
+        };
+        match queue.send(Command::Publish(publish)).await {
+            Ok(_) => retained::remove(&store, &message),
+            Err(e) => failures.push(format!("{topic}: {e}")),
+        }
+    }
+    failures
+}
+
+pub fn build_retained_panel(store: &gio::ListStore, queue: &CommandQueue) -> gtk::Frame {
+    let frame = gtk::Frame::new(Some("Retained Messages"));
+    let vbox = gtk::Box::new(gtk::Orientation::Vertical, 4);
+    vbox.set_margin_start(FRAME_MARGIN);
+    vbox.set_margin_end(FRAME_MARGIN);
+    vbox.set_margin_top(FRAME_MARGIN);
+    vbox.set_margin_bottom(FRAME_MARGIN);
+
+    let browse_box = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+    browse_box.append(&gtk::Label::new(Some("Topic Filter:")));
+    let filter_entry = gtk::Entry::new();
+    filter_entry.set_text("#");
+    filter_entry.set_hexpand(true);
+    filter_entry.set_tooltip_text(Some(
+        "Filter to browse, subscribed at QoS 0 with Retain Handling 0",
+    ));
+    browse_box.append(&filter_entry);
+    let browse_button = gtk::Button::with_label("Browse");
+    browse_button.set_tooltip_text(Some(
+        "Subscribe to the filter and list the retained messages the broker sends",
+    ));
+    browse_box.append(&browse_button);
+    vbox.append(&browse_box);
+
+    let column_view = gtk::ColumnView::new(None::<gtk::SelectionModel>);
+    column_view.set_show_column_separators(true);
+    column_view.append_column(&column("Topic", |m| m.topic()));
+    column_view.append_column(&column("QoS", |m| m.qos().to_string()));
+    column_view.append_column(&column("Size", |m| m.size().to_string()));
+    let preview_column = column("Payload", |m| m.preview());
+    preview_column.set_expand(true);
+    column_view.append_column(&preview_column);
+    let selection = gtk::MultiSelection::new(Some(store.clone()));
+    column_view.set_model(Some(&selection));
+
+    let scrolled_window = gtk::ScrolledWindow::new();
+    scrolled_window.set_policy(gtk::PolicyType::Automatic, gtk::PolicyType::Automatic);
+    scrolled_window.set_vexpand(true);
+    scrolled_window.set_child(Some(&column_view));
+    vbox.append(&scrolled_window);
+
+    let button_box = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+    let status_label = gtk::Label::new(None);
+    status_label.set_halign(gtk::Align::Start);
+    status_label.set_hexpand(true);
+    status_label.set_ellipsize(gtk::pango::EllipsizeMode::End);
+    button_box.append(&status_label);
+    let count_label = gtk::Label::new(Some("0 topics"));
+    button_box.append(&count_label);
+    let clear_button = gtk::Button::with_label("Clear Selected");
+    clear_button.set_tooltip_text(Some(
+        "Publish a zero-length retained message to each selected topic, \
+         removing its retained message from the broker",
+    ));
+    clear_button.add_css_class("destructive-action");
+    button_box.append(&clear_button);
+    vbox.append(&button_box);
+
+    store.connect_items_changed(clone!(
+        #[weak]
+        count_label,
+        move |store, _, _, _| count_label.set_text(&format!("{} topics", store.n_items()))
+    ));
+
+    // filter of the running browse subscription
+    let browsing: Rc<RefCell<Option<String>>> = Rc::new(RefCell::new(None));
+    browse_button.connect_clicked(clone!(
+        #[weak]
+        filter_entry,
+        #[weak]
+        store,
+        #[weak]
+        queue,
+        #[weak]
+        status_label,
+        #[strong]
+        browsing,
+        move |button| {
+            button.set_sensitive(false);
+            let stopping = browsing.borrow().clone();
+            let filter = filter_entry.text().to_string();
+            let command = match &stopping {
+                Some(filter) => Command::Unsubscribe(BROWSE_PACKET_ID, filter.clone()),
+                None if filter.is_empty() => {
+                    show_status(&status_label, "Enter a topic filter to browse", true);
+                    button.set_sensitive(true);
+                    return;
+                }
+                None => {
+                    // the broker sends the retained messages again on subscribe
+                    store.remove_all();
+                    Command::BrowseRetained(BROWSE_PACKET_ID, filter.clone())
+                }
+            };
+            let sent = queue.send(command);
+            glib::spawn_future_local(clone!(
+                #[weak]
+                button,
+                #[weak]
+                filter_entry,
+                #[weak]
+                status_label,
+                #[strong]
+                browsing,
+                async move {
+                    let result = sent.await;
+                    button.set_sensitive(true);
+                    if stopping.is_some() {
+                        browsing.replace(None);
+                        button.set_label("Browse");
+                        filter_entry.set_sensitive(true);
+                        match result {
+                            Err(e) => show_status(
+                                &status_label,
+                                &format!("Unsubscribe failed: {e}"),
+                                true,
+                            ),
+                            _ => show_status(&status_label, "Browsing stopped", false),
+                        }
+                        return;
+                    }
+                    match result {
+                        Ok(Reply::Acknowledged(reasons)) if reasons.failed() => show_status(
+                            &status_label,
+                            &format!("Browse refused: {}", reasons.summary(reason::suback_name)),
+                            true,
+                        ),
+                        Ok(_) => {
+                            browsing.replace(Some(filter));
+                            button.set_label("Stop");
+                            filter_entry.set_sensitive(false);
+                            show_status(
+                                &status_label,
+                                "Subscribed, retained messages are listed as they arrive",
+                                false,
+                            );
+                        }
+                        Err(e) => show_status(&status_label, &format!("Browse failed: {e}"), true),
+                    }
+                }
+            ));
+        }
+    ));
+
+    clear_button.connect_clicked(clone!(
+        #[weak]
+        selection,
+        #[weak]
+        store,
+        #[weak]
+        queue,
+        #[weak]
+        status_label,
+        move |_| {
+            let messages: Vec<RetainedMessage> = (0..selection.n_items())
+                .filter(|i| selection.is_selected(*i))
+                .filter_map(|i| selection.item(i).and_downcast::<RetainedMessage>())
+                .collect();
+            if messages.is_empty() {
+                show_status(&status_label, "Select the topics to clear", true);
+                return;
+            }
+            let count = messages.len();
+            show_status(
+                &status_label,
+                &format!("Clearing {count} retained messages..."),
+                false,
+            );
+            let cleared = clear_topics(queue.clone(), store.clone(), messages);
+            glib::spawn_future_local(clone!(
+                #[weak]
+                status_label,
+                async move {
+                    let failures = cleared.await;
+                    if failures.is_empty() {
+                        show_status(
+                            &status_label,
+                            &format!("Cleared {count} retained messages"),
+                            false,
+                        );
+                    } else {
+                        eprintln!("Failed to clear retained messages: {}", failures.join(", "));
+                        show_status(
+                            &status_label,
+                            &format!(
+                                "{} of {count} not cleared: {}",
+                                failures.len(),
+                                failures.join(", ")
+                            ),
+                            true,
+                        );
+                    }
+                }
+            ));
+        }
+    ));
+
+    frame.set_child(Some(&vbox));
+    frame
+}
@@ -0,0 +1,254 @@
+//! Scenarios tab: load a TOML scenario, run it on its own connection and list
+//! the step results. Scenario traffic is added to the message log.
+
+use std::{cell::RefCell, rc::Rc};
+
+use glib::clone;
+use gtk4::{self as gtk};
+
+use gtk::prelude::*;
+
+use crate::client::ClientSetting;
+use crate::model::packet::Exchange;
+use crate::model::{MessageLog, PacketObject};
+use crate::profile::Profile;
+use crate::runtime::SharedRuntime;
+use crate::scenario::Scenario;
+use crate::scenario::runner::{self, StepResult};
+use crate::ui::file::choose_file;
+use crate::wire;
+
+const FRAME_MARGIN: i32 = 6;
+
+enum Update {
+    Packet(Exchange, vaux_mqtt::Packet),
+    Step(StepResult),
+    Failed(String),
+}
+
+fn result_text(result: &StepResult) -> String {
+    format!(
+        "{} {}. {} ({} ms) {}",
+        if result.passed {
+            "\u{2714}"
+        } else {
+            "\u{2718}"
+        },
+        result.index,
+        result.description,
+        result.elapsed.as_millis(),
+        result.detail
+    )
+}
+
+/// Run the scenario with its own runner on the shared runtime, reporting
+/// through `updates`.
+fn spawn_scenario(
+    runtime: &SharedRuntime,
+    scenario: Scenario,
+    profile: Profile,
+    updates: tokio::sync::mpsc::UnboundedSender<Update>,
+) {
+    runtime.spawn_local(move || async move {
+        let setting = match profile.to_setting() {
+            Ok(setting) => setting,
+            Err(e) => {
+                let _ = updates.send(Update::Failed(e));
+                return;
+            }
+        };
+        let step_updates = updates.clone();
+        let mut on_result = move |result: &StepResult| {
+            let _ = step_updates.send(Update::Step(result.clone()));
+        };
+        let mut on_packet = move |exchange: &Exchange, packet: &vaux_mqtt::Packet| {
+            let _ = updates.send(Update::Packet(*exchange, packet.clone()));
+        };
+        runner::execute(&scenario, &setting, &mut on_result, &mut on_packet).await;
+    });
+}
+
+pub fn build_scenario_panel(
+    client_setting: &ClientSetting,
+    message_log: &MessageLog,
+    runtime: &SharedRuntime,
+) -> gtk::Frame {
+    let frame = gtk::Frame::new(Some("Scenarios"));
+    let grid = gtk::Grid::new();
+    grid.set_column_spacing(4);
+    grid.set_row_spacing(4);
+    grid.set_margin_bottom(FRAME_MARGIN);
+    grid.set_margin_start(FRAME_MARGIN);
+    grid.set_margin_end(FRAME_MARGIN);
+
+    let scenario: Rc<RefCell<Option<Scenario>>> = Rc::new(RefCell::new(None));
+
+    let load_button = gtk::Button::with_label("Load Scenario...");
+    load_button.set_tooltip_text(Some("Load a TOML scenario file"));
+    grid.attach(&load_button, 0, 0, 1, 1);
+    let run_button = gtk::Button::with_label("Run");
+    run_button.set_sensitive(false);
+    grid.attach(&run_button, 1, 0, 1, 1);
+    let current_settings_check = gtk::CheckButton::with_label("Use current connection settings");
+    current_settings_check.set_tooltip_text(Some(
+        "Connect with the Settings tab instead of the profile named in the scenario",
+    ));
+    grid.attach(&current_settings_check, 2, 0, 1, 1);
+    let summary_label = gtk::Label::new(Some("No scenario loaded"));
+    summary_label.set_halign(gtk::Align::Start);
+    summary_label.set_hexpand(true);
+    grid.attach(&summary_label, 3, 0, 1, 1);
+
+    let results = gtk::StringList::new(&[]);
+    let factory = gtk::SignalListItemFactory::new();
+    factory.connect_setup(|_, item| {
+        let label = gtk::Label::new(None);
+        label.set_halign(gtk::Align::Start);
+        label.set_selectable(true);
+        item.downcast_ref::<gtk::ListItem>()
+            .expect("Failed to downcast")
+            .set_child(Some(&label));
+    });
+    factory.connect_bind(|_, item| {
+        let item = item
+            .downcast_ref::<gtk::ListItem>()
+            .expect("Failed to downcast");
+        if let (Some(label), Some(row)) = (
+            item.child().and_downcast::<gtk::Label>(),
+            item.item().and_downcast::<gtk::StringObject>(),
+        ) {
+            label.set_text(&row.string());
+        }
+    });
+    let list = gtk::ListView::new(
+        Some(gtk::NoSelection::new(Some(results.clone()))),
+        Some(factory),
+    );
+    let scrolled = gtk::ScrolledWindow::new();
+    scrolled.set_child(Some(&list));
+    scrolled.set_vexpand(true);
+    grid.attach(&scrolled, 0, 1, 4, 1);
+
+    load_button.connect_clicked(clone!(
+        #[strong]
+        scenario,
+        #[weak]
+        run_button,
+        #[weak]
+        summary_label,
+        #[weak]
+        results,
+        move |b| {
+            let scenario = scenario.clone();
+            choose_file(
+                b,
+                "Load Scenario",
+                gtk::FileChooserAction::Open,
+                "Load",
+                move |path| match Scenario::load(&path) {
+                    Ok(loaded) => {
+                        let rows: Vec<String> = loaded
+                            .steps
+                            .iter()
+                            .enumerate()
+                            .map(|(i, step)| format!("  {}. {}", i + 1, step.describe()))
+                            .collect();
+                        let rows: Vec<&str> = rows.iter().map(String::as_str).collect();
+                        results.splice(0, results.n_items(), &rows);
+                        summary_label.set_text(&format!(
+                            "{} ({} steps)",
+                            loaded.name,
+                            loaded.steps.len()
+                        ));
+                        run_button.set_sensitive(true);
+                        scenario.replace(Some(loaded));
+                    }
+                    Err(e) => summary_label.set_text(&e),
+                },
+            );
+        }
+    ));
+
+    let client_setting = client_setting.clone();
+    let runtime = runtime.clone();
+    run_button.connect_clicked(clone!(
+        #[strong]
+        scenario,
+        #[weak]
+        current_settings_check,
+        #[weak]
+        summary_label,
+        #[weak]
+        results,
+        #[weak]
+        message_log,
+        move |run_button| {
+            let Some(scenario) = scenario.borrow().clone() else {
+                return;
+            };
+            let profile = if current_settings_check.is_active() {
+                Profile::from_setting(&client_setting)
+            } else {
+                match scenario.profile.as_deref().map(Profile::load) {
+                    Some(Ok(profile)) => profile,
+                    Some(Err(e)) => {
+                        summary_label.set_text(&e);
+                        return;
+                    }
+                    None => {
+                        summary_label.set_text(
+                            "The scenario names no profile, use the current connection settings",
+                        );
+                        return;
+                    }
+                }
+            };
+
+            results.splice(0, results.n_items(), &[]);
+            summary_label.set_text(&format!("Running {}...", scenario.name));
+            run_button.set_sensitive(false);
+            let total = scenario.steps.len();
+            let name = scenario.name.clone();
+            let (updates_tx, mut updates_rx) = tokio::sync::mpsc::unbounded_channel();
+            spawn_scenario(&runtime, scenario, profile, updates_tx);
+            glib::spawn_future_local(clone!(
+                #[weak]
+                run_button,
+                #[weak]
+                summary_label,
+                #[weak]
+                results,
+                #[weak]
+                message_log,
+                async move {
+                    let mut passed = 0;
+                    let mut error = None;
+                    while let Some(update) = updates_rx.recv().await {
+                        match update {
+                            Update::Packet(exchange, packet) => {
+                                let size = wire::Encoded::of(&packet).size;
+                                message_log.append(&PacketObject::new(exchange, packet, size));
+                            }
+                            Update::Step(result) => {
+                                if result.passed {
+                                    passed += 1;
+                                }
+                                results.append(&result_text(&result));
+                            }
+                            Update::Failed(e) => error = Some(e),
+                        }
+                    }
+                    summary_label.set_text(&match error {
+                        Some(e) => format!("{name}: {e}"),
+                        None if passed == total => format!("{name}: all {total} steps passed"),
+                        None => format!("{name}: {passed} of {total} steps passed"),
+                    });
+                    run_button.set_sensitive(true);
+                }
+            ));
+        }
+    ));
+
+    frame.set_child(Some(&grid));
+    frame
+}
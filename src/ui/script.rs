@@ -0,0 +1,328 @@
+//! Scripts tab: edit the Rhai hook script, load it into the runner and
+//! reload it whenever the file is saved, from here or another editor.
+
+use std::{cell::RefCell, path::PathBuf, rc::Rc};
+
+use glib::clone;
+use gtk4::{self as gtk, gio};
+
+use gtk::prelude::*;
+
+use crate::client::Command;
+use crate::model::{CommandQueue, ScriptObject};
+use crate::ui::file::choose_file;
+
+const FRAME_MARGIN: i32 = 6;
+
+const TEMPLATE: &str = r#"// Hooks are optional, define the ones you need.
+
+fn on_publish(topic, payload) {
+    if topic.starts_with("device/") && topic.ends_with("/ping") {
+        let reply = topic;
+        reply.replace("/ping", "/pong");
+        publish(reply, payload);
+        tag("answered");
+    }
+    // returning a string replaces the payload shown in the log
+}
+
+fn on_connack(reason, session_present) {
+    if reason >= 0x80 {
+        alert(`connection refused: ${reason}`);
+    }
+}
+
+fn on_disconnect(reason) {
+    alert(`server disconnected: ${reason}`);
+}
+"#;
+
+fn buffer_text(buffer: &gtk::TextBuffer) -> String {
+    buffer
+        .text(&buffer.start_iter(), &buffer.end_iter(), false)
+        .to_string()
+}
+
+pub fn build_script_panel(script: &ScriptObject, queue: &CommandQueue) -> gtk::Frame {
+    let frame = gtk::Frame::new(Some("Scripts"));
+    let grid = gtk::Grid::new();
+    grid.set_column_spacing(4);
+    grid.set_row_spacing(4);
+    grid.set_margin_bottom(FRAME_MARGIN);
+    grid.set_margin_start(FRAME_MARGIN);
+    grid.set_margin_end(FRAME_MARGIN);
+
+    let path: Rc<RefCell<Option<PathBuf>>> = Rc::new(RefCell::new(None));
+    // the source last sent to the runner, so a save seen by the file monitor
+    // does not load the same script twice
+    let loaded_source = Rc::new(RefCell::new(String::new()));
+    let monitor: Rc<RefCell<Option<gio::FileMonitor>>> = Rc::new(RefCell::new(None));
+
+    let toolbar = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+    let open_button = gtk::Button::with_label("Open...");
+    let save_button = gtk::Button::with_label("Save");
+    save_button.set_tooltip_text(Some("Save to the open file, or choose one"));
+    let save_as_button = gtk::Button::with_label("Save As...");
+    let load_button = gtk::Button::with_label("Load");
+    load_button.set_tooltip_text(Some(
+        "Load the editor contents into the runner without saving",
+    ));
+    let unload_button = gtk::Button::with_label("Unload");
+    let reload_check = gtk::CheckButton::with_label("Reload on save");
+    reload_check.set_active(true);
+    reload_check.set_tooltip_text(Some(
+        "Load the script whenever the file is saved, here or in another editor",
+    ));
+    for widget in [
+        open_button.upcast_ref::<gtk::Widget>(),
+        save_button.upcast_ref(),
+        save_as_button.upcast_ref(),
+        load_button.upcast_ref(),
+        unload_button.upcast_ref(),
+        reload_check.upcast_ref(),
+    ] {
+        toolbar.append(widget);
+    }
+    grid.attach(&toolbar, 0, 0, 2, 1);
+
+    let file_label = gtk::Label::new(Some("Unsaved script"));
+    file_label.set_halign(gtk::Align::Start);
+    grid.attach(&file_label, 0, 1, 1, 1);
+    let status_label = gtk::Label::new(None);
+    status_label.set_halign(gtk::Align::End);
+    status_label.set_hexpand(true);
+    script
+        .bind_property("status", &status_label, "label")
+        .sync_create()
+        .build();
+    script
+        .bind_property("loaded", &unload_button, "sensitive")
+        .sync_create()
+        .build();
+    grid.attach(&status_label, 1, 1, 1, 1);
+
+    let editor = gtk::TextView::new();
+    editor.set_monospace(true);
+    editor.buffer().set_text(TEMPLATE);
+    let editor_scrolled = gtk::ScrolledWindow::new();
+    editor_scrolled.set_child(Some(&editor));
+    editor_scrolled.set_vexpand(true);
+    editor_scrolled.set_hexpand(true);
+    grid.attach(&editor_scrolled, 0, 2, 1, 1);
+
+    let alerts = gtk::StringList::new(&[]);
+    let factory = gtk::SignalListItemFactory::new();
+    factory.connect_setup(|_, item| {
+        let label = gtk::Label::new(None);
+        label.set_halign(gtk::Align::Start);
+        item.downcast_ref::<gtk::ListItem>()
+            .expect("Failed to downcast")
+            .set_child(Some(&label));
+    });
+    factory.connect_bind(|_, item| {
+        let item = item
+            .downcast_ref::<gtk::ListItem>()
+            .expect("Failed to downcast");
+        if let (Some(label), Some(row)) = (
+            item.child().and_downcast::<gtk::Label>(),
+            item.item().and_downcast::<gtk::StringObject>(),
+        ) {
+            label.set_text(&row.string());
+        }
+    });
+    let alert_list = gtk::ListView::new(
+        Some(gtk::NoSelection::new(Some(alerts.clone()))),
+        Some(factory),
+    );
+    let alert_scrolled = gtk::ScrolledWindow::new();
+    alert_scrolled.set_child(Some(&alert_list));
+    alert_scrolled.set_min_content_width(260);
+    let alert_frame = gtk::Frame::new(Some("Alerts"));
+    alert_frame.set_child(Some(&alert_scrolled));
+    grid.attach(&alert_frame, 1, 2, 1, 1);
+    script.connect_alert_count_notify(clone!(
+        #[weak]
+        alerts,
+        move |script| {
+            if script.alert_count() > 0 {
+                alerts.append(&format!(
+                    "{} {}",
+                    chrono::Local::now().format("%H:%M:%S"),
+                    script.last_alert()
+                ));
+            }
+        }
+    ));
+
+    let load_source = Rc::new(clone!(
+        #[strong]
+        loaded_source,
+        #[weak]
+        queue,
+        move |source: String| {
+            loaded_source.replace(source.clone());
+            queue.dispatch(Command::LoadScript(source));
+        }
+    ));
+
+    // watch the file so saves from other editors reload too
+    let watch = Rc::new(clone!(
+        #[strong]
+        monitor,
+        #[strong]
+        loaded_source,
+        #[strong]
+        load_source,
+        #[weak]
+        editor,
+        #[weak]
+        reload_check,
+        move |file_path: &PathBuf| {
+            let file = gio::File::for_path(file_path);
+            match file.monitor_file(gio::FileMonitorFlags::NONE, gio::Cancellable::NONE) {
+                Ok(file_monitor) => {
+                    let load_source = load_source.clone();
+                    let loaded_source = loaded_source.clone();
+                    file_monitor.connect_changed(clone!(
+                        #[weak]
+                        editor,
+                        #[weak]
+                        reload_check,
+                        move |_, file, _, event| {
+                            if event != gio::FileMonitorEvent::ChangesDoneHint
+                                || !reload_check.is_active()
+                            {
+                                return;
+                            }
+                            let Some(path) = file.path() else {
+                                return;
+                            };
+                            match std::fs::read_to_string(&path) {
+                                Ok(source) if source != *loaded_source.borrow() => {
+                                    editor.buffer().set_text(&source);
+                                    load_source(source);
+                                }
+                                Ok(_) => {}
+                                Err(e) => eprintln!("Failed to read {}: {e}", path.display()),
+                            }
+                        }
+                    ));
+                    monitor.replace(Some(file_monitor));
+                }
+                Err(e) => eprintln!("Failed to watch {}: {e}", file_path.display()),
+            }
+        }
+    ));
+
+    let save_to = Rc::new(clone!(
+        #[strong]
+        path,
+        #[strong]
+        watch,
+        #[strong]
+        load_source,
+        #[weak]
+        editor,
+        #[weak]
+        reload_check,
+        #[weak]
+        file_label,
+        move |file_path: PathBuf| {
+            let source = buffer_text(&editor.buffer());
+            if let Err(e) = std::fs::write(&file_path, &source) {
+                eprintln!("Failed to save script to {}: {e}", file_path.display());
+                return;
+            }
+            if path.borrow().as_ref() != Some(&file_path) {
+                watch(&file_path);
+                file_label.set_text(&file_path.display().to_string());
+                path.replace(Some(file_path));
+            }
+            if reload_check.is_active() {
+                load_source(source);
+            }
+        }
+    ));
+
+    open_button.connect_clicked(clone!(
+        #[strong]
+        path,
+        #[strong]
+        watch,
+        #[strong]
+        load_source,
+        #[weak]
+        editor,
+        #[weak]
+        reload_check,
+        #[weak]
+        file_label,
+        move |b| {
+            let path = path.clone();
+            let watch = watch.clone();
+            let load_source = load_source.clone();
+            choose_file(
+                b,
+                "Open Script",
+                gtk::FileChooserAction::Open,
+                "Open",
+                move |file_path| match std::fs::read_to_string(&file_path) {
+                    Ok(source) => {
+                        editor.buffer().set_text(&source);
+                        watch(&file_path);
+                        file_label.set_text(&file_path.display().to_string());
+                        path.replace(Some(file_path));
+                        if reload_check.is_active() {
+                            load_source(source);
+                        }
+                    }
+                    Err(e) => eprintln!("Failed to open {}: {e}", file_path.display()),
+                },
+            );
+        }
+    ));
+    save_as_button.connect_clicked(clone!(
+        #[strong]
+        save_to,
+        move |b| {
+            let save_to = save_to.clone();
+            choose_file(
+                b,
+                "Save Script",
+                gtk::FileChooserAction::Save,
+                "Save",
+                move |file_path| save_to(file_path),
+            );
+        }
+    ));
+    save_button.connect_clicked(clone!(
+        #[strong]
+        path,
+        #[strong]
+        save_to,
+        #[weak]
+        save_as_button,
+        move |_| {
+            let file_path = path.borrow().clone();
+            match file_path {
+                Some(file_path) => save_to(file_path),
+                None => save_as_button.emit_clicked(),
+            }
+        }
+    ));
+    load_button.connect_clicked(clone!(
+        #[strong]
+        load_source,
+        #[weak]
+        editor,
+        move |_| load_source(buffer_text(&editor.buffer()))
+    ));
+    unload_button.connect_clicked(clone!(
+        #[weak]
+        queue,
+        move |_| queue.dispatch(Command::UnloadScript)
+    ));
+
+    frame.set_child(Some(&grid));
+    frame
+}
@@ -0,0 +1,154 @@
+use gtk4::{self as gtk};
+
+use glib_macros::clone;
+use gtk::prelude::*;
+
+use crate::client::Command;
+use crate::model::{CommandQueue, SessionObject};
+use crate::ui::file::choose_file;
+
+const FRAME_MARGIN: i32 = 6;
+const LIST_HEIGHT_REQUEST: i32 = 60;
+
+/// Panel showing the session state the runner will use for a resume.
+pub fn build_session_panel(session: &SessionObject, queue: &CommandQueue) -> gtk::Frame {
+    let frame = gtk::Frame::new(Some("Stored Session"));
+    let grid = gtk::Grid::new();
+    grid.set_column_spacing(12);
+    grid.set_row_spacing(4);
+    grid.set_margin_start(FRAME_MARGIN);
+    grid.set_margin_end(FRAME_MARGIN);
+    grid.set_margin_top(FRAME_MARGIN);
+    grid.set_margin_bottom(FRAME_MARGIN);
+
+    let mut row = 0;
+
+    let label = gtk::Label::new(Some("Client ID:"));
+    label.set_halign(gtk::Align::End);
+    grid.attach(&label, 0, row, 1, 1);
+    let client_id_label = gtk::Label::new(None);
+    client_id_label.set_halign(gtk::Align::Start);
+    client_id_label.set_selectable(true);
+    session
+        .bind_property("client-id", &client_id_label, "label")
+        .sync_create()
+        .build();
+    grid.attach(&client_id_label, 1, row, 1, 1);
+    row += 1;
+
+    let label = gtk::Label::new(Some("Expires In:"));
+    label.set_halign(gtk::Align::End);
+    grid.attach(&label, 0, row, 1, 1);
+    let expiry_label = gtk::Label::new(Some("-"));
+    expiry_label.set_halign(gtk::Align::Start);
+    grid.attach(&expiry_label, 1, row, 1, 1);
+    row += 1;
+    glib::timeout_add_seconds_local(
+        1,
+        clone!(
+            #[weak]
+            session,
+            #[weak]
+            expiry_label,
+            #[upgrade_or]
+            glib::ControlFlow::Break,
+            move || {
+                if session.present() {
+                    let remaining = session.expires_at() - chrono::Utc::now().timestamp();
+                    if remaining > 0 {
+                        expiry_label.set_text(&format!("{remaining} s"));
+                    } else {
+                        expiry_label.set_text("expired");
+                    }
+                } else {
+                    expiry_label.set_text("-");
+                }
+                glib::ControlFlow::Continue
+            }
+        ),
+    );
+
+    for (name, property, tooltip) in [
+        (
+            "Unacknowledged Outgoing:",
+            "unacked",
+            "Packet ID, type and topic of packets awaiting acknowledgement",
+        ),
+        (
+            "Pending Incoming QoS 2:",
+            "pending-qos2",
+            "Packet IDs of QoS 2 publishes received but not yet released",
+        ),
+        (
+            "Subscriptions:",
+            "subscriptions",
+            "Subscriptions held in the session",
+        ),
+    ] {
+        let label = gtk::Label::new(Some(name));
+        label.set_halign(gtk::Align::End);
+        label.set_valign(gtk::Align::Start);
+        grid.attach(&label, 0, row, 1, 1);
+        let value = gtk::Label::new(None);
+        value.set_halign(gtk::Align::Start);
+        value.set_valign(gtk::Align::Start);
+        value.set_selectable(true);
+        value.set_tooltip_text(Some(tooltip));
+        session
+            .bind_property(property, &value, "label")
+            .sync_create()
+            .build();
+        let scrolled = gtk::ScrolledWindow::new();
+        scrolled.set_child(Some(&value));
+        scrolled.set_min_content_height(LIST_HEIGHT_REQUEST);
+        scrolled.set_hexpand(true);
+        grid.attach(&scrolled, 1, row, 1, 1);
+        row += 1;
+    }
+
+    let button_box = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+    button_box.set_halign(gtk::Align::End);
+    let save_button = gtk::Button::with_label("Save Snapshot...");
+    save_button.set_tooltip_text(Some("Save the stored session to a file"));
+    session
+        .bind_property("present", &save_button, "sensitive")
+        .sync_create()
+        .build();
+    let load_button = gtk::Button::with_label("Load Snapshot...");
+    load_button.set_tooltip_text(Some(
+        "Load a saved session so it can be resumed with Resume Session",
+    ));
+    button_box.append(&save_button);
+    button_box.append(&load_button);
+    grid.attach(&button_box, 1, row, 1, 1);
+
+    save_button.connect_clicked(clone!(
+        #[weak]
+        queue,
+        move |b| {
+            choose_file(
+                b,
+                "Save Session Snapshot",
+                gtk::FileChooserAction::Save,
+                "Save",
+                move |path| queue.dispatch(Command::SaveSession(path)),
+            );
+        }
+    ));
+    load_button.connect_clicked(clone!(
+        #[weak]
+        queue,
+        move |b| {
+            choose_file(
+                b,
+                "Load Session Snapshot",
+                gtk::FileChooserAction::Open,
+                "Load",
+                move |path| queue.dispatch(Command::LoadSession(path)),
+            );
+        }
+    ));
+
+    frame.set_child(Some(&grid));
+    frame
+}
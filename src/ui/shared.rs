@@ -0,0 +1,288 @@
+//! Shared Subscriptions tab: run the load-balancing test on its own
+//! connections and show how the broker distributed the messages. Test
+//! traffic is added to the message log.
+
+use std::time::Duration;
+
+use glib::clone;
+use gtk4::{self as gtk};
+
+use gtk::prelude::*;
+
+use crate::client::ClientSetting;
+use crate::model::{MessageLog, PacketObject};
+use crate::profile::Profile;
+use crate::runtime::SharedRuntime;
+use crate::shared::{self, MemberStats, SharedTest, Update};
+use crate::wire;
+
+const FRAME_MARGIN: i32 = 6;
+
+fn member_text(index: usize, member: &MemberStats, published: u32) -> String {
+    let share = if published == 0 {
+        0.0
+    } else {
+        member.received as f64 * 100.0 / published as f64
+    };
+    let mut text = format!(
+        "{}. {}: {} received ({share:.1}%), {} out of order, {} redelivered, {} duplicates",
+        index + 1,
+        member.client_id,
+        member.received,
+        member.out_of_order,
+        member.redelivered,
+        member.duplicates
+    );
+    if member.dropped {
+        text.push_str(" [dropped]");
+    }
+    text
+}
+
+/// Run the test and its member runners on the shared runtime, reporting
+/// through `updates`.
+fn spawn_test(
+    runtime: &SharedRuntime,
+    test: SharedTest,
+    profile: Profile,
+    updates: tokio::sync::mpsc::UnboundedSender<Update>,
+) {
+    runtime.spawn_local(move || async move {
+        let mut on_update = move |update| {
+            let _ = updates.send(update);
+        };
+        shared::execute(&test, &profile, &mut on_update).await;
+    });
+}
+
+fn spin(min: f64, max: f64, value: f64, tooltip: &str) -> gtk::SpinButton {
+    let spin = gtk::SpinButton::with_range(min, max, 1.0);
+    spin.set_value(value);
+    spin.set_tooltip_text(Some(tooltip));
+    spin
+}
+
+fn attach_labeled(
+    grid: &gtk::Grid,
+    label: &str,
+    widget: &impl IsA<gtk::Widget>,
+    column: i32,
+    row: i32,
+) {
+    let label = gtk::Label::new(Some(label));
+    label.set_halign(gtk::Align::End);
+    grid.attach(&label, column, row, 1, 1);
+    grid.attach(widget, column + 1, row, 1, 1);
+}
+
+pub fn build_shared_panel(
+    client_setting: &ClientSetting,
+    message_log: &MessageLog,
+    runtime: &SharedRuntime,
+) -> gtk::Frame {
+    let frame = gtk::Frame::new(Some("Shared Subscriptions"));
+    let grid = gtk::Grid::new();
+    grid.set_column_spacing(4);
+    grid.set_row_spacing(4);
+    grid.set_margin_bottom(FRAME_MARGIN);
+    grid.set_margin_start(FRAME_MARGIN);
+    grid.set_margin_end(FRAME_MARGIN);
+
+    let group_entry = gtk::Entry::new();
+    group_entry.set_text("vaux");
+    group_entry.set_tooltip_text(Some("Share name of the group"));
+    attach_labeled(&grid, "Group:", &group_entry, 0, 0);
+    let filter_entry = gtk::Entry::new();
+    filter_entry.set_text("vaux/share/#");
+    filter_entry.set_hexpand(true);
+    filter_entry.set_tooltip_text(Some("Topic filter after $share/<group>/"));
+    attach_labeled(&grid, "Filter:", &filter_entry, 2, 0);
+    let topic_entry = gtk::Entry::new();
+    topic_entry.set_text("vaux/share/test");
+    topic_entry.set_hexpand(true);
+    topic_entry.set_tooltip_text(Some("Topic the test messages are published to"));
+    attach_labeled(&grid, "Topic:", &topic_entry, 4, 0);
+
+    let members_spin = spin(1.0, 32.0, 3.0, "Members subscribing to the shared filter");
+    attach_labeled(&grid, "Members:", &members_spin, 0, 1);
+    let messages_spin = spin(1.0, 100_000.0, 100.0, "Numbered messages to publish");
+    attach_labeled(&grid, "Messages:", &messages_spin, 2, 1);
+    let interval_spin = spin(
+        0.0,
+        10_000.0,
+        10.0,
+        "Pause between publishes in milliseconds",
+    );
+    attach_labeled(&grid, "Interval (ms):", &interval_spin, 4, 1);
+
+    let qos_combo = gtk::ComboBoxText::new();
+    qos_combo.append(Some("0"), "QoS 0");
+    qos_combo.append(Some("1"), "QoS 1");
+    qos_combo.append(Some("2"), "QoS 2");
+    qos_combo.set_active_id(Some("1"));
+    qos_combo.set_tooltip_text(Some("QoS of the subscriptions and the published messages"));
+    attach_labeled(&grid, "QoS:", &qos_combo, 0, 2);
+    let drop_spin = spin(
+        0.0,
+        100_000.0,
+        0.0,
+        "Drop member 1 without DISCONNECT after this many messages, 0 to keep it. \
+         It holds its acknowledgements so the broker has messages to redeliver",
+    );
+    attach_labeled(&grid, "Drop member 1 after:", &drop_spin, 2, 2);
+    let run_button = gtk::Button::with_label("Run");
+    run_button.set_tooltip_text(Some(
+        "Connect the members with the current connection settings and run the test",
+    ));
+    grid.attach(&run_button, 5, 2, 1, 1);
+
+    let summary_label = gtk::Label::new(Some("Not run"));
+    summary_label.set_halign(gtk::Align::Start);
+    summary_label.set_wrap(true);
+    summary_label.set_selectable(true);
+    grid.attach(&summary_label, 0, 3, 6, 1);
+
+    let members = gtk::StringList::new(&[]);
+    let factory = gtk::SignalListItemFactory::new();
+    factory.connect_setup(|_, item| {
+        let label = gtk::Label::new(None);
+        label.set_halign(gtk::Align::Start);
+        label.set_selectable(true);
+        item.downcast_ref::<gtk::ListItem>()
+            .expect("Failed to downcast")
+            .set_child(Some(&label));
+    });
+    factory.connect_bind(|_, item| {
+        let item = item
+            .downcast_ref::<gtk::ListItem>()
+            .expect("Failed to downcast");
+        if let (Some(label), Some(row)) = (
+            item.child().and_downcast::<gtk::Label>(),
+            item.item().and_downcast::<gtk::StringObject>(),
+        ) {
+            label.set_text(&row.string());
+        }
+    });
+    let list = gtk::ListView::new(
+        Some(gtk::NoSelection::new(Some(members.clone()))),
+        Some(factory),
+    );
+    let scrolled = gtk::ScrolledWindow::new();
+    scrolled.set_child(Some(&list));
+    scrolled.set_vexpand(true);
+    grid.attach(&scrolled, 0, 4, 6, 1);
+
+    let client_setting = client_setting.clone();
+    let runtime = runtime.clone();
+    run_button.connect_clicked(clone!(
+        #[weak]
+        group_entry,
+        #[weak]
+        filter_entry,
+        #[weak]
+        topic_entry,
+        #[weak]
+        members_spin,
+        #[weak]
+        messages_spin,
+        #[weak]
+        interval_spin,
+        #[weak]
+        qos_combo,
+        #[weak]
+        drop_spin,
+        #[weak]
+        summary_label,
+        #[weak]
+        members,
+        #[weak]
+        message_log,
+        move |run_button| {
+            let test = SharedTest {
+                group: group_entry.text().to_string(),
+                filter: filter_entry.text().to_string(),
+                topic: topic_entry.text().to_string(),
+                members: members_spin.value_as_int() as usize,
+                messages: messages_spin.value_as_int() as u32,
+                qos: qos_combo
+                    .active_id()
+                    .and_then(|id| id.parse().ok())
+                    .unwrap_or(1),
+                interval: Duration::from_millis(interval_spin.value_as_int() as u64),
+                drop_after: Some(drop_spin.value_as_int() as u32).filter(|n| *n > 0),
+            };
+            if test.group.is_empty() || test.filter.is_empty() || test.topic.is_empty() {
+                summary_label.set_text("Enter a group, filter and topic");
+                return;
+            }
+
+            members.splice(0, members.n_items(), &[]);
+            summary_label.set_text(&format!(
+                "Connecting {} members to {}...",
+                test.members,
+                test.share_filter()
+            ));
+            run_button.set_sensitive(false);
+            let profile = Profile::from_setting(&client_setting);
+            let total = test.messages;
+            let (updates_tx, mut updates_rx) = tokio::sync::mpsc::unbounded_channel();
+            spawn_test(&runtime, test, profile, updates_tx);
+            glib::spawn_future_local(clone!(
+                #[weak]
+                run_button,
+                #[weak]
+                summary_label,
+                #[weak]
+                members,
+                #[weak]
+                message_log,
+                async move {
+                    let mut published = 0;
+                    let mut stats: Vec<MemberStats> = Vec::new();
+                    while let Some(update) = updates_rx.recv().await {
+                        match update {
+                            Update::Packet(exchange, packet) => {
+                                let size = wire::Encoded::of(&packet).size;
+                                message_log.append(&PacketObject::new(exchange, packet, size));
+                            }
+                            Update::Member(index, member) => {
+                                let text = member_text(index, &member, published);
+                                if index < stats.len() {
+                                    members.splice(index as u32, 1, &[text.as_str()]);
+                                    stats[index] = member;
+                                } else {
+                                    members.append(&text);
+                                    stats.push(member);
+                                }
+                            }
+                            Update::Published(number) => {
+                                published = number;
+                                summary_label.set_text(&format!("Published {number} of {total}"));
+                            }
+                            Update::Finished(summary) => {
+                                // shares of the final count
+                                let rows: Vec<String> = stats
+                                    .iter()
+                                    .enumerate()
+                                    .map(|(index, member)| {
+                                        member_text(index, member, summary.published)
+                                    })
+                                    .collect();
+                                let rows: Vec<&str> = rows.iter().map(String::as_str).collect();
+                                members.splice(0, members.n_items(), &rows);
+                                summary_label.set_text(&summary.text());
+                            }
+                            Update::Failed(e) => {
+                                summary_label.set_text(&format!("Test failed: {e}"))
+                            }
+                        }
+                    }
+                    run_button.set_sensitive(true);
+                }
+            ));
+        }
+    ));
+
+    frame.set_child(Some(&grid));
+    frame
+}
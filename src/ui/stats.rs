@@ -0,0 +1,275 @@
+//! Statistics view: rolling throughput charts, ping round trip, QoS ack
+//! latency percentiles and per-topic counters.
+
+use std::{cell::RefCell, rc::Rc, time::Duration, time::Instant};
+
+use glib::clone;
+use gtk4::{self as gtk, cairo};
+
+use gtk::prelude::*;
+
+use crate::stats::{Rate, Statistics, WINDOW};
+
+const FRAME_MARGIN: i32 = 6;
+const CHART_HEIGHT: i32 = 110;
+const SEND_COLOR: (f64, f64, f64) = (0.2, 0.4, 0.8);
+const RECEIVE_COLOR: (f64, f64, f64) = (0.2, 0.6, 0.3);
+pub(crate) const RTT_COLOR: (f64, f64, f64) = (0.8, 0.4, 0.1);
+
+pub(crate) fn millis(d: Duration) -> String {
+    format!("{:.1} ms", d.as_secs_f64() * 1000.0)
+}
+
+/// Draw each series as a polyline scaled to the largest value, with the
+/// title and the scale maximum in the top left corner.
+pub(crate) fn draw_series(
+    cr: &cairo::Context,
+    width: i32,
+    height: i32,
+    title: &str,
+    unit: &str,
+    series: &[((f64, f64, f64), Vec<f64>)],
+) {
+    let (width, height) = (width as f64, height as f64);
+    let top = 16.0;
+    let max = series
+        .iter()
+        .flat_map(|(_, values)| values.iter().copied())
+        .fold(0.0, f64::max)
+        .max(1.0);
+
+    cr.set_source_rgba(0.5, 0.5, 0.5, 0.6);
+    cr.set_line_width(1.0);
+    cr.move_to(0.0, top);
+    cr.line_to(width, top);
+    cr.move_to(0.0, height - 0.5);
+    cr.line_to(width, height - 0.5);
+    let _ = cr.stroke();
+
+    cr.set_source_rgba(0.3, 0.3, 0.3, 1.0);
+    cr.set_font_size(11.0);
+    cr.move_to(2.0, 12.0);
+    let _ = cr.show_text(&format!("{title} (max {max:.0} {unit})"));
+
+    for ((r, g, b), values) in series {
+        if values.is_empty() {
+            continue;
+        }
+        cr.set_source_rgba(*r, *g, *b, 1.0);
+        cr.set_line_width(1.5);
+        let step = if values.len() > 1 {
+            width / (values.len() - 1) as f64
+        } else {
+            0.0
+        };
+        for (i, value) in values.iter().enumerate() {
+            let x = i as f64 * step;
+            let y = height - 1.0 - (value / max) * (height - top - 2.0);
+            if i == 0 {
+                cr.move_to(x, y);
+            } else {
+                cr.line_to(x, y);
+            }
+        }
+        let _ = cr.stroke();
+    }
+}
+
+pub(crate) fn build_chart(
+    statistics: &Rc<RefCell<Statistics>>,
+    draw: impl Fn(&Statistics, &cairo::Context, i32, i32) + 'static,
+) -> gtk::DrawingArea {
+    let chart = gtk::DrawingArea::new();
+    chart.set_content_height(CHART_HEIGHT);
+    chart.set_hexpand(true);
+    let statistics = statistics.clone();
+    chart.set_draw_func(move |_, cr, width, height| draw(&statistics.borrow(), cr, width, height));
+    chart
+}
+
+fn legend(text: &str, (r, g, b): (f64, f64, f64)) -> gtk::Label {
+    let label = gtk::Label::new(None);
+    label.set_markup(&format!(
+        "<span foreground=\"#{:02X}{:02X}{:02X}\">\u{25A0}</span> {text}",
+        (r * 255.0) as u8,
+        (g * 255.0) as u8,
+        (b * 255.0) as u8
+    ));
+    label.set_halign(gtk::Align::Start);
+    label
+}
+
+pub fn build_statistics_view(statistics: &Rc<RefCell<Statistics>>) -> gtk::Frame {
+    let frame = gtk::Frame::new(Some("Statistics"));
+    let grid = gtk::Grid::new();
+    grid.set_column_spacing(8);
+    grid.set_row_spacing(4);
+    grid.set_margin_bottom(FRAME_MARGIN);
+    grid.set_margin_start(FRAME_MARGIN);
+    grid.set_margin_end(FRAME_MARGIN);
+
+    let legend_box = gtk::Box::new(gtk::Orientation::Horizontal, 12);
+    legend_box.append(&legend("Sent", SEND_COLOR));
+    legend_box.append(&legend("Received", RECEIVE_COLOR));
+    legend_box.append(&gtk::Label::new(Some(&format!(
+        "last {} seconds",
+        WINDOW.as_secs()
+    ))));
+    let reset_button = gtk::Button::with_label("Reset");
+    reset_button.set_tooltip_text(Some("Clear the statistics window and topic counters"));
+    reset_button.set_hexpand(true);
+    reset_button.set_halign(gtk::Align::End);
+    legend_box.append(&reset_button);
+    grid.attach(&legend_box, 0, 0, 1, 1);
+
+    let rate_series = |rates: &[Rate],
+                       sent: fn(&Rate) -> f64,
+                       received: fn(&Rate) -> f64|
+     -> Vec<((f64, f64, f64), Vec<f64>)> {
+        vec![
+            (SEND_COLOR, rates.iter().map(sent).collect()),
+            (RECEIVE_COLOR, rates.iter().map(received).collect()),
+        ]
+    };
+    let messages_chart = build_chart(statistics, move |stats, cr, width, height| {
+        let rates = stats.rates(Instant::now());
+        let series = rate_series(
+            &rates,
+            |r| r.messages_sent as f64,
+            |r| r.messages_received as f64,
+        );
+        draw_series(cr, width, height, "Messages", "msg/s", &series);
+    });
+    grid.attach(&messages_chart, 0, 1, 1, 1);
+    let bytes_chart = build_chart(statistics, move |stats, cr, width, height| {
+        let rates = stats.rates(Instant::now());
+        let series = rate_series(&rates, |r| r.bytes_sent as f64, |r| r.bytes_received as f64);
+        draw_series(cr, width, height, "Bytes", "B/s", &series);
+    });
+    grid.attach(&bytes_chart, 0, 2, 1, 1);
+    let rtt_chart = build_chart(statistics, |stats, cr, width, height| {
+        let rtt = stats
+            .ping_rtt()
+            .iter()
+            .map(|rtt| rtt.as_secs_f64() * 1000.0)
+            .collect();
+        draw_series(
+            cr,
+            width,
+            height,
+            "Ping round trip",
+            "ms",
+            &[(RTT_COLOR, rtt)],
+        );
+    });
+    grid.attach(&rtt_chart, 0, 3, 1, 1);
+
+    let latency_label = gtk::Label::new(None);
+    latency_label.set_halign(gtk::Align::Start);
+    grid.attach(&latency_label, 0, 4, 1, 1);
+
+    // per-topic counters beside the charts
+    let topics = gtk::StringList::new(&[]);
+    let factory = gtk::SignalListItemFactory::new();
+    factory.connect_setup(|_, item| {
+        let label = gtk::Label::new(None);
+        label.set_halign(gtk::Align::Start);
+        item.downcast_ref::<gtk::ListItem>()
+            .expect("Failed to downcast")
+            .set_child(Some(&label));
+    });
+    factory.connect_bind(|_, item| {
+        let item = item
+            .downcast_ref::<gtk::ListItem>()
+            .expect("Failed to downcast");
+        if let (Some(label), Some(row)) = (
+            item.child().and_downcast::<gtk::Label>(),
+            item.item().and_downcast::<gtk::StringObject>(),
+        ) {
+            label.set_text(&row.string());
+        }
+    });
+    let topic_list = gtk::ListView::new(
+        Some(gtk::NoSelection::new(Some(topics.clone()))),
+        Some(factory),
+    );
+    let topic_scroll = gtk::ScrolledWindow::new();
+    topic_scroll.set_child(Some(&topic_list));
+    topic_scroll.set_min_content_width(260);
+    topic_scroll.set_vexpand(true);
+    let topic_frame = gtk::Frame::new(Some("Topics (sent / received / bytes)"));
+    topic_frame.set_child(Some(&topic_scroll));
+    grid.attach(&topic_frame, 1, 0, 1, 5);
+
+    let refresh = Rc::new(clone!(
+        #[strong]
+        statistics,
+        #[weak]
+        messages_chart,
+        #[weak]
+        bytes_chart,
+        #[weak]
+        rtt_chart,
+        #[weak]
+        latency_label,
+        #[weak]
+        topics,
+        move || {
+            let mut stats = statistics.borrow_mut();
+            stats.expire(Instant::now());
+            let latency = stats.ack_latency();
+            latency_label.set_text(&if latency.count == 0 {
+                "QoS 1/2 ack latency: no acknowledgements in the window".to_string()
+            } else {
+                format!(
+                    "QoS 1/2 ack latency ({} acks): p50 {}, p90 {}, p99 {}, max {}",
+                    latency.count,
+                    millis(latency.p50),
+                    millis(latency.p90),
+                    millis(latency.p99),
+                    millis(latency.max)
+                )
+            });
+            let rows: Vec<String> = stats
+                .topics()
+                .iter()
+                .map(|(topic, c)| format!("{topic}: {} / {} / {}", c.sent, c.received, c.bytes))
+                .collect();
+            let rows: Vec<&str> = rows.iter().map(String::as_str).collect();
+            topics.splice(0, topics.n_items(), &rows);
+            messages_chart.queue_draw();
+            bytes_chart.queue_draw();
+            rtt_chart.queue_draw();
+        }
+    ));
+    refresh();
+    reset_button.connect_clicked(clone!(
+        #[strong]
+        statistics,
+        #[strong]
+        refresh,
+        move |_| {
+            statistics.borrow_mut().clear();
+            refresh();
+        }
+    ));
+    // the window slides even when no packets arrive
+    glib::timeout_add_seconds_local(
+        1,
+        clone!(
+            #[weak]
+            frame,
+            #[upgrade_or]
+            glib::ControlFlow::Break,
+            move || {
+                if frame.is_mapped() {
+                    refresh();
+                }
+                glib::ControlFlow::Continue
+            }
+        ),
+    );
+
+    frame.set_child(Some(&grid));
+    frame
+}
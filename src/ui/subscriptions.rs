@@ -0,0 +1,182 @@
+//! Subscription manager: every filter subscribed from the Subscribe tab with
+//! the broker's answer, failed filters in red.
+
+use std::future::Future;
+
+use glib::clone;
+use gtk4::{self as gtk, gio};
+
+use gtk::prelude::*;
+use vaux_mqtt::QoSLevel;
+
+use crate::client::{Command, CommandResult, Reply};
+use crate::model::CommandQueue;
+use crate::model::subscription::{self, Subscription};
+use crate::reason;
+
+const FRAME_MARGIN: i32 = 6;
+
+/// Subscribe to `topic` and track it in `store`. The list is updated when
+/// the returned future completes.
+pub(crate) fn subscribe(
+    queue: &CommandQueue,
+    store: &gio::ListStore,
+    packet_id: u16,
+    qos: QoSLevel,
+    topic: String,
+) -> impl Future<Output = CommandResult> + use<> {
+    let subscription = subscription::find_or_add(store, &topic);
+    subscription.pending(packet_id, qos.clone() as u32);
+    subscription::refresh(store, &subscription);
+    let sent = queue.send(Command::Subscribe(packet_id, qos, topic));
+    let store = store.clone();
+    async move {
+        let result = sent.await;
+        let accepted = subscription.acknowledged(&result, reason::suback_name);
+        subscription.set_active(accepted);
+        subscription::refresh(&store, &subscription);
+        result
+    }
+}
+
+/// Unsubscribe from `topic`, dropping it from `store` once the broker
+/// accepts. A refusal is shown on the listed subscription.
+pub(crate) fn unsubscribe(
+    queue: &CommandQueue,
+    store: &gio::ListStore,
+    packet_id: u16,
+    topic: String,
+) -> impl Future<Output = CommandResult> + use<> {
+    let sent = queue.send(Command::Unsubscribe(packet_id, topic.clone()));
+    let store = store.clone();
+    async move {
+        let result = sent.await;
+        if let Some(subscription) = subscription::find(&store, &topic) {
+            // "No subscription existed" is not a failure, the filter is gone either way
+            let removed = matches!(&result, Ok(Reply::Acknowledged(reasons))
+                if reasons.codes.first().is_some_and(|code| !reason::is_failure(*code)));
+            if removed {
+                subscription::remove(&store, &subscription);
+            } else {
+                subscription.acknowledged(&result, reason::name);
+                subscription::refresh(&store, &subscription);
+            }
+        }
+        result
+    }
+}
+
+fn column(
+    title: &str,
+    text: impl Fn(&Subscription) -> String + 'static,
+    highlight_failed: bool,
+) -> gtk::ColumnViewColumn {
+    let factory = gtk::SignalListItemFactory::new();
+    factory.connect_setup(move |_, item| {
+        let label = gtk::Label::new(None);
+        label.set_halign(gtk::Align::Start);
+        label.set_ellipsize(gtk::pango::EllipsizeMode::End);
+        item.downcast_ref::<gtk::ListItem>()
+            .expect("Failed to downcast")
+            .set_child(Some(&label));
+    });
+    factory.connect_bind(move |_, item| {
+        let item = item
+            .downcast_ref::<gtk::ListItem>()
+            .expect("Failed to downcast");
+        let subscription = item
+            .item()
+            .and_downcast::<Subscription>()
+            .expect("Failed to downcast to Subscription");
+        let label = item
+            .child()
+            .and_downcast::<gtk::Label>()
+            .expect("Failed to downcast to Label");
+        let text = text(&subscription);
+        label.set_tooltip_text(Some(&text));
+        label.set_text(&text);
+        if highlight_failed && subscription.failed() {
+            label.add_css_class("error");
+        } else {
+            label.remove_css_class("error");
+        }
+    });
+    let column = gtk::ColumnViewColumn::new(Some(title), Some(factory));
+    column.set_resizable(true);
+    column
+}
+
+pub fn build_subscription_panel(store: &gio::ListStore, queue: &CommandQueue) -> gtk::Frame {
+    let frame = gtk::Frame::new(Some("Subscriptions"));
+    let vbox = gtk::Box::new(gtk::Orientation::Vertical, 4);
+    vbox.set_margin_start(FRAME_MARGIN);
+    vbox.set_margin_end(FRAME_MARGIN);
+    vbox.set_margin_top(FRAME_MARGIN);
+    vbox.set_margin_bottom(FRAME_MARGIN);
+
+    let column_view = gtk::ColumnView::new(None::<gtk::SelectionModel>);
+    column_view.set_show_column_separators(true);
+    column_view.append_column(&column("Topic Filter", |s| s.topic(), true));
+    column_view.append_column(&column("QoS", |s| s.qos().to_string(), false));
+    column_view.append_column(&column("ID", |s| s.id().to_string(), false));
+    column_view.append_column(&column("Status", |s| s.status(), true));
+    let reason_column = column("Reason String / User Properties", |s| s.reason(), false);
+    reason_column.set_expand(true);
+    column_view.append_column(&reason_column);
+    let selection = gtk::SingleSelection::new(Some(store.clone()));
+    column_view.set_model(Some(&selection));
+
+    let scrolled_window = gtk::ScrolledWindow::new();
+    scrolled_window.set_policy(gtk::PolicyType::Automatic, gtk::PolicyType::Automatic);
+    scrolled_window.set_vexpand(true);
+    scrolled_window.set_child(Some(&column_view));
+    vbox.append(&scrolled_window);
+
+    let button_box = gtk::Box::new(gtk::Orientation::Horizontal, 4);
+    button_box.set_halign(gtk::Align::End);
+    let unsubscribe_button = gtk::Button::with_label("Unsubscribe");
+    unsubscribe_button.set_tooltip_text(Some(
+        "Unsubscribe from the selected filter, reusing its packet ID",
+    ));
+    let remove_button = gtk::Button::with_label("Remove");
+    remove_button.set_tooltip_text(Some(
+        "Drop the selected filter from the list without unsubscribing",
+    ));
+    button_box.append(&unsubscribe_button);
+    button_box.append(&remove_button);
+    vbox.append(&button_box);
+
+    unsubscribe_button.connect_clicked(clone!(
+        #[weak]
+        selection,
+        #[weak]
+        store,
+        #[weak]
+        queue,
+        move |_| {
+            let Some(selected) = selection.selected_item().and_downcast::<Subscription>() else {
+                return;
+            };
+            selected.set_status("Unsubscribing");
+            subscription::refresh(&store, &selected);
+            let done = unsubscribe(&queue, &store, selected.id() as u16, selected.topic());
+            glib::spawn_future_local(async move {
+                let _ = done.await;
+            });
+        }
+    ));
+    remove_button.connect_clicked(clone!(
+        #[weak]
+        selection,
+        #[weak]
+        store,
+        move |_| {
+            if let Some(selected) = selection.selected_item().and_downcast::<Subscription>() {
+                subscription::remove(&store, &selected);
+            }
+        }
+    ));
+
+    frame.set_child(Some(&vbox));
+    frame
+}
@@ -0,0 +1,188 @@
+//! Conversion between vaux-mqtt packets and their raw wire bytes.
+
+use bytes::{BufMut, BytesMut};
+use vaux_mqtt::{Packet, UserPropertyMap};
+
+use crate::properties::{PropertyEncoder, put_variable_byte_int};
+use crate::reason;
+
+/// Reason codes and properties of a SUBACK or UNSUBACK.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct AckReasons {
+    /// One per topic filter, in the order they were subscribed.
+    pub codes: Vec<u8>,
+    pub reason_string: Option<String>,
+    pub user_properties: Vec<(String, String)>,
+}
+
+impl AckReasons {
+    pub fn failed(&self) -> bool {
+        self.codes.iter().any(|code| reason::is_failure(*code))
+    }
+
+    /// Reason string and user properties, empty when the broker sent neither.
+    pub fn properties_text(&self) -> String {
+        let mut text = self.reason_string.clone().unwrap_or_default();
+        if !self.user_properties.is_empty() {
+            let properties = self
+                .user_properties
+                .iter()
+                .map(|(key, value)| format!("{key}={value}"))
+                .collect::<Vec<_>>()
+                .join(", ");
+            if !text.is_empty() {
+                text.push(' ');
+            }
+            text.push_str(&format!("[{properties}]"));
+        }
+        text
+    }
+
+    /// Every reason code by name, then the reason string and user properties.
+    pub fn summary(&self, name: fn(u8) -> &'static str) -> String {
+        let mut summary = self
+            .codes
+            .iter()
+            .map(|code| format!("0x{code:02X} {}", name(*code)))
+            .collect::<Vec<_>>()
+            .join(", ");
+        let properties = self.properties_text();
+        if !properties.is_empty() {
+            summary.push_str(&format!(": {properties}"));
+        }
+        summary
+    }
+}
+
+/// Encode a packet to the bytes sent on the wire.
+pub fn encode(packet: &Packet) -> Result<BytesMut, String> {
+    let mut dest = BytesMut::new();
+    vaux_mqtt::codec::encode(packet.clone(), &mut dest).map_err(|e| e.to_string())?;
+    Ok(dest)
+}
+
+/// Decode a single complete packet from wire bytes.
+pub fn decode(bytes: &[u8]) -> Result<Packet, String> {
+    let mut src = BytesMut::from(bytes);
+    match vaux_mqtt::codec::decode(&mut src) {
+        Ok(Some(packet)) => Ok(packet),
+        Ok(None) => Err("incomplete packet".to_string()),
+        Err(e) => Err(e.to_string()),
+    }
+}
+
+/// Size and fixed header byte of a packet, taken from one encoding so each
+/// received packet is encoded once.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct Encoded {
+    pub size: usize,
+    pub header: u8,
+}
+
+impl Encoded {
+    /// Zero size when the packet does not encode.
+    pub fn of(packet: &Packet) -> Self {
+        encode(packet)
+            .map(|raw| Self {
+                size: raw.len(),
+                header: raw.first().copied().unwrap_or_default(),
+            })
+            .unwrap_or_default()
+    }
+
+    /// RETAIN flag of a PUBLISH.
+    pub fn retain(&self) -> bool {
+        self.header & 0x01 != 0
+    }
+
+    /// QoS of a PUBLISH.
+    pub fn qos(&self) -> u8 {
+        (self.header >> 1) & 0x03
+    }
+}
+
+/// SUBSCRIBE for a single filter with an explicit Retain Handling option,
+/// which the vaux-client subscribe call does not take.
+pub fn subscribe(
+    packet_id: u16,
+    filter: &str,
+    qos: u8,
+    retain_handling: u8,
+) -> Result<Packet, String> {
+    let mut body = BytesMut::new();
+    body.put_u16(packet_id);
+    body.extend_from_slice(&PropertyEncoder::new().finish());
+    body.put_u16(filter.len() as u16);
+    body.extend_from_slice(filter.as_bytes());
+    body.put_u8((qos & 0x03) | (retain_handling & 0x03) << 4);
+    let mut raw = BytesMut::with_capacity(body.len() + 5);
+    raw.put_u8(0x82);
+    put_variable_byte_int(&mut raw, body.len() as u32);
+    raw.extend_from_slice(&body);
+    decode(&raw)
+}
+
+/// Reason codes and properties of a SUBACK or UNSUBACK.
+pub fn ack_reasons(packet: &Packet) -> Option<AckReasons> {
+    let (codes, reason_desc, user_props) = match packet {
+        Packet::SubAck(ack) => (&ack.reason_codes()[..], &ack.reason_desc, &ack.user_props),
+        Packet::UnsubAck(ack) => (&ack.reason_code[..], &ack.reason_desc, &ack.user_props),
+        _ => return None,
+    };
+    Some(AckReasons {
+        codes: codes.iter().map(reason::code).collect(),
+        reason_string: reason_desc.clone(),
+        user_properties: user_props.as_ref().map(user_properties).unwrap_or_default(),
+    })
+}
+
+/// User properties as key and value pairs, sorted so they show in a stable
+/// order.
+fn user_properties(map: &UserPropertyMap) -> Vec<(String, String)> {
+    let mut properties: Vec<(String, String)> = map
+        .map()
+        .iter()
+        .flat_map(|(key, values)| values.iter().map(|value| (key.clone(), value.clone())))
+        .collect();
+    properties.sort();
+    properties
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn suback_reasons_and_properties() {
+        let raw = [
+            0x90, 0x11, 0x00, 0x07, 0x0C, 0x1F, 0x00, 0x02, b'o', b'k', 0x26, 0x00, 0x01, b'k',
+            0x00, 0x01, b'v', 0x01, 0x87,
+        ];
+        let reasons = ack_reasons(&decode(&raw).unwrap()).unwrap();
+        assert_eq!(reasons.codes, vec![0x01, 0x87]);
+        assert_eq!(reasons.reason_string.as_deref(), Some("ok"));
+        assert_eq!(
+            reasons.user_properties,
+            vec![("k".to_string(), "v".to_string())]
+        );
+        assert!(reasons.failed());
+        assert_eq!(reasons.properties_text(), "ok [k=v]");
+    }
+
+    #[test]
+    fn unsuback_reasons_without_properties() {
+        let raw = [0xB0, 0x05, 0x00, 0x03, 0x00, 0x00, 0x11];
+        let reasons = ack_reasons(&decode(&raw).unwrap()).unwrap();
+        assert_eq!(reasons.codes, vec![0x00, 0x11]);
+        assert_eq!(reasons.reason_string, None);
+        assert!(reasons.user_properties.is_empty());
+        assert!(!reasons.failed());
+        assert_eq!(reasons.properties_text(), "");
+    }
+
+    #[test]
+    fn other_packets_have_no_ack_reasons() {
+        let puback = [0x40, 0x03, 0x00, 0x01, 0x00];
+        assert_eq!(ack_reasons(&decode(&puback).unwrap()), None);
+    }
+}